@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use searcher::{Granularity, HighlightOptions, Query, Searcher};
+
+// index arbitrary text and run everything that re-reads it
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let mut searcher = Searcher::new();
+    searcher.add_document("doc", &text);
+    searcher.add_copy_field("title");
+    searcher.add_keyword("doc", "title", &text);
+
+    let query = Query::from_words(&text);
+    searcher.search(&text);
+    searcher.search_query(&query);
+    searcher.highlight("doc", &query, &HighlightOptions::default());
+    searcher.best_passages(&query, Granularity::Sentence, 3);
+    searcher.best_passages(&query, Granularity::Window(4), 3);
+    searcher.summarize("doc", Some(&query), 2);
+    searcher.key_terms("doc", 5);
+});
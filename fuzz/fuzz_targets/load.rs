@@ -0,0 +1,25 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use searcher::{HighlightOptions, ParseMode, Query, Searcher};
+
+// load arbitrary bytes as a saved index and use whatever loads
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut searcher) = Searcher::from_bytes(data) else {
+        return;
+    };
+
+    let query = Query::parse("rust \"borrow checker\" rock* ext:rs", ParseMode::Strict).expect("the query is valid");
+    let doc_ids: Vec<String> = searcher.search_query(&query).into_keys().collect();
+    for doc_id in &doc_ids {
+        searcher.highlight(doc_id, &query, &HighlightOptions::default());
+        searcher.term_vector(doc_id);
+    }
+    searcher.term_stats();
+    searcher.build_report();
+    searcher.run_alerts();
+    Searcher::from_bytes(&searcher.to_bytes()).expect("a loaded index saves and loads again");
+    for doc_id in &doc_ids {
+        searcher.remove_document(doc_id);
+    }
+});
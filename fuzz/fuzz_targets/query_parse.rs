@@ -0,0 +1,27 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use searcher::{HighlightOptions, ParseMode, Query, Searcher};
+
+// parse arbitrary query strings and evaluate whatever parses
+fuzz_target!(|data: &[u8]| {
+    let Ok(input) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let mut searcher = Searcher::new();
+    searcher.add_document("doc", "rust compiler, borrow checker. Ownership rules.");
+    searcher.add_keyword("doc", "ext", "rs");
+
+    match Query::parse(input, ParseMode::Strict) {
+        Ok(query) => {
+            searcher.search_query(&query);
+            searcher.highlight("doc", &query, &HighlightOptions::default());
+        }
+        Err(err) => {
+            err.underline(input);
+        }
+    }
+    let query = Query::parse(input, ParseMode::Lenient).expect("lenient parsing never fails");
+    searcher.search_query(&query);
+});
@@ -0,0 +1,146 @@
+//! Standing searches: named queries saved with the index and run against the documents added
+//! since they last ran, e.g. to hear about new notes that mention a project.
+//!
+//! Documents count as new when they were added or replaced after the previous `run_alerts`, so a
+//! query saved later only sees what comes after that run.
+//!
+//! The saved queries can also be matched against a document without indexing it, with
+//! `percolate`: the inverse of a search, e.g. to label or route incoming tickets by the queries
+//! saved for each team.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::backend::Failures;
+use crate::query::ParseError;
+use crate::{Document, IndexBackend, ParseMode, Query, Searcher};
+
+/// A new document matching a saved query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub query: String, // name of the saved query
+    pub doc_id: String,
+    pub score: f32,
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Save `query`, in the query syntax, under `name` to be run by `run_alerts`, replacing any
+    /// query saved under that name. Saved queries are kept when the index is saved.
+    pub fn save_query(&mut self, name: &str, query: &str) -> Result<(), ParseError> {
+        let parsed = Query::parse(query, ParseMode::Strict)?;
+        self.saved_queries.insert(name.to_string(), (query.to_string(), parsed));
+        Ok(())
+    }
+
+    /// Remove a saved query. Returns false if there is no query saved under `name`.
+    pub fn remove_saved_query(&mut self, name: &str) -> bool {
+        self.saved_queries.remove(name).is_some()
+    }
+
+    /// The saved queries as (name, query), sorted by name.
+    pub fn saved_queries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.saved_queries.iter().map(|(name, (query, _))| (name.as_str(), query.as_str()))
+    }
+
+    /// Run every saved query against the documents added or replaced since the last run, and
+    /// mark them as seen. Alerts are sorted by query name, then best match first.
+    pub fn run_alerts(&mut self) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (name, (_, query)) in &self.saved_queries {
+            let mut matches: Vec<Alert> = self
+                .search_query_in(query, &Failures::default())
+                .into_iter()
+                .filter(|(doc_id, _)| self.docs[doc_id.as_str()].ordinal >= self.alerted_ordinal)
+                .map(|(doc_id, score)| Alert { query: name.clone(), doc_id, score })
+                .collect();
+            matches.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.doc_id.cmp(&b.doc_id)));
+            alerts.extend(matches);
+        }
+        self.alerted_ordinal = self.next_ordinal;
+        alerts
+    }
+
+    /// The names of the saved queries `content` matches, sorted, without adding it to the index.
+    /// It is analyzed like indexed documents; queries about other documents, like joins, and
+    /// keyword queries never match.
+    pub fn percolate(&self, content: &str) -> Vec<&str> {
+        let mut probe = self.child_searcher();
+        probe.add_document("", content);
+        self.matching_queries(&probe)
+    }
+
+    /// Same as `percolate`, for a document with text fields, which `field:text` queries match.
+    pub fn percolate_document(&self, document: &Document) -> Vec<&str> {
+        let mut probe = self.child_searcher();
+        probe.add_fields(document);
+        self.matching_queries(&probe)
+    }
+
+    /// The names of the saved queries matching the only document of `probe`.
+    fn matching_queries(&self, probe: &Searcher) -> Vec<&str> {
+        self.saved_queries
+            .iter()
+            .filter(|(_, (_, query))| !probe.search_query(query).is_empty())
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(alerts: &[Alert]) -> Vec<(&str, &str)> {
+        alerts.iter().map(|alert| (alert.query.as_str(), alert.doc_id.as_str())).collect()
+    }
+
+    #[test]
+    fn test_run_alerts() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("old", "rocket launch delayed");
+        searcher.save_query("launches", "+rocket +launch").unwrap();
+        searcher.save_query("tides", "ocean tide*").unwrap();
+        assert!(searcher.save_query("broken", "(rocket").is_err());
+        assert!(searcher.saved_queries().eq([("launches", "+rocket +launch"), ("tides", "ocean tide*")]));
+
+        // the first run sees everything indexed so far
+        assert_eq!(matches(&searcher.run_alerts()), [("launches", "old")]);
+        assert!(searcher.run_alerts().is_empty());
+
+        searcher.add_document("new", "the rocket launch is tonight");
+        searcher.add_document("coast", "ocean tides");
+        searcher.update_document("old", "rocket launch moved to friday");
+        let alerts = searcher.run_alerts();
+        assert_eq!(matches(&alerts), [("launches", "new"), ("launches", "old"), ("tides", "coast")]);
+        assert!(alerts[0].score >= alerts[1].score);
+
+        // kept with the index, along with what was already seen
+        let mut loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        assert!(loaded.saved_queries().eq(searcher.saved_queries()));
+        assert!(loaded.run_alerts().is_empty());
+        assert!(loaded.remove_saved_query("tides"));
+        assert!(!loaded.remove_saved_query("tides"));
+        loaded.add_document("beach", "ocean tide and rocket launch");
+        assert_eq!(matches(&loaded.run_alerts()), [("launches", "beach")]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_percolate() {
+        let mut searcher = Searcher::new();
+        searcher.set_stemmer(Some(crate::Stemmer::English));
+        searcher.save_query("billing", "invoice refund").unwrap();
+        searcher.save_query("outage", "+\"service down\" -maintenance").unwrap();
+        searcher.save_query("urgent", "title:urgent").unwrap();
+
+        assert_eq!(searcher.percolate("Where is my refund?"), ["billing"]);
+        // analyzed like the index: stop words and stems
+        assert_eq!(searcher.percolate("The service is down, no invoices since"), ["billing", "outage"]);
+        assert_eq!(searcher.percolate("service down for maintenance"), Vec::<&str>::new());
+
+        let ticket = Document::new("ticket-7").field("title", "Urgent: service down").field("body", "since noon");
+        assert_eq!(searcher.percolate_document(&ticket), ["outage", "urgent"]);
+        // percolating doesn't index anything
+        assert!(searcher.search("service").is_empty());
+    }
+}
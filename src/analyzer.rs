@@ -0,0 +1,301 @@
+//! Pluggable text analysis, for documents that need a different pipeline than the Searcher's
+//! default (source code, CJK text, ...).
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+#[cfg(feature = "std")]
+use crate::Stemmer;
+use crate::{math, HashSet};
+
+/// A normalized term, its word position and the byte range it was read from. Terms borrow from
+/// the analyzed text when normalization left them as written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token<'a> {
+    pub term: Cow<'a, str>,
+    pub position: u32,
+    pub span: Range<usize>,
+}
+
+/// Turns text into the terms that get indexed or searched. Tokens must come in order of
+/// position, and of span within a position.
+pub trait Analyzer {
+    fn analyze<'a>(&self, text: &'a str) -> Vec<Token<'a>>;
+}
+
+impl<F: for<'a> Fn(&'a str) -> Vec<Token<'a>>> Analyzer for F {
+    fn analyze<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        self(text)
+    }
+}
+
+/// Splits text into terms, e.g. keeping code identifiers or chemical names whole. The Searcher
+/// drops its stop words from the terms afterwards.
+pub trait Tokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String>;
+
+    /// The terms with their word positions and byte ranges. By default every term takes the next
+    /// position, and its span is where it next occurs in the text as written, or empty if it
+    /// doesn't occur as written.
+    fn tokens<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        let mut end = 0;
+        self.tokenize(text)
+            .into_iter()
+            .enumerate()
+            .map(|(position, term)| {
+                let span = match text[end..].find(term.as_str()) {
+                    Some(start) if !term.is_empty() => end + start..end + start + term.len(),
+                    _ => end..end,
+                };
+                end = span.end;
+                let term = if span.is_empty() { Cow::Owned(term) } else { Cow::Borrowed(&text[span.clone()]) };
+                Token { term, position: position as u32, span }
+            })
+            .collect()
+    }
+}
+
+impl<F: Fn(&str) -> Vec<String>> Tokenizer for F {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self(text)
+    }
+}
+
+/// The Searcher's default tokenization: case folded alphanumeric words, with numbers, URLs and
+/// email addresses also kept whole.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimpleTokenizer;
+
+impl Tokenizer for SimpleTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self.tokens(text).into_iter().map(|token| token.term.into_owned()).collect()
+    }
+
+    fn tokens<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        crate::analyze(text, &HashSet::new())
+    }
+}
+
+/// The default analysis: case folded alphanumeric words with stop words removed.
+pub struct StandardAnalyzer {
+    stop_words: HashSet<String>,
+    magnitudes: bool,         // also emit the order of magnitude of every number
+    #[cfg(feature = "std")]
+    stemmer: Option<Stemmer>, // reduces words to their stem after stop words are dropped
+}
+
+impl StandardAnalyzer {
+    pub fn new<I, S>(stop_words: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        StandardAnalyzer {
+            stop_words: stop_words.into_iter().map(|w| crate::fold_case(w.as_ref())).collect(),
+            magnitudes: false,
+            #[cfg(feature = "std")]
+            stemmer: None,
+        }
+    }
+
+    /// Also index the order of magnitude of every number next to it, e.g. `1e3` for `1,500`, so
+    /// that numbers of similar size can be matched together.
+    pub fn with_magnitudes(mut self) -> Self {
+        self.magnitudes = true;
+        self
+    }
+
+    /// Reduce words to their stem, e.g. `running` to `run`.
+    #[cfg(feature = "std")]
+    pub fn with_stemmer(mut self, stemmer: Stemmer) -> Self {
+        self.stemmer = Some(stemmer);
+        self
+    }
+}
+
+impl Analyzer for StandardAnalyzer {
+    fn analyze<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        #[cfg_attr(not(feature = "std"), allow(unused_mut))] // only stemmed in place
+        let mut tokens = crate::analyze(text, &self.stop_words);
+        #[cfg(feature = "std")]
+        if let Some(stemmer) = self.stemmer {
+            stemmer.stem_tokens(&mut tokens);
+        }
+        if !self.magnitudes {
+            return tokens;
+        }
+
+        let mut with_magnitudes = Vec::with_capacity(tokens.len());
+        for token in tokens {
+            let magnitude = match token.term.parse::<f64>() {
+                Ok(number) if number != 0.0 => Some(format!("1e{}", math::magnitude(number))),
+                _ => None,
+            };
+            let (position, span) = (token.position, token.span.clone());
+            with_magnitudes.push(token);
+            if let Some(term) = magnitude {
+                with_magnitudes.push(Token { term: Cow::Owned(term), position, span });
+            }
+        }
+        with_magnitudes
+    }
+}
+
+/// Analysis for mixed text with scripts written without spaces (Chinese, Japanese, Thai, ...).
+/// Such runs can't be split into words without a dictionary, so every candidate segmentation
+/// into pieces of up to `max_gram` characters is indexed together: each piece starts at the
+/// position of its first character. Other text is analyzed like `StandardAnalyzer` does.
+pub struct SegmentingAnalyzer {
+    stop_words: HashSet<String>,
+    max_gram: usize,
+}
+
+impl SegmentingAnalyzer {
+    pub fn new<I, S>(stop_words: I, max_gram: usize) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        SegmentingAnalyzer {
+            stop_words: stop_words.into_iter().map(|w| crate::fold_case(w.as_ref())).collect(),
+            max_gram: max_gram.max(1),
+        }
+    }
+
+    fn push_grams<'a>(&self, text: &'a str, run: &[(usize, char)], end: usize, position: &mut u32, tokens: &mut Vec<Token<'a>>) {
+        for (i, &(start, _)) in run.iter().enumerate() {
+            for n in 1..=self.max_gram.min(run.len() - i) {
+                let gram_end = run.get(i + n).map_or(end, |&(next, _)| next);
+                // these scripts have no case, so grams are slices of the text
+                tokens.push(Token {
+                    term: Cow::Borrowed(&text[start..gram_end]),
+                    position: *position + i as u32,
+                    span: start..gram_end,
+                });
+            }
+        }
+        *position += run.len() as u32;
+    }
+
+    fn push_words<'a>(&self, text: &'a str, offset: usize, position: &mut u32, tokens: &mut Vec<Token<'a>>) {
+        let words = crate::analyze(text, &self.stop_words);
+        let next = words.last().map(|token| *position + token.position + 1);
+        tokens.extend(words.into_iter().map(|token| Token {
+            term: token.term,
+            position: *position + token.position,
+            span: token.span.start + offset..token.span.end + offset,
+        }));
+        *position = next.unwrap_or(*position);
+    }
+}
+
+/// Characters of scripts that don't separate words with spaces.
+fn is_unsegmented(c: char) -> bool {
+    matches!(c,
+        '\u{0e00}'..='\u{0eff}'     // Thai, Lao
+        | '\u{1000}'..='\u{109f}'   // Myanmar
+        | '\u{1780}'..='\u{17ff}'   // Khmer
+        | '\u{3040}'..='\u{30ff}'   // Hiragana, Katakana
+        | '\u{3400}'..='\u{4dbf}'   // CJK extension A
+        | '\u{4e00}'..='\u{9fff}'   // CJK unified ideographs
+        | '\u{f900}'..='\u{faff}')  // CJK compatibility ideographs
+}
+
+impl Analyzer for SegmentingAnalyzer {
+    fn analyze<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        let mut position = 0;
+        let mut run: Vec<(usize, char)> = Vec::new();
+        let mut words_start = 0;
+
+        for (i, c) in text.char_indices() {
+            if is_unsegmented(c) {
+                if run.is_empty() {
+                    self.push_words(&text[words_start..i], words_start, &mut position, &mut tokens);
+                }
+                run.push((i, c));
+            } else if !run.is_empty() {
+                self.push_grams(text, &run, i, &mut position, &mut tokens);
+                run.clear();
+                words_start = i;
+            }
+        }
+        match run.is_empty() {
+            true => self.push_words(&text[words_start..], words_start, &mut position, &mut tokens),
+            false => self.push_grams(text, &run, text.len(), &mut position, &mut tokens),
+        }
+
+        tokens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Searcher;
+
+    /// Every character is a term, a crude analysis for text without spaces.
+    fn chars(text: &str) -> Vec<Token<'_>> {
+        text.char_indices()
+            .filter(|(_, c)| !c.is_whitespace())
+            .enumerate()
+            .map(|(position, (i, c))| Token {
+                term: Cow::Borrowed(&text[i..i + c.len_utf8()]),
+                position: position as u32,
+                span: i..i + c.len_utf8(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_document_analyzer() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("en", "search engine basics");
+        searcher.add_document_with_analyzer("ja", "全文検索エンジン", &chars);
+
+        // the default analysis finds no terms in CJK text
+        assert!(searcher.search("検索").is_empty());
+        let results = searcher.search_with_analyzer("検索", &chars);
+        assert_eq!(results.keys().collect::<Vec<_>>(), vec!["ja"]);
+
+        let standard = StandardAnalyzer::new(["basics"]);
+        assert!(searcher.search_with_analyzer("basics", &standard).is_empty());
+        assert_eq!(searcher.search_with_analyzer("Search", &standard).len(), 1);
+    }
+
+    #[test]
+    fn test_magnitudes() {
+        let analyzer = StandardAnalyzer::new(Vec::<String>::new()).with_magnitudes();
+        let terms: Vec<String> = analyzer.analyze("1,500 units at 0.25 in v1.2.3").into_iter().map(|t| t.term.into_owned()).collect();
+        assert_eq!(terms, vec!["1500", "1e3", "units", "at", "0.25", "1e-1", "in", "v1", "2", "1e0", "3", "1e0"]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_stemming_analyzer() {
+        let analyzer = StandardAnalyzer::new(["the"]).with_stemmer(Stemmer::English);
+        let mut searcher = Searcher::new();
+        searcher.add_document_with_analyzer("1", "the rockets are launching", &analyzer);
+        assert_eq!(searcher.search_with_analyzer("rocket launches", &analyzer).len(), 1);
+        assert!(searcher.search("rockets").is_empty());
+    }
+
+    #[test]
+    fn test_segmenting_analyzer() {
+        let analyzer = SegmentingAnalyzer::new(["the"], 2);
+        let tokens = analyzer.analyze("the Rust 検索");
+        let terms: Vec<(&str, u32)> = tokens.iter().map(|t| (t.term.as_ref(), t.position)).collect();
+        assert_eq!(terms, vec![("rust", 1), ("検", 2), ("検索", 2), ("索", 3)]);
+        assert_eq!(&"the Rust 検索"[tokens[2].span.clone()], "検索");
+
+        let mut searcher = Searcher::new();
+        searcher.add_document_with_analyzer("1", "全文検索エンジン", &analyzer);
+        searcher.add_document_with_analyzer("2", "検査結果と索引", &analyzer);
+        let results = searcher.search_with_analyzer("検索", &analyzer);
+        // both contain the characters, but only the first one the word
+        assert!(results["1"] > results["2"]);
+    }
+}
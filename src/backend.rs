@@ -0,0 +1,371 @@
+//! Where an index keeps its postings, and indexes kept outside the process.
+//!
+//! A `Searcher` keeps its documents, keyword values and collection statistics itself, and the
+//! postings of its terms in an `IndexBackend`: `MemoryBackend` by default, or any other store
+//! given to `Searcher::with_backend`. Postings are handed out as `Cow`s, so that a backend holding
+//! them in memory lends them out while one reading them from elsewhere returns its own copy.
+//!
+//! `redis::RedisBackend`, behind the `redis` feature, keeps postings in Redis.
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use core::cell::OnceCell;
+use core::error::Error;
+use core::ops::Bound;
+
+use crate::{entry_mut, FastMap, Positions, Postings};
+
+#[cfg(feature = "redis")]
+pub mod redis;
+
+/// An error of a backend outside the process, e.g. a lost connection.
+pub type BackendError = Box<dyn Error + Send + Sync>;
+
+/// Storage of the postings of an index: for every term, the documents containing it, ordered by
+/// the ordinal the Searcher gave each when indexing it, and the positions of the term in each.
+pub trait IndexBackend: Send + Sync {
+    /// Set the positions of `term` in document `ordinal`, adding the term if no document
+    /// contained it yet. `doc_id` is shared with the Searcher's table of documents.
+    fn add_postings(&mut self, term: &str, ordinal: u32, doc_id: &Arc<str>, positions: Positions);
+
+    /// Remove document `ordinal` from the postings of `term`, and the term once no document
+    /// contains it.
+    fn remove_postings(&mut self, term: &str, ordinal: u32);
+
+    /// Remove `term` with all of its postings and return them. None if no document contains it.
+    fn remove_term(&mut self, term: &str) -> Result<Option<Postings>, BackendError>;
+
+    /// The postings of `term`. None if no document contains it.
+    fn postings(&self, term: &str) -> Result<Option<Cow<'_, Postings>>, BackendError>;
+
+    /// Number of documents containing `term`, which backends can often tell without fetching
+    /// its postings.
+    fn doc_frequency(&self, term: &str) -> Result<usize, BackendError> {
+        Ok(self.postings(term)?.map_or(0, |postings| postings.len()))
+    }
+
+    /// Number of distinct terms.
+    fn term_count(&self) -> Result<usize, BackendError>;
+
+    /// The terms from `start` on, sorted, for prefix and fuzzy scans of the dictionary. A term
+    /// that failed to be read ends the scan.
+    fn terms_from(&self, start: Bound<&str>) -> Box<dyn Iterator<Item = Result<Cow<'_, str>, BackendError>> + '_>;
+
+    /// Apply the changes held back since the last call. Backends that send changes elsewhere can
+    /// hold them back to send them together; reads have to see them all the same.
+    fn flush(&mut self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+/// The first error of the backend while a `Searcher` works out one call. The Searcher goes on
+/// past a failed read as if the index had nothing for it, so that only the caller of the call
+/// the read was made for gets the error, e.g. through `SearchEngine`.
+#[derive(Default)]
+pub(crate) struct Failures(OnceCell<BackendError>);
+
+impl Failures {
+    /// The value of `result`, or the default one if it failed, with the error kept.
+    pub(crate) fn ok<T: Default>(&self, result: Result<T, BackendError>) -> T {
+        result.unwrap_or_else(|error| {
+            // later errors most likely follow from the first
+            let _ = self.0.set(error);
+            T::default()
+        })
+    }
+
+    /// `value`, unless a read failed while it was worked out, and may have left it short.
+    pub(crate) fn check<T>(self, value: T) -> Result<T, BackendError> {
+        match self.0.into_inner() {
+            Some(error) => Err(error),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Postings in a hash map, with the terms also kept sorted for prefix scans.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    index: FastMap<String, Postings>, // term -> postings in document order
+    terms: BTreeSet<String>,          // the terms of index, sorted
+}
+
+impl IndexBackend for MemoryBackend {
+    fn add_postings(&mut self, term: &str, ordinal: u32, doc_id: &Arc<str>, positions: Positions) {
+        if !self.index.contains_key(term) {
+            self.terms.insert(term.to_string());
+        }
+        *entry_mut(&mut self.index, term).positions_mut(ordinal, doc_id) = positions;
+    }
+
+    fn remove_postings(&mut self, term: &str, ordinal: u32) {
+        let Some(postings) = self.index.get_mut(term) else {
+            return;
+        };
+        postings.remove(ordinal);
+        if postings.is_empty() {
+            self.index.remove(term);
+            self.terms.remove(term);
+        }
+    }
+
+    fn remove_term(&mut self, term: &str) -> Result<Option<Postings>, BackendError> {
+        self.terms.remove(term);
+        Ok(self.index.remove(term))
+    }
+
+    fn postings(&self, term: &str) -> Result<Option<Cow<'_, Postings>>, BackendError> {
+        Ok(self.index.get(term).map(Cow::Borrowed))
+    }
+
+    fn doc_frequency(&self, term: &str) -> Result<usize, BackendError> {
+        Ok(self.index.get(term).map_or(0, Postings::len))
+    }
+
+    fn term_count(&self) -> Result<usize, BackendError> {
+        Ok(self.index.len())
+    }
+
+    fn terms_from(&self, start: Bound<&str>) -> Box<dyn Iterator<Item = Result<Cow<'_, str>, BackendError>> + '_> {
+        Box::new(self.terms.range::<str, _>((start, Bound::Unbounded)).map(|term| Ok(Cow::Borrowed(term.as_str()))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::{ParseMode, Query, Searcher};
+
+    /// A backend handing out copies of its postings, like one reading them from outside the
+    /// process would.
+    #[derive(Default)]
+    struct CopyingBackend(BTreeMap<String, Postings>);
+
+    impl IndexBackend for CopyingBackend {
+        fn add_postings(&mut self, term: &str, ordinal: u32, doc_id: &Arc<str>, positions: Positions) {
+            *self.0.entry(term.to_string()).or_default().positions_mut(ordinal, doc_id) = positions;
+        }
+
+        fn remove_postings(&mut self, term: &str, ordinal: u32) {
+            if let Some(postings) = self.0.get_mut(term) {
+                postings.remove(ordinal);
+                if postings.is_empty() {
+                    self.0.remove(term);
+                }
+            }
+        }
+
+        fn remove_term(&mut self, term: &str) -> Result<Option<Postings>, BackendError> {
+            Ok(self.0.remove(term))
+        }
+
+        fn postings(&self, term: &str) -> Result<Option<Cow<'_, Postings>>, BackendError> {
+            Ok(self.0.get(term).cloned().map(Cow::Owned))
+        }
+
+        fn term_count(&self) -> Result<usize, BackendError> {
+            Ok(self.0.len())
+        }
+
+        fn terms_from(&self, start: Bound<&str>) -> Box<dyn Iterator<Item = Result<Cow<'_, str>, BackendError>> + '_> {
+            Box::new(self.0.range::<str, _>((start, Bound::Unbounded)).map(|(term, _)| Ok(Cow::Owned(term.clone()))))
+        }
+    }
+
+    /// A backend in memory writing down the changes it gets, and `flush` between those the Searcher
+    /// sends together.
+    #[derive(Default)]
+    struct RecordingBackend {
+        memory: MemoryBackend,
+        log: Vec<String>,
+    }
+
+    impl RecordingBackend {
+        /// The changes sent together, sorted within each flush.
+        fn flushes(&mut self) -> Vec<Vec<String>> {
+            let mut flushes: Vec<Vec<String>> = self.log.split(|entry| entry == "flush").map(<[String]>::to_vec).collect();
+            flushes.iter_mut().for_each(|changes| changes.sort());
+            flushes.pop();
+            self.log.clear();
+            flushes
+        }
+    }
+
+    impl IndexBackend for RecordingBackend {
+        fn add_postings(&mut self, term: &str, ordinal: u32, doc_id: &Arc<str>, positions: Positions) {
+            self.log.push(format!("+{}@{}", term, ordinal));
+            self.memory.add_postings(term, ordinal, doc_id, positions)
+        }
+
+        fn remove_postings(&mut self, term: &str, ordinal: u32) {
+            self.log.push(format!("-{}@{}", term, ordinal));
+            self.memory.remove_postings(term, ordinal)
+        }
+
+        fn remove_term(&mut self, term: &str) -> Result<Option<Postings>, BackendError> {
+            self.log.push(format!("-{}", term));
+            self.memory.remove_term(term)
+        }
+
+        fn postings(&self, term: &str) -> Result<Option<Cow<'_, Postings>>, BackendError> {
+            self.memory.postings(term)
+        }
+
+        fn term_count(&self) -> Result<usize, BackendError> {
+            self.memory.term_count()
+        }
+
+        fn terms_from(&self, start: Bound<&str>) -> Box<dyn Iterator<Item = Result<Cow<'_, str>, BackendError>> + '_> {
+            self.memory.terms_from(start)
+        }
+
+        fn flush(&mut self) -> Result<(), BackendError> {
+            self.log.push("flush".to_string());
+            Ok(())
+        }
+    }
+
+    pub(super) fn terms(backend: &dyn IndexBackend, start: Bound<&str>) -> Vec<String> {
+        backend.terms_from(start).map(|term| term.unwrap().into_owned()).collect()
+    }
+
+    fn positions(backend: &dyn IndexBackend, term: &str, ordinal: u32) -> Option<Vec<u32>> {
+        Some(backend.postings(term).unwrap()?.get(ordinal)?.to_vec())
+    }
+
+    /// What every backend has to do for a Searcher to work on it.
+    pub(super) fn conformance(backend: &mut dyn IndexBackend) {
+        let (a, b, c): (Arc<str>, Arc<str>, Arc<str>) = ("a".into(), "b".into(), "c".into());
+        assert_eq!(backend.term_count().unwrap(), 0);
+        assert!(backend.postings("moon").unwrap().is_none());
+        assert_eq!(backend.doc_frequency("moon").unwrap(), 0);
+        assert!(terms(backend, Bound::Unbounded).is_empty());
+
+        // documents added out of order are still listed by ordinal
+        backend.add_postings("moon", 2, &c, Positions::from_slice(&[3]));
+        backend.add_postings("moon", 0, &a, Positions::from_slice(&[0, 2]));
+        backend.add_postings("rocket", 1, &b, Positions::from_slice(&[1]));
+        backend.add_postings("ocean", 1, &b, Positions::from_slice(&[0]));
+        let moon = backend.postings("moon").unwrap().unwrap();
+        assert_eq!(moon.iter().map(|posting| (posting.ordinal, posting.doc_id.as_ref())).collect::<Vec<_>>(), [(0, "a"), (2, "c")]);
+        drop(moon);
+        assert_eq!(backend.doc_frequency("moon").unwrap(), 2);
+        assert_eq!(backend.term_count().unwrap(), 3);
+
+        // adding a document again replaces its positions
+        backend.add_postings("moon", 0, &a, Positions::from_slice(&[5]));
+        assert_eq!(positions(backend, "moon", 0), Some(vec![5]));
+        assert_eq!(positions(backend, "moon", 1), None);
+        assert_eq!(backend.doc_frequency("moon").unwrap(), 2);
+
+        assert_eq!(terms(backend, Bound::Unbounded), ["moon", "ocean", "rocket"]);
+        assert_eq!(terms(backend, Bound::Included("o")), ["ocean", "rocket"]);
+        assert_eq!(terms(backend, Bound::Excluded("ocean")), ["rocket"]);
+        assert_eq!(terms(backend, Bound::Included("ocean")), ["ocean", "rocket"]);
+
+        // a term goes with the last document containing it
+        backend.remove_postings("moon", 2);
+        assert_eq!(backend.doc_frequency("moon").unwrap(), 1);
+        backend.remove_postings("moon", 2);
+        backend.remove_postings("missing", 0);
+        backend.remove_postings("moon", 0);
+        assert!(backend.postings("moon").unwrap().is_none());
+        assert_eq!(terms(backend, Bound::Unbounded), ["ocean", "rocket"]);
+
+        let removed = backend.remove_term("rocket").unwrap().unwrap();
+        assert_eq!(removed.get(1).map(|positions| positions.to_vec()), Some(vec![1]));
+        assert!(backend.remove_term("rocket").unwrap().is_none());
+        assert_eq!(backend.term_count().unwrap(), 1);
+        assert_eq!(terms(backend, Bound::Unbounded), ["ocean"]);
+
+        // a term removed and added again before the changes are applied
+        backend.remove_postings("ocean", 1);
+        backend.add_postings("ocean", 3, &c, Positions::from_slice(&[4, 6]));
+        backend.add_postings("tide", 3, &c, Positions::from_slice(&[5]));
+        backend.flush().unwrap();
+        assert_eq!(positions(backend, "ocean", 1), None);
+        assert_eq!(positions(backend, "ocean", 3), Some(vec![4, 6]));
+        assert_eq!(terms(backend, Bound::Excluded("ocean")), ["tide"]);
+    }
+
+    #[test]
+    fn test_memory_backend() {
+        conformance(&mut MemoryBackend::default());
+    }
+
+    #[test]
+    fn test_copying_backend() {
+        conformance(&mut CopyingBackend::default());
+    }
+
+    #[test]
+    fn test_searcher_with_backend() {
+        fn build<B: IndexBackend>(searcher: &mut Searcher<B>) {
+            searcher.add_document("1", "The moon rocket launched. Ocean tide charts were updated.");
+            searcher.add_document("2", "moon moon ocean");
+            searcher.add_document("3", "a rocket to the moon");
+            searcher.add_document("4", "rust compiler borrow checker");
+            searcher.add_keyword("4", "lang", "en");
+            searcher.update_document("3", "the rocket reached the moon");
+        }
+        let mut memory = Searcher::new();
+        let mut copying = Searcher::with_backend(CopyingBackend::default());
+        build(&mut memory);
+        build(&mut copying);
+
+        // regular expressions need the `regex` feature
+        let queries: Vec<&str> = ["moon", "\"moon rocket\"", "roc*", "mon~1", "+ocean -tide", "lang:en", "/r.*t/"]
+            .into_iter()
+            .filter(|query| cfg!(feature = "regex") || !query.starts_with('/'))
+            .collect();
+        let check = |memory: &Searcher, copying: &Searcher<CopyingBackend>| {
+            for query in &queries {
+                let query = Query::parse(query, ParseMode::Strict).unwrap();
+                assert_eq!(memory.search_query(&query), copying.search_query(&query), "{:?}", query);
+            }
+            assert_eq!(memory.term_stats(), copying.term_stats());
+        };
+        check(&memory, &copying);
+        assert_eq!(copying.search("rocket").len(), 2);
+
+        assert!(copying.remove_document("1") && memory.remove_document("1"));
+        check(&memory, &copying);
+        assert_eq!(memory.prune(2, 1.0, false), copying.prune(2, 1.0, false));
+        check(&memory, &copying);
+        assert_eq!(copying.term_stats().iter().map(|stats| stats.term.as_str()).collect::<Vec<_>>(), ["moon"]);
+    }
+
+    #[test]
+    fn test_searcher_flushes_whole_documents() {
+        let mut searcher = Searcher::with_backend(RecordingBackend::default());
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("1", "moon");
+        searcher.update_document("1", "ocean");
+        assert!(searcher.remove_document("1"));
+        assert!(!searcher.remove_document("1"));
+        assert_eq!(
+            searcher.index.flushes(),
+            [
+                vec!["+moon@0", "+rocket@0"],
+                // a replaced document goes in the same flush as its replacement
+                vec!["+moon@1", "-moon@0", "-rocket@0"],
+                vec!["+ocean@2", "-moon@1"],
+                vec!["-ocean@2"],
+                vec![],
+            ]
+        );
+
+        searcher.add_document("2", "moon tide");
+        searcher.add_document("3", "moon");
+        assert_eq!(searcher.prune(2, 1.0, false), 1);
+        assert_eq!(searcher.index.flushes()[2], ["-tide"]);
+        // documents added together go in one flush, whether or not they are analyzed in parallel
+        searcher.add_batch(vec![("4".to_string(), "ocean".to_string()), ("5".to_string(), "rocket".to_string())], &Failures::default());
+        assert_eq!(searcher.index.flushes(), [["+ocean@5", "+rocket@6"]]);
+    }
+}
@@ -0,0 +1,402 @@
+//! Postings kept in Redis, for a `Searcher` created `with_backend`.
+//!
+//! Every term has a sorted set with a member per document containing it, holding the positions of
+//! the term and the doc id, scored by the ordinal of the document so that the set lists documents
+//! in order. The dictionary is another sorted set, with every term at the same score, so that lex
+//! ranges scan it in order. Every key starts with the prefix the backend was opened with as a hash
+//! tag, e.g. `{notes}:terms` and `{notes}:term:moon`, so that one server can hold several indexes
+//! and a cluster keeps the keys of an index together, as transactions over them need. A `}` or `\`
+//! in the prefix is escaped with a `\`, so that no index has keys starting like those of another.
+//!
+//! The Searcher evaluates and scores queries on the postings it fetches, exactly like in memory.
+//! ZUNIONSTORE could add up the postings of the terms of a query on the server, weighted by idf,
+//! but not normalize them by the length of each document.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex};
+
+use ::redis::{Client, Commands, Connection, ErrorKind, RedisError, RedisResult};
+use r2d2::{Pool, PooledConnection};
+
+use crate::backend::BackendError;
+use crate::{DocId, IndexBackend, Positions, Postings};
+
+/// Number of terms fetched at a time when scanning the dictionary.
+const TERMS_PAGE: usize = 512;
+
+/// Postings kept in Redis, for `Searcher::with_backend`. The Searcher keeps its table of documents
+/// in memory, so the postings are only good for as long as it lives.
+///
+/// Changes are held back until `flush` and then sent in a single MULTI transaction; reads send
+/// the changes held back first. The Searcher flushes once it is done with a document, so that its
+/// postings change at once, together with those of the document it replaces, and flushes a batch
+/// of documents added together at a time. Reads take a connection from a pool, so that threads
+/// searching at the same time don't wait on each other.
+///
+/// Changes that fail to be sent are held back for the next flush or read, and a read fails
+/// rather than miss them.
+pub struct RedisBackend {
+    pool: Pool<Client>,
+    prefix: String,                               // start of every key of the backend, see `key_prefix`
+    pending: Mutex<BTreeMap<String, TermChange>>, // term -> changes not sent yet
+}
+
+/// The changes to the postings of a term held back until the next flush.
+#[derive(Default)]
+struct TermChange {
+    cleared: bool,                // every posting sent before is removed
+    removed: BTreeSet<u32>,       // ordinals of documents whose postings are removed
+    added: BTreeMap<u32, String>, // ordinal -> member with the positions and doc id of the document
+}
+
+impl RedisBackend {
+    /// Connect to the Redis server at `url`, e.g. `redis://127.0.0.1/`, and keep postings under
+    /// `prefix` there, with a pool of up to 10 connections. Postings mean nothing without the
+    /// table of documents of the Searcher they were added by, so anything left under the prefix,
+    /// e.g. by an earlier run, is removed.
+    pub fn open(url: &str, prefix: &str) -> RedisResult<RedisBackend> {
+        let client = Client::open(url)?;
+        // the pool would keep trying a server that can't be reached until it times out
+        drop(client.get_connection()?);
+        // a connection that broke is replaced when it's returned, without a PING on every read
+        RedisBackend::with_pool(Pool::builder().test_on_check_out(false).build_unchecked(client), prefix)
+    }
+
+    /// Keep postings under `prefix` on the server of `pool`, sharing its connections with
+    /// whatever else uses it, e.g. the backends of other indexes. Like `open`, anything left under
+    /// the prefix is removed.
+    pub fn with_pool(pool: Pool<Client>, prefix: &str) -> RedisResult<RedisBackend> {
+        let prefix = key_prefix(prefix);
+        let mut connection = pool.get().map_err(pool_error)?;
+        let keys: Vec<String> = connection.scan_match(format!("{}*", glob_escape(&prefix)))?.collect();
+        if !keys.is_empty() {
+            connection.del::<_, ()>(keys)?;
+        }
+        drop(connection);
+        Ok(RedisBackend {
+            pool,
+            prefix,
+            pending: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    fn dictionary_key(&self) -> String {
+        format!("{}terms", self.prefix)
+    }
+
+    fn term_key(&self, term: &str) -> String {
+        format!("{}term:{}", self.prefix, term)
+    }
+
+    fn change(&mut self, term: &str) -> &mut TermChange {
+        self.pending.get_mut().unwrap().entry(term.to_string()).or_default()
+    }
+
+    fn connection(&self) -> RedisResult<PooledConnection<Client>> {
+        self.pool.get().map_err(pool_error)
+    }
+
+    /// Run `read` once the changes held back are sent.
+    fn read<T>(&self, read: impl FnOnce(&mut Connection) -> RedisResult<T>) -> RedisResult<T> {
+        let mut connection = self.connection()?;
+        self.send_pending(&mut connection)?;
+        read(&mut connection)
+    }
+
+    fn send_pending(&self, connection: &mut Connection) -> RedisResult<()> {
+        let mut pending = self.pending.lock().unwrap();
+        if !pending.is_empty() {
+            self.send(connection, &pending)?;
+            pending.clear();
+        }
+        Ok(())
+    }
+
+    /// Apply `pending` in one transaction, tried again if another client changes one of its terms
+    /// in between.
+    fn send(&self, connection: &mut Connection, pending: &BTreeMap<String, TermChange>) -> RedisResult<()> {
+        let keys: Vec<String> = pending.keys().map(|term| self.term_key(term)).collect();
+        ::redis::transaction(connection, &keys, |connection, pipe| {
+            // a term only losing documents leaves the dictionary with the last one, which takes
+            // counting the documents it has left before the transaction
+            let shrinking: Vec<(&String, &TermChange)> =
+                pending.iter().filter(|(_, change)| !change.cleared && change.added.is_empty()).collect();
+            let mut emptied = BTreeSet::new();
+            if !shrinking.is_empty() {
+                let mut counts = ::redis::pipe();
+                for (term, change) in &shrinking {
+                    counts.zcard(self.term_key(term));
+                    for ordinal in &change.removed {
+                        counts.zcount(self.term_key(term), ordinal, ordinal);
+                    }
+                }
+                let mut counts = counts.query::<Vec<usize>>(connection)?.into_iter();
+                for (term, change) in &shrinking {
+                    let total = counts.next().unwrap_or(0);
+                    let removed: usize = change.removed.iter().map(|_| counts.next().unwrap_or(0)).sum();
+                    if total <= removed {
+                        emptied.insert(term.as_str());
+                    }
+                }
+            }
+
+            for (term, change) in pending {
+                let key = self.term_key(term);
+                if change.cleared {
+                    pipe.del(&key).ignore();
+                }
+                for ordinal in change.removed.iter().chain(change.added.keys()) {
+                    pipe.zrembyscore(&key, ordinal, ordinal).ignore();
+                }
+                for (ordinal, member) in &change.added {
+                    pipe.zadd(&key, member, ordinal).ignore();
+                }
+                if !change.added.is_empty() {
+                    pipe.zadd(self.dictionary_key(), term, 0).ignore();
+                } else if change.cleared || emptied.contains(term.as_str()) {
+                    pipe.zrem(self.dictionary_key(), term).ignore();
+                }
+            }
+            pipe.query(connection)
+        })
+    }
+}
+
+/// The member of the sorted set of a term for a document: the positions of the term in it, then
+/// the doc id, e.g. `0,7:notes/moon.md`.
+fn posting_member(positions: &Positions, doc_id: &str) -> String {
+    let mut member = String::new();
+    for (i, position) in positions.iter().enumerate() {
+        if i > 0 {
+            member.push(',');
+        }
+        member.push_str(&position.to_string());
+    }
+    member.push(':');
+    member.push_str(doc_id);
+    member
+}
+
+/// The positions and doc id of a member written by `posting_member`.
+fn parse_member(member: &str) -> RedisResult<(Positions, &str)> {
+    let invalid = || RedisError::from((ErrorKind::TypeError, "invalid posting", member.to_string()));
+    let (positions, doc_id) = member.split_once(':').ok_or_else(invalid)?;
+    let positions = positions.split(',').map(|position| position.parse().map_err(|_| invalid())).collect::<RedisResult<_>>()?;
+    Ok((positions, doc_id))
+}
+
+impl IndexBackend for RedisBackend {
+    fn add_postings(&mut self, term: &str, ordinal: u32, doc_id: &Arc<str>, positions: Positions) {
+        let member = posting_member(&positions, doc_id);
+        let change = self.change(term);
+        change.removed.remove(&ordinal);
+        change.added.insert(ordinal, member);
+    }
+
+    fn remove_postings(&mut self, term: &str, ordinal: u32) {
+        let change = self.change(term);
+        change.added.remove(&ordinal);
+        change.removed.insert(ordinal);
+    }
+
+    fn remove_term(&mut self, term: &str) -> Result<Option<Postings>, BackendError> {
+        let Some(postings) = self.postings(term)? else {
+            return Ok(None);
+        };
+        let postings = postings.into_owned();
+        *self.change(term) = TermChange { cleared: true, ..TermChange::default() };
+        Ok(Some(postings))
+    }
+
+    fn postings(&self, term: &str) -> Result<Option<Cow<'_, Postings>>, BackendError> {
+        let postings = self.read(|connection| {
+            let members: Vec<(String, u32)> = connection.zrange_withscores(self.term_key(term), 0, -1)?;
+            let mut postings = Postings::default();
+            for (member, ordinal) in &members {
+                let (positions, doc_id) = parse_member(member)?;
+                *postings.positions_mut(*ordinal, &DocId::from(doc_id)) = positions;
+            }
+            Ok(postings)
+        })?;
+        Ok((!postings.is_empty()).then_some(Cow::Owned(postings)))
+    }
+
+    fn doc_frequency(&self, term: &str) -> Result<usize, BackendError> {
+        Ok(self.read(|connection| connection.zcard(self.term_key(term)))?)
+    }
+
+    fn term_count(&self) -> Result<usize, BackendError> {
+        Ok(self.read(|connection| connection.zcard(self.dictionary_key()))?)
+    }
+
+    fn terms_from(&self, start: Bound<&str>) -> Box<dyn Iterator<Item = Result<Cow<'_, str>, BackendError>> + '_> {
+        // lex range bounds, each page starting after the last term of the one before
+        let mut from = match start {
+            Bound::Included(term) => format!("[{}", term),
+            Bound::Excluded(term) => format!("({}", term),
+            Bound::Unbounded => "-".to_string(),
+        };
+        let mut page = Vec::new().into_iter();
+        let mut last_page = false;
+        Box::new(std::iter::from_fn(move || loop {
+            if let Some(term) = page.next() {
+                return Some(Ok(Cow::Owned(term)));
+            }
+            if last_page {
+                return None;
+            }
+            let terms: RedisResult<Vec<String>> = self.read(|connection| {
+                ::redis::cmd("ZRANGE")
+                    .arg(self.dictionary_key())
+                    .arg(&from)
+                    .arg("+")
+                    .arg("BYLEX")
+                    .arg("LIMIT")
+                    .arg(0)
+                    .arg(TERMS_PAGE)
+                    .query(connection)
+            });
+            let terms = match terms {
+                Ok(terms) => terms,
+                Err(error) => {
+                    last_page = true;
+                    return Some(Err(error.into()));
+                }
+            };
+            last_page = terms.len() < TERMS_PAGE;
+            if let Some(term) = terms.last() {
+                from = format!("({}", term);
+            }
+            page = terms.into_iter();
+        }))
+    }
+
+    fn flush(&mut self) -> Result<(), BackendError> {
+        let mut connection = self.connection()?;
+        Ok(self.send_pending(&mut connection)?)
+    }
+}
+
+/// A pool that failed to hand out a connection, as the Redis error of the last attempt.
+pub(crate) fn pool_error(error: r2d2::Error) -> RedisError {
+    RedisError::from((ErrorKind::IoError, "no connection from the pool", error.to_string()))
+}
+
+/// The start of the keys of the index under `prefix`: the prefix as a hash tag, with `}` and `\`
+/// escaped so that it's the start of no other prefix's keys, e.g. `{notes}:`.
+pub(crate) fn key_prefix(prefix: &str) -> String {
+    let mut keys = String::with_capacity(prefix.len() + 3);
+    keys.push('{');
+    for c in prefix.chars() {
+        if matches!(c, '}' | '\\') {
+            keys.push('\\');
+        }
+        keys.push(c);
+    }
+    keys.push_str("}:");
+    keys
+}
+
+/// `text` with the characters special to Redis glob patterns escaped, so that it only matches itself.
+pub(crate) fn glob_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use testcontainers_modules::redis::{Redis, REDIS_PORT};
+    use testcontainers_modules::testcontainers::runners::SyncRunner;
+    use testcontainers_modules::testcontainers::Container;
+
+    use super::*;
+    use crate::backend::tests::{conformance, terms};
+
+    /// The URL of the Redis server at `REDIS_URL`, or of one in a Docker container, stopped when
+    /// the container is dropped.
+    fn server() -> (String, Option<Container<Redis>>) {
+        if let Ok(url) = std::env::var("REDIS_URL") {
+            return (url, None);
+        }
+        let container = Redis::default().start().expect("starting a Redis container needs Docker");
+        let url = format!("redis://{}:{}", container.get_host().unwrap(), container.get_host_port_ipv4(REDIS_PORT).unwrap());
+        (url, Some(container))
+    }
+
+    #[test]
+    fn test_posting_member() {
+        let positions = Positions::from_slice(&[0, 7, 12]);
+        let member = posting_member(&positions, "notes/a:b,c.md");
+        assert_eq!(member, "0,7,12:notes/a:b,c.md");
+        assert_eq!(parse_member(&member).unwrap(), (positions, "notes/a:b,c.md"));
+        assert!(parse_member("notes.md").is_err());
+        assert!(parse_member("1,x:notes.md").is_err());
+    }
+
+    #[test]
+    #[ignore = "needs Docker or a Redis server at REDIS_URL"]
+    fn test_redis_backend() {
+        let (url, _container) = server();
+        let mut backend = RedisBackend::open(&url, "searcher-test:backend").unwrap();
+        conformance(&mut backend);
+        assert_eq!(backend.term_count().unwrap(), 2);
+
+        // opening clears the prefix, and only that prefix
+        let mut other = RedisBackend::open(&url, "searcher-test:backend*").unwrap();
+        other.add_postings("moon", 0, &DocId::from("a"), Positions::from_slice(&[0]));
+        other.flush().unwrap();
+        assert_eq!(backend.term_count().unwrap(), 2);
+        let backend = RedisBackend::open(&url, "searcher-test:backend").unwrap();
+        assert_eq!(backend.term_count().unwrap(), 0);
+        assert_eq!(other.doc_frequency("moon").unwrap(), 1);
+
+        // backends sharing a pool keep their postings apart
+        let pool = Pool::builder().max_size(2).build(Client::open(url.as_str()).unwrap()).unwrap();
+        let mut first = RedisBackend::with_pool(pool.clone(), "searcher-test:pool-a").unwrap();
+        let mut second = RedisBackend::with_pool(pool, "searcher-test:pool-b").unwrap();
+        first.add_postings("moon", 0, &DocId::from("a"), Positions::from_slice(&[0]));
+        first.flush().unwrap();
+        conformance(&mut second);
+        assert_eq!(first.term_count().unwrap(), 1);
+        assert_eq!(first.doc_frequency("moon").unwrap(), 1);
+
+        // neither clearing a prefix nor its dictionary reaches the keys of prefixes starting with it
+        let mut outer = RedisBackend::open(&url, "searcher-test:nest").unwrap();
+        outer.add_postings("terms", 0, &DocId::from("a"), Positions::from_slice(&[0]));
+        outer.flush().unwrap();
+        let mut inner = RedisBackend::open(&url, "searcher-test:nest:term").unwrap();
+        inner.add_postings("moon", 0, &DocId::from("b"), Positions::from_slice(&[1]));
+        inner.flush().unwrap();
+        assert_eq!(outer.postings("terms").unwrap().unwrap().get(0).map(|positions| positions.to_vec()), Some(vec![0]));
+        assert_eq!(terms(&outer, Bound::Unbounded), ["terms"]);
+        let outer = RedisBackend::open(&url, "searcher-test:nest").unwrap();
+        assert_eq!(outer.term_count().unwrap(), 0);
+        assert_eq!(terms(&inner, Bound::Unbounded), ["moon"]);
+        assert_eq!(inner.doc_frequency("moon").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_key_prefix() {
+        assert_eq!(key_prefix("notes"), "{notes}:");
+        assert_eq!(key_prefix("a:term"), "{a:term}:");
+        // unescaped, the keys of `a}:x` would start with `{a}:` and be cleared with those of `a`
+        assert_eq!(key_prefix("a}:x"), r"{a\}:x}:");
+        assert_eq!(key_prefix(r"a\"), r"{a\\}:");
+    }
+
+    #[test]
+    fn test_glob_escape() {
+        assert_eq!(glob_escape("notes"), "notes");
+        // `a*` would also clear the index under `ab`
+        assert_eq!(glob_escape("a*"), "a\\*");
+        assert_eq!(glob_escape(r"[x]?\y"), r"\[x\]\?\\y");
+    }
+}
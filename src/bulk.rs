@@ -0,0 +1,82 @@
+//! Indexing many documents at once on every core. Analysis, which takes most of the time of
+//! indexing a document, runs in parallel on a batch of documents; the analyzed documents are then
+//! added to the index one after the other, in order, so that ordinals and the resulting index are
+//! the same as when adding the documents one by one.
+
+use rayon::prelude::*;
+
+use crate::backend::Failures;
+use crate::{AnalyzedDoc, IndexBackend, Searcher};
+
+/// Number of documents analyzed before they are added, which bounds the memory held by analyzed
+/// documents waiting to be added.
+const BATCH: usize = 4096;
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index `docs`, pairs of doc id and content, analyzing them on all cores. Equivalent to
+    /// calling `add_document` on each of them in order, except that the backend gets the
+    /// documents a batch at a time.
+    pub fn add_documents_parallel<I, S, C>(&mut self, docs: I)
+    where
+        I: IntoIterator<Item = (S, C)>,
+        S: AsRef<str> + Send + Sync,
+        C: AsRef<str> + Send + Sync,
+    {
+        self.add_documents_parallel_in(docs, &Failures::default());
+    }
+
+    /// Same as `add_documents_parallel`, keeping the errors of the backend in `failures`.
+    pub(crate) fn add_documents_parallel_in<I, S, C>(&mut self, docs: I, failures: &Failures)
+    where
+        I: IntoIterator<Item = (S, C)>,
+        S: AsRef<str> + Send + Sync,
+        C: AsRef<str> + Send + Sync,
+    {
+        let docs: Vec<(S, C)> = docs.into_iter().collect();
+        for batch in docs.chunks(BATCH) {
+            let searcher = &*self;
+            let analyzed: Vec<(AnalyzedDoc, usize)> = batch
+                .par_iter()
+                .map(|(_, content)| {
+                    let (tokens, dropped) = searcher.analyze_dropping(content.as_ref(), &searcher.stop_words);
+                    (AnalyzedDoc::new(tokens, searcher.store_offsets), dropped)
+                })
+                .collect();
+
+            for ((doc_id, content), (analyzed, dropped)) in batch.iter().zip(analyzed) {
+                self.stop_words_dropped += dropped as u64;
+                self.insert_analyzed(doc_id.as_ref(), content.as_ref(), analyzed, failures);
+            }
+            failures.ok(self.index.flush());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    #[test]
+    fn test_add_documents_parallel() {
+        let docs: Vec<(String, String)> = (0..5000)
+            .map(|i| (i.to_string(), format!("moon rocket {} ocean tide {}", i % 7, if i % 3 == 0 { "launch" } else { "tent" })))
+            .collect();
+
+        let mut serial = Searcher::new();
+        serial.set_store_offsets(true);
+        for (doc_id, content) in &docs {
+            serial.add_document(doc_id, content);
+        }
+        let mut parallel = Searcher::new();
+        parallel.set_store_offsets(true);
+        parallel.add_documents_parallel(docs.iter().map(|(doc_id, content)| (doc_id.as_str(), content.as_str())));
+
+        assert_eq!(parallel.term_stats(), serial.term_stats());
+        assert_eq!(parallel.build_report(), serial.build_report());
+        assert_eq!(parallel.search("launch"), serial.search("launch"));
+        let query = Query::parse("\"rocket 3 ocean\"", ParseMode::Strict).unwrap();
+        assert_eq!(parallel.search_query(&query), serial.search_query(&query));
+        assert_eq!(parallel.snippet("42", &query, "[", "]"), serial.snippet("42", &query, "[", "]"));
+    }
+}
@@ -0,0 +1,178 @@
+//! Caching of search results and idf behind a small trait, so a cache can live in memory or in a
+//! shared store: `redis::RedisCache`, behind the `redis` feature, lets the processes of a
+//! deployment share their caches.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+
+use crate::{HashMap, IndexBackend, Query, Searcher};
+
+#[cfg(feature = "redis")]
+pub mod redis;
+
+/// A key-value cache. Methods take `&self` so a cache can be shared between searches and threads.
+pub trait Cache<K, V> {
+    fn get(&self, key: &K) -> Option<V>;
+    fn insert(&self, key: K, value: V);
+    fn clear(&self);
+}
+
+/// A cache in process memory holding at most `capacity` entries. It starts over empty when full.
+#[cfg(feature = "std")]
+pub struct MemoryCache<K, V> {
+    entries: Mutex<HashMap<K, V>>,
+    capacity: usize,
+}
+
+#[cfg(feature = "std")]
+impl<K, V> MemoryCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        MemoryCache {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<K: Eq + Hash, V: Clone> Cache<K, V> for MemoryCache<K, V> {
+    fn get(&self, key: &K) -> Option<V> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            entries.clear();
+        }
+        if self.capacity > 0 {
+            entries.insert(key, value);
+        }
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Cached results of `search_query`, keyed by the query, the scoring parameters and the state of
+/// the index.
+pub type QueryCache = dyn Cache<String, HashMap<String, f32>>;
+
+/// Cached idf of terms, keyed by the term and the state of the index.
+pub type IdfCache = dyn Cache<String, f32> + Send + Sync;
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Look up the idf of terms in `cache` before counting the documents containing them, e.g. to
+    /// save a round trip to the backend per term, or stop caching with None. Like a query cache, a
+    /// cache should only be used with one Searcher, and caches aren't saved with the index.
+    pub fn set_idf_cache(&mut self, cache: Option<Arc<IdfCache>>) {
+        self.idf_cache = cache;
+    }
+
+    /// Same as `search_query`, but reuses the results in `cache` if the same query was run against
+    /// the same state of this index. A cache should only be used with one Searcher.
+    pub fn search_query_cached(&self, query: &Query, cache: &QueryCache) -> HashMap<String, f32> {
+        let key = format!("{}/{}/{}/{:?}", self.generation, self.k1, self.b, query);
+        if let Some(results) = cache.get(&key) {
+            return results;
+        }
+        let results = self.search_query(query);
+        cache.insert(key, results.clone());
+        results
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    /// Counts lookups that hit, to observe the cache from the outside.
+    struct CountingCache<V> {
+        inner: MemoryCache<String, V>,
+        hits: Mutex<usize>,
+    }
+
+    impl<V: Clone> Cache<String, V> for CountingCache<V> {
+        fn get(&self, key: &String) -> Option<V> {
+            let value = self.inner.get(key);
+            *self.hits.lock().unwrap() += value.is_some() as usize;
+            value
+        }
+
+        fn insert(&self, key: String, value: V) {
+            self.inner.insert(key, value)
+        }
+
+        fn clear(&self) {
+            self.inner.clear()
+        }
+    }
+
+    #[test]
+    fn test_query_cache() {
+        let cache = CountingCache {
+            inner: MemoryCache::new(16),
+            hits: Mutex::new(0),
+        };
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust compiler");
+        let query = Query::Term("rust".to_string());
+
+        assert_eq!(searcher.search_query_cached(&query, &cache).len(), 1);
+        assert_eq!(searcher.search_query_cached(&query, &cache).len(), 1);
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+
+        // changing the index invalidates earlier results
+        searcher.add_document("2", "rust borrow checker");
+        assert_eq!(searcher.search_query_cached(&query, &cache).len(), 2);
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+
+        // and so does capping the results
+        searcher.set_max_results(Some(1));
+        assert_eq!(searcher.search_query_cached(&query, &cache).len(), 1);
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_idf_cache() {
+        let cache = Arc::new(CountingCache {
+            inner: MemoryCache::new(16),
+            hits: Mutex::new(0),
+        });
+        let (mut searcher, mut uncached) = (Searcher::new(), Searcher::new());
+        searcher.set_idf_cache(Some(cache.clone()));
+        for index in [&mut searcher, &mut uncached] {
+            index.add_document("1", "rust compiler");
+            index.add_document("2", "borrow checker");
+        }
+
+        assert_eq!(searcher.search("rust"), uncached.search("rust"));
+        assert_eq!(searcher.search("rust"), uncached.search("rust"));
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+
+        // a new document changes the idf of every term
+        for index in [&mut searcher, &mut uncached] {
+            index.add_document("3", "rust borrow checker");
+        }
+        assert_eq!(searcher.search("rust"), uncached.search("rust"));
+        assert_eq!(*cache.hits.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_memory_cache_capacity() {
+        let cache = MemoryCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        cache.insert(2, "c");
+        assert_eq!(cache.get(&1), Some("a"));
+        cache.insert(3, "d");
+        assert_eq!(cache.get(&1), None);
+        assert_eq!(cache.get(&3), Some("d"));
+    }
+}
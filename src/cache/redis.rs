@@ -0,0 +1,157 @@
+//! A cache kept in Redis, so that the processes of a deployment share it.
+//!
+//! Every entry is a string holding the value as bytes, under the prefix the cache was opened with
+//! as a hash tag like the keys of `RedisBackend`, e.g. `{notes}:cache:3/moon`. The keys of a cache
+//! start like those of no index, so that it can share the prefix of the index it caches, which
+//! then drops the cache along with the postings left by an earlier run.
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use ::redis::{Client, Commands, RedisResult};
+use r2d2::{Pool, PooledConnection};
+
+use crate::backend::redis::{glob_escape, key_prefix, pool_error};
+use crate::cache::Cache;
+use crate::HashMap;
+
+/// A value a `RedisCache` can hold, kept in Redis as bytes.
+pub trait CacheValue: Sized {
+    fn encode(&self) -> Vec<u8>;
+    /// None if `bytes` don't hold a value of this type, which the cache takes for a miss.
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl CacheValue for f32 {
+    fn encode(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<f32> {
+        Some(f32::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// The scores of a query, as the length of each doc id, the doc id and its score.
+impl CacheValue for HashMap<String, f32> {
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for (doc_id, score) in self {
+            bytes.extend_from_slice(&(doc_id.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(doc_id.as_bytes());
+            bytes.extend_from_slice(&score.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn decode(mut bytes: &[u8]) -> Option<HashMap<String, f32>> {
+        let mut scores = HashMap::new();
+        while !bytes.is_empty() {
+            let (len, rest) = bytes.split_first_chunk::<4>()?;
+            let len = u32::from_le_bytes(*len) as usize;
+            let doc_id = std::str::from_utf8(rest.get(..len)?).ok()?;
+            let (score, rest) = rest[len..].split_first_chunk::<4>()?;
+            scores.insert(doc_id.to_string(), f32::from_le_bytes(*score));
+            bytes = rest;
+        }
+        Some(scores)
+    }
+}
+
+/// A cache in Redis, e.g. the `QueryCache` or `IdfCache` of a Searcher, shared by every process
+/// using the same prefix on the same server. Entries are kept until cleared, or for the time to
+/// live set with `set_ttl`.
+///
+/// A cache only saves work, so one that can't be reached misses: a lookup that fails finds
+/// nothing and an entry that can't be written is dropped. Searchers key entries by how many times
+/// the index changed, which starts over when one is loaded, so clear the cache of an index that
+/// is replaced.
+pub struct RedisCache<V> {
+    pool: Pool<Client>,
+    prefix: String,        // start of every key of the cache
+    ttl: Option<Duration>, // how long an entry is kept, if not until cleared
+    values: PhantomData<fn() -> V>,
+}
+
+impl<V> RedisCache<V> {
+    /// Connect to the Redis server at `url`, e.g. `redis://127.0.0.1/`, and keep entries under
+    /// `prefix` there, with a pool of up to 10 connections. Unlike a `RedisBackend`, the cache
+    /// keeps what it finds under the prefix, so that a process can use what others cached.
+    pub fn open(url: &str, prefix: &str) -> RedisResult<RedisCache<V>> {
+        let client = Client::open(url)?;
+        // the pool would keep trying a server that can't be reached until it times out
+        drop(client.get_connection()?);
+        Ok(RedisCache::with_pool(Pool::builder().test_on_check_out(false).build_unchecked(client), prefix))
+    }
+
+    /// Keep entries under `prefix` on the server of `pool`, sharing its connections with whatever
+    /// else uses it, e.g. the backend of the index cached.
+    pub fn with_pool(pool: Pool<Client>, prefix: &str) -> RedisCache<V> {
+        RedisCache {
+            pool,
+            prefix: format!("{}cache:", key_prefix(prefix)),
+            ttl: None,
+            values: PhantomData,
+        }
+    }
+
+    /// Expire entries `ttl` after they are inserted, e.g. so that those of states of the index
+    /// no process searches anymore don't pile up, or keep them until cleared with None. Redis
+    /// counts in whole seconds, at least one.
+    pub fn set_ttl(&mut self, ttl: Option<Duration>) {
+        self.ttl = ttl;
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn connection(&self) -> RedisResult<PooledConnection<Client>> {
+        self.pool.get().map_err(pool_error)
+    }
+
+    fn clear_keys(&self) -> RedisResult<()> {
+        let mut connection = self.connection()?;
+        let keys: Vec<String> = connection.scan_match(format!("{}*", glob_escape(&self.prefix)))?.collect();
+        if !keys.is_empty() {
+            connection.del::<_, ()>(keys)?;
+        }
+        Ok(())
+    }
+}
+
+impl<V: CacheValue> Cache<String, V> for RedisCache<V> {
+    fn get(&self, key: &String) -> Option<V> {
+        let bytes: Option<Vec<u8>> = self.connection().and_then(|mut connection| connection.get(self.key(key))).ok()?;
+        V::decode(&bytes?)
+    }
+
+    fn insert(&self, key: String, value: V) {
+        let (key, value) = (self.key(&key), value.encode());
+        let _: RedisResult<()> = self.connection().and_then(|mut connection| match self.ttl {
+            Some(ttl) => connection.set_ex(key, value, ttl.as_secs().max(1)),
+            None => connection.set(key, value),
+        });
+    }
+
+    fn clear(&self) {
+        let _ = self.clear_keys();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_values() {
+        let scores = HashMap::from([("notes/a.md".to_string(), 1.5), (String::new(), -0.25), ("ü".to_string(), 0.0)]);
+        assert_eq!(HashMap::decode(&scores.encode()), Some(scores));
+        assert_eq!(HashMap::decode(&HashMap::new().encode()), Some(HashMap::new()));
+        assert_eq!(f32::decode(&2.5f32.encode()), Some(2.5));
+
+        // bytes left by something else are a miss
+        assert_eq!(f32::decode(b"2.5"), None);
+        assert_eq!(HashMap::decode(&[9, 0, 0, 0, b'a']), None);
+    }
+}
@@ -0,0 +1,89 @@
+//! Classification by k nearest neighbours: a new document gets the label most common among the
+//! labeled documents it is most similar to, found by searching the index with the whole document
+//! as the query. Needs no training and a labeled document added is used right away, which makes
+//! it a strong baseline for e.g. routing tickets or tagging notes.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::backend::Failures;
+use crate::{HashMap, IndexBackend, Searcher};
+
+/// The label `Searcher::classify` picked for a document, with the votes it was picked from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub label: String,
+    pub confidence: f32,           // share of the votes that went to `label`, in (0, 1]
+    pub votes: Vec<(String, f32)>, // label -> summed score of the neighbours with it, most first
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Classify `content` by the values of the keyword field `label_field` of the `k` labeled
+    /// documents that score best with `content` as the query. Each of them votes for its labels
+    /// with its score, so closer neighbours count more. None if no labeled document shares a term
+    /// with `content`.
+    pub fn classify(&self, content: &str, label_field: &str, k: usize) -> Option<Classification> {
+        let tokens = self.analyze_text(content);
+        let scores = self.score_terms(tokens.iter().map(|token| token.term.as_ref()), self.k1, self.b, 1, &Failures::default());
+
+        let mut neighbours: Vec<(&Vec<String>, f32)> = scores
+            .iter()
+            .filter_map(|(doc_id, &score)| Some((self.docs[doc_id.as_str()].keywords.get(label_field)?, score)))
+            .filter(|(labels, _)| !labels.is_empty())
+            .collect();
+        neighbours.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        neighbours.truncate(k);
+
+        let mut votes: HashMap<&str, f32> = HashMap::new();
+        for (labels, score) in &neighbours {
+            for label in labels.iter() {
+                *votes.entry(label).or_insert(0.0) += score;
+            }
+        }
+        let total: f32 = votes.values().sum();
+        let mut votes: Vec<(String, f32)> = votes.into_iter().map(|(label, votes)| (label.to_string(), votes)).collect();
+        votes.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let (label, best) = votes.first()?.clone();
+        Some(Classification { label, confidence: best / total, votes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let mut searcher = Searcher::new();
+        let labeled = [
+            ("1", "refund for a double charge on my invoice", "billing"),
+            ("2", "invoice shows the wrong amount", "billing"),
+            ("3", "charged twice, please refund", "billing"),
+            ("4", "the app crashes when I open settings", "bug"),
+            ("5", "crash on startup after the update", "bug"),
+        ];
+        for (doc_id, content, label) in labeled {
+            searcher.add_document(doc_id, content);
+            searcher.add_keyword(doc_id, "label", label);
+        }
+        // unlabeled documents don't vote
+        searcher.add_document("6", "refund refund refund");
+
+        let ticket = searcher.classify("I want a refund, the invoice charged me twice", "label", 3).unwrap();
+        assert_eq!(ticket.label, "billing");
+        assert_eq!(ticket.confidence, 1.0);
+
+        let crash = searcher.classify("it crashes after the update", "label", 3).unwrap();
+        assert_eq!(crash.label, "bug");
+        assert_eq!(crash.votes[0].0, "bug");
+
+        // neighbours with different labels split the votes
+        let mixed = searcher.classify("the invoice crashes", "label", 5).unwrap();
+        assert_eq!(mixed.votes.len(), 2);
+        assert!((mixed.votes.iter().map(|(_, votes)| votes).sum::<f32>() * mixed.confidence - mixed.votes[0].1).abs() < 1e-6);
+
+        assert_eq!(searcher.classify("rocket launch", "label", 3), None);
+        assert_eq!(searcher.classify("refund", "missing", 3), None);
+    }
+}
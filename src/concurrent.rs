@@ -0,0 +1,169 @@
+//! Sharing an index between threads: one `IndexWriter` changes it while any number of
+//! `IndexReader`s search it. Searches run concurrently with each other and each sees the index
+//! either before or after a write, never halfway through one: a batch of changes made in a single
+//! `IndexWriter::write` call becomes visible all at once.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{ConfigError, Query, Searcher};
+
+/// The only handle that can change a shared index.
+pub struct IndexWriter {
+    searcher: Arc<RwLock<Searcher>>,
+}
+
+/// A handle to search a shared index, cheap to clone and send to other threads.
+#[derive(Clone)]
+pub struct IndexReader {
+    searcher: Arc<RwLock<Searcher>>,
+}
+
+impl Searcher {
+    /// Share this index between threads, as its writer and a first reader.
+    pub fn into_shared(self) -> (IndexWriter, IndexReader) {
+        let searcher = Arc::new(RwLock::new(self));
+        let reader = IndexReader { searcher: searcher.clone() };
+        (IndexWriter { searcher }, reader)
+    }
+}
+
+impl IndexWriter {
+    /// Apply a batch of changes, which readers see all at once when it returns. Searches wait
+    /// while the batch runs, so a large batch is better split.
+    pub fn write<R>(&mut self, f: impl FnOnce(&mut Searcher) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    pub fn add_document(&mut self, doc_id: &str, content: &str) {
+        self.lock().add_document(doc_id, content)
+    }
+
+    pub fn update_document(&mut self, doc_id: &str, content: &str) {
+        self.lock().update_document(doc_id, content)
+    }
+
+    pub fn remove_document(&mut self, doc_id: &str) -> bool {
+        self.lock().remove_document(doc_id)
+    }
+
+    pub fn add_keyword(&mut self, doc_id: &str, field: &str, value: &str) -> bool {
+        self.lock().add_keyword(doc_id, field, value)
+    }
+
+    pub fn set_static_rank(&mut self, doc_id: &str, rank: f32) -> Result<bool, ConfigError> {
+        self.lock().set_static_rank(doc_id, rank)
+    }
+
+    /// Another reader of the index.
+    pub fn reader(&self) -> IndexReader {
+        IndexReader { searcher: self.searcher.clone() }
+    }
+
+    /// Stop sharing the index and get it back, once every reader is dropped. Returns the writer
+    /// unchanged while readers remain.
+    pub fn into_inner(self) -> Result<Searcher, IndexWriter> {
+        match Arc::try_unwrap(self.searcher) {
+            Ok(searcher) => Ok(searcher.into_inner().expect("index lock poisoned by a panic while writing")),
+            Err(searcher) => Err(IndexWriter { searcher }),
+        }
+    }
+
+    fn lock(&self) -> RwLockWriteGuard<'_, Searcher> {
+        self.searcher.write().expect("index lock poisoned by a panic while writing")
+    }
+}
+
+impl IndexReader {
+    /// Run `f` on the index as it is now. Writes wait until it returns, so several searches run in
+    /// one call see the same index.
+    pub fn read<R>(&self, f: impl FnOnce(&Searcher) -> R) -> R {
+        f(&self.lock())
+    }
+
+    pub fn search(&self, query: &str) -> HashMap<String, f32> {
+        self.lock().search(query)
+    }
+
+    pub fn search_query(&self, query: &Query) -> HashMap<String, f32> {
+        self.lock().search_query(query)
+    }
+
+    /// Number of documents in the index.
+    pub fn doc_count(&self) -> usize {
+        self.lock().docs.len()
+    }
+
+    fn lock(&self) -> RwLockReadGuard<'_, Searcher> {
+        self.searcher.read().expect("index lock poisoned by a panic while writing")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<Searcher>();
+        assert_send_sync::<IndexWriter>();
+        assert_send_sync::<IndexReader>();
+    }
+
+    #[test]
+    fn test_concurrent_searches_during_writes() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("seed", "ocean tide");
+        let (mut writer, reader) = searcher.into_shared();
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|scope| {
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    let reader = reader.clone();
+                    let done = &done;
+                    scope.spawn(move || {
+                        let mut seen = 0;
+                        let mut searches = 0;
+                        while !done.load(Ordering::Acquire) || searches == 0 {
+                            let (moon, rocket) = reader.read(|searcher| (searcher.search("moon").len(), searcher.search("rocket").len()));
+                            // documents come in pairs and never disappear
+                            assert_eq!(moon % 2, 0);
+                            assert_eq!(moon, rocket);
+                            assert!(moon >= seen);
+                            seen = moon;
+                            searches += 1;
+                        }
+                        seen
+                    })
+                })
+                .collect();
+
+            for i in 0..200 {
+                writer.write(|searcher| {
+                    searcher.add_document(&format!("{}a", i), "moon rocket");
+                    searcher.add_document(&format!("{}b", i), "moon rocket");
+                });
+            }
+            done.store(true, Ordering::Release);
+            for handle in readers {
+                assert!(handle.join().unwrap() <= 400);
+            }
+        });
+
+        assert_eq!(reader.search("moon").len(), 400);
+        assert_eq!(writer.reader().doc_count(), 401);
+        assert!(writer.remove_document("seed"));
+        assert!(reader.search("ocean").is_empty());
+
+        let Err(writer) = writer.into_inner() else {
+            panic!("the index was handed back while a reader remained");
+        };
+        drop(reader);
+        assert_eq!(writer.into_inner().ok().unwrap().search("moon").len(), 400);
+    }
+}
@@ -0,0 +1,107 @@
+//! Document content kept outside the index. Search only needs the terms and statistics of a
+//! document; its text is only read back for snippets, highlighting and summaries. With a
+//! `ContentProvider` set, the index doesn't keep a copy of the text of the documents added, and
+//! fetches it from the provider when it is needed, so that the index doesn't grow with the corpus.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use crate::{IndexBackend, Searcher};
+
+/// Fetches the original content of documents, e.g. from disk, object storage or a database. It
+/// must return the content the document was indexed with, or highlights end up in the wrong place.
+pub trait ContentProvider: Send + Sync {
+    /// The content of a document, None if it can't be found.
+    fn content(&self, doc_id: &str) -> Option<String>;
+}
+
+/// Reads documents from files, taking doc ids as paths relative to a directory.
+#[cfg(feature = "std")]
+pub struct FileContentProvider {
+    root: PathBuf,
+}
+
+#[cfg(feature = "std")]
+impl FileContentProvider {
+    pub fn new<P: Into<PathBuf>>(root: P) -> FileContentProvider {
+        FileContentProvider { root: root.into() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ContentProvider for FileContentProvider {
+    fn content(&self, doc_id: &str) -> Option<String> {
+        std::fs::read_to_string(self.root.join(doc_id)).ok()
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Fetch the content of documents added from now on from `provider` instead of keeping it in
+    /// the index, or keep it again with None. Documents added before keep their stored content.
+    /// Providers aren't saved: an index built with one needs it set again after `load`.
+    pub fn set_content_provider(&mut self, provider: Option<Arc<dyn ContentProvider>>) {
+        self.content_provider = provider;
+    }
+
+    /// The content of a document, from the index or its provider. None if the document does not
+    /// exist or its content can't be fetched.
+    pub(crate) fn content(&self, doc_id: &str) -> Option<Cow<'_, str>> {
+        match &self.docs.get(doc_id)?.content {
+            Some(content) => Some(Cow::Borrowed(content)),
+            None => self.content_provider.as_ref()?.content(doc_id).map(Cow::Owned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    #[derive(Default)]
+    struct Store {
+        docs: Mutex<HashMap<String, String>>,
+        fetches: Mutex<Vec<String>>,
+    }
+
+    impl ContentProvider for Store {
+        fn content(&self, doc_id: &str) -> Option<String> {
+            self.fetches.lock().unwrap().push(doc_id.to_string());
+            self.docs.lock().unwrap().get(doc_id).cloned()
+        }
+    }
+
+    #[test]
+    fn test_content_provider() {
+        let store = Arc::new(Store::default());
+        let mut searcher = Searcher::new();
+        searcher.add_document("stored", "The moon rocket launched.");
+        searcher.set_content_provider(Some(store.clone()));
+        for (doc_id, content) in [("1", "The moon rocket launched. Ocean tide charts were updated."), ("2", "ocean tide")] {
+            store.docs.lock().unwrap().insert(doc_id.to_string(), content.to_string());
+            searcher.add_document(doc_id, content);
+        }
+        searcher.add_document("lost", "moon");
+
+        let query = Query::parse("moon", ParseMode::Strict).unwrap();
+        assert_eq!(searcher.search_query(&query).len(), 3);
+        assert!(store.fetches.lock().unwrap().is_empty());
+
+        assert_eq!(searcher.snippet("1", &query, "[", "]").unwrap(), "The [moon] rocket launched. Ocean tide charts were updated.");
+        assert_eq!(searcher.snippet("stored", &query, "[", "]").unwrap(), "The [moon] rocket launched.");
+        assert_eq!(searcher.snippet("lost", &query, "[", "]"), None);
+        assert_eq!(*store.fetches.lock().unwrap(), ["1", "lost"]);
+
+        // not saved with the index
+        let mut loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        assert_eq!(loaded.snippet("1", &query, "[", "]"), None);
+        loaded.set_content_provider(Some(store));
+        assert_eq!(loaded.matched_terms("1", &query).unwrap()[0].originals, ["moon"]);
+    }
+}
@@ -0,0 +1,305 @@
+//! Document-at-a-time scoring: the postings of all query terms are walked side by side in
+//! document order and every document is scored completely before moving on to the next one,
+//! instead of accumulating partial scores term by term in a map. With a minimum number of
+//! matching terms, documents that can't reach it are skipped over without being looked at.
+//! The buffers this takes can be kept in a `QueryContext` and reused from query to query.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::backend::Failures;
+use crate::postings::Posting;
+use crate::{DocId, HashMap, IndexBackend, Postings, Searcher};
+
+/// The unread postings of one distinct query term.
+#[derive(Clone, Copy)]
+pub(crate) struct Cursor<'a> {
+    postings: &'a [Posting],
+    weight: f32, // idf, times how often the term occurs in the query
+}
+
+impl Cursor<'_> {
+    fn head(&self) -> Option<u32> {
+        self.postings.first().map(|posting| posting.ordinal)
+    }
+
+    /// Skip the postings of documents indexed before `ordinal`.
+    fn seek(&mut self, ordinal: u32) {
+        let skip = self.postings.partition_point(|posting| posting.ordinal < ordinal);
+        self.postings = &self.postings[skip..];
+    }
+}
+
+/// Scratch space of query evaluation, kept between queries so that a caller running many of
+/// them, e.g. one context per worker thread, doesn't allocate the same buffers for every query.
+#[derive(Default)]
+pub struct QueryContext {
+    counts: Vec<(&'static str, f32)>,               // distinct query terms and their multiplicity, empty between queries
+    postings: Vec<(Cow<'static, Postings>, f32)>, // postings of the query terms with their weights, empty between queries
+    cursors: Vec<Cursor<'static>>,                  // empty between queries
+    scores: HashMap<String, f32>,                   // results of the last query
+}
+
+impl QueryContext {
+    pub fn new() -> QueryContext {
+        QueryContext::default()
+    }
+}
+
+/// A cursor at the start of each of the `postings` fetched by `Searcher::fetch_postings`.
+pub(crate) fn open_cursors<'p>(postings: &'p [(Cow<Postings>, f32)]) -> impl Iterator<Item = Cursor<'p>> {
+    postings.iter().map(|(postings, weight)| Cursor {
+        postings: postings.as_slice(),
+        weight: *weight,
+    })
+}
+
+/// An empty Vec that keeps the allocation of `v`, for items that only differ from those of `v`
+/// in their lifetimes. Collecting a Vec's own iterator reuses its buffer when the layouts match.
+fn recycle<T, U>(mut v: Vec<T>) -> Vec<U> {
+    v.clear();
+    v.into_iter().map(|_| unreachable!("the Vec is empty")).collect()
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Same as `search`, with the buffers of the query and its results kept in `context`, to be
+    /// reused by the next query run with it.
+    pub fn search_in<'c>(&self, query: &str, context: &'c mut QueryContext) -> &'c HashMap<String, f32> {
+        let mut timer = self.query_timer();
+        let tokens = self.analyze_with(query, &self.query_stop_words(query));
+        timer.phase("analysis");
+        let failures = Failures::default();
+        let terms = self.with_synonyms(tokens.iter().map(|token| token.term.as_ref()));
+        self.score_terms_in(terms, self.k1, self.b, 1, context, &failures);
+        if let Some(phrase_docs) = self.phrase_docs(query, &failures) {
+            context.scores.retain(|doc_id, _| phrase_docs.contains(doc_id.as_str()));
+        }
+        self.apply_static_ranks_in(&mut context.scores);
+        self.cap_results(&mut context.scores);
+        timer.phase("scoring");
+        timer.finish(|| query.to_string(), context.scores.len());
+        &context.scores
+    }
+
+    /// Same as `search`, passing each document to `found` with its score as soon as it is scored,
+    /// in indexing order rather than by score, e.g. to stream hits to a client with an
+    /// `NdjsonWriter` instead of collecting them first. Returns the number of documents found.
+    /// Hits aren't ranked before they are passed on, so `max_results` keeps the first ones.
+    pub fn search_each(&self, query: &str, mut found: impl FnMut(&str, f32)) -> usize {
+        let mut timer = self.query_timer();
+        let tokens = self.analyze_with(query, &self.query_stop_words(query));
+        timer.phase("analysis");
+        let failures = Failures::default();
+        let phrase_docs = self.phrase_docs(query, &failures);
+        let mut postings = Vec::new();
+        self.fetch_postings(self.with_synonyms(tokens.iter().map(|token| token.term.as_ref())), &mut Vec::new(), &mut postings, &failures);
+        let mut cursors = open_cursors(&postings).collect();
+        let max_results = self.max_results.unwrap_or(usize::MAX);
+        let mut count = 0;
+        self.traverse(&mut cursors, 0..u32::MAX, 1, self.k1, self.b, |doc_id, score| {
+            if count < max_results && phrase_docs.as_ref().is_none_or(|docs| docs.contains(doc_id.as_ref())) {
+                found(doc_id, score * self.docs[doc_id].static_rank);
+                count += 1;
+            }
+        });
+        timer.phase("scoring");
+        timer.finish(|| query.to_string(), count);
+        count
+    }
+
+    /// BM25 scores of the documents containing at least `min_should_match` (and at least one)
+    /// of the distinct `terms`. Repeated terms count once towards the minimum but score as often
+    /// as they are repeated.
+    pub(crate) fn score_terms<'t>(
+        &self,
+        terms: impl IntoIterator<Item = &'t str>,
+        k1: f32,
+        b: f32,
+        min_should_match: usize,
+        failures: &Failures,
+    ) -> HashMap<String, f32> {
+        let mut context = QueryContext::new();
+        self.score_terms_in(terms, k1, b, min_should_match, &mut context, failures);
+        context.scores
+    }
+
+    /// Same as `score_terms`, leaving the scores in `context`.
+    fn score_terms_in<'t>(
+        &self,
+        terms: impl IntoIterator<Item = &'t str>,
+        k1: f32,
+        b: f32,
+        min_should_match: usize,
+        context: &mut QueryContext,
+        failures: &Failures,
+    ) {
+        let mut counts: Vec<(&str, f32)> = recycle(core::mem::take(&mut context.counts));
+        let mut postings: Vec<(Cow<Postings>, f32)> = recycle(core::mem::take(&mut context.postings));
+        self.fetch_postings(terms, &mut counts, &mut postings, failures);
+        context.counts = recycle(counts);
+        let mut cursors: Vec<Cursor> = recycle(core::mem::take(&mut context.cursors));
+        cursors.extend(open_cursors(&postings));
+
+        let scores = &mut context.scores;
+        scores.clear();
+        let found = |doc_id: &DocId, score| {
+            scores.insert(doc_id.to_string(), score);
+        };
+        self.traverse(&mut cursors, 0..u32::MAX, min_should_match, k1, b, found);
+        context.cursors = recycle(cursors);
+        context.postings = recycle(postings);
+    }
+
+    /// Add the postings of every distinct term of `terms` that is in the index to `postings`,
+    /// weighted by its idf and how often it occurs in `terms`, using `counts` as scratch space.
+    pub(crate) fn fetch_postings<'a, 't>(
+        &'a self,
+        terms: impl IntoIterator<Item = &'t str>,
+        counts: &mut Vec<(&'t str, f32)>,
+        postings: &mut Vec<(Cow<'a, Postings>, f32)>,
+        failures: &Failures,
+    ) {
+        for term in terms {
+            match counts.iter_mut().find(|(t, _)| *t == term) {
+                Some((_, count)) => *count += 1.0,
+                None => counts.push((term, 1.0)),
+            }
+        }
+        postings.extend(
+            counts.drain(..).filter_map(|(term, count)| Some((failures.ok(self.index.postings(term))?, count * self.idf(term, failures)))),
+        );
+    }
+
+    /// Walk `cursors` through the documents with ordinals in `range`, passing every document that
+    /// matches at least `min_should_match` (and at least one) of them to `found` with its score.
+    pub(crate) fn traverse(
+        &self,
+        cursors: &mut Vec<Cursor>,
+        range: Range<u32>,
+        min_should_match: usize,
+        k1: f32,
+        b: f32,
+        mut found: impl FnMut(&DocId, f32),
+    ) {
+        let min = min_should_match.max(1);
+        let mut heads = Vec::new();
+        for cursor in cursors.iter_mut() {
+            cursor.seek(range.start);
+        }
+        loop {
+            // once too few lists are left, no further document can match
+            cursors.retain(|cursor| cursor.head().is_some_and(|head| head < range.end));
+            if cursors.len() < min {
+                break;
+            }
+
+            // a document before the min-th smallest head is missing from too many lists
+            if min > 1 {
+                heads.clear();
+                heads.extend(cursors.iter().filter_map(Cursor::head));
+                let (_, &mut target, _) = heads.select_nth_unstable(min - 1);
+                for cursor in cursors.iter_mut() {
+                    cursor.seek(target);
+                }
+            }
+
+            let Some(ordinal) = cursors.iter().filter_map(Cursor::head).min().filter(|&head| head < range.end) else {
+                break;
+            };
+            let mut matched = 0;
+            let mut score = 0.0;
+            let mut doc_id = None;
+            for cursor in cursors.iter_mut() {
+                if let [posting, rest @ ..] = cursor.postings {
+                    if posting.ordinal == ordinal {
+                        let tf = posting.positions.len() as f32;
+                        score += cursor.weight * self.tf_norm(tf, &posting.doc_id, k1, b);
+                        matched += 1;
+                        doc_id = Some(&posting.doc_id);
+                        cursor.postings = rest;
+                    }
+                }
+            }
+            if let Some(doc_id) = doc_id.filter(|_| matched >= min) {
+                found(doc_id, score);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_terms() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket ocean");
+        searcher.add_document("2", "moon");
+        searcher.add_document("3", "rocket ocean");
+        searcher.add_document("4", "ocean tide");
+        searcher.add_document("5", "moon rocket");
+        let (k1, b) = (searcher.k1, searcher.b);
+        let failures = Failures::default();
+
+        let any = searcher.score_terms(["moon", "rocket", "ocean"], k1, b, 0, &failures);
+        assert_eq!(any.len(), 5);
+        let two = searcher.score_terms(["moon", "rocket", "ocean"], k1, b, 2, &failures);
+        let mut matched: Vec<_> = two.keys().map(String::as_str).collect();
+        matched.sort();
+        assert_eq!(matched, ["1", "3", "5"]);
+        // a document scores the same however many other terms are required
+        assert_eq!(two["1"], any["1"]);
+        assert_eq!(searcher.score_terms(["moon", "rocket", "ocean"], k1, b, 3, &failures).len(), 1);
+        assert!(searcher.score_terms(["moon", "rocket", "ocean"], k1, b, 4, &failures).is_empty());
+
+        // repeated terms weigh more but don't count twice
+        let repeated = searcher.score_terms(["moon", "moon"], k1, b, 2, &failures);
+        assert!(repeated.is_empty());
+        let repeated = searcher.score_terms(["moon", "moon"], k1, b, 1, &failures);
+        assert_eq!(repeated["2"], 2.0 * searcher.score_terms(["moon"], k1, b, 1, &failures)["2"]);
+
+        // unknown terms match nothing
+        assert!(searcher.score_terms(["comet"], k1, b, 1, &failures).is_empty());
+    }
+
+    #[test]
+    fn test_search_in() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("2", "ocean tide");
+        searcher.set_static_rank("2", 2.0).unwrap();
+
+        let mut context = QueryContext::new();
+        assert_eq!(*searcher.search_in("moon", &mut context), searcher.search("moon"));
+        // results of the previous query don't leak into the next one
+        assert_eq!(*searcher.search_in("ocean tide", &mut context), searcher.search("ocean tide"));
+        assert!(searcher.search_in("comet", &mut context).is_empty());
+        assert!(context.cursors.is_empty() && context.counts.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_search_each() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("2", "ocean tide");
+        searcher.add_document("3", "rocket ocean");
+        searcher.set_static_rank("3", 2.0).unwrap();
+
+        let mut hits = Vec::new();
+        let count = searcher.search_each("rocket ocean", |doc_id, score| hits.push((doc_id.to_string(), score)));
+        assert_eq!(count, 3);
+        // hits come in indexing order, scored like `search` scores them
+        let expected = searcher.search("rocket ocean");
+        assert_eq!(hits.iter().map(|(doc_id, _)| doc_id.as_str()).collect::<Vec<_>>(), ["1", "2", "3"]);
+        assert!(hits.iter().all(|(doc_id, score)| expected[doc_id] == *score));
+
+        let mut writer = crate::NdjsonWriter::new(Vec::new());
+        searcher.search_each("\"rocket ocean\"", |doc_id, score| writer.write_hit(doc_id, score).unwrap());
+        let written = String::from_utf8(writer.into_inner()).unwrap();
+        assert!(written.starts_with(r#"{"doc_id":"3","score":"#) && written.lines().count() == 1, "{}", written);
+    }
+}
@@ -0,0 +1,532 @@
+//! A JSON form of `Query`, for API clients that build structured queries instead of writing the
+//! string syntax and escaping user input for it. Every object has a single key naming the query
+//! type, mirroring the `Query` variants:
+//!
+//! ```json
+//! {"bool": {
+//!     "must": [{"phrase": "borrow checker"}],
+//!     "should": [{"term": "rust"}, {"boost": {"query": {"wildcard": "lifetime*"}, "factor": 2}}],
+//!     "filter": [{"range": {"field": "year", "gte": "2015"}}],
+//!     "must_not": [{"keyword": {"field": "ext", "value": "md"}}],
+//!     "demote": [{"query": {"term": "archive"}, "factor": 0.5}]
+//! }}
+//! ```
+//!
+//! The other types are `regex`, `fuzzy` (`term`, optional `distance`), `nested` (`path`, `query`), `join` (`from`, `to`, `query`) and
+//! `query_string`, which parses a string in the query syntax.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+use core::ops::Bound;
+
+use crate::{BoolQuery, Intervals, ParseMode, Query};
+
+/// Deepest nesting of JSON arrays and objects accepted, so hostile input can't overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Error returned when a JSON query can't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DslError {
+    /// The input is not valid JSON.
+    Json { position: usize, expected: String },
+    /// The JSON doesn't describe a query. `path` locates the offending value, e.g.
+    /// `bool.must[1].range`.
+    Query { path: String, message: String },
+}
+
+impl fmt::Display for DslError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DslError::Json { position, expected } => write!(f, "invalid JSON at position {}: expected {}", position, expected),
+            DslError::Query { path, message } if path.is_empty() => write!(f, "{}", message),
+            DslError::Query { path, message } => write!(f, "{} at `{}`", message, path),
+        }
+    }
+}
+
+impl core::error::Error for DslError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn kind(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "a boolean",
+            Json::Number(_) => "a number",
+            Json::String(_) => "a string",
+            Json::Array(_) => "an array",
+            Json::Object(_) => "an object",
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    input: &'a str,
+    pos: usize,   // byte offset of the next unread character
+    depth: usize, // number of currently open arrays and objects
+}
+
+impl JsonParser<'_> {
+    fn error(&self, expected: &str) -> DslError {
+        DslError::Json {
+            position: self.pos,
+            expected: expected.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+            self.bump();
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), DslError> {
+        self.skip_whitespace();
+        match self.peek() == Some(c) {
+            true => {
+                self.bump();
+                Ok(())
+            }
+            false => Err(self.error(&format!("`{}`", c))),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Json, DslError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') | Some('[') if self.depth == MAX_DEPTH => Err(self.error(&format!("at most {} nested values", MAX_DEPTH))),
+            Some('{') => {
+                self.bump();
+                self.depth += 1;
+                let mut members = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some('}') {
+                    self.bump();
+                } else {
+                    loop {
+                        self.skip_whitespace();
+                        if self.peek() != Some('"') {
+                            return Err(self.error("a string key"));
+                        }
+                        let key = self.parse_string()?;
+                        self.expect(':')?;
+                        members.push((key, self.parse_value()?));
+                        self.skip_whitespace();
+                        match self.bump() {
+                            Some(',') => continue,
+                            Some('}') => break,
+                            _ => return Err(self.error("`,` or `}`")),
+                        }
+                    }
+                }
+                self.depth -= 1;
+                Ok(Json::Object(members))
+            }
+            Some('[') => {
+                self.bump();
+                self.depth += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.peek() == Some(']') {
+                    self.bump();
+                } else {
+                    loop {
+                        items.push(self.parse_value()?);
+                        self.skip_whitespace();
+                        match self.bump() {
+                            Some(',') => continue,
+                            Some(']') => break,
+                            _ => return Err(self.error("`,` or `]`")),
+                        }
+                    }
+                }
+                self.depth -= 1;
+                Ok(Json::Array(items))
+            }
+            Some('"') => Ok(Json::String(self.parse_string()?)),
+            Some('-' | '0'..='9') => self.parse_number(),
+            _ => {
+                for (literal, value) in [("null", Json::Null), ("true", Json::Bool(true)), ("false", Json::Bool(false))] {
+                    if self.input[self.pos..].starts_with(literal) {
+                        self.pos += literal.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("a value"))
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<Json, DslError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            self.bump();
+        }
+        match self.input[start..self.pos].parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(Json::Number(number)),
+            _ => Err(DslError::Json {
+                position: start,
+                expected: "a number".to_string(),
+            }),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, DslError> {
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("`\"` to close the string")),
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('/') => string.push('/'),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let unit = self.parse_hex()?;
+                        let c = match unit {
+                            // a surrogate pair encodes one character outside the basic plane
+                            0xd800..=0xdbff if self.input[self.pos..].starts_with("\\u") => {
+                                self.pos += 2;
+                                let low = self.parse_hex()?;
+                                char::from_u32(0x10000 + ((unit - 0xd800) << 10) + low.wrapping_sub(0xdc00))
+                            }
+                            unit => char::from_u32(unit),
+                        };
+                        string.push(c.ok_or_else(|| self.error("a valid \\u escape"))?);
+                    }
+                    _ => return Err(self.error("a valid escape after `\\`")),
+                },
+                Some(c) if c < ' ' => return Err(self.error("an escaped control character")),
+                Some(c) => string.push(c),
+            }
+        }
+    }
+
+    fn parse_hex(&mut self) -> Result<u32, DslError> {
+        let hex = self.input.get(self.pos..self.pos + 4).filter(|hex| hex.chars().all(|c| c.is_ascii_hexdigit()));
+        let unit = hex.and_then(|hex| u32::from_str_radix(hex, 16).ok()).ok_or_else(|| self.error("four hex digits"))?;
+        self.pos += 4;
+        Ok(unit)
+    }
+}
+
+/// Where a value sits in the query document, for error messages.
+#[derive(Clone)]
+struct Path(String);
+
+impl Path {
+    fn key(&self, key: &str) -> Path {
+        match self.0.is_empty() {
+            true => Path(key.to_string()),
+            false => Path(format!("{}.{}", self.0, key)),
+        }
+    }
+
+    fn index(&self, i: usize) -> Path {
+        Path(format!("{}[{}]", self.0, i))
+    }
+
+    fn error(&self, message: impl Into<String>) -> DslError {
+        DslError::Query {
+            path: self.0.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+/// The members of an object, checked against the keys its query type knows.
+struct Members<'a> {
+    members: &'a [(String, Json)],
+    path: Path,
+}
+
+impl<'a> Members<'a> {
+    fn new(value: &'a Json, path: &Path, known: &[&str]) -> Result<Members<'a>, DslError> {
+        let Json::Object(members) = value else {
+            return Err(path.error(format!("expected an object, found {}", value.kind())));
+        };
+        if let Some((key, _)) = members.iter().find(|(key, _)| !known.contains(&key.as_str())) {
+            return Err(path.error(format!("unknown key `{}`, expected one of {}", key, known.join(", "))));
+        }
+        Ok(Members { members, path: path.clone() })
+    }
+
+    fn get(&self, key: &str) -> Option<(&'a Json, Path)> {
+        self.members.iter().find(|(k, _)| k == key).map(|(_, value)| (value, self.path.key(key)))
+    }
+
+    fn required(&self, key: &str) -> Result<(&'a Json, Path), DslError> {
+        self.get(key).ok_or_else(|| self.path.error(format!("missing key `{}`", key)))
+    }
+
+    fn string(&self, key: &str) -> Result<String, DslError> {
+        let (value, path) = self.required(key)?;
+        string(value, &path)
+    }
+
+    fn factor(&self) -> Result<f32, DslError> {
+        match self.required("factor")? {
+            (Json::Number(factor), _) if *factor >= 0.0 => Ok(*factor as f32),
+            (_, path) => Err(path.error("expected a number of at least 0")),
+        }
+    }
+
+    fn query(&self) -> Result<Box<Query>, DslError> {
+        let (value, path) = self.required("query")?;
+        Ok(Box::new(to_query(value, &path)?))
+    }
+}
+
+fn string(value: &Json, path: &Path) -> Result<String, DslError> {
+    match value {
+        Json::String(string) => Ok(string.clone()),
+        other => Err(path.error(format!("expected a string, found {}", other.kind()))),
+    }
+}
+
+/// A range bound: strings as they are, numbers in their shortest form.
+fn bound_value(value: &Json, path: &Path) -> Result<String, DslError> {
+    match value {
+        Json::Number(number) => Ok(number.to_string()),
+        other => string(other, path),
+    }
+}
+
+fn to_queries(value: &Json, path: &Path) -> Result<Vec<Query>, DslError> {
+    match value {
+        Json::Array(items) => items.iter().enumerate().map(|(i, item)| to_query(item, &path.index(i))).collect(),
+        // a single clause doesn't need an array around it
+        Json::Object(_) => Ok(vec![to_query(value, path)?]),
+        other => Err(path.error(format!("expected an array of queries, found {}", other.kind()))),
+    }
+}
+
+fn to_query(value: &Json, path: &Path) -> Result<Query, DslError> {
+    let (kind, body) = match value {
+        Json::Object(members) if members.len() == 1 => (members[0].0.as_str(), &members[0].1),
+        _ => return Err(path.error("expected an object with a single key naming the query type")),
+    };
+    let path = path.key(kind);
+    let query = match kind {
+        "term" => Query::Term(string(body, &path)?),
+        "phrase" => Query::Intervals(Intervals::Term(string(body, &path)?)),
+        "regex" => Query::Regex(string(body, &path)?),
+        "wildcard" => Query::Wildcard(string(body, &path)?),
+        "fuzzy" => {
+            let members = Members::new(body, &path, &["term", "distance"])?;
+            let distance = match members.get("distance") {
+                None => crate::fuzzy::MAX_DISTANCE,
+                Some((Json::Number(distance), _)) if (0.0..=crate::fuzzy::MAX_DISTANCE as f64).contains(distance) && f64::from(*distance as u8) == *distance => *distance as u8,
+                Some((_, path)) => return Err(path.error(format!("expected a whole number up to {}", crate::fuzzy::MAX_DISTANCE))),
+            };
+            Query::Fuzzy {
+                term: members.string("term")?,
+                distance,
+            }
+        }
+        "query_string" => {
+            let text = string(body, &path)?;
+            Query::parse(&text, ParseMode::Strict).map_err(|err| path.error(format!("invalid query string: {}", err)))?
+        }
+        "keyword" => {
+            let members = Members::new(body, &path, &["field", "value"])?;
+            Query::Keyword {
+                field: members.string("field")?,
+                value: members.string("value")?,
+            }
+        }
+        "range" => {
+            let members = Members::new(body, &path, &["field", "gt", "gte", "lt", "lte"])?;
+            let bound = |exclusive: &str, inclusive: &str| -> Result<Bound<String>, DslError> {
+                match (members.get(exclusive), members.get(inclusive)) {
+                    (Some(_), Some(_)) => Err(path.error(format!("only one of `{}` and `{}` can be given", exclusive, inclusive))),
+                    (Some((value, path)), None) => Ok(Bound::Excluded(bound_value(value, &path)?)),
+                    (None, Some((value, path))) => Ok(Bound::Included(bound_value(value, &path)?)),
+                    (None, None) => Ok(Bound::Unbounded),
+                }
+            };
+            Query::Range {
+                field: members.string("field")?,
+                lower: bound("gt", "gte")?,
+                upper: bound("lt", "lte")?,
+            }
+        }
+        "boost" => {
+            let members = Members::new(body, &path, &["query", "factor"])?;
+            Query::Boost {
+                query: members.query()?,
+                factor: members.factor()?,
+            }
+        }
+        "nested" => {
+            let members = Members::new(body, &path, &["path", "query"])?;
+            Query::Nested {
+                path: members.string("path")?,
+                query: members.query()?,
+            }
+        }
+        "join" => {
+            let members = Members::new(body, &path, &["from", "to", "query"])?;
+            Query::Join {
+                from: members.string("from")?,
+                to: members.string("to")?,
+                query: members.query()?,
+            }
+        }
+        "bool" => {
+            let members = Members::new(body, &path, &["must", "should", "must_not", "filter", "demote"])?;
+            let clauses = |key: &str| match members.get(key) {
+                Some((value, path)) => to_queries(value, &path),
+                None => Ok(Vec::new()),
+            };
+            let mut bool_query = BoolQuery {
+                must: clauses("must")?,
+                should: clauses("should")?,
+                must_not: clauses("must_not")?,
+                filter: clauses("filter")?,
+                demote: Vec::new(),
+            };
+            if let Some((Json::Array(items), path)) = members.get("demote") {
+                for (i, item) in items.iter().enumerate() {
+                    let demote = Members::new(item, &path.index(i), &["query", "factor"])?;
+                    match demote.factor()? {
+                        factor if factor <= 1.0 => bool_query.demote.push((*demote.query()?, factor)),
+                        _ => return Err(path.index(i).key("factor").error("expected a demotion factor between 0 and 1")),
+                    }
+                }
+            } else if let Some((other, path)) = members.get("demote") {
+                return Err(path.error(format!("expected an array, found {}", other.kind())));
+            }
+            Query::Bool(bool_query)
+        }
+        other => return Err(path.error(format!("unknown query type `{}`", other))),
+    };
+    Ok(query)
+}
+
+impl Query {
+    /// Read a query from its JSON form, see the `dsl` module.
+    pub fn from_json(json: &str) -> Result<Query, DslError> {
+        let mut parser = JsonParser { input: json, pos: 0, depth: 0 };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.pos < json.len() {
+            return Err(parser.error("end of input"));
+        }
+        to_query(&value, &Path(String::new()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Searcher;
+
+    #[test]
+    fn test_from_json() {
+        let json = r#"{"bool": {
+            "must": {"phrase": "borrow checker"},
+            "should": [{"term": "rust"}, {"boost": {"query": {"wildcard": "life*"}, "factor": 2}}],
+            "filter": [{"range": {"field": "year", "gte": 2015, "lt": "2020"}}],
+            "must_not": [{"keyword": {"field": "ext", "value": "mdé"}}],
+            "demote": [{"query": {"query_string": "-x +archive"}, "factor": 0.5}]
+        }}"#;
+        let query = Query::from_json(json).unwrap();
+        let Query::Bool(bool_query) = &query else {
+            panic!("not a bool query: {:?}", query);
+        };
+        assert_eq!(bool_query.must, [Query::Intervals(Intervals::term("borrow checker"))]);
+        assert_eq!(
+            bool_query.should[1],
+            Query::Boost {
+                query: Box::new(Query::Wildcard("life*".to_string())),
+                factor: 2.0
+            }
+        );
+        assert_eq!(
+            bool_query.filter[0],
+            Query::Range {
+                field: "year".to_string(),
+                lower: Bound::Included("2015".to_string()),
+                upper: Bound::Excluded("2020".to_string()),
+            }
+        );
+        assert_eq!(
+            bool_query.must_not[0],
+            Query::Keyword {
+                field: "ext".to_string(),
+                value: "mdé".to_string()
+            }
+        );
+        assert_eq!(bool_query.demote[0].0, Query::parse("-x +archive", ParseMode::Strict).unwrap());
+    }
+
+    #[test]
+    fn test_from_json_errors() {
+        let query_error = |json: &str| match Query::from_json(json) {
+            Err(DslError::Query { path, .. }) => path,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(query_error(r#"{"bool": {"must": [{"term": "a"}, {"range": {"field": "x", "gt": 1, "gte": 2}}]}}"#), "bool.must[1].range");
+        assert_eq!(query_error(r#"{"boost": {"query": {"term": "a"}, "factor": -1}}"#), "boost.factor");
+        assert_eq!(query_error(r#"{"keyword": {"field": "ext"}}"#), "keyword");
+        assert_eq!(query_error(r#"{"geo": "a"}"#), "geo");
+        assert_eq!(query_error(r#"{"fuzzy": {"term": "a", "distance": 3}}"#), "fuzzy.distance");
+        assert_eq!(query_error(r#"{"term": "a", "regex": "b"}"#), "");
+        let err = Query::from_json(r#"{"query_string": "rust)"}"#).unwrap_err();
+        assert_eq!(err.to_string(), "invalid query string: expected a clause or end of input at position 4, found `)` at `query_string`");
+
+        assert!(matches!(Query::from_json(r#"{"term": "a"#), Err(DslError::Json { position: 11, .. })));
+        assert!(matches!(Query::from_json(r#"{"term": "a"} x"#), Err(DslError::Json { position: 14, .. })));
+        assert!(matches!(Query::from_json(&"[".repeat(100_000)), Err(DslError::Json { position: 64, .. })));
+    }
+
+    #[test]
+    fn test_range_and_boost() {
+        let mut searcher = Searcher::new();
+        for (doc_id, year) in [("1", "2009"), ("2", "2015"), ("3", "2021"), ("4", "999")] {
+            searcher.add_document(doc_id, "moon rocket");
+            searcher.add_keyword(doc_id, "year", year);
+        }
+        searcher.add_document("5", "moon");
+
+        let query = Query::from_json(r#"{"range": {"field": "year", "gte": 1000, "lte": "2015"}}"#).unwrap();
+        let mut ids: Vec<String> = searcher.search_query(&query).into_keys().collect();
+        ids.sort();
+        assert_eq!(ids, ["1", "2"]);
+
+        let query = Query::from_json(r#"{"bool": {"should": [{"term": "moon"}, {"boost": {"query": {"term": "rocket"}, "factor": 3}}]}}"#).unwrap();
+        let boosted = searcher.search_query(&query);
+        let rocket = searcher.search_query(&Query::Term("rocket".to_string()))["1"];
+        let moon = searcher.search_query(&Query::Term("moon".to_string()))["1"];
+        assert!((boosted["1"] - (moon + 3.0 * rocket)).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,304 @@
+//! A common interface over search backends, so applications can pick one at runtime.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::backend::{BackendError, Failures};
+use crate::{BuildReport, ConfigError, Explanation, HashMap, IndexBackend, Query, SearchHit, SearchPage, Searcher, TermStats};
+
+/// Error returned by a `SearchEngine` operation.
+#[derive(Debug)]
+pub enum EngineError {
+    /// A configuration value was rejected.
+    Config(ConfigError),
+    /// The backend holding the index failed, e.g. lost its connection to a server.
+    Backend(BackendError),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineError::Config(error) => write!(f, "{}", error),
+            EngineError::Backend(error) => write!(f, "index backend failed: {}", error),
+        }
+    }
+}
+
+impl core::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            EngineError::Config(error) => Some(error),
+            EngineError::Backend(error) => Some(error.as_ref()),
+        }
+    }
+}
+
+impl From<ConfigError> for EngineError {
+    fn from(error: ConfigError) -> Self {
+        EngineError::Config(error)
+    }
+}
+
+impl From<BackendError> for EngineError {
+    fn from(error: BackendError) -> Self {
+        EngineError::Backend(error)
+    }
+}
+
+/// Operations every search backend supports. Backends outside the process can fail at any of them,
+/// so every operation returns their errors.
+pub trait SearchEngine {
+    fn add_document(&mut self, doc_id: &str, content: &str) -> Result<(), EngineError>;
+
+    /// Add many documents, as pairs of doc id and content, in order. Backends that can index in
+    /// parallel override this.
+    fn add_documents(&mut self, docs: Vec<(String, String)>) -> Result<(), EngineError> {
+        for (doc_id, content) in docs {
+            self.add_document(&doc_id, &content)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the content of a document, or add it if it does not exist.
+    fn update_document(&mut self, doc_id: &str, content: &str) -> Result<(), EngineError>;
+
+    /// Remove a document from the index. Returns false if the document does not exist.
+    fn remove_document(&mut self, doc_id: &str) -> Result<bool, EngineError>;
+
+    /// Attach an exact keyword value to a document. Returns false if the document does not exist.
+    fn add_keyword(&mut self, doc_id: &str, field: &str, value: &str) -> Result<bool, EngineError>;
+
+    /// Set the query-independent score multiplier of a document. Returns false if the document does not exist.
+    fn set_static_rank(&mut self, doc_id: &str, rank: f32) -> Result<bool, EngineError>;
+
+    /// Score the documents matching `query`, as doc_id -> score.
+    fn search_query(&self, query: &Query) -> Result<HashMap<String, f32>, EngineError>;
+
+    /// The `limit` best documents matching `query` after the `offset` best, best first, with the
+    /// number of documents matching it. Backends may return fewer, up to a cap of their own.
+    fn search_hits<'a>(&'a self, query: &'a Query, offset: usize, limit: usize) -> Result<SearchPage<'a>, EngineError> {
+        self.search_hits_above(query, f32::NEG_INFINITY, offset, limit)
+    }
+
+    /// Same as `search_hits`, among the documents scoring at least `min_score` only: the others
+    /// neither take a place on the page nor count towards the total.
+    fn search_hits_above<'a>(&'a self, query: &'a Query, min_score: f32, offset: usize, limit: usize) -> Result<SearchPage<'a>, EngineError>;
+
+    /// The passage of a document that best matches `query`, with the matches wrapped in `pre` and
+    /// `post`. None if the document does not exist.
+    fn snippet(&self, doc_id: &str, query: &Query, pre: &str, post: &str) -> Result<Option<String>, EngineError>;
+
+    /// How the score of a document for `query` is made up, term by term. None if the document
+    /// does not exist.
+    fn explain(&self, query: &Query, doc_id: &str) -> Result<Option<Explanation>, EngineError>;
+
+    /// Number of indexed documents.
+    fn doc_count(&self) -> Result<usize, EngineError>;
+
+    /// Statistics of every indexed term, sorted by term.
+    fn term_stats(&self) -> Result<Vec<TermStats>, EngineError>;
+
+    /// What indexing kept and dropped so far.
+    fn build_report(&self) -> Result<BuildReport, EngineError>;
+}
+
+/// An index in this process, with its postings in memory or in any other backend. Every call
+/// collects the errors of the backend for itself, so that threads searching the same index at the
+/// same time each get the errors of their own searches.
+impl<B: IndexBackend> SearchEngine for Searcher<B> {
+    fn add_document(&mut self, doc_id: &str, content: &str) -> Result<(), EngineError> {
+        let failures = Failures::default();
+        self.add_document_in(doc_id, content, &failures);
+        Ok(failures.check(())?)
+    }
+
+    fn add_documents(&mut self, docs: Vec<(String, String)>) -> Result<(), EngineError> {
+        let failures = Failures::default();
+        self.add_batch(docs, &failures);
+        Ok(failures.check(())?)
+    }
+
+    fn update_document(&mut self, doc_id: &str, content: &str) -> Result<(), EngineError> {
+        let failures = Failures::default();
+        self.update_document_in(doc_id, content, &failures);
+        Ok(failures.check(())?)
+    }
+
+    fn remove_document(&mut self, doc_id: &str) -> Result<bool, EngineError> {
+        let failures = Failures::default();
+        let removed = self.remove_document_in(doc_id, &failures);
+        Ok(failures.check(removed)?)
+    }
+
+    fn add_keyword(&mut self, doc_id: &str, field: &str, value: &str) -> Result<bool, EngineError> {
+        let failures = Failures::default();
+        let added = self.add_keyword_in(doc_id, field, value, &failures);
+        Ok(failures.check(added)?)
+    }
+
+    fn set_static_rank(&mut self, doc_id: &str, rank: f32) -> Result<bool, EngineError> {
+        Ok(Searcher::set_static_rank(self, doc_id, rank)?)
+    }
+
+    fn search_query(&self, query: &Query) -> Result<HashMap<String, f32>, EngineError> {
+        let failures = Failures::default();
+        let mut scores = self.search_query_in(query, &failures);
+        self.cap_results(&mut scores);
+        Ok(failures.check(scores)?)
+    }
+
+    fn search_hits_above<'a>(&'a self, query: &'a Query, min_score: f32, offset: usize, limit: usize) -> Result<SearchPage<'a>, EngineError> {
+        let limit = self.max_results.map_or(limit, |max_results| limit.min(max_results));
+        // the cap is on the size of the page, so rank every hit
+        let failures = Failures::default();
+        let scores = self.search_query_in(query, &failures);
+        let mut scores = failures.check(scores)?;
+        scores.retain(|_, score| *score >= min_score);
+        Ok(SearchHit::ranked(self, query, scores, offset, limit))
+    }
+
+    fn snippet(&self, doc_id: &str, query: &Query, pre: &str, post: &str) -> Result<Option<String>, EngineError> {
+        let failures = Failures::default();
+        let snippet = self.snippet_in(doc_id, query, pre, post, &failures);
+        Ok(failures.check(snippet)?)
+    }
+
+    fn explain(&self, query: &Query, doc_id: &str) -> Result<Option<Explanation>, EngineError> {
+        let failures = Failures::default();
+        let explanation = self.explain_in(query, doc_id, &failures);
+        Ok(failures.check(explanation)?)
+    }
+
+    fn doc_count(&self) -> Result<usize, EngineError> {
+        Ok(self.docs.len())
+    }
+
+    fn term_stats(&self) -> Result<Vec<TermStats>, EngineError> {
+        let failures = Failures::default();
+        let stats = self.term_stats_in(&failures);
+        Ok(failures.check(stats)?)
+    }
+
+    fn build_report(&self) -> Result<BuildReport, EngineError> {
+        let failures = Failures::default();
+        let report = self.build_report_in(&failures);
+        Ok(failures.check(report)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::ops::Bound;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{MemoryBackend, ParseMode, Positions, Postings};
+
+    /// Postings in memory, except that reading those of `ocean` fails like a lost connection.
+    #[derive(Default)]
+    struct FlakyBackend {
+        memory: MemoryBackend,
+    }
+
+    impl IndexBackend for FlakyBackend {
+        fn add_postings(&mut self, term: &str, ordinal: u32, doc_id: &Arc<str>, positions: Positions) {
+            self.memory.add_postings(term, ordinal, doc_id, positions)
+        }
+
+        fn remove_postings(&mut self, term: &str, ordinal: u32) {
+            self.memory.remove_postings(term, ordinal)
+        }
+
+        fn remove_term(&mut self, term: &str) -> Result<Option<Postings>, BackendError> {
+            self.memory.remove_term(term)
+        }
+
+        fn postings(&self, term: &str) -> Result<Option<Cow<'_, Postings>>, BackendError> {
+            if term == "ocean" {
+                return Err("connection refused".into());
+            }
+            self.memory.postings(term)
+        }
+
+        fn term_count(&self) -> Result<usize, BackendError> {
+            self.memory.term_count()
+        }
+
+        fn terms_from(&self, start: Bound<&str>) -> Box<dyn Iterator<Item = Result<Cow<'_, str>, BackendError>> + '_> {
+            self.memory.terms_from(start)
+        }
+    }
+
+    #[test]
+    fn test_engine_trait_object() {
+        let mut engine: Box<dyn SearchEngine> = Box::new(Searcher::new());
+        engine.add_document("1", "rust compiler").unwrap();
+        engine.add_document("2", "moon rocket").unwrap();
+        assert!(engine.add_keyword("1", "ext", "rs").unwrap());
+        assert!(!engine.add_keyword("missing", "ext", "rs").unwrap());
+        assert!(matches!(engine.set_static_rank("1", -1.0), Err(EngineError::Config(ConfigError::InvalidBoost(_)))));
+
+        assert_eq!(engine.doc_count().unwrap(), 2);
+        assert_eq!(engine.term_stats().unwrap().len(), 4);
+        let results = engine.search_query(&Query::Term("rust".to_string())).unwrap();
+        assert_eq!(results.keys().collect::<Vec<_>>(), vec!["1"]);
+
+        let mut capped = Searcher::new();
+        for i in 0..5 {
+            capped.add_document(&i.to_string(), "moon");
+        }
+        capped.set_max_results(Some(3));
+        let moon = Query::Term("moon".to_string());
+        assert_eq!(SearchEngine::search_hits(&capped, &moon, 0, usize::MAX).unwrap().hits.len(), 3);
+        assert_eq!(SearchEngine::search_hits(&capped, &moon, 0, 2).unwrap().hits.len(), 2);
+        // the cap is on the size of a page, not on how far pages go
+        let page = SearchEngine::search_hits(&capped, &moon, 4, usize::MAX).unwrap();
+        assert_eq!((page.hits.len(), page.total), (1, 5));
+        capped.set_max_results(None);
+        assert_eq!(SearchEngine::search_hits(&capped, &moon, 0, usize::MAX).unwrap().hits.len(), 5);
+
+        assert!(engine.remove_document("1").unwrap());
+        assert!(!engine.remove_document("1").unwrap());
+        assert_eq!(engine.doc_count().unwrap(), 1);
+        assert!(engine.search_query(&Query::Term("rust".to_string())).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_engine_backend_errors() {
+        let mut engine: Box<dyn SearchEngine> = Box::new(Searcher::with_backend(FlakyBackend::default()));
+        engine.add_document("1", "moon rocket").unwrap();
+        engine.add_document("2", "moon").unwrap();
+
+        // a failed read is an error, not a search that found nothing or too little
+        let query = Query::parse("moon ocean", ParseMode::Strict).unwrap();
+        let error = engine.search_query(&query).unwrap_err();
+        assert!(matches!(error, EngineError::Backend(_)));
+        assert_eq!(error.to_string(), "index backend failed: connection refused");
+        assert!(engine.search_hits(&query, 0, 10).is_err());
+
+        // and is only reported once
+        let moon = Query::parse("moon", ParseMode::Strict).unwrap();
+        assert_eq!(engine.search_query(&moon).unwrap().len(), 2);
+        assert!(engine.remove_document("1").unwrap());
+    }
+
+    #[test]
+    fn test_engine_backend_errors_per_call() {
+        let mut searcher = Searcher::with_backend(FlakyBackend::default());
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("2", "moon ocean");
+        let ocean = Query::parse("ocean", ParseMode::Strict).unwrap();
+        let moon = Query::parse("moon", ParseMode::Strict).unwrap();
+
+        // a thread searching a term that fails doesn't hand its errors to one searching at the same time
+        let engine = &searcher;
+        std::thread::scope(|scope| {
+            let failing = scope.spawn(|| (0..200).all(|_| SearchEngine::search_query(engine, &ocean).is_err()));
+            let healthy = scope.spawn(|| (0..200).all(|_| SearchEngine::search_query(engine, &moon).is_ok_and(|scores| scores.len() == 2)));
+            assert!(failing.join().unwrap());
+            assert!(healthy.join().unwrap());
+        });
+    }
+}
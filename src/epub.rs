@@ -0,0 +1,470 @@
+//! E-books in the EPUB format: the title, authors, language and chapters of a book, read from the
+//! zip archive of its XHTML files, so that a library of books can be searched.
+//!
+//! A book is indexed as one document holding the text of every chapter. Its chapters can also be
+//! indexed as documents of their own, split into chunks of a bounded number of words so that a hit
+//! points at a passage rather than at a whole book; the chunks name their book in the `book`
+//! keyword field.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use crate::html::html_text;
+use crate::xml::{Event, Parser, XmlError};
+use crate::{Document, IndexBackend, Searcher};
+
+/// Name of the keyword field holding the id of the book a document is, or is a chunk of.
+pub const BOOK_FIELD: &str = "book";
+
+/// Where an EPUB archive says its package document is.
+const CONTAINER: &str = "META-INF/container.xml";
+
+/// Files of the archive larger than this once uncompressed aren't read, so that a small archive
+/// can't fill the memory.
+const MAX_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Error returned when an EPUB file can't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EpubError {
+    /// The file can't be read or isn't a zip archive.
+    Archive(String),
+    /// A file the book refers to is missing from the archive.
+    Missing(String),
+    /// A file of the archive can't be understood.
+    Invalid { file: String, message: String },
+}
+
+impl fmt::Display for EpubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EpubError::Archive(message) => write!(f, "invalid EPUB archive: {}", message),
+            EpubError::Missing(file) => write!(f, "EPUB archive without `{}`", file),
+            EpubError::Invalid { file, message } => write!(f, "invalid `{}` in EPUB archive: {}", file, message),
+        }
+    }
+}
+
+impl std::error::Error for EpubError {}
+
+/// The metadata and text of an e-book.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Epub {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub language: Option<String>,
+    pub chapters: Vec<Chapter>, // in reading order
+}
+
+/// A chapter of a book, or any other part of its reading order with text, e.g. a preface.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub title: Option<String>, // its first heading, or else its XHTML title
+    pub text: String,          // one line per block of text
+}
+
+impl Epub {
+    /// Read the EPUB file at `path`.
+    pub fn open(path: &Path) -> Result<Epub, EpubError> {
+        let file = File::open(path).map_err(|error| EpubError::Archive(error.to_string()))?;
+        Epub::read(file)
+    }
+
+    /// Read an EPUB file. Parts of the reading order that aren't XHTML or have no text, like a
+    /// cover image, aren't chapters.
+    pub fn read<R: Read + Seek>(reader: R) -> Result<Epub, EpubError> {
+        let mut archive = ZipArchive::new(reader).map_err(|error| EpubError::Archive(error.to_string()))?;
+        let container = read_file(&mut archive, CONTAINER)?;
+        let package_path = rootfile(&container).ok_or_else(|| EpubError::Invalid {
+            file: CONTAINER.to_string(),
+            message: "no rootfile".to_string(),
+        })?;
+        let package = read_file(&mut archive, &package_path)?;
+        let package = Package::parse(&package).map_err(|error| EpubError::Invalid { file: package_path.clone(), message: error.to_string() })?;
+
+        // hrefs are relative to the package document
+        let base = package_path.rsplit_once('/').map_or("", |(directory, _)| directory);
+        let mut chapters = Vec::new();
+        for idref in &package.spine {
+            let Some((href, media_type)) = package.manifest.get(idref) else {
+                continue;
+            };
+            if media_type != "application/xhtml+xml" {
+                continue;
+            }
+            let xhtml = read_file(&mut archive, &resolve(base, href))?;
+            let text = html_text(&xhtml);
+            if !text.is_empty() {
+                chapters.push(Chapter { title: heading(&xhtml), text });
+            }
+        }
+        Ok(Epub {
+            title: package.title,
+            authors: package.authors,
+            language: package.language,
+            chapters,
+        })
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index a book as document `id`, with text fields `title`, `author` and `text`, the text of
+    /// all chapters, and keyword values `book`, its id, and `language`. With `chunk_words`, every
+    /// chapter is also indexed in documents `id#chapter.chunk`, counted from 1, of at most that many
+    /// words, with text fields `chapter`, the chapter title, and `text`, and the same keyword
+    /// values; `book:id` then finds a book and its chunks. Adding a book again replaces it and its
+    /// chunks. Returns the number of documents indexed.
+    pub fn add_epub(&mut self, id: &str, book: &Epub, chunk_words: Option<usize>) -> usize {
+        let chunk_ids: Vec<String> = self
+            .keywords
+            .get(BOOK_FIELD)
+            .and_then(|books| books.get(id))
+            .into_iter()
+            .flatten()
+            .filter(|doc_id| doc_id.as_ref() != id)
+            .map(|doc_id| doc_id.to_string())
+            .collect();
+        for chunk_id in chunk_ids {
+            self.remove_document(&chunk_id);
+        }
+
+        let mut document = Document::new(id);
+        if let Some(title) = &book.title {
+            document = document.field("title", title);
+        }
+        for author in &book.authors {
+            document = document.field("author", author);
+        }
+        for chapter in &book.chapters {
+            document = document.field("text", &chapter.text);
+        }
+        self.add_fields(&document);
+        self.add_book_keywords(id, id, book);
+
+        let mut indexed = 1;
+        let Some(chunk_words) = chunk_words.filter(|&words| words > 0) else {
+            return indexed;
+        };
+        for (i, chapter) in book.chapters.iter().enumerate() {
+            for (j, chunk) in chunks(&chapter.text, chunk_words).into_iter().enumerate() {
+                let chunk_id = format!("{}#{}.{}", id, i + 1, j + 1);
+                let mut document = Document::new(&chunk_id);
+                if let Some(title) = &chapter.title {
+                    document = document.field("chapter", title);
+                }
+                self.add_fields(&document.field("text", chunk));
+                self.add_book_keywords(&chunk_id, id, book);
+                indexed += 1;
+            }
+        }
+        indexed
+    }
+
+    fn add_book_keywords(&mut self, doc_id: &str, book_id: &str, book: &Epub) {
+        self.add_keyword(doc_id, BOOK_FIELD, book_id);
+        if let Some(language) = &book.language {
+            self.add_keyword(doc_id, "language", language);
+        }
+    }
+}
+
+/// The package document of a book: its metadata, files and reading order.
+#[derive(Default)]
+struct Package {
+    title: Option<String>,
+    authors: Vec<String>,
+    language: Option<String>,
+    manifest: HashMap<String, (String, String)>, // item id -> (href, media type)
+    spine: Vec<String>,                           // item ids in reading order
+}
+
+impl Package {
+    fn parse(opf: &str) -> Result<Package, XmlError> {
+        let mut package = Package::default();
+        let mut parser = Parser::new(opf);
+        let mut reading: Option<(&str, String)> = None; // metadata element being read, and its text
+        while let Some(event) = parser.next()? {
+            match event {
+                Event::Start { name, attributes, empty } => {
+                    let attribute = |key: &str| attributes.iter().find(|(name, _)| *name == key).map(|(_, value)| value.clone());
+                    match local_name(name) {
+                        element @ ("title" | "creator" | "language") if !empty => reading = Some((element, String::new())),
+                        "item" => {
+                            if let (Some(id), Some(href)) = (attribute("id"), attribute("href")) {
+                                package.manifest.insert(id, (href, attribute("media-type").unwrap_or_default()));
+                            }
+                        }
+                        "itemref" => package.spine.extend(attribute("idref")),
+                        _ => {}
+                    }
+                }
+                Event::Text(text) => {
+                    if let Some((_, value)) = &mut reading {
+                        value.push_str(&text);
+                    }
+                }
+                Event::End(name) => {
+                    let Some((element, value)) = reading.take_if(|(element, _)| *element == local_name(name)) else {
+                        continue;
+                    };
+                    let value = collapse_whitespace(&value);
+                    match element {
+                        _ if value.is_empty() => {}
+                        "title" => {
+                            package.title.get_or_insert(value);
+                        }
+                        "creator" => package.authors.push(value),
+                        _ => {
+                            package.language.get_or_insert(value);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(package)
+    }
+}
+
+/// Read a file of the archive as text.
+fn read_file<R: Read + Seek>(archive: &mut ZipArchive<R>, path: &str) -> Result<String, EpubError> {
+    let file = match archive.by_name(path) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Err(EpubError::Missing(path.to_string())),
+        Err(error) => return Err(EpubError::Archive(error.to_string())),
+    };
+    let mut bytes = Vec::new();
+    file.take(MAX_FILE_SIZE + 1).read_to_end(&mut bytes).map_err(|error| EpubError::Archive(error.to_string()))?;
+    if bytes.len() as u64 > MAX_FILE_SIZE {
+        return Err(EpubError::Invalid { file: path.to_string(), message: "too large".to_string() });
+    }
+    String::from_utf8(bytes).map_err(|_| EpubError::Invalid { file: path.to_string(), message: "not UTF-8".to_string() })
+}
+
+/// The path of the package document, as the container gives it.
+fn rootfile(container: &str) -> Option<String> {
+    let mut parser = Parser::new(container);
+    while let Ok(Some(event)) = parser.next() {
+        if let Event::Start { name, attributes, .. } = event {
+            if local_name(name) == "rootfile" {
+                return attributes.into_iter().find(|(name, _)| *name == "full-path").map(|(_, path)| path);
+            }
+        }
+    }
+    None
+}
+
+/// The first heading of an XHTML document, or else its title.
+fn heading(xhtml: &str) -> Option<String> {
+    let mut parser = Parser::new(xhtml);
+    let mut title = None;
+    let mut reading: Option<(&str, String)> = None; // element being read, and its text
+    // past a syntax error, a document has no more headings
+    while let Ok(Some(event)) = parser.next() {
+        match event {
+            Event::Start { name, empty: false, .. }
+                if reading.is_none() && matches!(local_name(name), "title" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6") =>
+            {
+                reading = Some((name, String::new()));
+            }
+            Event::Start { .. } => {
+                // e.g. a `<br/>` in a heading separates words
+                if let Some((_, value)) = &mut reading {
+                    value.push(' ');
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, value)) = &mut reading {
+                    value.push_str(&text);
+                }
+            }
+            Event::End(name) => {
+                let Some((element, value)) = reading.take_if(|(element, _)| *element == name) else {
+                    continue;
+                };
+                let value = collapse_whitespace(&value);
+                match local_name(element) {
+                    _ if value.is_empty() => {}
+                    "title" => {
+                        title.get_or_insert(value);
+                    }
+                    _ => return Some(value),
+                }
+            }
+        }
+    }
+    title
+}
+
+/// `name` without its namespace prefix, e.g. `title` for `dc:title`.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// The path in the archive of `href`, a URL relative to the directory `base`.
+fn resolve(base: &str, href: &str) -> String {
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    let mut parts: Vec<&str> = base.split('/').filter(|part| !part.is_empty()).collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    percent_decode(&parts.join("/"))
+}
+
+/// Decode the `%XX` escapes of a URL path.
+fn percent_decode(path: &str) -> String {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        let escaped = after.get(..2).and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &after[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = after;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// `text` split into pieces of at most `words` words.
+fn chunks(text: &str, words: usize) -> Vec<&str> {
+    let mut word_starts = Vec::new();
+    let mut in_word = false;
+    for (i, c) in text.char_indices() {
+        if !c.is_whitespace() && !in_word {
+            word_starts.push(i);
+        }
+        in_word = !c.is_whitespace();
+    }
+    let bounds: Vec<usize> = word_starts.into_iter().step_by(words).chain([text.len()]).collect();
+    bounds.windows(2).map(|bounds| text[bounds[0]..bounds[1]].trim_end()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    const PACKAGE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:isbn:0000000000</dc:identifier>
+    <dc:title>Tides &amp; Rockets</dc:title>
+    <dc:creator>Ada Moon</dc:creator>
+    <dc:creator>Sam Ocean</dc:creator>
+    <dc:language>en</dc:language>
+  </metadata>
+  <manifest>
+    <item id="cover" href="images/cover.png" media-type="image/png"/>
+    <item id="ch1" href="text/chapter%201.xhtml" media-type="application/xhtml+xml"/>
+    <item id="ch2" href="text/chapter2.xhtml#start" media-type="application/xhtml+xml"/>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+  </manifest>
+  <spine><itemref idref="cover"/><itemref idref="ch1"/><itemref idref="ch2"/></spine>
+</package>"#;
+
+    fn epub(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, contents) in files {
+            let method = if *path == "mimetype" { CompressionMethod::Stored } else { CompressionMethod::Deflated };
+            zip.start_file(*path, SimpleFileOptions::default().compression_method(method)).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn book() -> Vec<u8> {
+        epub(&[
+            ("mimetype", "application/epub+zip"),
+            (
+                CONTAINER,
+                r#"<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles>
+</container>"#,
+            ),
+            ("OEBPS/content.opf", PACKAGE),
+            ("OEBPS/images/cover.png", "not text"),
+            (
+                "OEBPS/text/chapter 1.xhtml",
+                "<html><head><title>One</title></head><body><h1>The <em>Moon</em><br/>rising</h1><p>The moon pulls the ocean.</p></body></html>",
+            ),
+            (
+                "OEBPS/text/chapter2.xhtml",
+                "<html><head><title>Two</title></head><body><p>A rocket launch at high tide, then a second launch.</p></body></html>",
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_read_epub() {
+        let book = Epub::read(Cursor::new(book())).unwrap();
+        assert_eq!(book.title.as_deref(), Some("Tides & Rockets"));
+        assert_eq!(book.authors, ["Ada Moon", "Sam Ocean"]);
+        assert_eq!(book.language.as_deref(), Some("en"));
+        let titles: Vec<Option<&str>> = book.chapters.iter().map(|chapter| chapter.title.as_deref()).collect();
+        assert_eq!(titles, [Some("The Moon rising"), Some("Two")]);
+        assert_eq!(book.chapters[0].text, "One\nThe Moon\nrising\nThe moon pulls the ocean.");
+
+        let missing_chapter = epub(&[(CONTAINER, "<container><rootfiles><rootfile full-path=\"content.opf\"/></rootfiles></container>"), ("content.opf", PACKAGE)]);
+        assert_eq!(Epub::read(Cursor::new(missing_chapter)), Err(EpubError::Missing("text/chapter 1.xhtml".to_string())));
+        assert!(matches!(Epub::read(Cursor::new(epub(&[(CONTAINER, "<container/>")]))), Err(EpubError::Invalid { .. })));
+        assert!(matches!(Epub::read(Cursor::new(b"not a zip".to_vec())), Err(EpubError::Archive(_))));
+    }
+
+    #[test]
+    fn test_add_epub() {
+        let book = Epub::read(Cursor::new(book())).unwrap();
+        let mut searcher = Searcher::new();
+        let search = |searcher: &Searcher, query: &str| {
+            let mut ids: Vec<String> = searcher.search_query(&Query::parse(query, ParseMode::Strict).unwrap()).into_keys().collect();
+            ids.sort();
+            ids
+        };
+
+        assert_eq!(searcher.add_epub("tides", &book, None), 1);
+        assert_eq!(search(&searcher, "author:ada"), ["tides"]);
+        assert_eq!(search(&searcher, "launch"), ["tides"]);
+
+        assert_eq!(searcher.add_epub("tides", &book, Some(6)), 5);
+        assert_eq!(search(&searcher, "launch"), ["tides", "tides#2.1", "tides#2.2"]);
+        assert_eq!(search(&searcher, "chapter:moon"), ["tides#1.1", "tides#1.2"]);
+        assert_eq!(search(&searcher, "book:tides").len(), 5);
+        assert_eq!(search(&searcher, "language:en").len(), 5);
+
+        // chunks of an earlier version of the book don't linger
+        assert_eq!(searcher.add_epub("tides", &book, Some(100)), 3);
+        assert_eq!(search(&searcher, "launch"), ["tides", "tides#2.1"]);
+    }
+
+    #[test]
+    fn test_chunks() {
+        assert_eq!(chunks(" a b\nc  d e ", 2), ["a b", "c  d", "e"]);
+        assert!(chunks("  ", 2).is_empty());
+        assert_eq!(resolve("OEBPS/text", "../images/a%20b.png#top"), "OEBPS/images/a b.png");
+        assert_eq!(resolve("", "./chapter.xhtml"), "chapter.xhtml");
+    }
+}
@@ -0,0 +1,101 @@
+//! Index change notifications behind a small hook trait, so that downstream systems, e.g. a cache
+//! in front of the index or a replica, can react to changes without polling the Searcher.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::{IndexBackend, Searcher};
+
+/// A change to the index, passed to the listener once it has been applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndexEvent<'a> {
+    /// A document was indexed for the first time.
+    Added { doc_id: &'a str },
+    /// The content of an existing document was replaced.
+    Updated { doc_id: &'a str },
+    /// A document was removed.
+    Removed { doc_id: &'a str },
+    /// The index was written to disk by `save`.
+    #[cfg(feature = "std")]
+    Committed { path: &'a Path },
+}
+
+/// Receives every change applied to a Searcher. Methods take `&self` so a listener can be shared
+/// between Searchers and threads; a listener is called while the Searcher is borrowed, so slow
+/// work such as a webhook call is better handed off to another thread.
+pub trait IndexListener: Send + Sync {
+    fn on_event(&self, event: &IndexEvent);
+}
+
+/// Several listeners at once, each receiving every event.
+impl IndexListener for Vec<Arc<dyn IndexListener>> {
+    fn on_event(&self, event: &IndexEvent) {
+        for listener in self {
+            listener.on_event(event);
+        }
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Set the listener notified of every change to the index, or None to stop notifying.
+    pub fn set_listener(&mut self, listener: Option<Arc<dyn IndexListener>>) {
+        self.listener = listener;
+    }
+
+    pub(crate) fn notify(&self, event: IndexEvent) {
+        if let Some(listener) = &self.listener {
+            listener.on_event(&event);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl IndexListener for Recorder {
+        fn on_event(&self, event: &IndexEvent) {
+            self.0.lock().unwrap().push(format!("{:?}", event));
+        }
+    }
+
+    #[test]
+    fn test_listener() {
+        let recorder = Arc::new(Recorder::default());
+        let mut searcher = Searcher::new();
+        searcher.set_listener(Some(Arc::new(vec![recorder.clone() as Arc<dyn IndexListener>])));
+
+        searcher.add_document("1", "moon rocket");
+        searcher.update_document("1", "ocean tide");
+        searcher.update_document("2", "moon");
+        searcher.remove_document("2");
+        searcher.remove_document("3");
+        let path = std::env::temp_dir().join(format!("searcher-events-{}.idx", std::process::id()));
+        searcher.save(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let events = recorder.0.lock().unwrap().clone();
+        assert_eq!(
+            events[..4],
+            [
+                "Added { doc_id: \"1\" }",
+                "Updated { doc_id: \"1\" }",
+                "Added { doc_id: \"2\" }",
+                "Removed { doc_id: \"2\" }"
+            ]
+        );
+        assert_eq!(events[4], format!("Committed {{ path: {:?} }}", path));
+        assert_eq!(events.len(), 5);
+
+        searcher.set_listener(None);
+        searcher.add_document("4", "tent");
+        assert_eq!(recorder.0.lock().unwrap().len(), 5);
+    }
+}
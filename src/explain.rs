@@ -0,0 +1,138 @@
+//! Why a document scores what it does for a query: the statistics behind the score of each of its
+//! terms, to find out why one document outranks another.
+
+use alloc::collections::BTreeSet;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::backend::Failures;
+use crate::highlight::Wanted;
+use crate::{IndexBackend, Query, Searcher};
+
+/// How the score of a document for a query is made up, see `Searcher::explain`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub doc_id: String,
+    pub score: f32,                  // what the query scores the document, 0 if it doesn't match
+    pub static_rank: f32,            // multiplier of the document's scores, included in `score`
+    pub length: usize,               // number of terms of the document
+    pub average_length: f32,         // over the index
+    pub terms: Vec<TermExplanation>, // highest contribution first
+}
+
+/// The part a term of the query plays in the score of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermExplanation {
+    pub term: String,
+    pub tf: usize,         // occurrences in the document
+    pub df: usize,         // documents containing the term
+    pub idf: f32,
+    pub length_norm: f32,  // `(1 - b) + b * length / average_length`, above 1 for longer documents than average
+    pub contribution: f32, // `idf` times the frequency part of the scorer, 0 if the document lacks the term
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Break down the score of a document for `query` by term: how often the term occurs in the
+    /// document and in the index, and how much it adds to the score. Keyword, path and field
+    /// matches, boosts and the static rank are in `score` but not in `terms`, so that the two can
+    /// differ. Returns None if the document does not exist.
+    pub fn explain(&self, query: &Query, doc_id: &str) -> Option<Explanation> {
+        self.explain_in(query, doc_id, &Failures::default())
+    }
+
+    /// Same as `explain`, keeping the errors of the backend in `failures`.
+    pub(crate) fn explain_in(&self, query: &Query, doc_id: &str, failures: &Failures) -> Option<Explanation> {
+        let doc = self.docs.get(doc_id)?;
+        let mut wanted = Wanted::default();
+        self.collect_wanted(query, &mut wanted, failures);
+
+        let average_length = self.collection.avdl();
+        let length_norm = (1.0 - self.b) + self.b * doc.nterms as f32 / average_length;
+        let mut terms: Vec<TermExplanation> = wanted
+            .terms
+            .into_iter()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(|term| {
+                let postings = failures.ok(self.index.postings(&term));
+                let tf = postings.as_ref().and_then(|postings| postings.get(doc.ordinal)).map_or(0, |positions| positions.len());
+                let idf = self.idf(&term, failures);
+                let contribution = match tf {
+                    0 => 0.0,
+                    tf => idf * self.tf_norm(tf as f32, doc_id, self.k1, self.b),
+                };
+                TermExplanation { df: postings.map_or(0, |postings| postings.len()), term, tf, idf, length_norm, contribution }
+            })
+            .collect();
+        terms.sort_by(|a, b| b.contribution.total_cmp(&a.contribution));
+
+        Some(Explanation {
+            doc_id: doc_id.to_string(),
+            score: self.search_query_in(query, failures).get(doc_id).copied().unwrap_or(0.0),
+            static_rank: doc.static_rank,
+            length: doc.nterms.max(0) as usize,
+            average_length,
+            terms,
+        })
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: score {}, static rank {}, {} terms (average {:.1})",
+            self.doc_id, self.score, self.static_rank, self.length, self.average_length
+        )?;
+        for term in &self.terms {
+            writeln!(
+                f,
+                "  {}: tf {}, df {}, idf {:.3}, length norm {:.3} -> {:.3}",
+                term.term, term.tf, term.df, term.idf, term.length_norm, term.contribution
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseMode;
+
+    #[test]
+    fn test_explain() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("short", "moon rocket");
+        searcher.add_document("long", "moon moon ocean tide launch pad crew fuel");
+        searcher.add_document("other", "ocean tide");
+        let query = Query::parse("moon rocket", ParseMode::Strict).unwrap();
+
+        let short = searcher.explain(&query, "short").unwrap();
+        let scores = searcher.search_query(&query);
+        assert_eq!(short.score, scores["short"]);
+        assert_eq!((short.length, short.average_length), (2, 4.0));
+        let contributions: f32 = short.terms.iter().map(|term| term.contribution).sum();
+        assert!((contributions - short.score).abs() < 1e-6);
+        // the rarer term adds more
+        assert_eq!(short.terms[0].term, "rocket");
+        assert_eq!((short.terms[0].tf, short.terms[0].df), (1, 1));
+        assert!(short.terms[0].idf > short.terms[1].idf);
+        assert_eq!(short.terms[0].length_norm, 0.25 + 0.75 * 0.5);
+
+        // why short outranks long: rocket is missing and long is longer than average
+        let long = searcher.explain(&query, "long").unwrap();
+        assert!(long.score < short.score);
+        let moon = long.terms.iter().find(|term| term.term == "moon").unwrap();
+        assert_eq!((moon.tf, moon.df), (2, 2));
+        assert!(moon.length_norm > 1.0);
+        let rocket = long.terms.iter().find(|term| term.term == "rocket").unwrap();
+        assert_eq!((rocket.tf, rocket.contribution), (0, 0.0));
+        assert!(long.to_string().contains("moon: tf 2, df 2"));
+
+        let other = searcher.explain(&query, "other").unwrap();
+        assert_eq!(other.score, 0.0);
+        assert!(searcher.explain(&query, "missing").is_none());
+    }
+}
@@ -0,0 +1,410 @@
+//! Structured documents: named text fields such as a title, a body and tags, searched together or
+//! one at a time with `field:text`.
+//!
+//! Each field is indexed on its own, like nested documents are, so it has its own postings and
+//! length statistics and a `title:rust` query scores BM25 against titles only. The fields are also
+//! indexed together as the document content, which is what unqualified queries search.
+//!
+//! A field can be boosted so that matches in it count more, e.g. to rank documents matching in
+//! their title above those matching only in their body. With `FieldScoring::Bm25f`, text queries
+//! score the fields together instead, weighted by their boosts, rather than adding a score per
+//! field to that of the content.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::analyzer::Token;
+use crate::backend::Failures;
+use crate::{validate_boost, ConfigError, HashMap, IndexBackend, Intervals, Query, Searcher, StoredDoc};
+
+/// How text queries score documents with text fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldScoring {
+    /// BM25 of the content, plus BM25 of each boosted field times its boost.
+    #[default]
+    Sum,
+    /// BM25F: the frequencies of a term in the fields, each normalized by the length of its field
+    /// and weighted by its boost (1 for fields without one), add up to a single frequency that is
+    /// saturated and scored once. A term repeated in a long description then can't outweigh it
+    /// appearing once in a short title, nor score as two matches when in both.
+    Bm25f,
+}
+
+/// A document with named text fields, e.g.
+/// `Document::new("post-1").field("title", "Rust rockets").field("tags", "rust")`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    id: String,
+    fields: Vec<(String, String)>, // (field, value) in the order added
+}
+
+impl Document {
+    pub fn new(id: &str) -> Document {
+        Document { id: id.to_string(), fields: Vec::new() }
+    }
+
+    /// Add a value to a field. A field given several values, e.g. tags, holds all of them; phrases
+    /// don't match across two values.
+    pub fn field(mut self, field: &str, value: &str) -> Self {
+        self.fields.push((field.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The values of `field`, in the order added.
+    pub fn values<'a>(&'a self, field: &'a str) -> impl Iterator<Item = &'a str> {
+        self.fields.iter().filter(move |(name, _)| name == field).map(|(_, value)| value.as_str())
+    }
+
+    /// The names of the fields, in the order they were first added.
+    pub fn field_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        for (name, _) in &self.fields {
+            if !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index a structured document: each field on its own, and all of them together, one per line,
+    /// as the document content. Replaces any document with the same id, like `add_document`.
+    pub fn add_fields(&mut self, document: &Document) {
+        let names = document.field_names();
+        let content: Vec<&str> = names.iter().flat_map(|name| document.values(name)).collect();
+        self.add_document(&document.id, &content.join("\n"));
+
+        for name in names {
+            if !self.fields.contains_key(name) {
+                let field = self.child_searcher();
+                self.fields.insert(name.to_string(), field);
+            }
+            let field = self.fields.get_mut(name).expect("inserted above");
+            let (text, tokens) = field_tokens(field, document.values(name));
+            field.insert_tokens(&document.id, &text, tokens, &Failures::default());
+        }
+        self.generation += 1;
+    }
+
+    /// The names of the text fields of the index, sorted.
+    pub fn text_fields(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.fields.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Weigh matches in `field` `boost` times. Text queries then also search the field, adding its
+    /// BM25 score times `boost` to the score of the content (or weighing its term frequencies with
+    /// `FieldScoring::Bm25f`), and `field:text` queries on it are multiplied by `boost`. 0 removes
+    /// the boost.
+    pub fn set_field_boost(&mut self, field: &str, boost: f32) -> Result<(), ConfigError> {
+        let boost = validate_boost(boost)?;
+        if boost == 0.0 {
+            self.field_boosts.remove(field);
+        } else {
+            self.field_boosts.insert(field.to_string(), boost);
+        }
+        self.generation += 1;
+        Ok(())
+    }
+
+    /// Choose how text queries score documents with text fields, see `FieldScoring`.
+    pub fn set_field_scoring(&mut self, scoring: FieldScoring) {
+        self.field_scoring = scoring;
+        self.generation += 1;
+    }
+
+    /// The field boosts, with `weights` in place of those of the same fields as
+    /// `SearchOptions::field_weights` gives them for one search.
+    pub(crate) fn field_boosts_with(&self, weights: &HashMap<String, f32>) -> Result<Cow<'_, HashMap<String, f32>>, ConfigError> {
+        if weights.is_empty() {
+            return Ok(Cow::Borrowed(&self.field_boosts));
+        }
+        let mut boosts = self.field_boosts.clone();
+        for (field, weight) in weights {
+            let weight = validate_boost(*weight)?;
+            if weight == 0.0 {
+                boosts.remove(field);
+            } else {
+                boosts.insert(field.clone(), weight);
+            }
+        }
+        Ok(Cow::Owned(boosts))
+    }
+
+    /// Score the documents whose `field` contains `text`, as a phrase if it analyzes to several
+    /// terms, times the boost of the field in `boosts`. None if `field` isn't a text field or
+    /// `text` has no searchable terms.
+    pub(crate) fn field_matches(
+        &self,
+        field: &str,
+        text: &str,
+        k1: f32,
+        b: f32,
+        boosts: &HashMap<String, f32>,
+        failures: &Failures,
+    ) -> Option<HashMap<String, f32>> {
+        let field_searcher = self.fields.get(field)?;
+        let query = Query::Intervals(Intervals::term(text));
+        let mut scores = field_searcher.evaluate(&query, k1, b, &field_searcher.field_boosts, None, failures)?;
+        if let Some(boost) = boosts.get(field) {
+            scores.values_mut().for_each(|score| *score *= boost);
+        }
+        Some(scores)
+    }
+
+    /// Scores of the documents whose fields boosted in `boosts` contain terms of `text`, times the
+    /// boosts.
+    pub(crate) fn boosted_field_scores(&self, text: &str, k1: f32, b: f32, boosts: &HashMap<String, f32>, failures: &Failures) -> HashMap<String, f32> {
+        let mut scores = HashMap::new();
+        for (name, boost) in boosts {
+            let Some(field) = self.fields.get(name) else {
+                continue;
+            };
+            let query = Query::Term(text.to_string());
+            for (doc_id, score) in field.evaluate(&query, k1, b, &field.field_boosts, None, failures).unwrap_or_default() {
+                *scores.entry(doc_id).or_insert(0.0) += score * boost;
+            }
+        }
+        scores
+    }
+
+    /// BM25F scores of the documents containing `terms`, with the fields weighted by `boosts`,
+    /// repeated terms scoring as often as they are repeated. Documents without text fields are
+    /// scored as if their content was their only field.
+    pub(crate) fn bm25f_scores<'t>(
+        &self,
+        terms: impl IntoIterator<Item = &'t str>,
+        k1: f32,
+        b: f32,
+        boosts: &HashMap<String, f32>,
+        failures: &Failures,
+    ) -> HashMap<String, f32> {
+        let mut counts: Vec<(&str, f32)> = Vec::new();
+        for term in terms {
+            match counts.iter_mut().find(|(t, _)| *t == term) {
+                Some((_, count)) => *count += 1.0,
+                None => counts.push((term, 1.0)),
+            }
+        }
+
+        let mut scores = HashMap::new();
+        for (term, count) in counts {
+            let Some(postings) = failures.ok(self.index.postings(term)) else {
+                continue;
+            };
+            // the content holds every field, so its documents are those with the term in any field
+            let idf = self.idf(term, failures);
+            for posting in postings.iter() {
+                let mut tf = 0.0;
+                let mut has_fields = false;
+                for (name, field) in &self.fields {
+                    let Some(doc) = field.docs.get(posting.doc_id.as_ref()) else {
+                        continue;
+                    };
+                    has_fields = true;
+                    let field_tf = failures.ok(field.index.postings(term)).and_then(|postings| Some(postings.get(doc.ordinal)?.len()));
+                    if let Some(field_tf) = field_tf {
+                        let weight = boosts.get(name).copied().unwrap_or(1.0);
+                        tf += weight * field_tf as f32 / field.length_norm(doc, b);
+                    }
+                }
+                if !has_fields {
+                    tf = posting.positions.len() as f32 / self.length_norm(&self.docs[posting.doc_id.as_ref()], b);
+                }
+                if tf > 0.0 {
+                    *scores.entry(posting.doc_id.to_string()).or_insert(0.0) += count * idf * tf * (k1 + 1.0) / (k1 + tf);
+                }
+            }
+        }
+        scores
+    }
+
+    /// Length of `doc` relative to the average of the index, as BM25 weighs it with `b`.
+    fn length_norm(&self, doc: &StoredDoc, b: f32) -> f32 {
+        (1.0 - b) + b * (doc.nterms as f32 / self.collection.avdl())
+    }
+}
+
+/// The values of a field joined one per line, with their tokens. A position is left free between
+/// two values so that phrases can't span them.
+fn field_tokens<'a>(field: &mut Searcher, values: impl Iterator<Item = &'a str>) -> (String, Vec<Token<'a>>) {
+    let mut text = String::new();
+    let mut tokens = Vec::new();
+    let mut next_position = 0;
+    for value in values {
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        let start = text.len();
+        text.push_str(value);
+        for mut token in field.analyze_document(value) {
+            token.position += next_position;
+            token.span = token.span.start + start..token.span.end + start;
+            tokens.push(token);
+        }
+        if let Some(last) = tokens.last() {
+            next_position = last.position + 2;
+        }
+    }
+    (text, tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, SearchOptions};
+
+    fn posts() -> Searcher {
+        let mut searcher = Searcher::new();
+        let post = Document::new("1")
+            .field("title", "Moon rocket")
+            .field("body", "The launch was delayed by the tide.")
+            .field("tags", "space")
+            .field("tags", "rocket");
+        searcher.add_fields(&post);
+        let post = Document::new("2").field("title", "Ocean tide charts").field("body", "The moon pulls the ocean, rocket or not.");
+        searcher.add_fields(&post);
+        searcher.add_fields(&Document::new("3").field("title", "Borrow checker").field("body", "rocket"));
+        searcher
+    }
+
+    #[test]
+    fn test_field_query() {
+        let searcher = posts();
+        let search = |query| {
+            let query = Query::parse(query, ParseMode::Strict).unwrap();
+            let mut ids: Vec<String> = searcher.search_query(&query).into_keys().collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(search("rocket"), ["1", "2", "3"]);
+        assert_eq!(search("title:rocket"), ["1"]);
+        assert_eq!(search("body:rocket"), ["2", "3"]);
+        assert_eq!(search("title:tide"), ["2"]);
+        assert_eq!(search("title:\"tide charts\""), ["2"]);
+        assert!(search("title:\"charts tide\"").is_empty());
+        assert!(search("missing:rocket").is_empty());
+        // values of a field are separate: no phrase across them
+        assert_eq!(search("tags:\"space\""), ["1"]);
+        assert!(search("tags:\"space rocket\"").is_empty());
+        assert_eq!(searcher.text_fields(), ["body", "tags", "title"]);
+    }
+
+    #[test]
+    fn test_field_statistics() {
+        let searcher = posts();
+        let title = &searcher.fields["title"];
+        assert_eq!(title.collection.documents, 3);
+        assert_eq!(title.collection.terms, 2 + 3 + 2);
+
+        // BM25 within the body only: the short body of 3 beats the long one of 2
+        let scores = searcher.field_matches("body", "rocket", searcher.k1, searcher.b, &searcher.field_boosts, &Failures::default()).unwrap();
+        assert!(scores["3"] > scores["2"]);
+        let idf = title.idf_for_count(1);
+        let expected = idf * title.tf_norm(1.0, "1", searcher.k1, searcher.b);
+        assert_eq!(searcher.field_matches("title", "rocket", searcher.k1, searcher.b, &searcher.field_boosts, &Failures::default()).unwrap()["1"], expected);
+
+        // removing or replacing the document removes its fields
+        let mut searcher = posts();
+        searcher.remove_document("1");
+        assert_eq!(searcher.fields["title"].collection.documents, 2);
+        searcher.add_fields(&Document::new("2").field("title", "Moon"));
+        assert!(searcher.field_matches("body", "rocket", searcher.k1, searcher.b, &searcher.field_boosts, &Failures::default()).unwrap().keys().eq(["3"]));
+
+        let loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        let moon = |searcher: &Searcher| searcher.field_matches("title", "moon", searcher.k1, searcher.b, &searcher.field_boosts, &Failures::default());
+        assert_eq!(moon(&loaded), moon(&searcher));
+    }
+
+    #[test]
+    fn test_field_boost() {
+        let mut searcher = Searcher::new();
+        let long_body = "Notes on the launch schedule, the weather, the crew and the fuel.";
+        searcher.add_fields(&Document::new("title-match").field("title", "Moon").field("body", long_body));
+        searcher.add_fields(&Document::new("body-match").field("title", "Launch notes").field("body", "The moon."));
+        let ranking = |searcher: &Searcher| {
+            let mut hits: Vec<(String, f32)> = searcher.search_query(&Query::Term("moon".to_string())).into_iter().collect();
+            hits.sort_by(|a, b| b.1.total_cmp(&a.1));
+            hits.into_iter().map(|(doc_id, _)| doc_id).collect::<Vec<_>>()
+        };
+        // the shorter document wins on content alone
+        assert_eq!(ranking(&searcher), ["body-match", "title-match"]);
+
+        let title_only = searcher.search_query(&Query::parse("title:moon", ParseMode::Strict).unwrap())["title-match"];
+        searcher.set_field_boost("title", 3.0).unwrap();
+        assert_eq!(ranking(&searcher), ["title-match", "body-match"]);
+        assert_eq!(searcher.search_query(&Query::parse("title:moon", ParseMode::Strict).unwrap())["title-match"], title_only * 3.0);
+        let loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        assert_eq!(ranking(&loaded), ["title-match", "body-match"]);
+
+        assert_eq!(searcher.set_field_boost("title", f32::NAN).map_err(|_| ()), Err(()));
+        searcher.set_field_boost("title", 0.0).unwrap();
+        assert_eq!(ranking(&searcher), ["body-match", "title-match"]);
+    }
+
+    #[test]
+    fn test_field_weights() {
+        let mut searcher = Searcher::new();
+        let long_body = "Notes on the launch schedule, the weather, the crew and the fuel.";
+        searcher.add_fields(&Document::new("title-match").field("title", "Moon").field("body", long_body));
+        searcher.add_fields(&Document::new("body-match").field("title", "Launch notes").field("body", "The moon."));
+        let moon = Query::Term("moon".to_string());
+        let title_moon = Query::parse("title:moon", ParseMode::Strict).unwrap();
+        let weighted = |title: f32| SearchOptions { field_weights: HashMap::from([("title".to_string(), title)]), ..SearchOptions::default() };
+
+        // weights count for one search like boosts, leaving the Searcher's own untouched
+        let scores = searcher.search_query_with(&moon, &weighted(3.0)).unwrap();
+        assert!(scores["title-match"] > scores["body-match"]);
+        let title_only = searcher.search_query(&title_moon)["title-match"];
+        assert_eq!(searcher.search_query_with(&title_moon, &weighted(3.0)).unwrap()["title-match"], title_only * 3.0);
+        searcher.set_field_boost("title", 3.0).unwrap();
+        assert_eq!(searcher.search_query(&moon), scores);
+
+        // and 0 leaves out a boosted field, for BM25F too
+        let scores = searcher.search_query_with(&moon, &weighted(0.0)).unwrap();
+        assert!(scores["body-match"] > scores["title-match"]);
+        searcher.set_field_scoring(FieldScoring::Bm25f);
+        let bm25f = searcher.search_query(&moon);
+        assert!(bm25f["title-match"] > bm25f["body-match"]);
+        assert_ne!(searcher.search_query_with(&moon, &weighted(1.0)).unwrap(), bm25f);
+        assert!(searcher.search_query_with(&moon, &weighted(f32::NAN)).is_err());
+    }
+
+    #[test]
+    fn test_bm25f() {
+        let mut searcher = Searcher::new();
+        let description = "Rocket kit: a rocket, a launch pad, spare rocket fins and a manual for the launch site.";
+        searcher.add_fields(&Document::new("kit").field("title", "Launch kit").field("description", description));
+        searcher.add_fields(&Document::new("rocket").field("title", "Moon rocket").field("description", "A scale model."));
+        searcher.add_document("note", "rocket");
+        let rocket = Query::Term("rocket".to_string());
+
+        // the kit repeats the term in its long description
+        let sum = searcher.search_query(&rocket);
+        assert!(sum["kit"] > sum["rocket"]);
+
+        searcher.set_field_scoring(FieldScoring::Bm25f);
+        searcher.set_field_boost("title", 3.0).unwrap();
+        let scores = searcher.search_query(&rocket);
+        assert!(scores["rocket"] > scores["kit"]);
+        let (idf, k1, b) = (searcher.idf("rocket", &Failures::default()), searcher.k1, searcher.b);
+        let title = &searcher.fields["title"];
+        let tf = 3.0 / title.length_norm(&title.docs["rocket"], b);
+        assert_eq!(scores["rocket"], idf * tf * (k1 + 1.0) / (k1 + tf));
+        // frequencies saturate, and a document without fields is scored from its content
+        assert!(scores.values().all(|&score| score < idf * (k1 + 1.0)));
+        assert!(scores.contains_key("note"));
+
+        let loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        assert_eq!(loaded.search_query(&rocket), scores);
+        searcher.set_field_scoring(FieldScoring::Sum);
+        assert_ne!(searcher.search_query(&rocket), scores);
+    }
+}
@@ -0,0 +1,376 @@
+//! Encodings of search results for clients other than people: JSON by default, MessagePack for
+//! constrained clients, CSV for spreadsheets, newline-delimited JSON for clients that render hits
+//! as they arrive and TSV for shell pipelines, picked from an HTTP `Accept` header or by name.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::io;
+
+/// A serialization of `(doc_id, score)` results.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ResultFormat {
+    /// An array of `{"doc_id": ..., "score": ...}` objects.
+    #[default]
+    Json,
+    /// The same array of maps as JSON, with scores as 32-bit floats.
+    MessagePack,
+    /// A `doc_id,score` header and one row per result.
+    Csv,
+    /// One `{"doc_id": ..., "score": ...}` object per line, which can be written hit by hit with
+    /// an `NdjsonWriter`.
+    Ndjson,
+    /// A `doc_id<TAB>score` header and one row per result, with tabs and line breaks in values
+    /// replaced by spaces so that every result stays on one line.
+    Tsv,
+}
+
+/// Media types each format is served for, the first one being the one it's served as.
+const MEDIA_TYPES: [(ResultFormat, &[&str]); 5] = [
+    (ResultFormat::Json, &["application/json", "text/json"]),
+    (ResultFormat::MessagePack, &["application/msgpack", "application/x-msgpack", "application/vnd.msgpack"]),
+    (ResultFormat::Csv, &["text/csv", "application/csv"]),
+    (ResultFormat::Ndjson, &["application/x-ndjson", "application/ndjson", "application/jsonlines"]),
+    (ResultFormat::Tsv, &["text/tab-separated-values"]),
+];
+
+/// A result as encoded: doc id, score and, if the results come with snippets, its snippet.
+type Row<'a> = (&'a str, f32, Option<&'a str>);
+
+impl ResultFormat {
+    /// The `Content-Type` of responses in this format.
+    pub fn content_type(self) -> &'static str {
+        MEDIA_TYPES.iter().find(|(format, _)| *format == self).expect("every format has media types").1[0]
+    }
+
+    /// The format an HTTP client asks for in its `Accept` header, e.g.
+    /// `text/csv;q=0.9, application/msgpack`: the supported media type with the highest quality,
+    /// earlier ones winning ties. JSON for an empty header or wildcards, None if the client
+    /// accepts none of the formats, which a server would answer with `406 Not Acceptable`.
+    pub fn negotiate(accept: &str) -> Option<ResultFormat> {
+        if accept.trim().is_empty() {
+            return Some(ResultFormat::Json);
+        }
+        let mut best: Option<(ResultFormat, f32)> = None;
+        for range in accept.split(',') {
+            let mut params = range.split(';');
+            let media_type = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let format = match media_type.as_str() {
+                "*/*" | "application/*" => Some(ResultFormat::Json),
+                "text/*" => Some(ResultFormat::Csv),
+                _ => MEDIA_TYPES
+                    .iter()
+                    .find(|(_, types)| types.contains(&media_type.as_str()))
+                    .map(|(format, _)| *format),
+            };
+            if let Some(format) = format.filter(|_| quality > 0.0) {
+                if best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                    best = Some((format, quality));
+                }
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    /// The results, in the order given, encoded in this format.
+    pub fn encode(self, results: &[(String, f32)]) -> Vec<u8> {
+        let rows: Vec<Row> = results.iter().map(|(doc_id, score)| (doc_id.as_str(), *score, None)).collect();
+        self.encode_rows(&rows, false)
+    }
+
+    /// The results with the passage of each that best matches the query, e.g. from
+    /// `SearchHit::snippet`, as a `snippet` field or column after the score. Results without a
+    /// snippet have a null or empty one.
+    pub fn encode_with_snippets(self, results: &[(String, f32, Option<String>)]) -> Vec<u8> {
+        let rows: Vec<Row> = results.iter().map(|(doc_id, score, snippet)| (doc_id.as_str(), *score, snippet.as_deref())).collect();
+        self.encode_rows(&rows, true)
+    }
+
+    fn encode_rows(self, rows: &[Row], snippets: bool) -> Vec<u8> {
+        match self {
+            ResultFormat::Json => to_json(rows, snippets).into_bytes(),
+            ResultFormat::MessagePack => to_msgpack(rows, snippets),
+            ResultFormat::Csv => to_csv(rows, snippets).into_bytes(),
+            ResultFormat::Ndjson => {
+                let mut ndjson = String::new();
+                for &row in rows {
+                    json_hit(&mut ndjson, row, snippets);
+                    ndjson.push('\n');
+                }
+                ndjson.into_bytes()
+            }
+            ResultFormat::Tsv => to_tsv(rows, snippets).into_bytes(),
+        }
+    }
+}
+
+/// Writes hits as newline-delimited JSON as soon as they are found, flushing after each one, so
+/// that a client reading a streamed response sees them without waiting for the whole result set.
+#[cfg(feature = "std")]
+pub struct NdjsonWriter<W: io::Write> {
+    writer: W,
+    line: String, // reused for every hit
+}
+
+#[cfg(feature = "std")]
+impl<W: io::Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        NdjsonWriter { writer, line: String::new() }
+    }
+
+    pub fn write_hit(&mut self, doc_id: &str, score: f32) -> io::Result<()> {
+        self.line.clear();
+        json_hit(&mut self.line, (doc_id, score, None), false);
+        self.line.push('\n');
+        self.writer.write_all(self.line.as_bytes())?;
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Error returned when a format name is not one of `json`, `msgpack`, `csv`, `ndjson` or `tsv`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownFormat(pub String);
+
+impl fmt::Display for UnknownFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown result format `{}`, expected json, msgpack, csv, ndjson or tsv", self.0)
+    }
+}
+
+impl core::error::Error for UnknownFormat {}
+
+impl FromStr for ResultFormat {
+    type Err = UnknownFormat;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "json" => Ok(ResultFormat::Json),
+            "msgpack" | "messagepack" => Ok(ResultFormat::MessagePack),
+            "csv" => Ok(ResultFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ResultFormat::Ndjson),
+            "tsv" => Ok(ResultFormat::Tsv),
+            _ => Err(UnknownFormat(name.to_string())),
+        }
+    }
+}
+
+fn to_json(rows: &[Row], snippets: bool) -> String {
+    let mut json = String::from("[");
+    for (i, &row) in rows.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json_hit(&mut json, row, snippets);
+    }
+    json.push(']');
+    json
+}
+
+fn json_hit(json: &mut String, (doc_id, score, snippet): Row, snippets: bool) {
+    json.push_str(r#"{"doc_id":"#);
+    json_string(json, doc_id);
+    match score.is_finite() {
+        true => write!(json, r#","score":{}"#, score).unwrap(),
+        false => json.push_str(r#","score":null"#),
+    }
+    if snippets {
+        json.push_str(r#","snippet":"#);
+        match snippet {
+            Some(snippet) => json_string(json, snippet),
+            None => json.push_str("null"),
+        }
+    }
+    json.push('}');
+}
+
+pub(crate) fn json_string(json: &mut String, value: &str) {
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+fn to_csv(rows: &[Row], snippets: bool) -> String {
+    let mut csv = String::from(if snippets { "doc_id,score,snippet\n" } else { "doc_id,score\n" });
+    for &(doc_id, score, snippet) in rows {
+        // doc ids are file names or caller-chosen, so they may need quoting
+        csv_field(&mut csv, doc_id);
+        write!(csv, ",{}", score).unwrap();
+        if snippets {
+            csv.push(',');
+            csv_field(&mut csv, snippet.unwrap_or(""));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+pub(crate) fn csv_field(csv: &mut String, value: &str) {
+    if value.contains([',', '"', '\n', '\r']) {
+        write!(csv, "\"{}\"", value.replace('"', "\"\"")).unwrap();
+    } else {
+        csv.push_str(value);
+    }
+}
+
+fn to_tsv(rows: &[Row], snippets: bool) -> String {
+    let mut tsv = String::from(if snippets { "doc_id\tscore\tsnippet\n" } else { "doc_id\tscore\n" });
+    for &(doc_id, score, snippet) in rows {
+        tsv_field(&mut tsv, doc_id);
+        write!(tsv, "\t{}", score).unwrap();
+        if snippets {
+            tsv.push('\t');
+            tsv_field(&mut tsv, snippet.unwrap_or(""));
+        }
+        tsv.push('\n');
+    }
+    tsv
+}
+
+fn tsv_field(tsv: &mut String, value: &str) {
+    tsv.extend(value.chars().map(|c| if matches!(c, '\t' | '\n' | '\r') { ' ' } else { c }));
+}
+
+fn to_msgpack(rows: &[Row], snippets: bool) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    match rows.len() {
+        len if len < 16 => bytes.push(0x90 | len as u8),
+        len if len <= u16::MAX as usize => {
+            bytes.push(0xdc);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            bytes.push(0xdd);
+            bytes.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+    for &(doc_id, score, snippet) in rows {
+        bytes.push(if snippets { 0x83 } else { 0x82 }); // map of three or two entries
+        msgpack_str(&mut bytes, "doc_id");
+        msgpack_str(&mut bytes, doc_id);
+        msgpack_str(&mut bytes, "score");
+        bytes.push(0xca);
+        bytes.extend_from_slice(&score.to_be_bytes());
+        if snippets {
+            msgpack_str(&mut bytes, "snippet");
+            match snippet {
+                Some(snippet) => msgpack_str(&mut bytes, snippet),
+                None => bytes.push(0xc0), // nil
+            }
+        }
+    }
+    bytes
+}
+
+fn msgpack_str(bytes: &mut Vec<u8>, value: &str) {
+    match value.len() {
+        len if len < 32 => bytes.push(0xa0 | len as u8),
+        len if len <= u8::MAX as usize => bytes.extend_from_slice(&[0xd9, len as u8]),
+        len if len <= u16::MAX as usize => {
+            bytes.push(0xda);
+            bytes.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            bytes.push(0xdb);
+            bytes.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+    }
+    bytes.extend_from_slice(value.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(ResultFormat::negotiate(""), Some(ResultFormat::Json));
+        assert_eq!(ResultFormat::negotiate("*/*"), Some(ResultFormat::Json));
+        assert_eq!(ResultFormat::negotiate("text/csv"), Some(ResultFormat::Csv));
+        assert_eq!(ResultFormat::negotiate("text/html, application/x-msgpack"), Some(ResultFormat::MessagePack));
+        assert_eq!(ResultFormat::negotiate("text/csv;q=0.5, application/msgpack;q=0.8"), Some(ResultFormat::MessagePack));
+        assert_eq!(ResultFormat::negotiate("Text/CSV, application/json"), Some(ResultFormat::Csv));
+        assert_eq!(ResultFormat::negotiate("application/json;q=0, text/*"), Some(ResultFormat::Csv));
+        assert_eq!(ResultFormat::negotiate("text/html, image/png"), None);
+        assert_eq!(ResultFormat::negotiate("application/x-ndjson, application/json;q=0.5"), Some(ResultFormat::Ndjson));
+
+        assert_eq!(ResultFormat::negotiate("text/tab-separated-values"), Some(ResultFormat::Tsv));
+
+        assert_eq!("msgpack".parse(), Ok(ResultFormat::MessagePack));
+        assert_eq!("TSV".parse(), Ok(ResultFormat::Tsv));
+        assert!("xml".parse::<ResultFormat>().is_err());
+        assert_eq!(ResultFormat::Csv.content_type(), "text/csv");
+    }
+
+    #[test]
+    fn test_encode() {
+        let results = vec![("a \"b\".txt".to_string(), 1.5), ("c,d".to_string(), 0.25)];
+
+        let json = String::from_utf8(ResultFormat::Json.encode(&results)).unwrap();
+        assert_eq!(json, r#"[{"doc_id":"a \"b\".txt","score":1.5},{"doc_id":"c,d","score":0.25}]"#);
+        assert_eq!(ResultFormat::Json.encode(&[]), b"[]");
+
+        let csv = String::from_utf8(ResultFormat::Csv.encode(&results)).unwrap();
+        assert_eq!(csv, "doc_id,score\n\"a \"\"b\"\".txt\",1.5\n\"c,d\",0.25\n");
+
+        let msgpack = ResultFormat::MessagePack.encode(&results[1..]);
+        let mut expected = vec![0x91, 0x82, 0xa6];
+        expected.extend_from_slice(b"doc_id");
+        expected.push(0xa3);
+        expected.extend_from_slice(b"c,d");
+        expected.push(0xa5);
+        expected.extend_from_slice(b"score");
+        expected.push(0xca);
+        expected.extend_from_slice(&0.25f32.to_be_bytes());
+        assert_eq!(msgpack, expected);
+        assert_eq!(ResultFormat::MessagePack.encode(&vec![results[0].clone(); 20])[..3], [0xdc, 0, 20]);
+
+        let ndjson = String::from_utf8(ResultFormat::Ndjson.encode(&results)).unwrap();
+        assert_eq!(ndjson, "{\"doc_id\":\"a \\\"b\\\".txt\",\"score\":1.5}\n{\"doc_id\":\"c,d\",\"score\":0.25}\n");
+    }
+
+    #[test]
+    fn test_encode_with_snippets() {
+        let results = vec![
+            ("a.txt".to_string(), 1.5, Some("the \"moon\",\ttwice\na day".to_string())),
+            ("b.txt".to_string(), 0.25, None),
+        ];
+
+        let json = String::from_utf8(ResultFormat::Json.encode_with_snippets(&results)).unwrap();
+        assert_eq!(
+            json,
+            r#"[{"doc_id":"a.txt","score":1.5,"snippet":"the \"moon\",\ttwice\na day"},{"doc_id":"b.txt","score":0.25,"snippet":null}]"#
+        );
+        let ndjson = String::from_utf8(ResultFormat::Ndjson.encode_with_snippets(&results[1..])).unwrap();
+        assert_eq!(ndjson, "{\"doc_id\":\"b.txt\",\"score\":0.25,\"snippet\":null}\n");
+
+        let csv = String::from_utf8(ResultFormat::Csv.encode_with_snippets(&results)).unwrap();
+        assert_eq!(csv, "doc_id,score,snippet\na.txt,1.5,\"the \"\"moon\"\",\ttwice\na day\"\nb.txt,0.25,\n");
+        let tsv = String::from_utf8(ResultFormat::Tsv.encode_with_snippets(&results)).unwrap();
+        assert_eq!(tsv, "doc_id\tscore\tsnippet\na.txt\t1.5\tthe \"moon\", twice a day\nb.txt\t0.25\t\n");
+        assert_eq!(ResultFormat::Tsv.encode(&[("c\td".to_string(), 2.0)]), b"doc_id\tscore\nc d\t2\n");
+
+        let msgpack = ResultFormat::MessagePack.encode_with_snippets(&results[1..]);
+        assert_eq!(msgpack[..2], [0x91, 0x83]);
+        assert_eq!(msgpack[msgpack.len() - 9..], [0xa7, b's', b'n', b'i', b'p', b'p', b'e', b't', 0xc0]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Fuzzy queries, e.g. `serach~1`: terms within an edit distance of the query term, counting
+//! insertions, deletions, substitutions and swaps of two adjacent characters as one edit each.
+//!
+//! The sorted term dictionary is walked like a trie: terms sharing a prefix with the previous one
+//! reuse its rows of the edit distance table, and once every entry of a row is over the distance,
+//! all terms starting with that prefix are skipped with a single range lookup.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Bound;
+
+use crate::backend::Failures;
+use crate::{fold_case, IndexBackend, Searcher};
+
+/// Largest edit distance accepted, beyond which nearly every short term matches.
+pub(crate) const MAX_DISTANCE: u8 = 2;
+
+/// The smallest string greater than every string starting with `prefix`, None if there is none.
+fn successor(prefix: &[char]) -> Option<String> {
+    let mut prefix = prefix.to_vec();
+    while let Some(last) = prefix.pop() {
+        // skip the surrogate range, which holds no chars
+        let next = (last as u32 + 1..=char::MAX as u32).find_map(char::from_u32);
+        if let Some(next) = next {
+            prefix.push(next);
+            return Some(prefix.into_iter().collect());
+        }
+    }
+    None
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// The index terms within `distance` edits of `term`, closest first, capped at
+    /// `max_expansions`. Equally close terms found in more documents come first.
+    pub(crate) fn expand_fuzzy(&self, term: &str, distance: u8, failures: &Failures) -> Vec<Cow<'_, str>> {
+        let query: Vec<char> = fold_case(term).chars().collect();
+        let distance = distance.min(MAX_DISTANCE) as usize;
+
+        let mut matches: Vec<(Cow<str>, usize)> = Vec::new();
+        // rows[i] is the row of the table for the first i characters of `prefix`
+        let mut rows: Vec<Vec<usize>> = vec![(0..=query.len()).collect()];
+        let mut prefix: Vec<char> = Vec::new();
+        let mut terms = self.index.terms_from(Bound::Unbounded);
+
+        while let Some(candidate) = terms.next().and_then(|term| failures.ok(term.map(Some))) {
+            let chars: Vec<char> = candidate.chars().collect();
+            let shared = prefix.iter().zip(&chars).take_while(|(a, b)| a == b).count();
+            prefix.truncate(shared);
+            rows.truncate(shared + 1);
+
+            let mut dead_end = None;
+            for &c in &chars[shared..] {
+                prefix.push(c);
+                let i = prefix.len();
+                let above = &rows[i - 1];
+                let mut row = vec![i; query.len() + 1];
+                for j in 1..=query.len() {
+                    let substitution = above[j - 1] + (query[j - 1] != c) as usize;
+                    row[j] = substitution.min(above[j] + 1).min(row[j - 1] + 1);
+                    // a swap of this and the previous character
+                    if i > 1 && j > 1 && query[j - 1] == prefix[i - 2] && query[j - 2] == c {
+                        row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+                    }
+                }
+                let hopeless = row.iter().all(|&d| d > distance);
+                rows.push(row);
+                if hopeless {
+                    dead_end = Some(i);
+                    break;
+                }
+            }
+
+            match dead_end {
+                // no term starting with this prefix can match, jump past all of them
+                Some(len) => match successor(&prefix[..len]) {
+                    Some(next) => terms = self.index.terms_from(Bound::Included(next.as_str())),
+                    None => break,
+                },
+                None => {
+                    let edits = rows[prefix.len()][query.len()];
+                    if edits <= distance {
+                        matches.push((candidate, edits));
+                    }
+                }
+            }
+        }
+
+        let df = |term: &str| failures.ok(self.index.doc_frequency(term));
+        matches.sort_by(|a, b| a.1.cmp(&b.1).then(df(&b.0).cmp(&df(&a.0))).then(a.0.cmp(&b.0)));
+        matches.truncate(self.max_expansions);
+        matches.into_iter().map(|(term, _)| term).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    #[test]
+    fn test_expand_fuzzy() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "search engine");
+        searcher.add_document("2", "searches research");
+        searcher.add_document("3", "starch sear");
+        searcher.add_document("4", "zebra");
+        let failures = Failures::default();
+
+        assert_eq!(searcher.expand_fuzzy("serach", 1, &failures), ["search"]);
+        assert_eq!(searcher.expand_fuzzy("Search", 0, &failures), ["search"]);
+        assert_eq!(searcher.expand_fuzzy("serach", 2, &failures), ["search", "starch"]);
+        assert_eq!(searcher.expand_fuzzy("serach", 9, &failures).len(), 2);
+        assert!(searcher.expand_fuzzy("zzzzzz", 2, &failures).is_empty());
+
+        searcher.set_max_expansions(1);
+        assert_eq!(searcher.expand_fuzzy("sear", 2, &failures), ["sear"]);
+    }
+
+    #[test]
+    fn test_fuzzy_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "search engine");
+        searcher.add_document("2", "rocket engine");
+
+        let query = Query::parse("serach~1", ParseMode::Strict).unwrap();
+        assert_eq!(searcher.search_query(&query).keys().collect::<Vec<_>>(), ["1"]);
+        let query = Query::parse("+engnie~ -rokcet~", ParseMode::Strict).unwrap();
+        assert_eq!(searcher.search_query(&query).keys().collect::<Vec<_>>(), ["1"]);
+    }
+
+    #[test]
+    fn test_successor() {
+        assert_eq!(successor(&['a', 'b']).as_deref(), Some("ac"));
+        assert_eq!(successor(&['a', char::MAX]).as_deref(), Some("b"));
+        assert_eq!(successor(&['\u{d7ff}']).as_deref(), Some("\u{e000}"));
+        assert_eq!(successor(&[char::MAX]), None);
+    }
+}
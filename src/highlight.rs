@@ -0,0 +1,555 @@
+//! Highlighting of query matches in stored document text, per field.
+//!
+//! The document content is exposed as the `content` field and keyword fields under their own
+//! names. Each field can be returned whole or as a few of its best fragments.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::backend::Failures;
+use crate::passage::sentences;
+use crate::{HashMap, HashSet, IndexBackend, Intervals, Query, Searcher};
+
+/// Name of the field holding the document content.
+pub const CONTENT_FIELD: &str = "content";
+
+/// Approximate length in bytes of the passage picked by `Searcher::snippet`.
+const SNIPPET_SIZE: usize = 160;
+
+/// How a field is cut into fragments.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FragmentSettings {
+    /// Approximate fragment length in bytes, or None to return the field whole.
+    pub fragment_size: Option<usize>,
+    /// Maximum number of fragments returned for the field.
+    pub max_fragments: usize,
+    pub boundary: Boundary,
+}
+
+impl Default for FragmentSettings {
+    fn default() -> Self {
+        FragmentSettings {
+            fragment_size: Some(100),
+            max_fragments: 3,
+            boundary: Boundary::Word,
+        }
+    }
+}
+
+/// Where fragments may start and end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Boundary {
+    /// Between words.
+    Word,
+    /// Between sentences: the sentence of the match plus as many neighbouring sentences as fit in
+    /// the fragment size. Sentences longer than the fragment size are cut between words.
+    Sentence,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HighlightOptions {
+    /// Settings for fields without an entry in `fields`.
+    pub default: FragmentSettings,
+    pub fields: HashMap<String, FragmentSettings>,
+}
+
+impl HighlightOptions {
+    pub fn field(mut self, field: &str, settings: FragmentSettings) -> Self {
+        self.fields.insert(field.to_string(), settings);
+        self
+    }
+}
+
+/// A piece of a field's text with the byte ranges (relative to `text`) of the matches in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fragment {
+    pub text: String,
+    /// Byte offset of `text` in the field value.
+    pub offset: usize,
+    pub highlights: Vec<Range<usize>>,
+}
+
+impl Fragment {
+    /// The fragment text with every match wrapped in `pre` and `post`, e.g. `<em>` and `</em>`.
+    pub fn marked(&self, pre: &str, post: &str) -> String {
+        let mut marked = String::with_capacity(self.text.len());
+        let mut last = 0;
+        for range in &self.highlights {
+            marked.push_str(&self.text[last..range.start]);
+            marked.push_str(pre);
+            marked.push_str(&self.text[range.clone()]);
+            marked.push_str(post);
+            last = range.end;
+        }
+        marked.push_str(&self.text[last..]);
+        marked
+    }
+}
+
+/// A query term found in a document's content, with the character offsets of every occurrence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedTerm {
+    pub term: String,
+    /// Character (not byte) ranges in the content, in text order.
+    pub offsets: Vec<Range<usize>>,
+    /// The text of each occurrence as written in the document, e.g. `Rust` for the term `rust`.
+    pub originals: Vec<String>,
+}
+
+/// What a query looks for in the text of a document.
+#[derive(Default)]
+pub(crate) struct Wanted {
+    pub(crate) terms: HashSet<String>,
+    pub(crate) keywords: HashSet<(String, String)>, // (field, exact value)
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Highlight the matches of `query` in each field of a document. Fields without a match are
+    /// left out. Returns None if the document does not exist or its content can't be fetched.
+    pub fn highlight(&self, doc_id: &str, query: &Query, options: &HighlightOptions) -> Option<HashMap<String, Vec<Fragment>>> {
+        let doc = self.docs.get(doc_id)?;
+        let content = self.content(doc_id)?;
+        let failures = Failures::default();
+        let mut wanted = Wanted::default();
+        self.collect_wanted(query, &mut wanted, &failures);
+
+        let settings = |field: &str| options.fields.get(field).copied().unwrap_or(options.default);
+        let mut highlights = HashMap::new();
+
+        let matches: Vec<Range<usize>> = self.content_matches(doc_id, &content, &wanted, &failures).into_iter().map(|(_, span)| span).collect();
+        let fragments = self.fragments(&content, &matches, settings(CONTENT_FIELD));
+        if !fragments.is_empty() {
+            highlights.insert(CONTENT_FIELD.to_string(), fragments);
+        }
+
+        for (field, values) in &doc.keywords {
+            let mut fragments = Vec::new();
+            for value in values {
+                let matches = if wanted.keywords.contains(&(field.clone(), value.clone())) {
+                    let whole_value = 0..value.len();
+                    vec![whole_value]
+                } else if self.copy_fields.contains(field) {
+                    self.term_matches(value, &wanted).into_iter().map(|(_, span)| span).collect()
+                } else {
+                    Vec::new()
+                };
+                fragments.extend(self.fragments(value, &matches, settings(field)));
+            }
+            fragments.truncate(settings(field).max_fragments);
+            if !fragments.is_empty() {
+                highlights.insert(field.clone(), fragments);
+            }
+        }
+
+        Some(highlights)
+    }
+
+    /// The passage of a document's content that best matches `query`, with every match wrapped in
+    /// `pre` and `post`, e.g. `**` and `**`, and `…` where the content was cut. Falls back to the
+    /// start of the content when the query only matched outside of it, e.g. a keyword. Returns
+    /// None if the document does not exist or its content can't be fetched.
+    pub fn snippet(&self, doc_id: &str, query: &Query, pre: &str, post: &str) -> Option<String> {
+        self.snippet_in(doc_id, query, pre, post, &Failures::default())
+    }
+
+    /// Same as `snippet`, keeping the errors of the backend in `failures`.
+    pub(crate) fn snippet_in(&self, doc_id: &str, query: &Query, pre: &str, post: &str, failures: &Failures) -> Option<String> {
+        let content = &self.content(doc_id)?;
+        let mut wanted = Wanted::default();
+        self.collect_wanted(query, &mut wanted, failures);
+
+        let matches: Vec<Range<usize>> = self.content_matches(doc_id, content, &wanted, failures).into_iter().map(|(_, span)| span).collect();
+        let settings = FragmentSettings {
+            fragment_size: Some(SNIPPET_SIZE),
+            max_fragments: 1,
+            boundary: Boundary::Sentence,
+        };
+        let best = self.fragments(content, &matches, settings).into_iter().next();
+        let fragment = best.unwrap_or_else(|| fragment(content, word_window(content, &(0..0), SNIPPET_SIZE, 0), &[]));
+
+        let mut snippet = String::new();
+        if fragment.offset > 0 {
+            snippet.push('…');
+        }
+        snippet.push_str(&fragment.marked(pre, post));
+        if fragment.offset + fragment.text.len() < content.trim_end().len() {
+            snippet.push('…');
+        }
+        Some(snippet)
+    }
+
+    /// The terms of `query` found in a document's content, in order of first occurrence. Returns
+    /// None if the document does not exist or its content can't be fetched.
+    pub fn matched_terms(&self, doc_id: &str, query: &Query) -> Option<Vec<MatchedTerm>> {
+        let content = &self.content(doc_id)?;
+        let failures = Failures::default();
+        let mut wanted = Wanted::default();
+        self.collect_wanted(query, &mut wanted, &failures);
+
+        let mut matched: Vec<MatchedTerm> = Vec::new();
+        let (mut byte, mut char) = (0, 0);
+        for (term, span) in self.content_matches(doc_id, content, &wanted, &failures) {
+            // spans come in text order, so count characters incrementally
+            let start = char + content[byte..span.start].chars().count();
+            let end = start + content[span.clone()].chars().count();
+            (byte, char) = (span.end, end);
+
+            let occurrence = start..end;
+            let original = content[span].to_string();
+            match matched.iter_mut().find(|m| m.term == term) {
+                Some(m) => {
+                    m.offsets.push(occurrence);
+                    m.originals.push(original);
+                }
+                None => matched.push(MatchedTerm {
+                    term,
+                    offsets: vec![occurrence],
+                    originals: vec![original],
+                }),
+            }
+        }
+        Some(matched)
+    }
+
+    /// Gather the terms and keyword values a query matches on. Excluded clauses and clauses about
+    /// other documents (nested, joins) can't be found in this document's text and are skipped.
+    pub(crate) fn collect_wanted(&self, query: &Query, wanted: &mut Wanted, failures: &Failures) {
+        match query {
+            Query::Term(text) => wanted.terms.extend(self.analyze_text(text).into_iter().map(|t| t.term.into_owned())),
+            Query::Keyword { field, value } => {
+                wanted.keywords.insert((field.clone(), value.clone()));
+            }
+            Query::Range { field, lower, upper } => {
+                wanted.keywords.extend(self.range_values(field, lower, upper).map(|(value, _)| (field.clone(), value.to_string())));
+            }
+            Query::Boost { query, .. } => self.collect_wanted(query, wanted, failures),
+            Query::Regex(pattern) => wanted.terms.extend(self.expand_regex(pattern, failures).into_iter().map(Cow::into_owned)),
+            Query::Wildcard(pattern) => wanted.terms.extend(self.expand_wildcard(pattern, failures).into_iter().map(Cow::into_owned)),
+            Query::Fuzzy { term, distance } => {
+                wanted.terms.extend(self.expand_fuzzy(term, *distance, failures).into_iter().map(Cow::into_owned))
+            }
+            Query::Intervals(source) => wanted.terms.extend(self.interval_terms(&strip_excluded(source))),
+            Query::Bool(bool_query) => {
+                let clauses = bool_query.must.iter().chain(&bool_query.should).chain(&bool_query.filter);
+                for clause in clauses {
+                    self.collect_wanted(clause, wanted, failures);
+                }
+            }
+            Query::Nested { .. } | Query::Join { .. } => {}
+        }
+    }
+
+    /// The wanted terms found in a document's `content` with their byte ranges, in text order.
+    /// Uses the stored offsets of the document if it has them.
+    fn content_matches(&self, doc_id: &str, content: &str, wanted: &Wanted, failures: &Failures) -> Vec<(String, Range<usize>)> {
+        let doc = &self.docs[doc_id];
+        match &doc.offsets {
+            // content read back from a provider may have changed since it was indexed
+            Some(offsets) => self.stored_matches(doc_id, offsets, wanted, failures).into_iter().filter(|(_, span)| content.get(span.clone()).is_some()).collect(),
+            None => self.term_matches(content, wanted),
+        }
+    }
+
+    /// The words in `text` that analyze to a wanted term, with their byte ranges.
+    fn term_matches(&self, text: &str, wanted: &Wanted) -> Vec<(String, Range<usize>)> {
+        self.analyze_text(text)
+            .into_iter()
+            .filter(|token| wanted.terms.contains(token.term.as_ref()))
+            .map(|token| (token.term.into_owned(), token.span))
+            .collect()
+    }
+
+    /// Byte ranges of the wanted terms in a document's content, read from its stored offsets.
+    /// Positions past the stored offsets belong to copied keyword values and are skipped.
+    fn stored_matches(&self, doc_id: &str, offsets: &[Range<usize>], wanted: &Wanted, failures: &Failures) -> Vec<(String, Range<usize>)> {
+        let ordinal = self.docs[doc_id].ordinal;
+        let mut matches: Vec<(String, Range<usize>)> = wanted
+            .terms
+            .iter()
+            .filter_map(|term| Some((term, failures.ok(self.index.postings(term))?.get(ordinal)?.clone())))
+            .flat_map(|(term, positions)| {
+                positions
+                    .into_iter()
+                    .filter_map(|position| Some((term.clone(), offsets.get(position as usize)?.clone())))
+            })
+            .collect();
+        matches.sort_by_key(|(_, span)| span.start);
+        matches
+    }
+
+    /// Cut `text` into the best fragments around `matches`, best first.
+    fn fragments(&self, text: &str, matches: &[Range<usize>], settings: FragmentSettings) -> Vec<Fragment> {
+        if matches.is_empty() || settings.max_fragments == 0 {
+            return Vec::new();
+        }
+
+        let Some(size) = settings.fragment_size else {
+            return vec![fragment(text, 0..text.len(), matches)];
+        };
+
+        // one candidate window around each match, ranked by how many matches it holds
+        let sentences = match settings.boundary {
+            Boundary::Word => Vec::new(),
+            Boundary::Sentence => sentences(text),
+        };
+        let mut candidates: Vec<Range<usize>> = matches
+            .iter()
+            .map(|m| match sentences.iter().position(|s| s.start <= m.start && m.end <= s.end) {
+                Some(i) if sentences[i].len() <= size => sentence_window(&sentences, i, size),
+                Some(i) => word_window(&text[..sentences[i].end], m, size, sentences[i].start),
+                None => word_window(text, m, size, 0),
+            })
+            .collect();
+        let count = |window: &Range<usize>| matches.iter().filter(|m| window.start <= m.start && m.end <= window.end).count();
+        candidates.sort_by(|a, b| count(b).cmp(&count(a)).then(a.start.cmp(&b.start)));
+
+        let mut chosen: Vec<Range<usize>> = Vec::new();
+        for window in candidates {
+            if chosen.len() == settings.max_fragments {
+                break;
+            }
+            if chosen.iter().all(|c| window.end <= c.start || c.end <= window.start) {
+                chosen.push(window);
+            }
+        }
+
+        chosen.into_iter().map(|window| fragment(text, window, matches)).collect()
+    }
+}
+
+fn fragment(text: &str, window: Range<usize>, matches: &[Range<usize>]) -> Fragment {
+    Fragment {
+        text: text[window.clone()].to_string(),
+        offset: window.start,
+        highlights: matches
+            .iter()
+            .filter(|m| window.start <= m.start && m.end <= window.end)
+            .map(|m| m.start - window.start..m.end - window.start)
+            .collect(),
+    }
+}
+
+/// A window of about `size` bytes centered on the match `m`, starting at or after `min_start` and
+/// not cutting any word in two.
+fn word_window(text: &str, m: &Range<usize>, size: usize, min_start: usize) -> Range<usize> {
+    let padding = size.saturating_sub(m.len()) / 2;
+    let end = floor_char_boundary(text, (m.start.saturating_sub(padding).max(min_start) + size).max(m.end));
+    let start = floor_char_boundary(text, end.saturating_sub(size).clamp(min_start, m.start));
+
+    // move inwards out of partial words, but never past the match itself
+    let is_word = |c: char| c.is_alphanumeric();
+    let mid_word = |i: usize| text[..i].ends_with(is_word) && text[i..].starts_with(is_word);
+    let start = match mid_word(start) {
+        true => text[start..m.start]
+            .char_indices()
+            .find(|(_, c)| !is_word(*c))
+            .map_or(m.start, |(i, c)| start + i + c.len_utf8()),
+        false => start,
+    };
+    let end = match mid_word(end) {
+        true => text[m.end..end].rfind(|c: char| !is_word(c)).map_or(m.end, |i| m.end + i),
+        false => end,
+    };
+    start..end
+}
+
+/// Sentence `i` extended with neighbouring sentences, after then before, while the window stays
+/// within `size` bytes.
+fn sentence_window(sentences: &[Range<usize>], i: usize, size: usize) -> Range<usize> {
+    let (mut first, mut last) = (i, i);
+    loop {
+        if last + 1 < sentences.len() && sentences[last + 1].end - sentences[first].start <= size {
+            last += 1;
+        } else if first > 0 && sentences[last].end - sentences[first - 1].start <= size {
+            first -= 1;
+        } else {
+            return sentences[first].start..sentences[last].end;
+        }
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    index = index.min(text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+/// The interval source without the parts that must not occur.
+fn strip_excluded(source: &Intervals) -> Intervals {
+    match source {
+        Intervals::NotContaining { big, .. } => strip_excluded(big),
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseMode;
+
+    #[test]
+    fn test_highlight_fields() {
+        let mut searcher = Searcher::new();
+        let body = "Rust has no garbage collector. Memory is managed through ownership and borrowing, \
+            which the compiler checks. Ownership rules are simple once you get used to them.";
+        searcher.add_document("1", body);
+        searcher.add_keyword("1", "title", "Understanding Ownership");
+        searcher.add_copy_field("title");
+        searcher.add_keyword("1", "title", "Rust Ownership Guide");
+
+        let query = Query::parse("ownership title:Understanding\\ Ownership", ParseMode::Strict).unwrap();
+        let options = HighlightOptions {
+            default: FragmentSettings {
+                fragment_size: Some(40),
+                max_fragments: 2,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+        .field(
+            "title",
+            FragmentSettings {
+                fragment_size: None,
+                max_fragments: 5,
+                ..Default::default()
+            },
+        );
+        let highlights = searcher.highlight("1", &query, &options).unwrap();
+
+        let content = &highlights[CONTENT_FIELD];
+        assert_eq!(content.len(), 2);
+        for fragment in content {
+            assert!(fragment.text.len() <= 40);
+            assert_eq!(&body[fragment.offset..fragment.offset + fragment.text.len()], fragment.text);
+            assert!(fragment.marked("<em>", "</em>").to_lowercase().contains("<em>ownership</em>"));
+        }
+
+        // an exact keyword match is highlighted whole, a copy field word by word
+        let title = &highlights["title"];
+        assert_eq!(title[0].marked("[", "]"), "[Understanding Ownership]");
+        assert_eq!(title[1].marked("[", "]"), "Rust [Ownership] Guide");
+
+        assert!(searcher.highlight("missing", &query, &options).is_none());
+    }
+
+    #[test]
+    fn test_fragment_boundaries() {
+        let mut searcher = Searcher::new();
+        let body = "Rockets need thrust. The engine burns propellant quickly! Orbit is reached after \
+            about eight minutes of powered flight, then the engines shut down.";
+        searcher.add_document("1", body);
+        let query = Query::Term("engine".to_string());
+
+        let settings = |boundary| FragmentSettings {
+            fragment_size: Some(40),
+            max_fragments: 1,
+            boundary,
+        };
+        let highlight = |boundary| {
+            let options = HighlightOptions {
+                default: settings(boundary),
+                ..Default::default()
+            };
+            searcher.highlight("1", &query, &options).unwrap()[CONTENT_FIELD][0].clone()
+        };
+
+        let fragment = highlight(Boundary::Word);
+        assert!(fragment.text.len() <= 40);
+        let (start, end) = (fragment.offset, fragment.offset + fragment.text.len());
+        assert!(!body[..start].ends_with(char::is_alphanumeric) || !body[start..].starts_with(char::is_alphanumeric));
+        assert!(!body[..end].ends_with(char::is_alphanumeric) || !body[end..].starts_with(char::is_alphanumeric));
+        assert!(fragment.marked("[", "]").contains("[engine]"));
+
+        // the sentence fits, the ones around it don't
+        let fragment = highlight(Boundary::Sentence);
+        assert_eq!(fragment.text, "The engine burns propellant quickly!");
+        assert_eq!(fragment.highlights, vec![4..10]);
+    }
+
+    #[test]
+    fn test_stored_offsets() {
+        let body = "Ownership, the Rust way: OWNERSHIP rules are checked by the borrow checker.";
+        let query = Query::parse("ownership checker", ParseMode::Strict).unwrap();
+        let options = HighlightOptions::default().field(
+            CONTENT_FIELD,
+            FragmentSettings {
+                fragment_size: None,
+                ..Default::default()
+            },
+        );
+
+        let mut analyzed = Searcher::new();
+        analyzed.add_document("1", body);
+        let mut stored = Searcher::new();
+        stored.set_store_offsets(true);
+        stored.add_document("1", body);
+        stored.add_copy_field("title");
+        stored.add_keyword("1", "title", "ownership");
+
+        let expected = analyzed.highlight("1", &query, &options).unwrap();
+        let highlights = stored.highlight("1", &query, &options).unwrap();
+        assert_eq!(highlights[CONTENT_FIELD], expected[CONTENT_FIELD]);
+        assert_eq!(
+            highlights[CONTENT_FIELD][0].marked("[", "]"),
+            "[Ownership], the Rust way: [OWNERSHIP] rules are checked by the borrow [checker]."
+        );
+    }
+
+    #[test]
+    fn test_snippet() {
+        let mut searcher = Searcher::new();
+        let body = format!("{} The borrow checker rejects this program. {}", "Filler text. ".repeat(20), "More filler. ".repeat(20));
+        searcher.add_document("1", &body);
+        searcher.add_document("2", "Short note");
+        searcher.add_keyword("2", "ext", "md");
+
+        let query = Query::parse("checker", ParseMode::Strict).unwrap();
+        let snippet = searcher.snippet("1", &query, "<em>", "</em>").unwrap();
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'), "{}", snippet);
+        assert!(snippet.contains("The borrow <em>checker</em> rejects this program."), "{}", snippet);
+
+        // matched on a keyword only: the start of the content, whole when it is short
+        let query = Query::parse("ext:md", ParseMode::Strict).unwrap();
+        assert_eq!(searcher.snippet("2", &query, "**", "**").unwrap(), "Short note");
+        let snippet = searcher.snippet("1", &query, "**", "**").unwrap();
+        assert!(snippet.starts_with("Filler text.") && snippet.ends_with('…') && snippet.len() <= SNIPPET_SIZE + '…'.len_utf8());
+
+        assert!(searcher.snippet("missing", &query, "**", "**").is_none());
+    }
+
+    #[test]
+    fn test_matched_terms() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "Ça compile: rust borrow checker, then Rust again.");
+        let query = Query::parse("rust checker", ParseMode::Strict).unwrap();
+
+        let matched = searcher.matched_terms("1", &query).unwrap();
+        let checker = 24..31;
+        assert_eq!(
+            matched,
+            vec![
+                MatchedTerm {
+                    term: "rust".to_string(),
+                    offsets: vec![12..16, 38..42],
+                    originals: vec!["rust".to_string(), "Rust".to_string()],
+                },
+                MatchedTerm {
+                    term: "checker".to_string(),
+                    offsets: vec![checker],
+                    originals: vec!["checker".to_string()],
+                },
+            ]
+        );
+
+        // stored offsets give the same result
+        searcher.set_store_offsets(true);
+        searcher.add_document("2", "Ça compile: rust borrow checker, then Rust again.");
+        assert_eq!(searcher.matched_terms("2", &query).unwrap(), matched);
+        assert!(searcher.matched_terms("missing", &query).is_none());
+    }
+}
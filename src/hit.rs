@@ -0,0 +1,85 @@
+//! Ranked search results that keep a handle on the engine that found them, to show what they
+//! contain and where they matched.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use crate::{EngineError, HashMap, Query, SearchEngine};
+
+/// A document found by a query, with its score.
+pub struct SearchHit<'a> {
+    pub doc_id: String,
+    pub score: f32,
+    engine: &'a dyn SearchEngine,
+    query: &'a Query,
+}
+
+/// One page of the documents found by a query, best first.
+pub struct SearchPage<'a> {
+    /// The documents of the page with their scores.
+    pub hits: Vec<SearchHit<'a>>,
+    /// Number of documents the query found, on this page or not.
+    pub total: usize,
+}
+
+impl<'a> SearchHit<'a> {
+    /// The `limit` best of `scores` after the `offset` best, found by `query` on `engine`, best
+    /// first. Ties are broken by doc id, so that pages don't overlap.
+    pub(crate) fn ranked(engine: &'a dyn SearchEngine, query: &'a Query, scores: HashMap<String, f32>, offset: usize, limit: usize) -> SearchPage<'a> {
+        let total = scores.len();
+        let mut scores: Vec<(String, f32)> = scores.into_iter().collect();
+        scores.sort_by(by_rank);
+        let hits = scores
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(doc_id, score)| SearchHit { doc_id, score, engine, query })
+            .collect();
+        SearchPage { hits, total }
+    }
+
+    /// The passage of the document that best matches the query, with the matches wrapped in `pre`
+    /// and `post`, e.g. `<em>` and `</em>`. None if the document was removed since.
+    pub fn snippet(&self, pre: &str, post: &str) -> Result<Option<String>, EngineError> {
+        self.engine.snippet(&self.doc_id, self.query, pre, post)
+    }
+}
+
+/// The order of hits, best first and ties broken by doc id.
+pub(crate) fn by_rank(a: &(String, f32), b: &(String, f32)) -> Ordering {
+    b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ParseMode, Query, SearchEngine, Searcher};
+
+    #[test]
+    fn test_search_hits() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "The moon rocket launched. Ocean tide charts were updated.");
+        searcher.add_document("2", "Rocket fuel prices rose again this quarter across every ocean port.");
+        searcher.add_document("3", "ocean tide");
+
+        let query = Query::parse("rocket moon", ParseMode::Strict).unwrap();
+        let page = searcher.search_hits(&query, 0, 10).unwrap();
+        assert_eq!(page.total, 2);
+        let hits = page.hits;
+        assert_eq!(hits.iter().map(|hit| hit.doc_id.as_str()).collect::<Vec<_>>(), ["1", "2"]);
+        assert!(hits[0].score > hits[1].score);
+        assert_eq!(hits[0].snippet("**", "**").unwrap().unwrap(), "The **moon** **rocket** launched. Ocean tide charts were updated.");
+
+        // through the trait object the CLI uses
+        let engine: &dyn SearchEngine = &searcher;
+        let hits = engine.search_hits(&query, 0, 1).unwrap().hits;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].snippet("[", "]").unwrap().unwrap(), "The [moon] [rocket] launched. Ocean tide charts were updated.");
+
+        // the next page starts after the last hit of the one before, and counts the same total
+        let page = engine.search_hits(&query, 1, 1).unwrap();
+        assert_eq!((page.hits[0].doc_id.as_str(), page.total), ("2", 2));
+        let page = engine.search_hits(&query, 2, 1).unwrap();
+        assert_eq!((page.hits.len(), page.total), (0, 2));
+    }
+}
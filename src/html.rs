@@ -0,0 +1,98 @@
+//! Visible text of HTML pages, for indexing pages fetched from the web. Not a full HTML parser:
+//! it only needs to find where tags start and end, which elements hold no readable text, and which
+//! ones end a block of text.
+
+use crate::xml::decode_entities;
+
+/// Elements whose content is never shown as text.
+const HIDDEN: &[&str] = &["script", "style", "noscript", "template", "svg", "iframe", "object"];
+
+/// Elements that start a new block of text, so that words on either side aren't joined.
+const BLOCKS: &[&str] = &[
+    "address", "article", "aside", "blockquote", "br", "dd", "div", "dl", "dt", "figcaption", "footer", "form", "h1", "h2",
+    "h3", "h4", "h5", "h6", "header", "hr", "li", "main", "nav", "ol", "p", "pre", "section", "table", "td", "th", "title",
+    "tr", "ul",
+];
+
+/// The text of `html` with tags, comments and hidden elements removed and character references
+/// decoded, one line per block of text.
+pub(crate) fn html_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let after_lt = &rest[1..];
+        let Some(end) = after_lt.find('>') else {
+            // a stray `<` at the end is text
+            break;
+        };
+        let tag = &after_lt[..end];
+        rest = &after_lt[end + 1..];
+
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        if tag.starts_with('!') || tag.starts_with('?') {
+            // `<!doctype html>` or `<?xml ?>`
+            continue;
+        }
+        if name.is_empty() || tag.starts_with(char::is_whitespace) {
+            // a `<` that doesn't start a tag, e.g. in `1 < 2`
+            text.push('<');
+            rest = after_lt;
+            continue;
+        }
+        if HIDDEN.contains(&name.as_str()) && !tag.starts_with('/') && !tag.ends_with('/') {
+            rest = skip_element(rest, &name);
+        } else if BLOCKS.contains(&name.as_str()) {
+            text.push('\n');
+        }
+    }
+    text.push_str(&decode_entities(rest));
+
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// What follows the closing tag of a `name` element whose opening tag ends right before `rest`.
+fn skip_element<'a>(rest: &'a str, name: &str) -> &'a str {
+    let lower = rest.to_ascii_lowercase();
+    let closing = format!("</{}", name);
+    match lower.find(&closing) {
+        Some(start) => rest[start..].find('>').map_or("", |end| &rest[start + end + 1..]),
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_text() {
+        let html = r#"<!DOCTYPE html>
+<html><head><title>Moon &amp; Tides</title>
+<style>body { color: red }</style><script>let a = "<p>not text</p>";</script></head>
+<body><!-- a <p>comment</p> --><h1>Rocket<br/>launch</h1>
+<p>The <b>moon</b> pulls the <a href="/ocean">ocean</a>, so&nbsp;tides rise &#8212; twice a day &#x263A;.</p>
+<p>1 < 2 &unknown; &amp</p></body></html>"#;
+        assert_eq!(
+            html_text(html),
+            "Moon & Tides\nRocket\nlaunch\nThe moon pulls the ocean, so tides rise \u{2014} twice a day \u{263a}.\n1 < 2 &unknown; &amp"
+        );
+        assert_eq!(html_text("plain text"), "plain text");
+        assert_eq!(html_text("<SCRIPT>hidden</SCRIPT>shown<script>unclosed"), "shown");
+    }
+}
@@ -0,0 +1,132 @@
+//! Interval algebra over term positions, used to evaluate `Query::Intervals`.
+//!
+//! An interval is an inclusive `(start, end)` range of word positions within one document.
+//! Every function takes interval lists sorted by start and returns a sorted list of minimal
+//! intervals, i.e. no returned interval contains another one.
+
+pub(crate) type Interval = (u32, u32);
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Number of words inside `span` not covered by any of `parts`.
+fn gaps(span: Interval, parts: &[Interval]) -> u32 {
+    let width = span.1 - span.0 + 1;
+    let covered: u32 = parts.iter().map(|(start, end)| end - start + 1).sum();
+    width.saturating_sub(covered)
+}
+
+/// Sort, dedup and drop every interval that contains another one.
+fn minimize(mut intervals: Vec<Interval>) -> Vec<Interval> {
+    intervals.sort_unstable();
+    intervals.dedup();
+    let all = intervals.clone();
+    intervals.retain(|&(start, end)| {
+        !all.iter()
+            .any(|&(s, e)| (s, e) != (start, end) && start <= s && e <= end)
+    });
+    intervals
+}
+
+/// Intervals where one interval of each list appears in order without overlapping.
+pub(crate) fn ordered(lists: &[Vec<Interval>], max_gaps: Option<u32>) -> Vec<Interval> {
+    let Some((first, rest)) = lists.split_first() else {
+        return Vec::new();
+    };
+
+    let mut matches = Vec::new();
+    'outer: for &head in first {
+        let mut parts = vec![head];
+        for list in rest {
+            let end = parts[parts.len() - 1].1;
+            match list.iter().find(|(start, _)| *start > end) {
+                Some(&next) => parts.push(next),
+                // later heads end even further right, so nothing else can match
+                None => break 'outer,
+            }
+        }
+        let span = (head.0, parts[parts.len() - 1].1);
+        if max_gaps.is_none_or(|max| gaps(span, &parts) <= max) {
+            matches.push(span);
+        }
+    }
+    minimize(matches)
+}
+
+/// Intervals covering one interval of each list in any order.
+pub(crate) fn unordered(lists: &[Vec<Interval>], max_gaps: Option<u32>) -> Vec<Interval> {
+    let mut matches = Vec::new();
+    for (i, anchors) in lists.iter().enumerate() {
+        for &anchor in anchors {
+            // for every other list, the interval starting at or after the anchor that ends first
+            let parts: Option<Vec<Interval>> = lists
+                .iter()
+                .enumerate()
+                .map(|(j, list)| match i == j {
+                    true => Some(anchor),
+                    false => list.iter().filter(|(start, _)| *start >= anchor.0).min_by_key(|(_, end)| *end).copied(),
+                })
+                .collect();
+            let Some(parts) = parts else {
+                continue;
+            };
+            let span = (anchor.0, parts.iter().map(|(_, end)| *end).max().unwrap_or(anchor.1));
+            if max_gaps.is_none_or(|max| gaps(span, &parts) <= max) {
+                matches.push(span);
+            }
+        }
+    }
+    minimize(matches)
+}
+
+/// Intervals of `big` that contain at least one interval of `small`.
+pub(crate) fn containing(big: &[Interval], small: &[Interval]) -> Vec<Interval> {
+    big.iter()
+        .filter(|(start, end)| small.iter().any(|(s, e)| start <= s && e <= end))
+        .copied()
+        .collect()
+}
+
+/// Intervals of `big` that contain none of the intervals of `small`.
+pub(crate) fn not_containing(big: &[Interval], small: &[Interval]) -> Vec<Interval> {
+    big.iter()
+        .filter(|(start, end)| !small.iter().any(|(s, e)| start <= s && e <= end))
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(positions: &[u32]) -> Vec<Interval> {
+        positions.iter().map(|&p| (p, p)).collect()
+    }
+
+    #[test]
+    fn test_ordered() {
+        let lists = [points(&[0, 1, 9]), points(&[2, 5])];
+        assert_eq!(ordered(&lists, None), vec![(1, 2)]);
+        assert_eq!(ordered(&lists, Some(0)), vec![(1, 2)]);
+
+        let lists = [points(&[0, 9]), points(&[5])];
+        assert_eq!(ordered(&lists, Some(3)), vec![]);
+        assert_eq!(ordered(&lists, Some(4)), vec![(0, 5)]);
+    }
+
+    #[test]
+    fn test_unordered() {
+        let lists = [points(&[6]), points(&[2])];
+        assert_eq!(unordered(&lists, None), vec![(2, 6)]);
+        assert_eq!(unordered(&lists, Some(2)), vec![]);
+        assert_eq!(unordered(&lists, Some(3)), vec![(2, 6)]);
+    }
+
+    #[test]
+    fn test_containing() {
+        let big = vec![(0, 4), (6, 9)];
+        let small = points(&[3]);
+        assert_eq!(containing(&big, &small), vec![(0, 4)]);
+        assert_eq!(not_containing(&big, &small), vec![(6, 9)]);
+    }
+}
@@ -0,0 +1,273 @@
+//! Language support: a Searcher can analyze with the stop words and stemmer of one language, and
+//! with several query languages configured, a query is analyzed with the stop words of the
+//! language it is written in, or with all of them when that is unclear. `MultilingualIndex` goes
+//! further and keeps one sub-index per language.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+#[cfg(feature = "std")]
+use crate::Stemmer;
+use crate::{fold_case, HashMap, HashSet, IndexBackend, Searcher};
+
+/// The analysis pipeline of one language.
+#[derive(Debug, Clone)]
+pub struct Language {
+    pub name: String,
+    pub stop_words: HashSet<String>,
+    #[cfg(feature = "std")]
+    pub stemmer: Option<Stemmer>,
+}
+
+impl Language {
+    pub fn new<I, S>(name: &str, stop_words: I) -> Language
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Language {
+            name: name.to_string(),
+            stop_words: stop_words.into_iter().map(|w| fold_case(w.as_ref())).collect(),
+            #[cfg(feature = "std")]
+            stemmer: None,
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn with_stemmer(mut self, stemmer: Stemmer) -> Language {
+        self.stemmer = Some(stemmer);
+        self
+    }
+
+    /// A language with its bundled stop word list and its stemmer, by ISO 639-1 code, e.g.
+    /// `"de"`. Covers the languages there are both stop words and a stemmer for.
+    #[cfg(feature = "stop-words")]
+    pub fn bundled(code: &str) -> Option<Language> {
+        use stop_words::LANGUAGE;
+
+        let language = match code {
+            "da" => LANGUAGE::Danish,
+            "de" => LANGUAGE::German,
+            "en" => LANGUAGE::English,
+            "es" => LANGUAGE::Spanish,
+            "fi" => LANGUAGE::Finnish,
+            "fr" => LANGUAGE::French,
+            "hu" => LANGUAGE::Hungarian,
+            "it" => LANGUAGE::Italian,
+            "nl" => LANGUAGE::Dutch,
+            "no" => LANGUAGE::Norwegian,
+            "pt" => LANGUAGE::Portuguese,
+            "ro" => LANGUAGE::Romanian,
+            "ru" => LANGUAGE::Russian,
+            "sv" => LANGUAGE::Swedish,
+            "tr" => LANGUAGE::Turkish,
+            _ => return None,
+        };
+        let stemmer = Stemmer::from_code(code)?;
+        Some(Language::new(code, stop_words::get(language)).with_stemmer(stemmer))
+    }
+}
+
+/// The language `text` is most likely written in, judged by how many of its words are stop words
+/// of each language. None when no language has more hits than all others.
+pub fn detect_language<'a>(text: &str, languages: &'a [Language]) -> Option<&'a Language> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(fold_case)
+        .collect();
+
+    let mut hits: Vec<(usize, &Language)> = languages
+        .iter()
+        .map(|language| (words.iter().filter(|w| language.stop_words.contains(*w)).count(), language))
+        .collect();
+    hits.sort_by_key(|&(count, _)| core::cmp::Reverse(count));
+
+    match hits.as_slice() {
+        [(best, language), rest @ ..] if *best > 0 && rest.first().is_none_or(|(next, _)| next < best) => Some(language),
+        _ => None,
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Analyze documents and queries with the stop words and stemmer of `language`, e.g.
+    /// `Language::bundled("fr")`, instead of English stop words without stemming. Like
+    /// `set_stop_words`, this is meant to be done before adding documents.
+    pub fn set_language(&mut self, language: &Language) {
+        self.set_stop_words(&language.stop_words);
+        #[cfg(feature = "std")]
+        self.set_stemmer(language.stemmer);
+    }
+
+    /// Detect the language of text queries among `languages` and drop that language's stop words
+    /// from them instead of the Searcher's own. Queries of uncertain language drop the stop words
+    /// of every configured language. An empty list turns detection off.
+    pub fn set_query_languages(&mut self, languages: Vec<Language>) {
+        self.query_languages = languages;
+        self.generation += 1;
+    }
+
+    /// The stop words to analyze `query` with.
+    pub(crate) fn query_stop_words(&self, query: &str) -> Cow<'_, HashSet<String>> {
+        if self.query_languages.is_empty() {
+            return Cow::Borrowed(&self.stop_words);
+        }
+        match detect_language(query, &self.query_languages) {
+            Some(language) => Cow::Borrowed(&language.stop_words),
+            None => Cow::Owned(
+                self.query_languages
+                    .iter()
+                    .flat_map(|language| language.stop_words.iter().cloned())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// A corpus partitioned into one Searcher per language, so that every language keeps its own
+/// vocabulary and document frequencies. Documents and queries are routed by detected language.
+pub struct MultilingualIndex {
+    languages: Vec<Language>,
+    indexes: HashMap<String, Searcher>,     // language name -> sub-index
+    doc_languages: HashMap<String, String>, // doc_id -> language name
+}
+
+impl MultilingualIndex {
+    /// One sub-index per language, analyzing with that language's stop words and stemmer. Documents whose
+    /// language can't be detected go to the first language.
+    pub fn new(languages: Vec<Language>) -> MultilingualIndex {
+        let indexes = languages
+            .iter()
+            .map(|language| {
+                let mut searcher = Searcher::new();
+                searcher.set_language(language);
+                (language.name.clone(), searcher)
+            })
+            .collect();
+        MultilingualIndex {
+            languages,
+            indexes,
+            doc_languages: HashMap::new(),
+        }
+    }
+
+    /// Index a document in the sub-index of its language and return that language's name. None
+    /// when no languages are configured.
+    pub fn add_document(&mut self, doc_id: &str, content: &str) -> Option<&str> {
+        let language = detect_language(content, &self.languages).or(self.languages.first())?;
+        self.indexes.get_mut(&language.name)?.add_document(doc_id, content);
+        self.doc_languages.insert(doc_id.to_string(), language.name.clone());
+        Some(&language.name)
+    }
+
+    /// Remove a document from the sub-index it was routed to. Returns false if it does not exist.
+    pub fn remove_document(&mut self, doc_id: &str) -> bool {
+        let Some(language) = self.doc_languages.remove(doc_id) else {
+            return false;
+        };
+        self.indexes.get_mut(&language).is_some_and(|searcher| searcher.remove_document(doc_id))
+    }
+
+    /// Search the sub-index of the query's language, or all of them when it can't be detected.
+    pub fn search(&self, query: &str) -> HashMap<String, f32> {
+        match detect_language(query, &self.languages) {
+            Some(language) => self.indexes[&language.name].search(query),
+            None => self.indexes.values().flat_map(|searcher| searcher.search(query)).collect(),
+        }
+    }
+
+    /// The language a document was routed to.
+    pub fn language_of(&self, doc_id: &str) -> Option<&str> {
+        self.doc_languages.get(doc_id).map(String::as_str)
+    }
+
+    /// The sub-index of a language, e.g. to configure it or run structured queries on it.
+    pub fn index(&self, language: &str) -> Option<&Searcher> {
+        self.indexes.get(language)
+    }
+
+    pub fn index_mut(&mut self, language: &str) -> Option<&mut Searcher> {
+        self.indexes.get_mut(language)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn languages() -> Vec<Language> {
+        vec![
+            Language::new("en", ["the", "and", "of", "die"]),
+            Language::new("de", ["der", "die", "und", "das"]),
+        ]
+    }
+
+    #[test]
+    fn test_detect_language() {
+        let languages = languages();
+        assert_eq!(detect_language("the moon and the rocket", &languages).unwrap().name, "en");
+        assert_eq!(detect_language("der Mond und die Rakete", &languages).unwrap().name, "de");
+        // "die" is a stop word of both, and nothing else decides
+        assert!(detect_language("die", &languages).is_none());
+        assert!(detect_language("rocket", &languages).is_none());
+    }
+
+    #[test]
+    fn test_query_languages() {
+        let mut searcher = Searcher::new();
+        searcher.set_stop_words(Vec::<String>::new());
+        searcher.add_document("1", "the rocket");
+        searcher.add_document("2", "der Mond");
+        searcher.add_document("3", "die rakete");
+
+        // without detection "der" is an ordinary term
+        assert!(searcher.search("der").contains_key("2"));
+
+        searcher.set_query_languages(languages());
+        // a German query drops German stop words but keeps "the"
+        let results = searcher.search("der und the");
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("1"));
+        // uncertain queries drop the stop words of every language
+        assert!(searcher.search("die").is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "stop-words")]
+    fn test_set_language() {
+        let german = Language::bundled("de").unwrap();
+        assert_eq!(german.stemmer, Some(Stemmer::German));
+        assert!(Language::bundled("sv").is_some_and(|swedish| swedish.stop_words.contains("och")));
+        assert!(Language::bundled("xx").is_none());
+
+        let mut searcher = Searcher::new();
+        searcher.set_language(&german);
+        searcher.add_document("1", "Die Häuser am Meer");
+        searcher.add_document("2", "The house by the sea");
+
+        assert_eq!(searcher.search("Haus").keys().collect::<Vec<_>>(), ["1"]);
+        assert!(searcher.search("die").is_empty());
+        // English stop words are ordinary words now
+        assert!(searcher.search("the").contains_key("2"));
+    }
+
+    #[test]
+    fn test_multilingual_index() {
+        let mut index = MultilingualIndex::new(languages());
+        assert_eq!(index.add_document("1", "the rocket and the moon"), Some("en"));
+        assert_eq!(index.add_document("2", "der Mond und das Rakete"), Some("de"));
+        assert_eq!(index.add_document("3", "rocket"), Some("en"));
+        assert_eq!(index.language_of("2"), Some("de"));
+
+        // each language has its own document frequencies
+        assert_eq!(index.index("en").unwrap().term_stats().iter().find(|t| t.term == "rocket").unwrap().df, 2);
+        assert_eq!(index.index("de").unwrap().term_stats().len(), 2);
+
+        // a German query only searches German documents
+        let results = index.search("der rakete");
+        assert_eq!(results.keys().collect::<Vec<_>>(), ["2"]);
+        // an undetected language searches everything
+        assert_eq!(index.search("rakete rocket").len(), 3);
+    }
+}
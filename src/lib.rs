@@ -1,30 +1,462 @@
-use std::collections::HashMap;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-struct Document {
-    content: String,
-    nterms: i32, // number of terms (filtered words) in the document
+extern crate alloc;
+
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Bound, Range};
+
+#[cfg(feature = "std")]
+use caseless::Caseless;
+// the hash maps of `std`, or without it those of hashbrown, which `std` builds on
+#[cfg(not(feature = "std"))]
+pub(crate) use hashbrown::{HashMap, HashSet};
+#[cfg(feature = "std")]
+pub(crate) use std::collections::{HashMap, HashSet};
+
+pub mod alerts;
+pub mod analyzer;
+pub mod backend;
+#[cfg(feature = "rayon")]
+mod bulk;
+pub mod cache;
+pub mod classify;
+#[cfg(feature = "std")]
+pub mod concurrent;
+pub mod content;
+mod daat;
+pub mod dsl;
+pub mod engine;
+#[cfg(feature = "epub")]
+pub mod epub;
+pub mod events;
+pub mod explain;
+pub mod fields;
+pub mod format;
+mod fuzzy;
+pub mod highlight;
+mod hit;
+#[cfg(any(feature = "epub", feature = "sitemap"))]
+mod html;
+mod intervals;
+pub mod language;
+pub mod logs;
+mod math;
+pub mod metrics;
+mod nested;
+#[cfg(feature = "std")]
+mod parallel;
+mod path;
+pub mod passage;
+mod persist;
+mod postings;
+mod prune;
+pub mod query;
+#[cfg(feature = "sitemap")]
+mod robots;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scorer;
+pub mod scroll;
+#[cfg(feature = "sitemap")]
+pub mod sitemap;
+pub mod stats;
+#[cfg(feature = "std")]
+mod stem;
+pub mod subtitles;
+mod synonyms;
+#[cfg(feature = "summary")]
+pub mod summary;
+mod wildcard;
+pub mod xml;
+
+use backend::Failures;
+use intervals::Interval;
+pub use alerts::Alert;
+pub use analyzer::{Analyzer, SegmentingAnalyzer, SimpleTokenizer, StandardAnalyzer, Token, Tokenizer};
+pub use backend::{IndexBackend, MemoryBackend};
+pub use cache::Cache;
+#[cfg(feature = "std")]
+pub use cache::MemoryCache;
+pub use classify::Classification;
+#[cfg(feature = "std")]
+pub use concurrent::{IndexReader, IndexWriter};
+pub use content::ContentProvider;
+#[cfg(feature = "std")]
+pub use content::FileContentProvider;
+pub use daat::QueryContext;
+pub use dsl::DslError;
+pub use engine::{EngineError, SearchEngine};
+pub use events::{IndexEvent, IndexListener};
+pub use explain::{Explanation, TermExplanation};
+pub use fields::{Document, FieldScoring};
+#[cfg(feature = "std")]
+pub use format::NdjsonWriter;
+pub use format::ResultFormat;
+pub use highlight::{Boundary, Fragment, FragmentSettings, HighlightOptions, MatchedTerm};
+pub use hit::{SearchHit, SearchPage};
+pub use language::{Language, MultilingualIndex};
+pub use logs::LogEntry;
+pub use metrics::{LatencyHistogram, QueryMetrics, QuerySample};
+#[cfg(feature = "std")]
+pub use metrics::SlowQueryLog;
+pub use passage::{Granularity, Passage};
+pub use persist::PersistError;
+pub use postings::{Positions, Postings};
+pub use query::{BoolQuery, Intervals, ParseMode, Query};
+pub use scorer::{Bm25, Bm25L, Bm25Plus, Scorer, TfIdf};
+pub use scroll::{ScrollCursor, ScrollPage};
+pub use stats::{BuildReport, CollectionStats, IndexDiff, TermStats, TermVectorEntry};
+#[cfg(feature = "std")]
+pub use stem::Stemmer;
+pub use subtitles::Cue;
+pub use xml::{XmlError, XmlMapping};
+
+/// Hasher of the term and document maps, which are hashed on every indexed word.
+#[cfg(feature = "fx-hash")]
+type BuildHasher = rustc_hash::FxBuildHasher;
+#[cfg(all(not(feature = "fx-hash"), feature = "std"))]
+type BuildHasher = std::collections::hash_map::RandomState;
+#[cfg(all(not(feature = "fx-hash"), not(feature = "std")))]
+type BuildHasher = hashbrown::DefaultHashBuilder;
+
+pub(crate) type FastMap<K, V> = HashMap<K, V, BuildHasher>;
+
+/// A document id, shared between the document table and every posting, keyword and path entry
+/// of the document instead of being copied into each.
+pub(crate) type DocId = Arc<str>;
+
+struct StoredDoc {
+    content: Option<String>,                // None if fetched from the content provider
+    nterms: i32,                            // number of terms (filtered words) in the document
+    end_position: u32,                      // position after the last indexed word
+    keywords: HashMap<String, Vec<String>>, // field -> exact values
+    offsets: Option<Vec<Range<usize>>>,     // byte range in content of the word at each position, if stored
+    static_rank: f32,                       // query-independent multiplier of the document's scores
+    ordinal: u32,                           // indexing order, which postings lists are sorted by
+    terms: Vec<String>,                     // distinct terms with a posting for the document
+}
+
+/// The tokens of a document grouped by term, ready to be added to the index.
+struct AnalyzedDoc<'a> {
+    terms: FastMap<Cow<'a, str>, Positions>, // term -> positions in the document
+    nterms: i32,
+    end_position: u32,
+    offsets: Option<Vec<Range<usize>>>,
+}
+
+impl<'a> AnalyzedDoc<'a> {
+    fn new(tokens: Vec<Token<'a>>, store_offsets: bool) -> AnalyzedDoc<'a> {
+        let mut doc = AnalyzedDoc {
+            terms: FastMap::default(),
+            nterms: 0,
+            end_position: 0,
+            offsets: store_offsets.then(Vec::new),
+        };
+
+        // map the positions at which each term appears in the document
+        for token in tokens {
+            doc.nterms += 1;
+            doc.end_position = doc.end_position.max(token.position + 1);
+            if let Some(offsets) = doc.offsets.as_mut().filter(|offsets| offsets.len() <= token.position as usize) {
+                // stop words have no stored range
+                offsets.resize(token.position as usize, 0..0);
+                offsets.push(token.span);
+            }
+            doc.terms.entry(token.term).or_default().push(token.position);
+        }
+        doc
+    }
 }
 
-pub struct Searcher {
-    index: HashMap<String, HashMap<String, i32>>, // term -> doc_id -> count
-    docs: HashMap<String, Document>,              // doc_id -> document
-    avdl: f32,                                    // average document length
+/// An index of documents, searched by relevance. The postings of its terms are kept in `B`, in
+/// memory unless the Searcher was created `with_backend`.
+pub struct Searcher<B: IndexBackend = MemoryBackend> {
+    index: B,                                                   // term -> postings in document order
+    keywords: HashMap<String, HashMap<String, HashSet<DocId>>>, // field -> value -> doc_ids
+    paths: HashMap<String, HashSet<DocId>>,                     // path term -> doc_ids
+    nested: HashMap<String, nested::NestedDocs>,                // path -> child documents
+    fields: HashMap<String, Searcher>,                          // text field -> index of that field alone
+    field_boosts: HashMap<String, f32>,                         // text field -> weight of its matches, if boosted
+    field_scoring: FieldScoring,                                // how text queries combine the scores of the fields
+    copy_fields: HashSet<String>,                               // keyword fields also indexed as text
+    docs: FastMap<DocId, StoredDoc>,                             // doc_id -> document
+    collection: CollectionStats,                                // number and total length of the documents
+    next_ordinal: u32,                                          // ordinal of the next indexed document
+    stop_words_dropped: u64,                                    // words dropped as stop words while indexing
+    vocabulary_growth: Vec<(usize, usize)>,                     // (documents indexed, distinct terms) at powers of two
+    saved_queries: BTreeMap<String, (String, Query)>,           // name -> standing query, as written and parsed
+    alerted_ordinal: u32,                                       // documents from this ordinal on are new to the saved queries
 
     k1: f32, // limits the impact of term frequency for BM25
     b: f32,  // document length normalization parameter for BM25
+
+    tokenizer: Box<dyn Tokenizer + Send + Sync>,        // splits documents and queries into terms
+    scorer: Arc<dyn Scorer + Send + Sync>,              // turns term and document statistics into scores
+    stop_words: HashSet<String>,                        // words dropped during normalization
+    synonyms: HashMap<String, Vec<String>>,             // term -> terms of the same meaning queries also look for
+    #[cfg(feature = "std")]
+    stemmer: Option<Stemmer>,                           // reduces terms to their stem after stop words are dropped
+    query_languages: Vec<Language>,                     // languages text queries are detected among, if any
+    max_expansions: usize,                              // cap on the number of index terms a regex, wildcard or fuzzy query expands to
+    max_results: Option<usize>,                         // cap on the hits a query returns, if any
+    generation: u64,                                    // bumped on every change that can alter search results
+    store_offsets: bool,                                // keep the byte range of every term of new documents
+    index_paths: bool,                                  // index the ids of new documents as file paths
+    path_boost: f32,                                    // weight of path matches relative to content matches
+    #[cfg(feature = "std")]
+    metrics: Option<Arc<dyn QueryMetrics>>,             // receives the timing of every search, if set
+    listener: Option<Arc<dyn IndexListener>>,           // notified of every change to the index, if set
+    content_provider: Option<Arc<dyn ContentProvider>>, // fetches the content of new documents instead of keeping it, if set
+    idf_cache: Option<Arc<cache::IdfCache>>,            // idf of the terms looked up, if set
+}
+
+/// Error returned when a runtime configuration value is rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// k1 must be finite and positive.
+    InvalidK1(f32),
+    /// b must lie within `[0, 1]`.
+    InvalidB(f32),
+    /// Boosts must be finite and non-negative.
+    InvalidBoost(f32),
+    /// Synonyms must be single words that aren't stop words.
+    InvalidSynonym(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidK1(k1) => write!(f, "invalid k1 `{}`: expected a finite value > 0", k1),
+            ConfigError::InvalidB(b) => write!(f, "invalid b `{}`: expected a value in [0, 1]", b),
+            ConfigError::InvalidBoost(boost) => write!(f, "invalid boost `{}`: expected a finite value >= 0", boost),
+            ConfigError::InvalidSynonym(word) => write!(f, "invalid synonym `{}`: expected a single word that isn't a stop word", word),
+        }
+    }
+}
+
+impl core::error::Error for ConfigError {}
+
+fn validate_k1(k1: f32) -> Result<f32, ConfigError> {
+    if !k1.is_finite() || k1 <= 0.0 {
+        return Err(ConfigError::InvalidK1(k1));
+    }
+    Ok(k1)
+}
+
+fn validate_boost(boost: f32) -> Result<f32, ConfigError> {
+    if !boost.is_finite() || boost < 0.0 {
+        return Err(ConfigError::InvalidBoost(boost));
+    }
+    Ok(boost)
+}
+
+fn validate_b(b: f32) -> Result<f32, ConfigError> {
+    if !(0.0..=1.0).contains(&b) {
+        return Err(ConfigError::InvalidB(b));
+    }
+    Ok(b)
+}
+
+/// Overrides applied to a single search, leaving the Searcher's own configuration untouched.
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    pub k1: Option<f32>,
+    pub b: Option<f32>,
+    /// Minimum number of distinct query terms a document has to contain to be returned.
+    pub min_should_match: Option<usize>,
+    /// Weights of text fields in place of their boosts, see `Searcher::set_field_boost`; 0 leaves
+    /// a field out. Free text `search_with` doesn't search fields, so only queries use them.
+    pub field_weights: HashMap<String, f32>,
+}
+
+#[cfg(feature = "stop-words")]
+fn english_stop_words() -> HashSet<String> {
+    stop_words::get(stop_words::LANGUAGE::English).into_iter().collect()
+}
+
+/// Without the bundled lists nothing is dropped until `set_stop_words` is called.
+#[cfg(not(feature = "stop-words"))]
+fn english_stop_words() -> HashSet<String> {
+    HashSet::new()
+}
+
+/// Unicode default case folding of a character, e.g. `ß` to `ss` and final `ς` to `σ`. `İ` folds to
+/// a plain `i`, without the combining dot that would split the word.
+#[cfg(feature = "std")]
+pub(crate) fn fold_char(c: char) -> impl Iterator<Item = char> {
+    core::iter::once(c).default_case_fold().filter(move |&folded| !(c == 'İ' && folded == '\u{307}'))
+}
+
+/// Without `std` there is no case folding table: lowercasing folds all but a few characters, e.g.
+/// `ß` stays as is.
+#[cfg(not(feature = "std"))]
+pub(crate) fn fold_char(c: char) -> impl Iterator<Item = char> {
+    c.to_lowercase().filter(move |&folded| !(c == 'İ' && folded == '\u{307}'))
+}
+
+/// Case fold a whole string, for terms and stop words that bypass `analyze`.
+pub(crate) fn fold_case(s: &str) -> String {
+    s.chars().flat_map(fold_char).collect()
+}
+
+/// Split a string into case folded alphanumeric words, dropping stop words. Works on the original
+/// text so every term keeps the byte range it came from, and borrows it when folding left the
+/// word unchanged.
+fn analyze<'a>(s: &'a str, stop_words: &HashSet<String>) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    let mut link_tokens = Vec::new();
+    let mut links = link_spans(s).into_iter().peekable();
+    let mut position = 0;
+    let mut word = String::new();
+    let mut span = 0..0;
+
+    let end_word = |word: &mut String, span: &Range<usize>, position: &mut u32, tokens: &mut Vec<Token<'a>>| {
+        let term = if word.contains([',', '.']) {
+            Cow::Owned(normalize_number(word))
+        } else if *word == s[span.clone()] {
+            Cow::Borrowed(&s[span.clone()])
+        } else {
+            Cow::Owned(word.clone())
+        };
+        if !stop_words.contains(term.as_ref()) {
+            tokens.push(Token {
+                term,
+                position: *position,
+                span: span.clone(),
+            });
+        }
+        *position += 1;
+        word.clear();
+    };
+
+    for (i, c) in s.char_indices() {
+        // URLs and email addresses are also terms of their own, at the position of their first word
+        while let Some(link) = links.next_if(|link| link.start <= i) {
+            link_tokens.push(Token {
+                term: Cow::Owned(fold_case(&s[link.clone()])),
+                position: position + !word.is_empty() as u32,
+                span: link,
+            });
+        }
+        if joins_number(s, span.start, i, c, &word) {
+            word.push(c);
+            continue;
+        }
+        for folded in fold_char(c) {
+            if folded.is_alphanumeric() {
+                if word.is_empty() {
+                    span.start = i;
+                }
+                span.end = i + c.len_utf8();
+                word.push(folded);
+            } else if !word.is_empty() {
+                end_word(&mut word, &span, &mut position, &mut tokens);
+            }
+        }
+    }
+    if !word.is_empty() {
+        end_word(&mut word, &span, &mut position, &mut tokens);
+    }
+
+    if !link_tokens.is_empty() {
+        // stable, so the words of a link keep coming first
+        tokens.extend(link_tokens);
+        tokens.sort_by_key(|token| token.position);
+    }
+    tokens
+}
+
+/// Byte ranges of the URLs (`scheme://...`) and email addresses in `s`, in order.
+fn link_spans(s: &str) -> Vec<Range<usize>> {
+    let in_url = |c: char| !c.is_whitespace() && !matches!(c, '<' | '>' | '"' | '\'' | '(' | ')' | '[' | ']');
+    let in_scheme = |c: char| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-');
+    let in_email = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-');
+    let trailing = |c: char| matches!(c, '.' | ',' | ';' | ':' | '!' | '?' | '-');
+
+    let mut spans: Vec<Range<usize>> = Vec::new();
+    for (at, _) in s.match_indices("://") {
+        let start = s[..at].rfind(|c: char| !in_scheme(c)).map_or(0, |i| i + 1);
+        let rest = &s[at + 3..];
+        let end = at + 3 + rest.find(|c: char| !in_url(c)).unwrap_or(rest.len());
+        let end = start + s[start..end].trim_end_matches(trailing).len();
+        if s[start..].starts_with(|c: char| c.is_ascii_alphabetic()) && end > at + 3 {
+            spans.push(start..end);
+        }
+    }
+    for (at, _) in s.match_indices('@') {
+        let start = s[..at].rfind(|c: char| !in_email(c)).map_or(0, |i| i + 1);
+        let rest = &s[at + 1..];
+        let end = at + 1 + rest.find(|c: char| !in_email(c) || c == '%' || c == '+').unwrap_or(rest.len());
+        let end = start + s[start..end].trim_end_matches(trailing).len();
+        let is_email = start < at && s[at + 1..end.max(at + 1)].contains('.');
+        if is_email && !spans.iter().any(|url| url.start < end && start < url.end) {
+            spans.push(start..end);
+        }
+    }
+    spans.sort_by_key(|span| span.start);
+    spans
+}
+
+/// Whether `c` at byte `i` joins the digits read so far in `word` (starting at `word_start`) with
+/// the digits after it: a thousands separator (`1,000`) or a decimal or version point (`3.14`,
+/// `1.2.3`). Numbers right after a point belong to a dotted name like `v1.2.3` and aren't joined.
+fn joins_number(s: &str, word_start: usize, i: usize, c: char, word: &str) -> bool {
+    if word.is_empty() || !word.bytes().all(|b| b.is_ascii_digit() || b == b',' || b == b'.') || s[..word_start].ends_with('.') {
+        return false;
+    }
+    let digits = s[i + c.len_utf8()..].bytes().take_while(u8::is_ascii_digit).count();
+    match c {
+        ',' => digits == 3 && !word.contains('.'),
+        '.' => digits > 0,
+        _ => false,
+    }
+}
+
+/// Canonical form of a number read with its separators: thousands separators are dropped and
+/// trailing zeros of a decimal fraction removed, so `1,000.50` becomes `1000.5`. Numbers with
+/// several points, like versions, are kept as written.
+fn normalize_number(word: &str) -> String {
+    let number: String = word.chars().filter(|&c| c != ',').collect();
+    match number.split_once('.') {
+        Some((integer, fraction)) if !fraction.contains('.') => match fraction.trim_end_matches('0') {
+            "" => integer.to_string(),
+            fraction => format!("{}.{}", integer, fraction),
+        },
+        _ => number,
+    }
+}
+
+/// The value of `key` in `map`, inserted if missing. Only allocates the key when it is new.
+fn entry_mut<'m, V: Default>(map: &'m mut FastMap<String, V>, key: &str) -> &'m mut V {
+    if !map.contains_key(key) {
+        map.insert(key.to_string(), V::default());
+    }
+    map.get_mut(key).expect("key was just inserted")
 }
 
-/// Normalize a string by removing non-alphanumeric characters, converting to lowercase, and removing stop words.
-fn normalize_string(s: &str) -> String {
-    let stop_words_eng = stop_words::get(stop_words::LANGUAGE::English);
-    let non_words_re = regex::Regex::new(r"[^a-z0-9 ]").unwrap();
+/// Order keyword values as numbers when both are numbers, as strings otherwise.
+fn compare_values(a: &str, b: &str) -> core::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
 
-    non_words_re
-        .replace_all(&s.to_lowercase(), " ")
-        .split_whitespace()
-        .filter(|word| !stop_words_eng.contains(&word.to_string()))
-        .collect::<Vec<&str>>()
-        .join(" ")
+/// Add up per-term scores into a total score per document.
+fn sum_scores<I: IntoIterator<Item = HashMap<String, f32>>>(term_scores: I) -> HashMap<String, f32> {
+    term_scores.into_iter().fold(HashMap::new(), |mut acc, scores| {
+        for (doc_id, score) in scores {
+            *acc.entry(doc_id).or_insert(0.0) += score;
+        }
+        acc
+    })
 }
 
 impl Default for Searcher {
@@ -35,89 +467,978 @@ impl Default for Searcher {
 
 impl Searcher {
     pub fn new() -> Searcher {
+        Searcher::with_backend(MemoryBackend::default())
+    }
+
+    /// A Searcher scoring with BM25 parameters `k1` (term frequency saturation, > 0) and `b`
+    /// (document length normalization, in `[0, 1]`) instead of the defaults 1.2 and 0.75. Short
+    /// documents of similar length often rank better with a lower `b`.
+    pub fn with_params(k1: f32, b: f32) -> Result<Searcher, ConfigError> {
+        Ok(Searcher {
+            k1: validate_k1(k1)?,
+            b: validate_b(b)?,
+            ..Searcher::new()
+        })
+    }
+
+    /// A Searcher that splits documents and queries into terms with `tokenizer`.
+    pub fn with_tokenizer<T: Tokenizer + Send + Sync + 'static>(tokenizer: T) -> Searcher {
         Searcher {
-            index: HashMap::new(),
-            docs: HashMap::new(),
-            avdl: 0.0,
+            tokenizer: Box::new(tokenizer),
+            ..Searcher::new()
+        }
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// An empty Searcher keeping its postings in `backend` instead of in memory. Saving and
+    /// loading an index only covers Searchers with postings in memory. Reads of the backend that
+    /// fail are taken as finding nothing; use the Searcher as a `SearchEngine` to get the errors.
+    pub fn with_backend(backend: B) -> Searcher<B> {
+        Searcher {
+            index: backend,
+            keywords: HashMap::new(),
+            paths: HashMap::new(),
+            nested: HashMap::new(),
+            fields: HashMap::new(),
+            field_boosts: HashMap::new(),
+            field_scoring: FieldScoring::default(),
+            copy_fields: HashSet::new(),
+            docs: FastMap::default(),
+            collection: CollectionStats::default(),
+            next_ordinal: 0,
+            stop_words_dropped: 0,
+            vocabulary_growth: Vec::new(),
+            saved_queries: BTreeMap::new(),
+            alerted_ordinal: 0,
 
             k1: 1.2,
             b: 0.75,
+
+            tokenizer: Box::new(SimpleTokenizer),
+            scorer: Arc::new(Bm25),
+            stop_words: english_stop_words(),
+            synonyms: HashMap::new(),
+            #[cfg(feature = "std")]
+            stemmer: None,
+            query_languages: Vec::new(),
+            max_expansions: 64,
+            max_results: Some(10_000),
+            generation: 0,
+            store_offsets: false,
+            index_paths: false,
+            path_boost: 2.0,
+            #[cfg(feature = "std")]
+            metrics: None,
+            listener: None,
+            content_provider: None,
+            idf_cache: None,
         }
     }
 
+    /// Replace the tokenizer, e.g. after loading an index that was built with a custom one.
+    /// Documents already indexed keep the terms they were split into.
+    pub fn set_tokenizer<T: Tokenizer + Send + Sync + 'static>(&mut self, tokenizer: T) {
+        self.tokenizer = Box::new(tokenizer);
+        self.generation += 1;
+    }
+
+    /// The terms of `text` as the tokenizer splits them, without stop words. Stop words still take
+    /// up a position, so that proximity between the remaining terms is preserved.
+    pub(crate) fn analyze_text<'a>(&self, text: &'a str) -> Vec<Token<'a>> {
+        self.analyze_with(text, &self.stop_words)
+    }
+
+    fn analyze_with<'a>(&self, text: &'a str, stop_words: &HashSet<String>) -> Vec<Token<'a>> {
+        self.analyze_dropping(text, stop_words).0
+    }
+
+    /// Same as `analyze_with`, also returning how many tokens were dropped as stop words.
+    fn analyze_dropping<'a>(&self, text: &'a str, stop_words: &HashSet<String>) -> (Vec<Token<'a>>, usize) {
+        let mut tokens = self.tokenizer.tokens(text);
+        let count = tokens.len();
+        tokens.retain(|token| !stop_words.contains(token.term.as_ref()));
+        #[cfg(feature = "std")]
+        if let Some(stemmer) = self.stemmer {
+            stemmer.stem_tokens(&mut tokens);
+        }
+        let dropped = count - tokens.len();
+        (tokens, dropped)
+    }
+
+    /// The terms of a document's text, counting the stop words dropped from it.
+    fn analyze_document<'a>(&mut self, text: &'a str) -> Vec<Token<'a>> {
+        let (tokens, dropped) = self.analyze_dropping(text, &self.stop_words);
+        self.stop_words_dropped += dropped as u64;
+        tokens
+    }
+
+    pub fn k1(&self) -> f32 {
+        self.k1
+    }
+
+    pub fn b(&self) -> f32 {
+        self.b
+    }
+
+    /// Update k1 at runtime; takes effect on the next search.
+    pub fn set_k1(&mut self, k1: f32) -> Result<(), ConfigError> {
+        self.k1 = validate_k1(k1)?;
+        Ok(())
+    }
+
+    /// Update b at runtime; takes effect on the next search.
+    pub fn set_b(&mut self, b: f32) -> Result<(), ConfigError> {
+        self.b = validate_b(b)?;
+        Ok(())
+    }
+
+    /// Score documents with `scorer` instead of BM25, e.g. `TfIdf` or `Bm25Plus`. Like tokenizers,
+    /// scorers aren't saved with the index: set it again after loading one.
+    pub fn set_scorer<S: Scorer + Send + Sync + 'static>(&mut self, scorer: S) {
+        self.scorer = Arc::new(scorer);
+        self.generation += 1;
+        let scorer = self.scorer.clone();
+        for child in self.children_mut() {
+            child.scorer = scorer.clone();
+        }
+    }
+
+    /// Replace the stop word list. Queries use the new list immediately, but documents
+    /// that are already indexed keep the terms they were indexed with.
+    pub fn set_stop_words<I, S>(&mut self, words: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.stop_words = words.into_iter().map(|w| fold_case(w.as_ref())).collect();
+        self.generation += 1;
+        let stop_words = self.stop_words.clone();
+        for child in self.children_mut() {
+            child.stop_words = stop_words.clone();
+        }
+    }
+
+    /// Reduce terms to their stem, e.g. `running` to `run`, or stop doing so with None. Like stop
+    /// words, the stemmer has to be the same at index and query time, so set it before adding
+    /// documents: those already indexed keep the terms they were indexed with.
+    #[cfg(feature = "std")]
+    pub fn set_stemmer(&mut self, stemmer: Option<Stemmer>) {
+        self.stemmer = stemmer;
+        self.generation += 1;
+        for child in self.children_mut() {
+            child.stemmer = stemmer;
+        }
+    }
+
+    /// Limit how many index terms a single regex, wildcard or fuzzy query expands to. The most frequent terms are kept.
+    pub fn set_max_expansions(&mut self, max_expansions: usize) {
+        self.max_expansions = max_expansions;
+        self.generation += 1;
+        for child in self.children_mut() {
+            child.max_expansions = max_expansions;
+        }
+    }
+
+    /// Cap the hits a query returns, whatever limit the caller asks for, so that a caller asking
+    /// for everything can't get millions of them at once; 10,000 by default. Searches returning
+    /// every hit keep the best ones. None lifts the cap for callers that do need every hit, which
+    /// `scroll` pages through with or without it.
+    pub fn set_max_results(&mut self, max_results: Option<usize>) {
+        self.max_results = max_results;
+        self.generation += 1;
+    }
+
+    /// A new Searcher analyzing and expanding terms like this one, for nested documents and fields.
+    fn child_searcher(&self) -> Searcher {
+        let mut child = Searcher::new();
+        child.stop_words = self.stop_words.clone();
+        child.synonyms = self.synonyms.clone();
+        #[cfg(feature = "std")]
+        {
+            child.stemmer = self.stemmer;
+        }
+        child.max_expansions = self.max_expansions;
+        child.scorer = self.scorer.clone();
+        child
+    }
+
+    /// The Searchers of nested documents and fields, which follow this one's analysis settings.
+    fn children_mut(&mut self) -> impl Iterator<Item = &mut Searcher> {
+        self.nested.values_mut().map(|nested| &mut nested.searcher).chain(self.fields.values_mut())
+    }
+
+    /// Keep the byte offsets of the terms of documents added from now on, so that highlighting can
+    /// use them instead of analyzing the stored content again. Costs memory per indexed word.
+    pub fn set_store_offsets(&mut self, store_offsets: bool) {
+        self.store_offsets = store_offsets;
+    }
+
+    /// Add a document. A document already indexed under `doc_id` is replaced: it is removed first,
+    /// with everything `remove_document` removes, so nothing of it is left in the index.
     pub fn add_document(&mut self, doc_id: &str, doc_content: &str) {
-        let filtered_content = normalize_string(doc_content);
-        let mut nterms = 0;
+        self.add_document_in(doc_id, doc_content, &Failures::default());
+    }
+
+    /// Same as `add_document`, keeping the errors of the backend in `failures`.
+    pub(crate) fn add_document_in(&mut self, doc_id: &str, doc_content: &str, failures: &Failures) {
+        let tokens = self.analyze_document(doc_content);
+        self.insert_tokens(doc_id, doc_content, tokens, failures);
+    }
+
+    /// Add documents, as pairs of doc id and content, in order, analyzing them on all cores with
+    /// the `rayon` feature. The backend gets the documents together rather than one by one.
+    pub(crate) fn add_batch(&mut self, docs: Vec<(String, String)>, failures: &Failures) {
+        #[cfg(feature = "rayon")]
+        self.add_documents_parallel_in(docs, failures);
+        #[cfg(not(feature = "rayon"))]
+        {
+            for (doc_id, content) in &docs {
+                let analyzed = AnalyzedDoc::new(self.analyze_document(content), self.store_offsets);
+                self.insert_analyzed(doc_id, content, analyzed, failures);
+            }
+            failures.ok(self.index.flush());
+        }
+    }
+
+    /// Index a document with its own analysis instead of the Searcher's, e.g. for code or CJK text
+    /// in an otherwise English corpus. Search it with `search_with_analyzer` using a compatible
+    /// analyzer. Highlighting re-analyzes with the Searcher's tokenizer unless offsets are stored.
+    pub fn add_document_with_analyzer(&mut self, doc_id: &str, doc_content: &str, analyzer: &dyn Analyzer) {
+        let tokens = analyzer.analyze(doc_content);
+        self.insert_tokens(doc_id, doc_content, tokens, &Failures::default());
+    }
+
+    /// Add a document, replacing any document with the same id, and tell the listener. The backend
+    /// gets the removal and the addition together.
+    fn insert_tokens(&mut self, doc_id: &str, doc_content: &str, tokens: Vec<Token>, failures: &Failures) {
+        let analyzed = AnalyzedDoc::new(tokens, self.store_offsets);
+        self.insert_analyzed(doc_id, doc_content, analyzed, failures);
+        failures.ok(self.index.flush());
+    }
+
+    fn insert_analyzed(&mut self, doc_id: &str, doc_content: &str, analyzed: AnalyzedDoc, failures: &Failures) {
+        let replaced = self.remove_quietly(doc_id);
+        self.add_analyzed(doc_id, doc_content, analyzed, failures);
+        if replaced {
+            self.notify(IndexEvent::Updated { doc_id });
+        } else {
+            self.notify(IndexEvent::Added { doc_id });
+        }
+    }
+
+    fn add_tokens(&mut self, doc_id: &str, doc_content: &str, tokens: Vec<Token>, failures: &Failures) {
+        let analyzed = AnalyzedDoc::new(tokens, self.store_offsets);
+        self.add_analyzed(doc_id, doc_content, analyzed, failures);
+    }
+
+    fn add_analyzed(&mut self, doc_id: &str, doc_content: &str, analyzed: AnalyzedDoc, failures: &Failures) {
+        let doc_id = self.interned(doc_id).unwrap_or_else(|| DocId::from(doc_id));
+        let ordinal = self.next_ordinal;
+        self.next_ordinal += 1;
+        let nterms = analyzed.nterms;
 
-        // map the number of times each term appears in the document
-        for term in filtered_content.split_whitespace() {
-            nterms += 1;
-            let term = term.to_string();
-            let doc_index = self.index.entry(term).or_default();
-            doc_index.entry(doc_id.to_string()).and_modify(|x| *x += 1).or_insert(1);
+        let mut terms = Vec::with_capacity(analyzed.terms.len());
+        for (term, positions) in analyzed.terms {
+            self.index.add_postings(&term, ordinal, &doc_id, positions);
+            terms.push(term.into_owned());
         }
 
         self.docs.insert(
-            doc_id.to_string(),
-            Document {
-                content: doc_content.to_string(),
+            doc_id.clone(),
+            StoredDoc {
+                content: self.content_provider.is_none().then(|| doc_content.to_string()),
                 nterms,
+                end_position: analyzed.end_position,
+                keywords: HashMap::new(),
+                offsets: analyzed.offsets,
+                static_rank: 1.0,
+                ordinal,
+                terms,
             },
         );
 
-        // recalculate the average document length
-        self.avdl =
-            (self.avdl * (self.docs.len() - 1) as f32 + nterms as f32) / self.docs.len() as f32;
+        if self.index_paths {
+            self.add_path(&doc_id);
+        }
+        if self.next_ordinal.is_power_of_two() {
+            self.vocabulary_growth.push((self.next_ordinal as usize, failures.ok(self.index.term_count())));
+        }
+        self.collection.add(nterms as u64);
+        self.generation += 1;
     }
 
-    /// Receives a query, normalizes it, gets a score for each query term and returns a hashmap of doc_id -> total score
-    pub fn search(&self, query: &str) -> HashMap<String, f32> {
-        let normalized_query = normalize_string(query);
-        normalized_query
-            .split_whitespace()
-            .map(|term| self.bm25(term))
-            .fold(HashMap::new(), |mut acc, scores| {
-                for (doc_id, score) in scores {
-                    let total_score = acc.entry(doc_id).or_insert(0.0);
-                    *total_score += score;
+    /// Remove a document with its postings, keyword values, path terms and nested children. Terms
+    /// left without postings are dropped. Returns false if the document does not exist.
+    pub fn remove_document(&mut self, doc_id: &str) -> bool {
+        self.remove_document_in(doc_id, &Failures::default())
+    }
+
+    /// Same as `remove_document`, keeping the errors of the backend in `failures`.
+    pub(crate) fn remove_document_in(&mut self, doc_id: &str, failures: &Failures) -> bool {
+        let removed = self.remove_quietly(doc_id);
+        failures.ok(self.index.flush());
+        if removed {
+            self.notify(IndexEvent::Removed { doc_id });
+        }
+        removed
+    }
+
+    /// `remove_document` without telling the listener.
+    fn remove_quietly(&mut self, doc_id: &str) -> bool {
+        let Some(doc) = self.docs.remove(doc_id) else {
+            return false;
+        };
+
+        self.remove_postings(&doc);
+
+        for (field, values) in &doc.keywords {
+            let Some(field_values) = self.keywords.get_mut(field) else {
+                continue;
+            };
+            for value in values {
+                if let Some(docs) = field_values.get_mut(value) {
+                    docs.remove(doc_id);
+                    if docs.is_empty() {
+                        field_values.remove(value);
+                    }
                 }
-                acc
-            })
+            }
+            if field_values.is_empty() {
+                self.keywords.remove(field);
+            }
+        }
+
+        for term in path::path_terms(doc_id) {
+            if let Some(docs) = self.paths.get_mut(&term) {
+                docs.remove(doc_id);
+                if docs.is_empty() {
+                    self.paths.remove(&term);
+                }
+            }
+        }
+
+        for nested in self.nested.values_mut() {
+            let children: Vec<String> = nested
+                .parents
+                .iter()
+                .filter(|(_, parent)| *parent == doc_id)
+                .map(|(child_id, _)| child_id.clone())
+                .collect();
+            for child_id in children {
+                nested.searcher.remove_document(&child_id);
+                nested.parents.remove(&child_id);
+            }
+        }
+        for field in self.fields.values_mut() {
+            field.remove_quietly(doc_id);
+        }
+
+        self.collection.remove(doc.nterms as u64);
+        self.generation += 1;
+        true
     }
 
-    fn idf(&self, term: &str) -> f32 {
-        let docs_count = self.docs.len() as f32;
+    /// Drop the postings of a removed document, and the terms left without any. Only the terms of
+    /// the document are visited, not the whole vocabulary.
+    fn remove_postings(&mut self, doc: &StoredDoc) {
+        for term in &doc.terms {
+            self.index.remove_postings(term, doc.ordinal);
+        }
+    }
 
-        
-        let docs_with_term_count = match self.index.get(term) {
-            None => 0 as f32,
-            Some(docs) => docs.len() as f32,
+    /// Replace the content of a document, re-indexing it in place. Keyword values, including
+    /// copied ones, nested children and the static rank are kept. Adds the document if it does
+    /// not exist yet.
+    pub fn update_document(&mut self, doc_id: &str, doc_content: &str) {
+        self.update_document_in(doc_id, doc_content, &Failures::default());
+    }
+
+    /// Same as `update_document`, keeping the errors of the backend in `failures`.
+    pub(crate) fn update_document_in(&mut self, doc_id: &str, doc_content: &str, failures: &Failures) {
+        let Some(old) = self.docs.remove(doc_id) else {
+            self.add_document_in(doc_id, doc_content, failures);
+            return;
         };
-    
-        // idf smooth variant
-        ((docs_count - docs_with_term_count + 0.5) / (docs_with_term_count + 0.5) + 1.0).ln()
+        self.remove_postings(&old);
+        self.collection.remove(old.nterms as u64);
+
+        let tokens = self.analyze_document(doc_content);
+        self.add_tokens(doc_id, doc_content, tokens, failures);
+        failures.ok(self.index.flush());
+        for (field, values) in &old.keywords {
+            for value in values {
+                self.add_keyword_in(doc_id, field, value, failures);
+            }
+        }
+        if let Some(doc) = self.docs.get_mut(doc_id) {
+            doc.static_rank = old.static_rank;
+        }
+        self.notify(IndexEvent::Updated { doc_id });
+    }
+
+    /// Attach an exact value to a keyword field of a document, e.g. `("ext", "rs")`. Keyword values are
+    /// not analyzed and are matched with `field:value` queries. Returns false if the document does not exist.
+    pub fn add_keyword(&mut self, doc_id: &str, field: &str, value: &str) -> bool {
+        self.add_keyword_in(doc_id, field, value, &Failures::default())
+    }
+
+    /// Same as `add_keyword`, keeping the errors of the backend in `failures`.
+    pub(crate) fn add_keyword_in(&mut self, doc_id: &str, field: &str, value: &str, failures: &Failures) -> bool {
+        let Some(doc_id) = self.interned(doc_id) else {
+            return false;
+        };
+        let copied = self.copy_fields.contains(field);
+        let tokens = if copied { self.analyze_document(value) } else { Vec::new() };
+        let doc = self.docs.get_mut(&doc_id).expect("interned ids belong to documents");
+        let values = doc.keywords.entry(field.to_string()).or_default();
+        if values.iter().any(|v| v == value) {
+            return true;
+        }
+        values.push(value.to_string());
+
+        if copied {
+            // leave a one position gap so phrases can't span the content and the copied value
+            let start = doc.end_position + 1;
+            for token in &tokens {
+                doc.end_position = start + token.position + 1;
+                let postings = failures.ok(self.index.postings(&token.term));
+                let mut positions = postings.and_then(|postings| postings.get(doc.ordinal).cloned()).unwrap_or_default();
+                positions.push(start + token.position);
+                self.index.add_postings(&token.term, doc.ordinal, &doc_id, positions);
+                if !doc.terms.iter().any(|term| *term == token.term) {
+                    doc.terms.push(token.term.to_string());
+                }
+            }
+            doc.nterms += tokens.len() as i32;
+            self.collection.extend(tokens.len() as u64);
+            failures.ok(self.index.flush());
+        }
+
+        self.keywords
+            .entry(field.to_string())
+            .or_default()
+            .entry(value.to_string())
+            .or_default()
+            .insert(doc_id);
+        self.generation += 1;
+        true
+    }
+
+    /// The shared id of an indexed document.
+    fn interned(&self, doc_id: &str) -> Option<DocId> {
+        self.docs.get_key_value(doc_id).map(|(id, _)| id.clone())
+    }
+
+    /// Set the query-independent rank of a document, e.g. from signals about its source. Every
+    /// score of the document is multiplied by it, so 1.0 leaves it unchanged. Returns false if the
+    /// document does not exist.
+    pub fn set_static_rank(&mut self, doc_id: &str, rank: f32) -> Result<bool, ConfigError> {
+        let rank = validate_boost(rank)?;
+        let Some(doc) = self.docs.get_mut(doc_id) else {
+            return Ok(false);
+        };
+        doc.static_rank = rank;
+        self.generation += 1;
+        Ok(true)
+    }
+
+    /// Multiply every score by the static rank of its document.
+    fn apply_static_ranks(&self, mut scores: HashMap<String, f32>) -> HashMap<String, f32> {
+        self.apply_static_ranks_in(&mut scores);
+        scores
+    }
+
+    fn apply_static_ranks_in(&self, scores: &mut HashMap<String, f32>) {
+        for (doc_id, score) in scores.iter_mut() {
+            if let Some(doc) = self.docs.get(doc_id.as_str()) {
+                *score *= doc.static_rank;
+            }
+        }
+    }
+
+    /// Also index the values of keyword field `field` as text of the document, so that unqualified
+    /// queries find them while `field:value` queries still match exactly. Applies to values added
+    /// from now on.
+    pub fn add_copy_field(&mut self, field: &str) {
+        self.copy_fields.insert(field.to_string());
+    }
+
+    /// Count how many of the given documents have each value of a keyword field.
+    pub fn facet_counts<I, S>(&self, field: &str, doc_ids: I) -> HashMap<String, usize>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut counts = HashMap::new();
+        for doc_id in doc_ids {
+            let values = self.docs.get(doc_id.as_ref()).and_then(|doc| doc.keywords.get(field));
+            for value in values.into_iter().flatten() {
+                *counts.entry(value.clone()).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    /// Receives a query, normalizes it, gets a score for each query term and returns a hashmap of doc_id -> total score.
+    /// Documents have to contain the words of each `"quoted phrase"` next to each other, in order.
+    pub fn search(&self, query: &str) -> HashMap<String, f32> {
+        self.search_scored(query, self.k1, self.b, None)
+    }
+
+    /// Same as `search`, with the query analyzed by `analyzer`, e.g. the one its target documents
+    /// were indexed with.
+    pub fn search_with_analyzer(&self, query: &str, analyzer: &dyn Analyzer) -> HashMap<String, f32> {
+        let mut timer = self.query_timer();
+        let terms = analyzer.analyze(query).into_iter().map(|token| token.term.into_owned()).collect();
+        timer.phase("analysis");
+        let mut results = self.search_terms(terms, self.k1, self.b, None, &Failures::default());
+        self.cap_results(&mut results);
+        timer.phase("scoring");
+        timer.finish(|| query.to_string(), results.len());
+        results
+    }
+
+    /// Same as `search`, but with the scoring parameters in `options` overriding the Searcher's for this call only.
+    pub fn search_with(&self, query: &str, options: &SearchOptions) -> Result<HashMap<String, f32>, ConfigError> {
+        let k1 = options.k1.map(validate_k1).transpose()?.unwrap_or(self.k1);
+        let b = options.b.map(validate_b).transpose()?.unwrap_or(self.b);
+        Ok(self.search_scored(query, k1, b, options.min_should_match))
+    }
+
+    fn search_scored(&self, query: &str, k1: f32, b: f32, min_should_match: Option<usize>) -> HashMap<String, f32> {
+        let mut timer = self.query_timer();
+        let tokens = self.analyze_with(query, &self.query_stop_words(query));
+        let terms = tokens.into_iter().map(|token| token.term.into_owned()).collect();
+        timer.phase("analysis");
+        let failures = Failures::default();
+        let mut results = self.search_terms(terms, k1, b, min_should_match, &failures);
+        if let Some(phrase_docs) = self.phrase_docs(query, &failures) {
+            results.retain(|doc_id, _| phrase_docs.contains(doc_id.as_str()));
+        }
+        self.cap_results(&mut results);
+        timer.phase("scoring");
+        timer.finish(|| query.to_string(), results.len());
+        results
+    }
+
+    /// The documents containing every `"quoted phrase"` of a free text query, with the words of
+    /// each phrase next to each other in order. None when the query quotes nothing, phrases of
+    /// only stop words don't count.
+    pub(crate) fn phrase_docs(&self, query: &str, failures: &Failures) -> Option<HashSet<&str>> {
+        let mut phrases = query::quoted_phrases(query).into_iter().filter(|phrase| !self.analyze_text(phrase).is_empty());
+        let first = phrases.next()?;
+        let mut docs: HashSet<&str> = self.match_intervals(&Intervals::term(first), failures).into_keys().collect();
+        for phrase in phrases {
+            let matched = self.match_intervals(&Intervals::term(phrase), failures);
+            docs.retain(|doc_id| matched.contains_key(doc_id));
+        }
+        Some(docs)
+    }
+
+    /// Drop all but the best `max_results` of `results`, ranked like hits.
+    fn cap_results(&self, results: &mut HashMap<String, f32>) {
+        let Some(max_results) = self.max_results.filter(|max_results| results.len() > *max_results) else {
+            return;
+        };
+        let mut ranked: Vec<(String, f32)> = results.drain().collect();
+        ranked.select_nth_unstable_by(max_results, hit::by_rank);
+        ranked.truncate(max_results);
+        results.extend(ranked);
+    }
+
+    fn search_terms(&self, terms: Vec<String>, k1: f32, b: f32, min_should_match: Option<usize>, failures: &Failures) -> HashMap<String, f32> {
+        let terms = self.with_synonyms(terms.iter().map(String::as_str));
+        self.apply_static_ranks(self.score_terms(terms, k1, b, min_should_match.unwrap_or(1), failures))
+    }
+
+    /// Evaluate a parsed `Query` and return a hashmap of doc_id -> total score
+    pub fn search_query(&self, query: &Query) -> HashMap<String, f32> {
+        let mut results = self.search_query_in(query, &Failures::default());
+        self.cap_results(&mut results);
+        results
+    }
+
+    /// Same as `search_query`, keeping the errors of the backend in `failures`, and with every
+    /// document found whatever `max_results` is.
+    pub(crate) fn search_query_in(&self, query: &Query, failures: &Failures) -> HashMap<String, f32> {
+        self.run_query(query, self.k1, self.b, &self.field_boosts, None, failures)
+    }
+
+    /// Same as `search_query`, with `options` applied to this call only. `min_should_match` counts
+    /// the top level optional clauses of the query.
+    pub fn search_query_with(&self, query: &Query, options: &SearchOptions) -> Result<HashMap<String, f32>, ConfigError> {
+        let k1 = options.k1.map(validate_k1).transpose()?.unwrap_or(self.k1);
+        let b = options.b.map(validate_b).transpose()?.unwrap_or(self.b);
+        let boosts = self.field_boosts_with(&options.field_weights)?;
+        let mut results = self.run_query(query, k1, b, &boosts, options.min_should_match, &Failures::default());
+        self.cap_results(&mut results);
+        Ok(results)
+    }
+
+    fn run_query(
+        &self,
+        query: &Query,
+        k1: f32,
+        b: f32,
+        boosts: &HashMap<String, f32>,
+        min_should_match: Option<usize>,
+        failures: &Failures,
+    ) -> HashMap<String, f32> {
+        let mut timer = self.query_timer();
+        let scores = self.evaluate(query, k1, b, boosts, min_should_match, failures).unwrap_or_default();
+        timer.phase("evaluation");
+        let results = self.apply_static_ranks(scores);
+        timer.phase("ranking");
+        timer.finish(|| format!("{:?}", query), results.len());
+        results
+    }
+
+    /// Score the documents matching `query`, weighing text fields by `boosts`. Returns None when
+    /// the query has no searchable terms left after normalization (e.g. only stop words), so that
+    /// enclosing clauses can ignore it.
+    fn evaluate(
+        &self,
+        query: &Query,
+        k1: f32,
+        b: f32,
+        boosts: &HashMap<String, f32>,
+        min_should_match: Option<usize>,
+        failures: &Failures,
+    ) -> Option<HashMap<String, f32>> {
+        match query {
+            Query::Term(text) => {
+                let tokens = self.analyze_text(text);
+                let path_scores = self.path_scores(text);
+                if tokens.is_empty() && path_scores.is_empty() {
+                    return None;
+                }
+                let terms = self.with_synonyms(tokens.iter().map(|token| token.term.as_ref()));
+                let scores = match self.field_scoring {
+                    FieldScoring::Bm25f if !self.fields.is_empty() => self.bm25f_scores(terms, k1, b, boosts, failures),
+                    _ => sum_scores([self.score_terms(terms, k1, b, 1, failures), self.boosted_field_scores(text, k1, b, boosts, failures)]),
+                };
+                Some(sum_scores([scores, path_scores]))
+            }
+            Query::Keyword { field, value } => {
+                let docs = self.keywords.get(field).and_then(|values| values.get(value));
+                // exact values have no length, so rarer values simply score higher
+                let idf = self.idf_for_count(docs.map_or(0, HashSet::len));
+                let keyword_scores = docs.into_iter().flatten().map(|doc_id| (doc_id.to_string(), idf)).collect();
+                // a text field of the same name is searched for the value as text
+                let field_scores = self.field_matches(field, value, k1, b, boosts, failures).unwrap_or_default();
+                Some(sum_scores([keyword_scores, field_scores]))
+            }
+            Query::Range { field, lower, upper } => {
+                let docs: HashSet<&DocId> = self.range_values(field, lower, upper).flat_map(|(_, docs)| docs).collect();
+                // scored like a keyword value matching as many documents
+                let idf = self.idf_for_count(docs.len());
+                Some(docs.into_iter().map(|doc_id| (doc_id.to_string(), idf)).collect())
+            }
+            Query::Boost { query, factor } => {
+                let mut scores = self.evaluate(query, k1, b, boosts, min_should_match, failures)?;
+                scores.values_mut().for_each(|score| *score *= factor);
+                Some(scores)
+            }
+            Query::Regex(pattern) => {
+                let terms = self.expand_regex(pattern, failures);
+                Some(self.score_terms(terms.iter().map(AsRef::as_ref), k1, b, 1, failures))
+            }
+            Query::Wildcard(pattern) => {
+                let terms = self.expand_wildcard(pattern, failures);
+                Some(self.score_terms(terms.iter().map(AsRef::as_ref), k1, b, 1, failures))
+            }
+            Query::Fuzzy { term, distance } => {
+                let terms = self.expand_fuzzy(term, *distance, failures);
+                Some(self.score_terms(terms.iter().map(AsRef::as_ref), k1, b, 1, failures))
+            }
+            Query::Bool(bool_query) => self.evaluate_bool(bool_query, k1, b, boosts, min_should_match, failures),
+            Query::Nested { path, query } => Some(
+                self.nested_matches(path, query, k1, b, failures)?
+                    .into_iter()
+                    .map(|(parent_id, children)| (parent_id, children[0].1))
+                    .collect(),
+            ),
+            Query::Join { from, to, query } => {
+                let mut linked: HashMap<&str, f32> = HashMap::new(); // key value -> best score
+                for (doc_id, score) in self.evaluate(query, k1, b, boosts, None, failures)? {
+                    for value in self.docs[doc_id.as_str()].keywords.get(from).into_iter().flatten() {
+                        let best = linked.entry(value.as_str()).or_insert(score);
+                        *best = best.max(score);
+                    }
+                }
+
+                let mut scores: HashMap<String, f32> = HashMap::new();
+                let targets = self.keywords.get(to);
+                for (value, score) in linked {
+                    for doc_id in targets.and_then(|values| values.get(value)).into_iter().flatten() {
+                        let best = scores.entry(doc_id.to_string()).or_insert(score);
+                        *best = best.max(score);
+                    }
+                }
+                Some(scores)
+            }
+            Query::Intervals(source) => {
+                let terms = self.interval_terms(source);
+                if terms.is_empty() {
+                    return None;
+                }
+                let idf: f32 = terms.iter().map(|term| self.idf(term, failures)).sum();
+                Some(
+                    self.match_intervals(source, failures)
+                        .into_iter()
+                        .filter(|(_, intervals)| !intervals.is_empty())
+                        .map(|(doc_id, intervals)| {
+                            let score = idf * self.tf_norm(intervals.len() as f32, doc_id, k1, b);
+                            (doc_id.to_string(), score)
+                        })
+                        .collect(),
+                )
+            }
+        }
     }
 
-    fn bm25(&self, term: &str) -> HashMap<String, f32> {
-        match self.index.get(term) {
-            None => HashMap::new(),
-            Some(docs) => {
-                let idf = self.idf(term);
-                docs.iter()
-                    .map(|(doc_id, count)| {
-                        let doc = &self.docs[doc_id];
-                        let tf = *count as f32;
-                        let dl = doc.nterms as f32;
+    /// The analyzed terms of an interval source that count towards its score.
+    fn interval_terms(&self, source: &Intervals) -> Vec<String> {
+        match source {
+            Intervals::Term(text) => self.analyze_text(text).into_iter().map(|token| token.term.into_owned()).collect(),
+            Intervals::Ordered { sources, .. } | Intervals::Unordered { sources, .. } => {
+                sources.iter().flat_map(|source| self.interval_terms(source)).collect()
+            }
+            Intervals::Containing { big, small } => {
+                let mut terms = self.interval_terms(big);
+                terms.extend(self.interval_terms(small));
+                terms
+            }
+            Intervals::NotContaining { big, .. } => self.interval_terms(big),
+        }
+    }
 
-                        let numerator = tf * (self.k1 + 1.0);
-                        let denominator = self.k1 * ((1.0 - self.b) + self.b * (dl / self.avdl));
+    /// The intervals matched by `source` in each document containing it.
+    fn match_intervals(&self, source: &Intervals, failures: &Failures) -> HashMap<&str, Vec<Interval>> {
+        match source {
+            Intervals::Term(text) => {
+                let tokens = self.analyze_text(text);
+                let Some(first_position) = tokens.first().map(|token| token.position) else {
+                    return HashMap::new();
+                };
+                // a multi-term text is a phrase: each term at its offset from the first one
+                let lists: Vec<(u32, Cow<Postings>)> = match tokens
+                    .iter()
+                    .map(|token| Some((token.position - first_position, failures.ok(self.index.postings(&token.term))?)))
+                    .collect()
+                {
+                    Some(lists) => lists,
+                    None => return HashMap::new(),
+                };
+                let head = &lists[0].1;
+                let width = tokens[tokens.len() - 1].position - first_position;
 
-                        (doc_id.to_string(), idf * numerator / denominator)
+                head.iter()
+                    .map(|posting| {
+                        let intervals: Vec<Interval> = posting
+                            .positions
+                            .iter()
+                            .filter(|&&start| {
+                                lists.iter().all(|(offset, docs)| {
+                                    docs.get(posting.ordinal).is_some_and(|p| p.contains(&(start + offset)))
+                                })
+                            })
+                            .map(|&start| (start, start + width))
+                            .collect();
+                        (&posting.doc_id, intervals)
+                    })
+                    .filter(|(_, intervals)| !intervals.is_empty())
+                    // the ids of the document table outlive postings the backend fetched
+                    .filter_map(|(doc_id, intervals)| Some((self.docs.get_key_value(doc_id)?.0.as_ref(), intervals)))
+                    .collect()
+            }
+            Intervals::Ordered { sources, max_gaps } | Intervals::Unordered { sources, max_gaps } => {
+                let matched: Vec<_> = sources.iter().map(|source| self.match_intervals(source, failures)).collect();
+                let Some((first, rest)) = matched.split_first() else {
+                    return HashMap::new();
+                };
+                first
+                    .keys()
+                    .filter(|doc_id| rest.iter().all(|docs| docs.contains_key(*doc_id)))
+                    .map(|&doc_id| {
+                        let lists: Vec<Vec<Interval>> = matched.iter().map(|docs| docs[doc_id].clone()).collect();
+                        let intervals = match source {
+                            Intervals::Ordered { .. } => intervals::ordered(&lists, *max_gaps),
+                            _ => intervals::unordered(&lists, *max_gaps),
+                        };
+                        (doc_id, intervals)
+                    })
+                    .collect()
+            }
+            Intervals::Containing { big, small } => {
+                let small = self.match_intervals(small, failures);
+                self.match_intervals(big, failures)
+                    .into_iter()
+                    .filter_map(|(doc_id, big)| Some((doc_id, intervals::containing(&big, small.get(doc_id)?))))
+                    .collect()
+            }
+            Intervals::NotContaining { big, small } => {
+                let small = self.match_intervals(small, failures);
+                self.match_intervals(big, failures)
+                    .into_iter()
+                    .map(|(doc_id, big)| {
+                        let small = small.get(doc_id).map(Vec::as_slice).unwrap_or_default();
+                        (doc_id, intervals::not_containing(&big, small))
                     })
                     .collect()
             }
         }
     }
+
+    /// The index terms fully matching `pattern`, capped at `max_expansions`.
+    #[cfg(feature = "regex")]
+    fn expand_regex(&self, pattern: &str, failures: &Failures) -> Vec<Cow<'_, str>> {
+        let re = match regex::Regex::new(&format!("^(?:{})$", pattern)) {
+            Ok(re) => re,
+            Err(_) => return Vec::new(),
+        };
+
+        let terms = self.index.terms_from(Bound::Unbounded).map_while(|term| failures.ok(term.map(Some)));
+        self.most_frequent(terms.filter(|term| re.is_match(term)), failures)
+    }
+
+    /// The values of keyword field `field` between `lower` and `upper`, with their documents.
+    fn range_values<'a>(
+        &'a self,
+        field: &str,
+        lower: &'a Bound<String>,
+        upper: &'a Bound<String>,
+    ) -> impl Iterator<Item = (&'a str, &'a HashSet<DocId>)> + 'a {
+        let above = move |value: &str| match lower {
+            Bound::Included(bound) => compare_values(value, bound).is_ge(),
+            Bound::Excluded(bound) => compare_values(value, bound).is_gt(),
+            Bound::Unbounded => true,
+        };
+        let below = move |value: &str| match upper {
+            Bound::Included(bound) => compare_values(value, bound).is_le(),
+            Bound::Excluded(bound) => compare_values(value, bound).is_lt(),
+            Bound::Unbounded => true,
+        };
+        self.keywords
+            .get(field)
+            .into_iter()
+            .flatten()
+            .filter(move |(value, _)| above(value) && below(value))
+            .map(|(value, docs)| (value.as_str(), docs))
+    }
+
+    /// The `max_expansions` of `terms` found in the most documents, ties broken alphabetically so
+    /// the cap is deterministic.
+    fn most_frequent<'a>(&'a self, terms: impl Iterator<Item = Cow<'a, str>>, failures: &Failures) -> Vec<Cow<'a, str>> {
+        let mut terms: Vec<(Cow<str>, usize)> = terms.map(|term| {
+            let df = failures.ok(self.index.doc_frequency(&term));
+            (term, df)
+        })
+        .collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        terms.truncate(self.max_expansions);
+        terms.into_iter().map(|(term, _)| term).collect()
+    }
+
+    /// Regex queries match nothing without the `regex` feature.
+    #[cfg(not(feature = "regex"))]
+    fn expand_regex(&self, _pattern: &str, _failures: &Failures) -> Vec<Cow<'_, str>> {
+        Vec::new()
+    }
+
+    fn evaluate_bool(
+        &self,
+        query: &BoolQuery,
+        k1: f32,
+        b: f32,
+        boosts: &HashMap<String, f32>,
+        min_should_match: Option<usize>,
+        failures: &Failures,
+    ) -> Option<HashMap<String, f32>> {
+        let evaluate = |q| self.evaluate(q, k1, b, boosts, None, failures);
+        let must: Vec<_> = query.must.iter().filter_map(evaluate).collect();
+        let should: Vec<_> = query.should.iter().filter_map(evaluate).collect();
+        let must_not: Vec<_> = query.must_not.iter().filter_map(evaluate).collect();
+        let filter: Vec<_> = query.filter.iter().filter_map(evaluate).collect();
+        let demote: Vec<_> = query
+            .demote
+            .iter()
+            .filter_map(|(q, factor)| Some((evaluate(q)?, *factor)))
+            .collect();
+
+        if must.is_empty() && should.is_empty() && filter.is_empty() {
+            return if must_not.is_empty() { None } else { Some(HashMap::new()) };
+        }
+
+        let required = must.iter().chain(&filter);
+        let min_should_match = min_should_match.unwrap_or(if must.is_empty() && filter.is_empty() { 1 } else { 0 });
+        let mut scores: HashMap<String, f32> = HashMap::new();
+        let mut should_matches: HashMap<&str, usize> = HashMap::new();
+
+        for results in &should {
+            for (doc_id, score) in results {
+                *scores.entry(doc_id.clone()).or_insert(0.0) += score;
+                *should_matches.entry(doc_id).or_insert(0) += 1;
+            }
+        }
+
+        let mut candidates: Vec<String> = match required.clone().next() {
+            Some(first) => first.keys().cloned().collect(),
+            None => scores.keys().cloned().collect(),
+        };
+        candidates.retain(|doc_id| {
+            required.clone().all(|results| results.contains_key(doc_id))
+                && !must_not.iter().any(|results| results.contains_key(doc_id))
+                && should_matches.get(doc_id.as_str()).copied().unwrap_or(0) >= min_should_match
+        });
+
+        Some(
+            candidates
+                .into_iter()
+                .map(|doc_id| {
+                    let mut score = scores.get(&doc_id).copied().unwrap_or(0.0)
+                        + must.iter().map(|results| results[&doc_id]).sum::<f32>();
+                    for (results, factor) in &demote {
+                        if results.contains_key(&doc_id) {
+                            score *= factor;
+                        }
+                    }
+                    (doc_id, score)
+                })
+                .collect(),
+        )
+    }
+
+    fn idf(&self, term: &str, failures: &Failures) -> f32 {
+        let Some(cache) = &self.idf_cache else {
+            return self.idf_for_count(failures.ok(self.index.doc_frequency(term)));
+        };
+        let key = format!("{}/{}", self.generation, term);
+        if let Some(idf) = cache.get(&key) {
+            return idf;
+        }
+        match self.index.doc_frequency(term) {
+            Ok(docs_with_term_count) => {
+                let idf = self.idf_for_count(docs_with_term_count);
+                cache.insert(key, idf);
+                idf
+            }
+            // not cached, so that the next search reads it again
+            Err(error) => self.idf_for_count(failures.ok(Err(error))),
+        }
+    }
+
+    /// idf of a term or value found in `docs_with_term_count` documents.
+    fn idf_for_count(&self, docs_with_term_count: usize) -> f32 {
+        self.scorer.idf(self.collection.documents, docs_with_term_count)
+    }
+
+    /// The term frequency component of the score, normalized by the length of the document.
+    fn tf_norm(&self, tf: f32, doc_id: &str, k1: f32, b: f32) -> f32 {
+        let dl = self.docs[doc_id].nterms as f32;
+        self.scorer.tf(tf, dl, self.collection.avdl(), k1, b)
+    }
 }
 
 #[cfg(test)]
@@ -126,9 +1447,122 @@ mod tests {
 
     const TEST_STRING: &str = "Nice, hello world! I like 42.";
 
+    fn tokenize(s: &str, stop_words: &HashSet<String>) -> Vec<(u32, String)> {
+        analyze(s, stop_words)
+            .into_iter()
+            .map(|token| (token.position, token.term.into_owned()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize(TEST_STRING, &english_stop_words()), vec![(0, "nice".to_string()), (5, "42".to_string())]);
+    }
+
+    #[test]
+    fn test_analyze_numbers() {
+        let terms = |s: &str| -> Vec<String> { tokenize(s, &HashSet::new()).into_iter().map(|(_, term)| term).collect() };
+        assert_eq!(terms("1,000 or 1000.00 items"), vec!["1000", "or", "1000", "items"]);
+        assert_eq!(terms("pi is 3.140. Version 1.2.3"), vec!["pi", "is", "3.14", "version", "1.2.3"]);
+        assert_eq!(terms("1,2 and 12,3456"), vec!["1", "2", "and", "12", "3456"]);
+        assert_eq!(terms("v1.2.3"), vec!["v1", "2", "3"]);
+
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "Population: 1,250,000 people");
+        assert_eq!(searcher.search("1250000").len(), 1);
+    }
+
+    #[test]
+    fn test_analyze_links() {
+        let terms = |s: &str| -> Vec<(u32, String)> { tokenize(s, &HashSet::new()) };
+        assert_eq!(
+            terms("see https://Example.com/docs."),
+            vec![
+                (0, "see".to_string()),
+                (1, "https".to_string()),
+                (1, "https://example.com/docs".to_string()),
+                (2, "example".to_string()),
+                (3, "com".to_string()),
+                (4, "docs".to_string()),
+            ]
+        );
+        let terms: Vec<String> = terms("mail jane.doe@mail.example.org, or <ops@host>").into_iter().map(|(_, t)| t).collect();
+        assert!(terms.contains(&"jane.doe@mail.example.org".to_string()));
+        assert!(!terms.iter().any(|t| t.starts_with("ops@")));
+
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "Docs live at https://example.com/docs, questions to help@example.com");
+        searcher.add_document("2", "Example docs about examples");
+        let query = Query::parse("https://example.com/docs", ParseMode::Strict).unwrap();
+        let results = searcher.search_query(&query);
+        assert!(results["1"] > results["2"]);
+        // every part of the address is a stop word, the address itself isn't
+        assert_eq!(searcher.search_query(&Query::Term("help@example.com".to_string())).len(), 1);
+    }
+
     #[test]
-    fn test_normalize_string() {
-        assert_eq!(normalize_string(TEST_STRING), "nice 42".to_string());
+    #[cfg(feature = "std")]
+    fn test_stemming() {
+        let mut searcher = Searcher::new();
+        searcher.set_stemmer(Some(Stemmer::English));
+        searcher.add_document("1", "Launching the rockets");
+        searcher.add_document("2", "a launch to the moon");
+
+        assert_eq!(searcher.search("launches").len(), 2);
+        assert_eq!(searcher.search("rocket").keys().collect::<Vec<_>>(), ["1"]);
+        let query = Query::Intervals(Intervals::term("launched the rocket"));
+        assert_eq!(searcher.search_query(&query).len(), 1);
+
+        // matches are reported as written
+        let matched = searcher.matched_terms("1", &Query::Term("launches".to_string())).unwrap();
+        assert_eq!(matched[0].term, "launch");
+        assert_eq!(matched[0].originals, ["Launching"]);
+    }
+
+    #[test]
+    fn test_custom_tokenizer() {
+        // keep code identifiers whole instead of splitting them at underscores
+        let identifiers = |text: &str| -> Vec<String> {
+            text.split(|c: char| !c.is_alphanumeric() && c != '_')
+                .filter(|word| !word.is_empty())
+                .map(str::to_lowercase)
+                .collect()
+        };
+        let mut searcher = Searcher::with_tokenizer(identifiers);
+        searcher.set_store_offsets(true);
+        searcher.add_document("1", "call Parse_Header() first");
+        searcher.add_document("2", "parse the header");
+
+        let results = searcher.search("parse_header");
+        assert_eq!(results.keys().collect::<Vec<_>>(), ["1"]);
+        // stop words are still dropped
+        assert!(searcher.search("the").is_empty());
+        let query = Query::Intervals(Intervals::term("call parse_header"));
+        assert!(searcher.search_query(&query).contains_key("1"));
+
+        assert_eq!(SimpleTokenizer.tokenize("Parse_Header"), ["parse", "header"]);
+        // spans are found where terms occur as written
+        let spans: Vec<_> = identifiers.tokens("Parse_Header or read_header").into_iter().map(|t| t.span).collect();
+        assert_eq!(spans, [0..0, 13..15, 16..27]);
+    }
+
+    #[test]
+    fn test_case_folding() {
+        let terms = |s: &str| -> Vec<String> { tokenize(s, &HashSet::new()).into_iter().map(|(_, t)| t).collect() };
+        // full case folding needs std, lowercasing keeps `ß` and the final `ς`
+        #[cfg(feature = "std")]
+        assert_eq!(terms("STRASSE Straße"), vec!["strasse", "strasse"]);
+        #[cfg(feature = "std")]
+        assert_eq!(terms("ΟΔΟΣ οδός"), vec!["οδοσ", "οδόσ"]);
+        assert_eq!(terms("İstanbul istanbul"), vec!["istanbul", "istanbul"]);
+        // the dotless i is a letter of its own
+        assert_eq!(terms("ılık"), vec!["ılık"]);
+
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "Die Straße zum Mond");
+        #[cfg(feature = "std")]
+        assert!(searcher.search("STRASSE").contains_key("1"));
+        assert!(searcher.search("mond").contains_key("1"));
     }
 
     #[test]
@@ -140,6 +1574,29 @@ mod tests {
         assert_eq!(searcher.docs["1"].nterms, 2);
     }
 
+    #[test]
+    fn test_indexing_order_independent() {
+        let docs = [
+            ("1", "rust compiler borrow checker"),
+            ("2", "moon rocket"),
+            ("3", "rust rocket engine design notes"),
+        ];
+        let mut forward = Searcher::new();
+        let mut backward = Searcher::new();
+        for (doc_id, content) in docs {
+            forward.add_document(doc_id, content);
+        }
+        for (doc_id, content) in docs.iter().rev() {
+            backward.add_document(doc_id, content);
+        }
+
+        assert_eq!(forward.collection.avdl().to_bits(), backward.collection.avdl().to_bits());
+        let (a, b) = (forward.search("rust rocket"), backward.search("rust rocket"));
+        for (doc_id, score) in &a {
+            assert_eq!(score.to_bits(), b[doc_id].to_bits());
+        }
+    }
+
     #[test]
     fn test_search() {
         let mut searcher = Searcher::new();
@@ -162,8 +1619,444 @@ mod tests {
 
         assert_eq!(searcher.docs.len(), 3);
 
-        let results = searcher.bm25("moon");
+        let results = searcher.score_terms(["moon"], searcher.k1, searcher.b, 1, &Failures::default());
         assert_eq!(results.len(), 1);
         assert!(results["2"] > 1.0);
     }
+
+    #[test]
+    fn test_with_params() {
+        assert!(matches!(Searcher::with_params(0.0, 0.75), Err(ConfigError::InvalidK1(_))));
+        assert!(matches!(Searcher::with_params(1.2, 1.5), Err(ConfigError::InvalidB(_))));
+        assert!(matches!(Searcher::with_params(f32::NAN, 0.75), Err(ConfigError::InvalidK1(_))));
+
+        let corpus = |searcher: &mut Searcher| {
+            searcher.add_document("short", "rocket");
+            searcher.add_document("long", "rocket rocket moon ocean tide borrow checker ownership");
+        };
+        let mut default = Searcher::new();
+        corpus(&mut default);
+        let results = default.search("rocket");
+        assert!(results["short"] > results["long"]);
+
+        // without length normalization the repeated term wins
+        let mut searcher = Searcher::with_params(1.2, 0.0).unwrap();
+        corpus(&mut searcher);
+        assert_eq!((searcher.k1(), searcher.b()), (1.2, 0.0));
+        let results = searcher.search("rocket");
+        assert!(results["long"] > results["short"]);
+    }
+
+    #[test]
+    fn test_runtime_config() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("2", "moon");
+
+        let before = searcher.search("moon");
+        searcher.set_b(0.0).unwrap();
+        let after = searcher.search("moon");
+        assert!(before["2"] > before["1"]);
+        assert_eq!(after["1"], after["2"]);
+
+        assert_eq!(searcher.set_k1(0.0), Err(ConfigError::InvalidK1(0.0)));
+        assert_eq!(searcher.set_b(1.5), Err(ConfigError::InvalidB(1.5)));
+        assert_eq!(searcher.k1(), 1.2);
+        assert_eq!(searcher.b(), 0.0);
+
+        searcher.set_stop_words(["Moon"]);
+        assert!(searcher.search("moon rocket").contains_key("1"));
+        assert!(!searcher.search("moon rocket").contains_key("2"));
+    }
+
+    #[test]
+    fn test_search_with_overrides() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("2", "moon");
+        searcher.add_document("3", "rocket");
+
+        let options = SearchOptions {
+            b: Some(0.0),
+            ..Default::default()
+        };
+        let results = searcher.search_with("moon", &options).unwrap();
+        assert_eq!(results["1"], results["2"]);
+        // the Searcher's own configuration is untouched
+        let results = searcher.search("moon");
+        assert!(results["2"] > results["1"]);
+
+        let options = SearchOptions {
+            min_should_match: Some(2),
+            ..Default::default()
+        };
+        let results = searcher.search_with("moon rocket", &options).unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("1"));
+
+        let options = SearchOptions {
+            k1: Some(-2.0),
+            ..Default::default()
+        };
+        assert_eq!(searcher.search_with("moon", &options), Err(ConfigError::InvalidK1(-2.0)));
+    }
+
+    #[test]
+    fn test_max_results() {
+        let mut searcher = Searcher::new();
+        for i in 0..5 {
+            searcher.add_document(&i.to_string(), &"moon ".repeat(i + 1));
+        }
+        let all = searcher.search("moon");
+        let moon = Query::Term("moon".to_string());
+        searcher.set_max_results(Some(3));
+
+        // searches returning every hit keep the best ones
+        let ids = |results: HashMap<String, f32>| {
+            let mut ids: Vec<String> = results.into_keys().collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(searcher.search("moon")), ["2", "3", "4"]);
+        assert_eq!(searcher.search("moon")["4"], all["4"]);
+        assert_eq!(ids(searcher.search_with("moon", &SearchOptions::default()).unwrap()), ["2", "3", "4"]);
+        assert_eq!(ids(searcher.search_query(&moon)), ["2", "3", "4"]);
+        assert_eq!(ids(searcher.search_query_with(&moon, &SearchOptions::default()).unwrap()), ["2", "3", "4"]);
+        assert_eq!(ids(searcher.search_in("moon", &mut QueryContext::new()).clone()), ["2", "3", "4"]);
+        assert_eq!(searcher.search_each("moon", |_, _| ()), 3);
+        #[cfg(feature = "std")]
+        assert_eq!(searcher.search_parallel("moon", 10, 2).len(), 3);
+
+        searcher.set_max_results(None);
+        assert_eq!(searcher.search("moon"), all);
+    }
+
+    #[test]
+    fn test_search_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust search engine");
+        searcher.add_document("2", "java search engine");
+        searcher.add_document("3", "python scripting");
+
+        let query = Query::parse("+search -java", ParseMode::Strict).unwrap();
+        let results = searcher.search_query(&query);
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("1"));
+
+        let query = Query::parse("rust (python scripting)", ParseMode::Strict).unwrap();
+        let results = searcher.search_query(&query);
+        assert_eq!(results.len(), 2);
+        assert!(results["3"] > results["1"]);
+
+        // clauses made only of stop words are ignored instead of matching nothing
+        let query = Query::parse("+the python", ParseMode::Strict).unwrap();
+        assert_eq!(searcher.search_query(&query).len(), 1);
+
+        let query = Query::parse("rust search", ParseMode::Strict).unwrap();
+        let options = SearchOptions {
+            min_should_match: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(searcher.search_query_with(&query, &options).unwrap().len(), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn test_regex_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "error code e1001");
+        searcher.add_document("2", "error code e2002 e2003");
+        searcher.add_document("3", "warning code w3003");
+
+        let query = Query::parse("/e[0-9]+/", ParseMode::Strict).unwrap();
+        let results = searcher.search_query(&query);
+        assert_eq!(results.len(), 2);
+        assert!(!results.contains_key("3"));
+
+        // the regex must match the whole term
+        let query = Query::parse("/e2/", ParseMode::Strict).unwrap();
+        assert!(searcher.search_query(&query).is_empty());
+
+        searcher.set_max_expansions(1);
+        let query = Query::parse("/e[0-9]+/", ParseMode::Strict).unwrap();
+        let results = searcher.search_query(&query);
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("1"));
+    }
+
+    #[test]
+    fn test_filter_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust search engine");
+        searcher.add_document("2", "rust rust rust search");
+        searcher.add_document("3", "java search engine");
+
+        let plain = searcher.search_query(&Query::parse("search engine", ParseMode::Strict).unwrap());
+        let filtered = searcher.search_query(&Query::parse("#rust search engine", ParseMode::Strict).unwrap());
+        assert_eq!(filtered.len(), 2);
+        assert!(!filtered.contains_key("3"));
+        // the filter narrows the results without changing their scores
+        assert_eq!(filtered["1"], plain["1"]);
+        assert_eq!(filtered["2"], plain["2"]);
+
+        // a lone filter matches with a constant score of zero
+        let filtered = searcher.search_query(&Query::parse("#rust", ParseMode::Strict).unwrap());
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered["1"], 0.0);
+    }
+
+    #[test]
+    fn test_demotion() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "release notes archive");
+        searcher.add_document("2", "release notes draft");
+
+        let plain = searcher.search_query(&Query::parse("release notes", ParseMode::Strict).unwrap());
+        let demoted = searcher.search_query(&Query::parse("release notes -archive^0.5", ParseMode::Strict).unwrap());
+        assert_eq!(demoted.len(), 2);
+        assert_eq!(demoted["1"], plain["1"] * 0.5);
+        assert_eq!(demoted["2"], plain["2"]);
+    }
+
+    #[test]
+    fn test_interval_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "error: connection timeout after retry");
+        searcher.add_document("2", "timeout reached, then an unrelated error was logged much later on");
+        searcher.add_document("3", "error in the request handler");
+
+        let near = Intervals::unordered(vec![Intervals::term("error"), Intervals::term("timeout")], Some(2));
+        let results = searcher.search_query(&Query::Intervals(near));
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("1"));
+
+        let anywhere = Intervals::unordered(vec![Intervals::term("error"), Intervals::term("timeout")], None);
+        assert_eq!(searcher.search_query(&Query::Intervals(anywhere)).len(), 2);
+
+        let in_order = Intervals::ordered(vec![Intervals::term("timeout"), Intervals::term("error")], None);
+        let results = searcher.search_query(&Query::Intervals(in_order.clone()));
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("2"));
+
+        let without = in_order.not_containing(Intervals::term("unrelated"));
+        assert!(searcher.search_query(&Query::Intervals(without)).is_empty());
+
+        // stop words keep their position, so phrases still line up
+        let phrase = Intervals::term("error in the request");
+        let results = searcher.search_query(&Query::Intervals(phrase));
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("3"));
+    }
+
+    #[test]
+    fn test_phrase_search() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket, then tide and ocean");
+        searcher.add_document("2", "rocket moon");
+        searcher.add_document("3", "moon borrow checker rocket");
+
+        let results = searcher.search("\"moon rocket\"");
+        assert_eq!(results.keys().collect::<Vec<_>>(), ["1"]);
+        // the phrase restricts the results, other words still add to the score
+        let results = searcher.search("\"moon rocket\" tide");
+        assert!(results["1"] > searcher.search("\"moon rocket\"")["1"]);
+        assert_eq!(searcher.search("tide \"rocket moon\"").keys().collect::<Vec<_>>(), ["2"]);
+        assert!(searcher.search("\"moon rocket\" \"borrow checker\"").is_empty());
+        // unclosed quotes and phrases of stop words are ignored
+        assert_eq!(searcher.search("\"moon rocket").len(), 3);
+        assert_eq!(searcher.search("moon \"the\"").len(), 3);
+        #[cfg(feature = "std")]
+        assert_eq!(searcher.search_parallel("\"moon rocket\"", 10, 2)[0].0, "1");
+
+        let query = Query::parse("\"moon rocket\" -tide", ParseMode::Strict).unwrap();
+        assert!(searcher.search_query(&query).is_empty());
+        let query = Query::parse("+\"borrow checker rocket\"", ParseMode::Strict).unwrap();
+        assert_eq!(searcher.search_query(&query).keys().collect::<Vec<_>>(), ["3"]);
+    }
+
+    #[test]
+    fn test_keyword_field() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("main.rs", "fn main");
+        searcher.add_document("lib.rs", "pub fn search");
+        searcher.add_document("notes.md", "search notes");
+        searcher.add_keyword("main.rs", "ext", "rs");
+        searcher.add_keyword("lib.rs", "ext", "rs");
+        searcher.add_keyword("notes.md", "ext", "md");
+        assert!(!searcher.add_keyword("missing", "ext", "rs"));
+
+        let results = searcher.search_query(&Query::parse("#ext:rs +search", ParseMode::Strict).unwrap());
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("lib.rs"));
+
+        // values are matched exactly, not analyzed
+        assert!(searcher.search_query(&Query::parse("ext:RS", ParseMode::Strict).unwrap()).is_empty());
+
+        let results = searcher.search("search fn");
+        let facets = searcher.facet_counts("ext", results.keys());
+        assert_eq!(facets["rs"], 2);
+        assert_eq!(facets["md"], 1);
+    }
+
+    #[test]
+    fn test_remove_document() {
+        let mut searcher = Searcher::new();
+        searcher.set_index_paths(true);
+        searcher.add_document("src/rocket.rs", "rust rocket");
+        searcher.add_document("moon.md", "moon rocket rocket");
+        searcher.add_keyword("src/rocket.rs", "ext", "rs");
+        searcher.add_nested("src/rocket.rs", "comments", "borrow checker", &[]).unwrap();
+
+        assert!(searcher.remove_document("src/rocket.rs"));
+        assert!(!searcher.remove_document("src/rocket.rs"));
+
+        assert!(searcher.search("rust").is_empty());
+        assert!(searcher.index.postings("rust").unwrap().is_none());
+        assert!(searcher.keywords.is_empty());
+        assert!(!searcher.paths.contains_key("src"));
+        assert_eq!(searcher.nested["comments"].searcher.docs.len(), 0);
+        assert_eq!(searcher.collection.terms, 3);
+        assert_eq!(searcher.collection.avdl(), 3.0);
+
+        assert!(searcher.remove_document("moon.md"));
+        assert!(searcher.index.term_count().unwrap() == 0 && searcher.paths.is_empty());
+        assert_eq!(searcher.collection, CollectionStats::default());
+    }
+
+    #[test]
+    fn test_update_document() {
+        let mut searcher = Searcher::new();
+        searcher.add_copy_field("title");
+        searcher.add_document("1", "rust rocket");
+        searcher.add_document("2", "moon");
+        searcher.add_keyword("1", "title", "borrow checker");
+        searcher.set_static_rank("1", 2.0).unwrap();
+
+        searcher.update_document("1", "moon ocean");
+        assert!(searcher.search("rust").is_empty());
+        assert!(searcher.index.postings("rocket").unwrap().is_none());
+        assert_eq!(searcher.search("moon").len(), 2);
+        // keyword values and their copies survive the update
+        assert!(searcher.search("checker").contains_key("1"));
+        assert_eq!(searcher.search_query(&Query::parse("title:\"borrow checker\"", ParseMode::Strict).unwrap()).len(), 1);
+        assert_eq!(searcher.collection.terms, 5);
+        assert_eq!(searcher.docs["1"].static_rank, 2.0);
+
+        // unknown documents are added
+        searcher.update_document("3", "tide");
+        assert!(searcher.search("tide").contains_key("3"));
+    }
+
+    #[test]
+    fn test_add_document_again_replaces_it() {
+        let mut searcher = Searcher::new();
+        searcher.add_copy_field("title");
+        searcher.add_document("1", "rust rocket rocket");
+        searcher.add_document("2", "moon rocket");
+        searcher.add_keyword("1", "title", "borrow checker");
+        searcher.set_static_rank("1", 2.0).unwrap();
+
+        searcher.add_document("1", "moon ocean");
+        assert!(searcher.search("rust").is_empty());
+        assert_eq!(searcher.index.doc_frequency("rocket").unwrap(), 1);
+        assert_eq!(searcher.index.doc_frequency("moon").unwrap(), 2);
+        // unlike update_document, nothing of the old document is kept
+        assert!(searcher.search("checker").is_empty());
+        assert!(searcher.keywords.is_empty());
+        assert_eq!(searcher.docs["1"].static_rank, 1.0);
+        assert_eq!(searcher.collection, CollectionStats { documents: 2, terms: 4 });
+
+        let loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        assert_eq!(loaded.docs["1"].terms.len(), 2);
+    }
+
+    #[test]
+    fn test_interned_doc_ids() {
+        let mut searcher = Searcher::new();
+        searcher.set_index_paths(true);
+        searcher.add_document("src/rocket.rs", "rust rocket moon");
+        searcher.add_keyword("src/rocket.rs", "ext", "rs");
+
+        let (doc_id, _) = searcher.docs.get_key_value("src/rocket.rs").unwrap();
+        let moon = searcher.index.postings("moon").unwrap().unwrap();
+        let posting = moon.iter().next().unwrap();
+        assert!(Arc::ptr_eq(doc_id, &posting.doc_id));
+        // the table, three postings, one keyword value and the path terms all share one copy
+        assert_eq!(Arc::strong_count(doc_id), 1 + 3 + 1 + path::path_terms("src/rocket.rs").len());
+    }
+
+    #[test]
+    fn test_static_rank() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("README.md", "rust rocket");
+        searcher.add_document("vendor/README.md", "rust rocket");
+        assert_eq!(searcher.set_static_rank("vendor/README.md", 0.5), Ok(true));
+        assert_eq!(searcher.set_static_rank("missing", 0.5), Ok(false));
+        assert_eq!(searcher.set_static_rank("README.md", -1.0), Err(ConfigError::InvalidBoost(-1.0)));
+
+        let results = searcher.search("rocket");
+        assert!((results["vendor/README.md"] - results["README.md"] * 0.5).abs() < 1e-6);
+
+        let results = searcher.search_query(&Query::parse("rust", ParseMode::Strict).unwrap());
+        assert!(results["README.md"] > results["vendor/README.md"]);
+    }
+
+    #[test]
+    fn test_join_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("p1", "release announcement");
+        searcher.add_document("p2", "roadmap discussion");
+        searcher.add_document("c1", "crash on startup");
+        searcher.add_document("c2", "superb roadmap");
+        searcher.add_keyword("p1", "id", "p1");
+        searcher.add_keyword("p2", "id", "p2");
+        searcher.add_keyword("c1", "post_id", "p1");
+        searcher.add_keyword("c2", "post_id", "p2");
+
+        // posts whose comments mention a crash
+        let posts = Query::Join {
+            from: "post_id".to_string(),
+            to: "id".to_string(),
+            query: Box::new(Query::Term("crash".to_string())),
+        };
+        let results = searcher.search_query(&posts);
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("p1"));
+
+        // and the other way around: comments on posts about the roadmap
+        let comments = Query::Join {
+            from: "id".to_string(),
+            to: "post_id".to_string(),
+            query: Box::new(Query::Term("discussion".to_string())),
+        };
+        let results = searcher.search_query(&comments);
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("c2"));
+    }
+
+    #[test]
+    fn test_copy_field() {
+        let mut searcher = Searcher::new();
+        searcher.add_copy_field("tags");
+        searcher.add_document("1", "borrow checker errors");
+        searcher.add_document("2", "garbage collector pauses");
+        searcher.add_keyword("1", "tags", "Rust");
+        searcher.add_keyword("2", "tags", "java");
+        searcher.add_keyword("2", "lang", "rust");
+
+        // the copied value is searchable as text, the other keyword field is not
+        let results = searcher.search("rust");
+        assert_eq!(results.len(), 1);
+        assert!(results.contains_key("1"));
+        assert_eq!(searcher.docs["1"].nterms, 4);
+        assert_eq!(searcher.collection.avdl(), 4.0);
+
+        // while the field itself stays exact
+        assert!(searcher.search_query(&Query::parse("tags:rust", ParseMode::Strict).unwrap()).is_empty());
+        assert_eq!(searcher.search_query(&Query::parse("tags:Rust", ParseMode::Strict).unwrap()).len(), 1);
+
+        // phrases don't run from the content into the copied value
+        let phrase = Intervals::term("errors rust");
+        assert!(searcher.search_query(&Query::Intervals(phrase)).is_empty());
+    }
 }
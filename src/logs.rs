@@ -0,0 +1,227 @@
+//! Application logs: a log is indexed one entry at a time, with the timestamp the entry starts
+//! with as a `date` keyword value, so that a search can be narrowed to a time range with a
+//! `Query::Range` over `date`.
+//!
+//! An entry is a line starting with a timestamp and the lines after it that don't, e.g. the lines
+//! of a stack trace. Timestamps are written `YYYY-MM-DDTHH:MM:SS`, which sorts as text in time
+//! order, and are taken as they appear in the log, without converting time zones. Recognized:
+//! ISO 8601 with a `T` or a space (`2024-03-01 12:34:56,789`), syslog (`Mar  1 12:34:56`), and
+//! the `[01/Mar/2024:12:34:56 +0000]` of web server access logs, optionally in brackets.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::time::SystemTime;
+
+use crate::backend::Failures;
+use crate::{IndexBackend, Searcher};
+
+/// Name of the keyword field holding the timestamp of a log entry.
+pub const DATE_FIELD: &str = "date";
+
+/// A bracketed timestamp has to open this close to the start of its line.
+const MAX_TIMESTAMP_OFFSET: usize = 64;
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// An entry of a log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub line: usize,          // line number the entry starts on, from 1
+    pub date: Option<String>, // None for lines before the first timestamp
+    pub text: String,
+}
+
+/// Split a log into entries. `year` is the year of timestamps that don't say, like syslog's;
+/// usually the year the log was last written.
+pub fn parse_log(log: &str, year: i32) -> Vec<LogEntry> {
+    let mut entries: Vec<LogEntry> = Vec::new();
+    for (i, line) in log.lines().enumerate() {
+        match (timestamp(line, year), entries.last_mut()) {
+            (None, Some(entry)) => {
+                entry.text.push('\n');
+                entry.text.push_str(line);
+            }
+            (date, _) => entries.push(LogEntry { line: i + 1, date, text: line.to_string() }),
+        }
+    }
+    entries.retain(|entry| !entry.text.trim().is_empty());
+    entries
+}
+
+/// The year of `time`, in UTC.
+#[cfg(feature = "std")]
+pub fn year_of(time: SystemTime) -> i32 {
+    let seconds = match time.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    // the proleptic Gregorian calendar, counted in eras of 400 years from 0000-03-01
+    let days = seconds.div_euclid(86_400) + 719_468;
+    let (era, day_of_era) = (days.div_euclid(146_097), days.rem_euclid(146_097));
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let march_based_month = (5 * day_of_year + 2) / 153;
+    // January and February belong to the next year
+    (era * 400 + year_of_era + i64::from(march_based_month >= 10)) as i32
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index the entries of a log as documents `source:line`, each with its timestamp in the
+    /// `date` keyword field, and return how many there were. See `parse_log` for `year`.
+    /// Entries are identified by line, so adding a log again after more was written to it
+    /// replaces the entries it already had and adds the new ones.
+    pub fn add_log(&mut self, source: &str, log: &str, year: i32) -> usize {
+        let entries = parse_log(log, year);
+        let docs = entries.iter().map(|entry| (format!("{}:{}", source, entry.line), entry.text.clone())).collect();
+        self.add_batch(docs, &Failures::default());
+        for entry in &entries {
+            if let Some(date) = &entry.date {
+                self.add_keyword(&format!("{}:{}", source, entry.line), DATE_FIELD, date);
+            }
+        }
+        entries.len()
+    }
+}
+
+/// The timestamp a log line starts with. Indented lines continue an entry, so have none.
+fn timestamp(line: &str, year: i32) -> Option<String> {
+    if line.starts_with(char::is_whitespace) {
+        return None;
+    }
+    iso(line).or_else(|| syslog(line, year)).or_else(|| {
+        let open = line.find('[').filter(|&open| open < MAX_TIMESTAMP_OFFSET)?;
+        let bracketed = &line[open + 1..];
+        iso(bracketed).or_else(|| access_log(bracketed))
+    })
+}
+
+/// `2024-03-01T12:34:56` or `2024-03-01 12:34:56`.
+fn iso(s: &str) -> Option<String> {
+    let (year, s) = number(s, 4)?;
+    let (month, s) = number(s.strip_prefix('-')?, 2)?;
+    let (day, s) = number(s.strip_prefix('-')?, 2)?;
+    let s = s.strip_prefix(['T', ' '])?;
+    date(year as i32, month, day, s)
+}
+
+/// `Mar  1 12:34:56`, in `year`.
+fn syslog(s: &str, year: i32) -> Option<String> {
+    let month = month(s)?;
+    let s = s.get(3..)?.strip_prefix(' ')?;
+    let s = s.strip_prefix(' ').unwrap_or(s);
+    let (day, s) = number(s, 2).or_else(|| number(s, 1))?;
+    date(year, month, day, s.strip_prefix(' ')?)
+}
+
+/// `01/Mar/2024:12:34:56`.
+fn access_log(s: &str) -> Option<String> {
+    let (day, s) = number(s, 2)?;
+    let s = s.strip_prefix('/')?;
+    let month = month(s)?;
+    let (year, s) = number(s.get(3..)?.strip_prefix('/')?, 4)?;
+    date(year as i32, month, day, s.strip_prefix(':')?)
+}
+
+/// The timestamp of a date followed by `HH:MM:SS`, if both are valid.
+fn date(year: i32, month: u32, day: u32, time: &str) -> Option<String> {
+    let (hour, time) = number(time, 2)?;
+    let (minute, time) = number(time.strip_prefix(':')?, 2)?;
+    let (second, _) = number(time.strip_prefix(':')?, 2)?;
+    // a leap second is 60
+    let valid = (1..=12).contains(&month) && (1..=31).contains(&day) && hour < 24 && minute < 60 && second <= 60;
+    valid.then(|| format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, hour, minute, second))
+}
+
+/// The number written with the first `digits` characters of `s`, and the rest of `s`.
+fn number(s: &str, digits: usize) -> Option<(u32, &str)> {
+    let (number, rest) = s.split_at_checked(digits)?;
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((number.parse().ok()?, rest))
+}
+
+/// The month, from 1, whose abbreviated English name `s` starts with.
+fn month(s: &str) -> Option<u32> {
+    let name = s.get(..3)?;
+    MONTHS.iter().position(|month| *month == name).map(|i| i as u32 + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+    #[cfg(feature = "std")]
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{BoolQuery, ParseMode, Query};
+
+    #[test]
+    fn test_parse_log() {
+        let log = "starting up
+2024-03-01 12:34:56,789 INFO moon rocket launched
+2024-03-01T12:35:00Z ERROR ocean tide failed
+    at tide::rise (tide.rs:12)
+    at main (main.rs:3)
+
+[2024-03-02 08:00:00] WARN landing delayed
+Mar  3 09:15:02 host rocketd[42]: launch window opened
+127.0.0.1 - - [04/Mar/2024:10:00:00 +0000] \"GET /moon HTTP/1.1\" 200
+2024-13-01 00:00:00 not a date";
+        let entries = parse_log(log, 2024);
+        let dates: Vec<(usize, Option<&str>)> = entries.iter().map(|entry| (entry.line, entry.date.as_deref())).collect();
+        assert_eq!(
+            dates,
+            [
+                (1, None),
+                (2, Some("2024-03-01T12:34:56")),
+                (3, Some("2024-03-01T12:35:00")),
+                (7, Some("2024-03-02T08:00:00")),
+                (8, Some("2024-03-03T09:15:02")),
+                (9, Some("2024-03-04T10:00:00")),
+            ]
+        );
+        assert_eq!(entries[2].text, "2024-03-01T12:35:00Z ERROR ocean tide failed\n    at tide::rise (tide.rs:12)\n    at main (main.rs:3)\n");
+        assert!(entries[5].text.ends_with("200\n2024-13-01 00:00:00 not a date"));
+        assert_eq!(timestamp("Mar 13 09:15:02 host", 1999).as_deref(), Some("1999-03-13T09:15:02"));
+        assert_eq!(timestamp("later, [2024-03-02 08:00:00]", 2024), Some("2024-03-02T08:00:00".to_string()));
+        assert_eq!(timestamp("Marathon 1 12:00:00", 2024), None);
+    }
+
+    #[test]
+    fn test_add_log() {
+        let mut searcher = Searcher::new();
+        let log = "2024-03-01 10:00:00 rocket launch scrubbed\n2024-03-02 10:00:00 rocket launch\n2024-03-03 10:00:00 ocean tide\n";
+        assert_eq!(searcher.add_log("app.log", log, 2024), 3);
+
+        let since_march_2 = Query::Bool(BoolQuery {
+            must: vec![Query::parse("rocket", ParseMode::Strict).unwrap()],
+            filter: vec![Query::Range {
+                field: DATE_FIELD.to_string(),
+                lower: Bound::Included("2024-03-02".to_string()),
+                upper: Bound::Unbounded,
+            }],
+            ..Default::default()
+        });
+        assert!(searcher.search_query(&since_march_2).keys().eq(["app.log:2"]));
+        assert_eq!(searcher.search("rocket").len(), 2);
+
+        // the log grew: known entries are replaced, not added twice
+        assert_eq!(searcher.add_log("app.log", &format!("{}2024-03-04 10:00:00 rocket landing\n", log), 2024), 4);
+        assert_eq!(searcher.search("rocket").len(), 3);
+        assert_eq!(searcher.docs.len(), 4);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_year_of() {
+        let at = |seconds: u64| year_of(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(at(0), 1970);
+        assert_eq!(at(951_782_399), 2000); // 2000-02-28T23:59:59
+        assert_eq!(at(1_704_067_199), 2023); // 2023-12-31T23:59:59
+        assert_eq!(at(1_704_067_200), 2024);
+        assert_eq!(year_of(SystemTime::UNIX_EPOCH - Duration::from_secs(1)), 1969);
+    }
+}
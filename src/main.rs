@@ -1,57 +1,868 @@
 use anyhow::{Context, Result};
 use clap::Parser;
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
 
-use searcher::Searcher;
+use std::io::{Read, Write};
+use std::ops::Bound;
+use std::sync::Arc;
+
+use searcher::{
+    logs, stats, Bm25, Bm25L, Bm25Plus, BoolQuery, BuildReport, ContentProvider, FileContentProvider, IndexBackend, ParseMode, Query, ResultFormat,
+    SearchEngine, SearchPage, Searcher, TfIdf,
+};
+#[cfg(feature = "redis")]
+use searcher::backend::redis::RedisBackend;
+#[cfg(feature = "epub")]
+use searcher::epub::Epub;
+#[cfg(feature = "s3")]
+use searcher::s3::{S3Config, S3Source};
+#[cfg(feature = "sitemap")]
+use searcher::sitemap::SitemapCrawler;
 
 #[derive(Parser)]
-#[command(version, about)]
+#[command(version, about, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    query: String,
-    path: std::path::PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    query: Option<String>,
+    /// Directory to index with its subdirectories, leaving out hidden files and what `.gitignore`
+    /// files ignore, or `s3://bucket/prefix` when built with the `s3` feature, with credentials
+    /// from the usual `AWS_*` environment variables, or the URL of a sitemap.xml when built with
+    /// the `sitemap` feature
+    #[arg(required = true)]
+    path: Option<std::path::PathBuf>,
+    /// Load the index from this file instead of reading the directory, or save it there if the
+    /// file doesn't exist yet. Delete the file to pick up changes to the directory
+    #[arg(long)]
+    index: Option<std::path::PathBuf>,
+    /// Keep the postings in the Redis server at this URL, e.g. `redis://localhost:6379`, instead
+    /// of in memory. The directory is indexed again on every run, so it can't be combined with
+    /// `--index`
+    #[cfg(feature = "redis")]
+    #[arg(long, conflicts_with = "index")]
+    redis: Option<String>,
+    /// Prefix of the Redis keys of the index, to keep several indexes on one server apart. What
+    /// is under it is replaced
+    #[cfg(feature = "redis")]
+    #[arg(long, default_value = "searcher", requires = "redis")]
+    redis_prefix: String,
+    #[command(flatten)]
+    indexing: IndexOptions,
+    #[command(flatten)]
+    searching: SearchOptions,
 }
 
-fn main() -> Result<()> {
-    let args = Cli::parse();
+/// How to read and index a directory.
+#[derive(clap::Args)]
+struct IndexOptions {
+    /// Also write the term statistics of the indexed files to this file, as JSON if it ends in
+    /// `.json` and as CSV otherwise
+    #[arg(long)]
+    export_stats: Option<std::path::PathBuf>,
+    /// Rank files by depth in the tree, recency and extension on top of text relevance, e.g. so a
+    /// README near the root outranks a deep vendored copy
+    #[arg(long)]
+    file_signals: bool,
+    /// Print what indexing kept and dropped to stderr: skipped files, stop words, empty and
+    /// largest documents and how the vocabulary grew
+    #[arg(long)]
+    report: bool,
+    /// Don't keep the contents of files in the index, read them again to show snippets. Keeps a
+    /// saved index small; pass it again when loading one built with it
+    #[arg(long)]
+    lazy_content: bool,
+    /// Only index files matching this glob, e.g. `*.md` or `docs/**`. Can be repeated
+    #[arg(long)]
+    include: Vec<String>,
+    /// Skip files and directories matching this glob, e.g. `*.min.js` or `vendor/`. Can be repeated
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Follow symbolic links to files and directories instead of skipping them. Links back to a
+    /// directory being walked are skipped
+    #[arg(long)]
+    follow_symlinks: bool,
+    /// Encoding of text files that aren't valid UTF-8. Files with a null byte near the start are
+    /// taken as binary and skipped
+    #[arg(long, value_enum, default_value_t = Encoding::Utf8)]
+    encoding: Encoding,
+    /// Index the files as logs, one entry per timestamped line, so results can be narrowed to a
+    /// time range with `--since` and `--before`
+    #[arg(long)]
+    logs: bool,
+    /// Also index every chapter of the EPUB books of the directory on its own, in chunks of at most
+    /// this many words, as `book.epub#chapter.chunk`
+    #[cfg(feature = "epub")]
+    #[arg(long)]
+    chapters: Option<usize>,
+    /// User agent to crawl a sitemap as, which also picks the robots.txt rules that apply
+    #[cfg(feature = "sitemap")]
+    #[arg(long)]
+    user_agent: Option<String>,
+    /// Seconds between the starts of two requests to the same host when crawling a sitemap,
+    /// unless robots.txt asks for longer
+    #[cfg(feature = "sitemap")]
+    #[arg(long, default_value_t = 1.0)]
+    crawl_delay: f64,
+}
 
-    let mut filepath = args.path;
+/// How to run a query and print its results.
+#[derive(clap::Args)]
+struct SearchOptions {
+    /// Print the results as `json`, `ndjson`, `tsv`, `csv` or `msgpack`, with their doc id, score
+    /// and snippet, instead of for reading
+    #[arg(long, alias = "format")]
+    output: Option<ResultFormat>,
+    /// Print at most this many results, 0 for all of them
+    #[arg(long, default_value_t = 10)]
+    limit: usize,
+    /// Skip this many of the best results, to page through them `--limit` at a time
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+    /// Leave out results scoring below this
+    #[arg(long)]
+    min_score: Option<f32>,
+    /// Scoring function to rank results with, to compare rankings on the same files
+    #[arg(long, value_enum, default_value_t = ScorerName::Bm25)]
+    scorer: ScorerName,
+    /// Print under each result how its score is made up: the frequency of each query term in the
+    /// file and in the index, its idf, the file's length normalization and what the term adds
+    #[arg(long)]
+    explain: bool,
+    /// Read the query as JSON, e.g. `{"phrase": "borrow checker"}`, instead of the query syntax
+    #[arg(long)]
+    json_query: bool,
+    /// Only return log entries from this time on, e.g. `2024-03-01` or `2024-03-01T12:00`
+    #[arg(long)]
+    since: Option<String>,
+    /// Only return log entries from before this time
+    #[arg(long)]
+    before: Option<String>,
+}
 
-    if filepath == std::path::PathBuf::from("") {
-        filepath = std::path::PathBuf::from(".");
+// Indexing and searching as separate steps, so that a large directory is only read once, and
+// changes to a saved index, e.g. to keep notes searchable from shell scripts.
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Index a directory, S3 prefix or sitemap and save the index to a file
+    Index {
+        /// Directory to index, or `s3://bucket/prefix` or the URL of a sitemap.xml as for a search
+        path: std::path::PathBuf,
+        /// File to save the index to, replacing it if it exists
+        #[arg(long)]
+        out: std::path::PathBuf,
+        #[command(flatten)]
+        options: IndexOptions,
+    },
+    /// Search an index saved by `index`
+    Search {
+        query: String,
+        #[arg(long)]
+        index: std::path::PathBuf,
+        /// Where to read the files of an index built with `--lazy-content` back from, to show
+        /// snippets: the path it was built from
+        #[arg(long)]
+        content: Option<std::path::PathBuf>,
+        #[command(flatten)]
+        options: SearchOptions,
+    },
+    /// Add a note read from stdin to a saved index, replacing any note with the same id. The index
+    /// is created if it doesn't exist yet
+    Add {
+        #[arg(long)]
+        index: std::path::PathBuf,
+        #[arg(long)]
+        id: String,
+    },
+    /// Delete a note from a saved index
+    Delete {
+        #[arg(long)]
+        index: std::path::PathBuf,
+        #[arg(long)]
+        id: String,
+    },
+    /// Compare two saved indexes, e.g. two crawls of the same tree: the documents added, removed
+    /// and changed from the first to the second, and how the vocabulary drifted
+    Diff {
+        /// The older index
+        old: std::path::PathBuf,
+        /// The newer index
+        new: std::path::PathBuf,
+    },
+    /// Standing searches saved in an index, run against what was added since they last ran
+    Alerts {
+        #[command(subcommand)]
+        command: AlertsCommand,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum AlertsCommand {
+    /// Save a query under a name, replacing any query saved under it
+    Save {
+        name: String,
+        query: String,
+        #[arg(long)]
+        index: std::path::PathBuf,
+    },
+    /// Remove a saved query
+    Remove {
+        name: String,
+        #[arg(long)]
+        index: std::path::PathBuf,
+    },
+    /// List the saved queries
+    List {
+        #[arg(long)]
+        index: std::path::PathBuf,
+    },
+    /// Print the documents added or replaced since the last run that match a saved query, and
+    /// mark them as seen
+    Run {
+        #[arg(long)]
+        index: std::path::PathBuf,
+    },
+    /// Print the names of the saved queries a document read from stdin matches, one per line,
+    /// without adding it to the index
+    Match {
+        #[arg(long)]
+        index: std::path::PathBuf,
+    },
+}
+
+impl Command {
+    fn run(self) -> Result<()> {
+        match self {
+            Command::Index { path, out, options } => {
+                let mut skipped = BuildReport::default();
+                let searcher = build_index(Searcher::new(), &path, &options, &mut skipped)?;
+                searcher.save(&out).with_context(|| format!("could not save index `{:?}`", out))?;
+                write_report(&searcher, &options, skipped)
+            }
+            Command::Search { query, index, content, options } => {
+                let mut searcher = Searcher::load(&index).with_context(|| format!("could not load index `{:?}`", index))?;
+                if let Some(content) = content {
+                    searcher.set_content_provider(Some(content_provider(&content)?));
+                }
+                options.configure(&mut searcher);
+                search(&searcher, &query, options)
+            }
+            Command::Add { index, id } => {
+                let mut note = String::new();
+                std::io::stdin().read_to_string(&mut note).context("could not read the note from stdin")?;
+                if note.trim().is_empty() {
+                    anyhow::bail!("no note on stdin");
+                }
+                let mut searcher = match index.exists() {
+                    true => Searcher::load(&index).with_context(|| format!("could not load index `{:?}`", index))?,
+                    false => Searcher::new(),
+                };
+                searcher.add_document(&id, &note);
+                searcher.save(&index).with_context(|| format!("could not save index `{:?}`", index))
+            }
+            Command::Delete { index, id } => {
+                let mut searcher = Searcher::load(&index).with_context(|| format!("could not load index `{:?}`", index))?;
+                if !searcher.remove_document(&id) {
+                    anyhow::bail!("no note `{}` in index `{:?}`", id, index);
+                }
+                searcher.save(&index).with_context(|| format!("could not save index `{:?}`", index))
+            }
+            Command::Diff { old, new } => {
+                let old_index = Searcher::load(&old).with_context(|| format!("could not load index `{:?}`", old))?;
+                let new_index = Searcher::load(&new).with_context(|| format!("could not load index `{:?}`", new))?;
+                print!("{}", old_index.diff(&new_index));
+                Ok(())
+            }
+            Command::Alerts { command } => command.run(),
+        }
     }
+}
 
-    let directory = std::fs::read_dir(&filepath)
-        .with_context(|| format!("could not read directory `{:?}`", &filepath))?;
+impl AlertsCommand {
+    fn run(self) -> Result<()> {
+        let index = match &self {
+            AlertsCommand::Save { index, .. }
+            | AlertsCommand::Remove { index, .. }
+            | AlertsCommand::List { index }
+            | AlertsCommand::Run { index }
+            | AlertsCommand::Match { index } => index,
+        };
+        let mut searcher = Searcher::load(index).with_context(|| format!("could not load index `{:?}`", index))?;
+        match &self {
+            AlertsCommand::Save { name, query, .. } => {
+                searcher.save_query(name, query).map_err(|error| anyhow::anyhow!("{}\n{}", error, error.underline(query)))?;
+            }
+            AlertsCommand::Remove { name, .. } => {
+                if !searcher.remove_saved_query(name) {
+                    anyhow::bail!("no saved query `{}` in index `{:?}`", name, index);
+                }
+            }
+            AlertsCommand::List { .. } => {
+                for (name, query) in searcher.saved_queries() {
+                    println!("{}: {}", name, query);
+                }
+                return Ok(());
+            }
+            AlertsCommand::Run { .. } => {
+                for alert in searcher.run_alerts() {
+                    println!("query: {}, doc_id: {}, score: {}", alert.query, alert.doc_id, alert.score);
+                }
+            }
+            AlertsCommand::Match { .. } => {
+                let mut document = String::new();
+                std::io::stdin().read_to_string(&mut document).context("could not read the document from stdin")?;
+                for name in searcher.percolate(&document) {
+                    println!("{}", name);
+                }
+                return Ok(());
+            }
+        }
+        searcher.save(index).with_context(|| format!("could not save index `{:?}`", index))
+    }
+}
 
-    let mut searcher = Searcher::new();
+impl SearchOptions {
+    /// Apply the options that are settings of the index: the scorer, and no cap on the number of
+    /// results with `--limit 0`.
+    fn configure<B: IndexBackend>(&self, searcher: &mut Searcher<B>) {
+        self.scorer.set(searcher);
+        if self.limit == 0 {
+            searcher.set_max_results(None);
+        }
+    }
+}
 
-    for entry in directory {
-        let entry = entry.with_context(|| format!("error while reading directory `{:?}`", &filepath))?;
+/// The scoring functions `--scorer` picks from.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ScorerName {
+    Bm25,
+    TfIdf,
+    Bm25Plus,
+    Bm25l,
+}
 
-        // TODO: handle symlinks and directories
-        match entry.file_type().with_context(|| format!("could not get file type of `{:?}`", &entry.path()))? {
-            t if t.is_file() => (),
-            t if t.is_dir() => continue,
-            t if t.is_symlink() => continue,
-            _ => continue,
+impl ScorerName {
+    fn set<B: IndexBackend>(self, searcher: &mut Searcher<B>) {
+        match self {
+            ScorerName::Bm25 => searcher.set_scorer(Bm25),
+            ScorerName::TfIdf => searcher.set_scorer(TfIdf),
+            ScorerName::Bm25Plus => searcher.set_scorer(Bm25Plus::default()),
+            ScorerName::Bm25l => searcher.set_scorer(Bm25L::default()),
         }
+    }
+}
+
+/// Query-independent rank of a file from where it sits, how recently it changed and what kind of
+/// file it is. Files without any notable signal rank 1.0.
+fn file_rank(relative_path: &std::path::Path, metadata: &std::fs::Metadata) -> f32 {
+    // each directory level below the root costs a little
+    let depth = relative_path.components().count().saturating_sub(1);
+    let mut rank = 1.0 / (1.0 + 0.1 * depth as f32);
+
+    // vendored and generated trees hold copies, not the project's own files
+    if relative_path
+        .components()
+        .any(|c| matches!(c.as_os_str().to_str(), Some("vendor" | "node_modules" | "target" | "third_party")))
+    {
+        rank *= 0.5;
+    }
+
+    // up to 1.25 for files changed today, decaying over a few months
+    if let Some(age) = metadata.modified().ok().and_then(|modified| modified.elapsed().ok()) {
+        let days = age.as_secs_f32() / 86_400.0;
+        rank *= 1.0 + 0.25 / (1.0 + days / 30.0);
+    }
+
+    let name = relative_path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+    let extension = relative_path.extension().map(|ext| ext.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.starts_with("readme") {
+        rank *= 1.5;
+    } else if matches!(extension.as_str(), "md" | "txt" | "rst") {
+        rank *= 1.2;
+    } else if matches!(extension.as_str(), "lock" | "map" | "log") || name.ends_with(".min.js") {
+        rank *= 0.5;
+    }
 
-        let file_name_os_str = entry.file_name();
-        let filename = file_name_os_str.to_string_lossy();
-        
-        let contents = std::fs::read_to_string(entry.path()).with_context(|| format!("could not read file `{:?}`", filename))?;
+    rank
+}
+
+/// A file found under the directory being indexed.
+struct FoundFile {
+    path: std::path::PathBuf, // where to read it from
+    doc_id: String,           // path relative to the directory, with `/` separators
+    metadata: std::fs::Metadata,
+}
 
-         searcher.add_document(&filename, &contents);
+/// Every file under `root` that `options` select, in name order. Hidden files and what
+/// `.gitignore` and `.ignore` files rule out are left out, and so are symlinks unless followed.
+/// Symlinks, links back to a directory being walked and entries that can't be read are recorded
+/// in `report` as skipped.
+fn find_files(root: &std::path::Path, options: &IndexOptions, report: &mut BuildReport) -> Result<Vec<FoundFile>> {
+    std::fs::read_dir(root).with_context(|| format!("could not read directory `{:?}`", root))?;
+
+    let mut globs = OverrideBuilder::new(root);
+    for glob in &options.include {
+        globs.add(glob).with_context(|| format!("invalid --include glob `{}`", glob))?;
+    }
+    for glob in &options.exclude {
+        globs.add(&format!("!{}", glob)).with_context(|| format!("invalid --exclude glob `{}`", glob))?;
     }
+    let walker = WalkBuilder::new(root)
+        .overrides(globs.build()?)
+        .follow_links(options.follow_symlinks)
+        // a .gitignore applies outside of git repositories too
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
 
-    let results = searcher.search(&args.query);
-    
-    if results.is_empty() {
-        return Err(anyhow::anyhow!(format!("No results found for query: {}", args.query)));
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(error) => {
+                let path = error_path(&error).unwrap_or(root);
+                report.skip(&path.strip_prefix(root).unwrap_or(path).to_string_lossy(), &error.to_string());
+                continue;
+            }
+        };
+        let relative_path = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        let doc_id = relative_path.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+        let skipped = match entry.file_type() {
+            Some(t) if t.is_file() => None,
+            Some(t) if t.is_dir() => continue,
+            Some(t) if t.is_symlink() => Some("symlink"),
+            _ => Some("not a regular file"),
+        };
+        if let Some(reason) = skipped {
+            report.skip(&doc_id, reason);
+            continue;
+        }
+        let metadata = entry.metadata().with_context(|| format!("could not read metadata of `{:?}`", doc_id))?;
+        files.push(FoundFile { path: entry.into_path(), doc_id, metadata });
     }
+    Ok(files)
+}
 
-    for (doc_id, score) in results {
-        println!("doc_id: {}, score: {}", doc_id, score);
+/// The entry a walking error is about.
+fn error_path(error: &ignore::Error) -> Option<&std::path::Path> {
+    match error {
+        ignore::Error::WithPath { path, .. } => Some(path),
+        ignore::Error::Loop { child, .. } => Some(child),
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => error_path(err),
+        _ => None,
+    }
+}
+
+/// How files that aren't valid UTF-8 are decoded.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Encoding {
+    /// UTF-8, with invalid bytes replaced by U+FFFD
+    Utf8,
+    /// ISO-8859-1, for legacy text files: every byte is the character with that code
+    Latin1,
+}
+
+/// The text of a file, decoded as `encoding` unless it is valid UTF-8. Files that look binary, with a null byte near the
+/// start like git checks for, are recorded in `report` as skipped and give None. Warns on stderr
+/// about both, and about UTF-8 files with invalid bytes.
+fn read_text(file: &FoundFile, encoding: Encoding, report: &mut BuildReport) -> Result<Option<String>> {
+    let bytes = std::fs::read(&file.path).with_context(|| format!("could not read file `{:?}`", file.doc_id))?;
+    if bytes[..bytes.len().min(8192)].contains(&0) {
+        eprintln!("warning: skipped binary file `{}`", file.doc_id);
+        report.skip(&file.doc_id, "binary file");
+        return Ok(None);
+    }
+    let text = match (String::from_utf8(bytes), encoding) {
+        (Ok(text), _) => text,
+        (Err(error), Encoding::Utf8) => {
+            eprintln!("warning: `{}` is not valid UTF-8, replaced the invalid bytes; see --encoding", file.doc_id);
+            String::from_utf8_lossy(error.as_bytes()).into_owned()
+        }
+        (Err(error), Encoding::Latin1) => error.as_bytes().iter().map(|&byte| byte as char).collect(),
+    };
+    Ok(Some(text))
+}
+
+/// Add files to `searcher`, by path. Binary files are recorded in `report` as skipped.
+fn index_directory(searcher: &mut dyn SearchEngine, files: &[FoundFile], file_signals: bool, encoding: Encoding, report: &mut BuildReport) -> Result<()> {
+    // files are read first and indexed all at once, so that they can be analyzed in parallel
+    let mut docs = Vec::new();
+    let mut extensions = Vec::new();
+    let mut ranks = Vec::new();
+    for file in files {
+        // subtitles and books are read by `index_subtitles` and `index_books`
+        if is_subtitles(&file.path) || cfg!(feature = "epub") && is_epub(&file.path) {
+            continue;
+        }
+
+        let Some(contents) = read_text(file, encoding, report)? else {
+            continue;
+        };
+
+        if let Some(ext) = file.path.extension() {
+            extensions.push((file.doc_id.clone(), ext.to_string_lossy().into_owned()));
+        }
+
+        if file_signals {
+            ranks.push((file.doc_id.clone(), file_rank(std::path::Path::new(&file.doc_id), &file.metadata)));
+        }
+
+        docs.push((file.doc_id.clone(), contents));
+    }
+
+    searcher.add_documents(docs)?;
+    for (doc_id, ext) in extensions {
+        searcher.add_keyword(&doc_id, "ext", &ext)?;
+    }
+    for (doc_id, rank) in ranks {
+        searcher.set_static_rank(&doc_id, rank)?;
     }
 
     Ok(())
 }
+
+/// Add the entries of log files to `searcher`, by path and line.
+fn index_logs<B: IndexBackend>(searcher: &mut Searcher<B>, files: &[FoundFile], encoding: Encoding, report: &mut BuildReport) -> Result<()> {
+    for file in files {
+        let Some(log) = read_text(file, encoding, report)? else {
+            continue;
+        };
+        // timestamps without a year are from the year the log was last written
+        let year = file.metadata.modified().map(logs::year_of).unwrap_or_else(|_| logs::year_of(std::time::SystemTime::now()));
+        searcher.add_log(&file.doc_id, &log, year);
+    }
+    Ok(())
+}
+
+/// Whether a file holds subtitles, by extension.
+fn is_subtitles(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("srt") || ext.eq_ignore_ascii_case("vtt"))
+}
+
+/// Add the cues of the subtitle files among `files` to `searcher`, by path and start time.
+fn index_subtitles<B: IndexBackend>(searcher: &mut Searcher<B>, files: &[FoundFile], encoding: Encoding, report: &mut BuildReport) -> Result<()> {
+    for file in files.iter().filter(|file| is_subtitles(&file.path)) {
+        let Some(subtitles) = read_text(file, encoding, report)? else {
+            continue;
+        };
+        searcher.add_subtitles(&file.doc_id, &subtitles);
+    }
+    Ok(())
+}
+
+/// Whether a file is an EPUB book, by extension.
+fn is_epub(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("epub"))
+}
+
+/// Add the EPUB books among `files` to `searcher`, by path, with their chapters split into chunks
+/// of `chapter_words` words if set. Books that can't be read are recorded in `report` as skipped.
+#[cfg(feature = "epub")]
+fn index_books<B: IndexBackend>(searcher: &mut Searcher<B>, files: &[FoundFile], chapter_words: Option<usize>, report: &mut BuildReport) {
+    for file in files.iter().filter(|file| is_epub(&file.path)) {
+        match Epub::open(&file.path) {
+            Ok(book) => {
+                searcher.add_epub(&file.doc_id, &book, chapter_words);
+            }
+            Err(error) => report.skip(&file.doc_id, &error.to_string()),
+        }
+    }
+}
+
+/// `query`, narrowed to log entries dated from `since` on and before `before`.
+fn within_dates(query: Query, since: Option<String>, before: Option<String>) -> Query {
+    if since.is_none() && before.is_none() {
+        return query;
+    }
+    let dates = Query::Range {
+        field: logs::DATE_FIELD.to_string(),
+        lower: since.map_or(Bound::Unbounded, Bound::Included),
+        upper: before.map_or(Bound::Unbounded, Bound::Excluded),
+    };
+    Query::Bool(BoolQuery {
+        must: vec![query],
+        filter: vec![dates],
+        ..Default::default()
+    })
+}
+
+/// The `bucket/prefix` of an `s3://` path.
+fn s3_location(path: &std::path::Path) -> Option<&str> {
+    path.to_str()?.strip_prefix("s3://")
+}
+
+/// A bucket to read from, with credentials from the environment.
+#[cfg(feature = "s3")]
+fn s3_source(location: &str) -> Result<S3Source> {
+    let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+    Ok(S3Source::new(S3Config::from_env(bucket, prefix)?)?)
+}
+
+/// Whether a path is the URL of a sitemap.
+fn is_url(path: &std::path::Path) -> bool {
+    path.to_str().is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Where `--lazy-content` reads documents back from.
+fn content_provider(path: &std::path::Path) -> Result<Arc<dyn ContentProvider>> {
+    if is_url(path) {
+        anyhow::bail!("--lazy-content can't read pages back from a sitemap");
+    }
+    match s3_location(path) {
+        #[cfg(feature = "s3")]
+        Some(location) => Ok(Arc::new(s3_source(location)?)),
+        _ => Ok(Arc::new(FileContentProvider::new(path))),
+    }
+}
+
+/// Index `filepath` into the empty `index` the way `options` ask: a directory, or an S3 location
+/// or sitemap when built with those features. Inputs that can't be indexed are recorded in
+/// `skipped`.
+fn build_index<B: IndexBackend>(mut index: Searcher<B>, filepath: &std::path::Path, options: &IndexOptions, skipped: &mut BuildReport) -> Result<Searcher<B>> {
+    // files are found by name as well as by content
+    index.set_index_paths(true);
+    if options.lazy_content {
+        index.set_content_provider(Some(content_provider(filepath)?));
+    }
+    match s3_location(filepath) {
+        #[cfg(feature = "sitemap")]
+        _ if is_url(filepath) => {
+            let url = filepath.to_string_lossy();
+            let mut crawler = SitemapCrawler::new();
+            if let Some(user_agent) = &options.user_agent {
+                crawler.set_user_agent(user_agent);
+            }
+            crawler.set_host_delay(std::time::Duration::try_from_secs_f64(options.crawl_delay).context("--crawl-delay must be a number of seconds")?);
+            crawler.crawl(&mut index, &url, skipped).with_context(|| format!("could not index `{}`", url))?;
+        }
+        #[cfg(feature = "s3")]
+        Some(location) => {
+            index.ingest_s3(&s3_source(location)?, skipped).with_context(|| format!("could not index `{}`", filepath.display()))?;
+        }
+        _ if options.logs => {
+            let files = find_files(filepath, options, skipped)?;
+            index_logs(&mut index, &files, options.encoding, skipped)?;
+        }
+        _ => {
+            let files = find_files(filepath, options, skipped)?;
+            index_directory(&mut index, &files, options.file_signals, options.encoding, skipped)?;
+            index_subtitles(&mut index, &files, options.encoding, skipped)?;
+            #[cfg(feature = "epub")]
+            index_books(&mut index, &files, options.chapters, skipped);
+        }
+    }
+    Ok(index)
+}
+
+/// Print the build report and export the term statistics if `options` ask for them.
+fn write_report(searcher: &dyn SearchEngine, options: &IndexOptions, skipped: BuildReport) -> Result<()> {
+    if options.report {
+        let mut report = searcher.build_report()?;
+        report.skipped = skipped.skipped;
+        eprint!("{}", report);
+    }
+
+    if let Some(stats_path) = &options.export_stats {
+        let term_stats = searcher.term_stats()?;
+        let exported = match stats_path.extension() {
+            Some(ext) if ext == "json" => stats::to_json(&term_stats),
+            _ => stats::to_csv(&term_stats),
+        };
+        std::fs::write(stats_path, exported).with_context(|| format!("could not write `{:?}`", stats_path))?;
+    }
+    Ok(())
+}
+
+/// The results of `query` that `options` ask for: `--limit` of them after the `--offset` best,
+/// among those scoring at least `--min-score`.
+fn search_page<'a>(searcher: &'a dyn SearchEngine, query: &'a Query, options: &SearchOptions) -> Result<SearchPage<'a>> {
+    let limit = match options.limit {
+        0 => usize::MAX,
+        limit => limit,
+    };
+    let min_score = options.min_score.unwrap_or(f32::NEG_INFINITY);
+    Ok(searcher.search_hits_above(query, min_score, options.offset, limit)?)
+}
+
+/// Run `query_text` and print the results, best first, in the `--output` format or for reading.
+fn search(searcher: &dyn SearchEngine, query_text: &str, options: SearchOptions) -> Result<()> {
+    let query = match options.json_query {
+        true => Query::from_json(query_text)?,
+        // the query comes straight from the user, so don't fail on malformed syntax
+        false => Query::parse(query_text, ParseMode::Lenient)?,
+    };
+    let query = within_dates(query, options.since.clone(), options.before.clone());
+    let page = search_page(searcher, &query, &options)?;
+    let hits = page.hits;
+
+    if hits.is_empty() && page.total > 0 && options.offset >= page.total {
+        return Err(anyhow::anyhow!(format!("Only {} results found for query: {}", page.total, query_text)));
+    }
+    if hits.is_empty() {
+        return Err(anyhow::anyhow!(format!("No results found for query: {}", query_text)));
+    }
+
+    if let Some(format) = options.output {
+        let results: Vec<(String, f32, Option<String>)> = hits
+            .into_iter()
+            .map(|hit| {
+                let snippet = hit.snippet("", "")?.map(|snippet| snippet.split_whitespace().collect::<Vec<_>>().join(" "));
+                Ok((hit.doc_id, hit.score, snippet))
+            })
+            .collect::<Result<_>>()?;
+        std::io::stdout().write_all(&format.encode_with_snippets(&results)).context("could not write results")?;
+        return Ok(());
+    }
+
+    for (rank, hit) in hits.iter().enumerate() {
+        println!("{}. doc_id: {}, score: {}", options.offset + rank + 1, hit.doc_id, hit.score);
+        // on one line, however the passage was laid out in the file
+        if let Some(snippet) = hit.snippet("**", "**")?.filter(|snippet| !snippet.trim().is_empty()) {
+            println!("    {}", snippet.split_whitespace().collect::<Vec<_>>().join(" "));
+        }
+        if options.explain {
+            if let Some(explanation) = searcher.explain(&query, &hit.doc_id)? {
+                for line in explanation.to_string().lines().skip(1) {
+                    println!("  {}", line);
+                }
+            }
+        }
+    }
+    if page.total > hits.len() {
+        println!("Results {}-{} of {}", options.offset + 1, options.offset + hits.len(), page.total);
+    }
+
+    Ok(())
+}
+
+/// The index to search `filepath` with, its postings in the backend `args` pick: a Redis server
+/// with `--redis`, or else memory, loaded from `--index` if it exists and saved there if not.
+fn open_index(args: &Cli, filepath: &std::path::Path, skipped: &mut BuildReport) -> Result<Box<dyn SearchEngine>> {
+    #[cfg(feature = "redis")]
+    if let Some(url) = &args.redis {
+        let backend = RedisBackend::open(url, &args.redis_prefix).with_context(|| format!("could not connect to `{}`", url))?;
+        let mut index = build_index(Searcher::with_backend(backend), filepath, &args.indexing, skipped)?;
+        args.searching.configure(&mut index);
+        return Ok(Box::new(index));
+    }
+
+    let mut index = match &args.index {
+        Some(index_path) if index_path.exists() => {
+            let mut index = Searcher::load(index_path).with_context(|| format!("could not load index `{:?}`", index_path))?;
+            if args.indexing.lazy_content {
+                index.set_content_provider(Some(content_provider(filepath)?));
+            }
+            index
+        }
+        _ => {
+            let index = build_index(Searcher::new(), filepath, &args.indexing, skipped)?;
+            if let Some(index_path) = &args.index {
+                index.save(index_path).with_context(|| format!("could not save index `{:?}`", index_path))?;
+            }
+            index
+        }
+    };
+    args.searching.configure(&mut index);
+    Ok(Box::new(index))
+}
+
+fn main() -> Result<()> {
+    let mut args = Cli::parse();
+    if let Some(command) = args.command.take() {
+        return command.run();
+    }
+    let (Some(query_text), Some(mut filepath)) = (args.query.take(), args.path.take()) else {
+        unreachable!("clap requires the query and path without a subcommand");
+    };
+
+    if filepath.as_os_str().is_empty() {
+        filepath = std::path::PathBuf::from(".");
+    }
+
+    // everything below goes through the SearchEngine interface, whatever keeps the postings
+    let mut skipped = BuildReport::default();
+    let searcher = open_index(&args, &filepath, &mut skipped)?;
+    write_report(&*searcher, &args.indexing, skipped)?;
+    search(&*searcher, &query_text, args.searching)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_mixed_encodings() {
+        let dir = std::env::temp_dir().join(format!("searcher-encodings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("utf8.txt"), "café au lait").unwrap();
+        std::fs::write(dir.join("latin1.txt"), b"caf\xe9 cr\xe8me").unwrap();
+        std::fs::write(dir.join("image.png"), b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR").unwrap();
+
+        let cli = Cli::try_parse_from(["pmse", "café", dir.to_str().unwrap(), "--encoding", "latin1"]).unwrap();
+        let mut report = BuildReport::default();
+        let searcher = build_index(Searcher::new(), &dir, &cli.indexing, &mut report);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let searcher = searcher.unwrap();
+
+        // valid UTF-8 isn't decoded again as Latin-1
+        let found = searcher.search("café");
+        assert!(found.contains_key("utf8.txt") && found.contains_key("latin1.txt"), "{:?}", found);
+        assert!(searcher.search("crème").contains_key("latin1.txt"));
+        assert!(searcher.search("cafã").is_empty());
+        assert_eq!(report.skipped, [("image.png".to_string(), "binary file".to_string())]);
+        assert_eq!(searcher.build_report().documents, 2);
+    }
+
+    #[test]
+    fn test_min_score_with_paging() {
+        let mut searcher = Searcher::new();
+        for i in 0..5 {
+            searcher.add_document(&i.to_string(), &"moon ".repeat(i + 1));
+        }
+        let query = Query::Term("moon".to_string());
+        let min_score = searcher.search_query(&query)["2"].to_string();
+        let page = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["pmse", "moon", "."].iter().chain(args).chain(&["--min-score", &min_score])).unwrap();
+            let page = search_page(&searcher, &query, &cli.searching).unwrap();
+            (page.hits.into_iter().map(|hit| hit.doc_id).collect::<Vec<_>>(), page.total)
+        };
+
+        // results below the minimum neither take a place on the page nor count
+        assert_eq!(page(&["--limit", "2", "--offset", "1"]), (vec!["3".to_string(), "2".to_string()], 3));
+        assert_eq!(page(&["--limit", "0", "--offset", "2"]), (vec!["2".to_string()], 3));
+        assert_eq!(page(&["--offset", "3"]), (vec![], 3));
+    }
+
+    #[cfg(feature = "redis")]
+    #[test]
+    fn test_redis_options() {
+        let cli = Cli::try_parse_from(["pmse", "moon", ".", "--redis", "redis://localhost"]).unwrap();
+        assert_eq!(cli.redis.as_deref(), Some("redis://localhost"));
+        assert_eq!(cli.redis_prefix, "searcher");
+        // an index in Redis isn't saved to or loaded from a file
+        assert!(Cli::try_parse_from(["pmse", "moon", ".", "--redis", "redis://localhost", "--index", "notes.idx"]).is_err());
+        assert!(Cli::try_parse_from(["pmse", "moon", ".", "--redis-prefix", "notes"]).is_err());
+    }
+
+    #[test]
+    fn test_diff_command() {
+        let dir = std::env::temp_dir().join(format!("searcher-diff-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (old, new) = (dir.join("old.idx"), dir.join("new.idx"));
+        let mut searcher = Searcher::new();
+        searcher.add_document("a.md", "moon rocket");
+        searcher.save(&old).unwrap();
+        searcher.add_document("b.md", "ocean tide");
+        searcher.save(&new).unwrap();
+
+        let diff = |old: &std::path::Path, new: &std::path::Path| {
+            let cli = Cli::try_parse_from(["pmse", "diff", old.to_str().unwrap(), new.to_str().unwrap()]).unwrap();
+            cli.command.unwrap().run()
+        };
+        let (found, missing) = (diff(&old, &new), diff(&old, &dir.join("missing.idx")));
+        std::fs::remove_dir_all(&dir).unwrap();
+        found.unwrap();
+        assert!(missing.unwrap_err().to_string().contains("missing.idx"));
+    }
+}
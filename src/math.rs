@@ -0,0 +1,43 @@
+//! Float math that `core` lacks: the methods of `std` when it is there, so that scores stay what
+//! they have always been, and `libm` without it.
+
+#[cfg(feature = "std")]
+pub(crate) fn ln(x: f32) -> f32 {
+    x.ln()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn ln(x: f32) -> f32 {
+    libm::logf(x)
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn sqrt(x: f32) -> f32 {
+    x.sqrt()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn sqrt(x: f32) -> f32 {
+    libm::sqrtf(x)
+}
+
+/// The exponent of the power of ten at or below `x`, e.g. 2 for 345.
+#[cfg(feature = "std")]
+pub(crate) fn magnitude(x: f64) -> f64 {
+    x.log10().floor()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn magnitude(x: f64) -> f64 {
+    libm::floor(libm::log10(x))
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn ceil(x: f64) -> f64 {
+    x.ceil()
+}
+
+#[cfg(not(feature = "std"))]
+pub(crate) fn ceil(x: f64) -> f64 {
+    libm::ceil(x)
+}
@@ -0,0 +1,331 @@
+//! Query metrics behind a small hook trait, so that embedders can watch search latency without
+//! wrapping every call: a latency histogram and a log of slow queries are built in.
+
+#[cfg(feature = "std")]
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::io::Write;
+#[cfg(feature = "std")]
+use std::sync::Mutex;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::{math, IndexBackend, Searcher};
+
+/// The timing of one search.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuerySample {
+    /// The query text, or the debug form of a structured query.
+    pub query: String,
+    /// Time spent in each phase, in order, e.g. `("analysis", ...)` then `("scoring", ...)`.
+    pub phases: Vec<(&'static str, Duration)>,
+    pub total: Duration,
+    /// Number of documents returned.
+    pub results: usize,
+}
+
+/// Receives the timing of every search run on a Searcher. Methods take `&self` so a hook can be
+/// shared between Searchers and threads.
+pub trait QueryMetrics: Send + Sync {
+    fn record(&self, sample: &QuerySample);
+}
+
+/// Several hooks at once, each receiving every sample.
+impl QueryMetrics for Vec<Arc<dyn QueryMetrics>> {
+    fn record(&self, sample: &QuerySample) {
+        for metrics in self {
+            metrics.record(sample);
+        }
+    }
+}
+
+const BUCKETS: usize = 40;
+
+/// Query latencies in buckets of powers of two microseconds: bucket `i` counts queries that took
+/// less than `2^i` µs and at least `2^(i-1)` µs.
+pub struct LatencyHistogram {
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        LatencyHistogram {
+            buckets: core::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        LatencyHistogram::default()
+    }
+
+    /// Number of queries recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).sum()
+    }
+
+    /// The upper bound of each non-empty bucket with the number of queries in it.
+    pub fn buckets(&self) -> Vec<(Duration, u64)> {
+        self.buckets
+            .iter()
+            .enumerate()
+            .map(|(i, bucket)| (Duration::from_micros(1 << i), bucket.load(Ordering::Relaxed)))
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    /// An upper bound of the latency below which a `quantile` (in `[0, 1]`) of the queries fall,
+    /// e.g. `0.99` for p99. None before any query was recorded.
+    pub fn quantile(&self, quantile: f64) -> Option<Duration> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let wanted = (math::ceil(total as f64 * quantile.clamp(0.0, 1.0)) as u64).max(1);
+        let mut seen = 0;
+        self.buckets().into_iter().find_map(|(bound, count)| {
+            seen += count;
+            (seen >= wanted).then_some(bound)
+        })
+    }
+}
+
+impl QueryMetrics for LatencyHistogram {
+    fn record(&self, sample: &QuerySample) {
+        let micros = sample.total.as_micros() as u64;
+        let bucket = ((u64::BITS - micros.leading_zeros()) as usize).min(BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Keeps the most recent queries that took at least `threshold`, and optionally writes a line
+/// for each of them, e.g. to stderr.
+#[cfg(feature = "std")]
+pub struct SlowQueryLog {
+    threshold: Duration,
+    capacity: usize,
+    entries: Mutex<VecDeque<QuerySample>>,
+    writer: Option<Mutex<Box<dyn Write + Send>>>,
+}
+
+#[cfg(feature = "std")]
+impl SlowQueryLog {
+    /// Keep up to `capacity` of the queries slower than `threshold`, dropping the oldest first.
+    pub fn new(threshold: Duration, capacity: usize) -> Self {
+        SlowQueryLog {
+            threshold,
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+            writer: None,
+        }
+    }
+
+    /// Also write every slow query to `writer` as it happens.
+    pub fn with_writer<W: Write + Send + 'static>(mut self, writer: W) -> Self {
+        self.writer = Some(Mutex::new(Box::new(writer)));
+        self
+    }
+
+    /// The slow queries kept, oldest first.
+    pub fn entries(&self) -> Vec<QuerySample> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(feature = "std")]
+impl QueryMetrics for SlowQueryLog {
+    fn record(&self, sample: &QuerySample) {
+        if sample.total < self.threshold {
+            return;
+        }
+        if let Some(writer) = &self.writer {
+            let phases: Vec<String> = sample.phases.iter().map(|(phase, time)| format!("{} {:?}", phase, time)).collect();
+            // a log line that can't be written shouldn't fail the search
+            let _ = writeln!(
+                writer.lock().unwrap(),
+                "slow query ({:?}, {} results, {}): {}",
+                sample.total,
+                sample.results,
+                phases.join(", "),
+                sample.query
+            );
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if self.capacity > 0 {
+            if entries.len() == self.capacity {
+                entries.pop_front();
+            }
+            entries.push_back(sample.clone());
+        }
+    }
+}
+
+/// Measures the phases of one search for the Searcher's metrics hook. Does nothing without one.
+#[cfg(feature = "std")]
+pub(crate) struct QueryTimer<'s> {
+    metrics: Option<&'s dyn QueryMetrics>,
+    start: Option<Instant>,
+    phase_start: Option<Instant>,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+#[cfg(feature = "std")]
+impl QueryTimer<'_> {
+    /// End the current phase, which started with the timer or at the end of the previous phase.
+    pub(crate) fn phase(&mut self, name: &'static str) {
+        if let Some(phase_start) = self.phase_start {
+            let now = Instant::now();
+            self.phases.push((name, now - phase_start));
+            self.phase_start = Some(now);
+        }
+    }
+
+    /// Hand the timing to the hook. `query` is only called when there is one.
+    pub(crate) fn finish(self, query: impl FnOnce() -> String, results: usize) {
+        if let (Some(metrics), Some(start)) = (self.metrics, self.start) {
+            metrics.record(&QuerySample {
+                query: query(),
+                phases: self.phases,
+                total: start.elapsed(),
+                results,
+            });
+        }
+    }
+}
+
+/// Without `std` there is no clock to time searches with, so there is no hook and the timer does
+/// nothing.
+#[cfg(not(feature = "std"))]
+pub(crate) struct QueryTimer;
+
+#[cfg(not(feature = "std"))]
+impl QueryTimer {
+    pub(crate) fn phase(&mut self, _name: &'static str) {}
+
+    pub(crate) fn finish(self, _query: impl FnOnce() -> String, _results: usize) {}
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Report the timing of every search to `metrics`, or stop reporting with None. Keep a clone
+    /// of the Arc to read built-in hooks like `LatencyHistogram` back.
+    #[cfg(feature = "std")]
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn QueryMetrics>>) {
+        self.metrics = metrics;
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn query_timer(&self) -> QueryTimer<'_> {
+        let metrics = self.metrics.as_deref();
+        let start = metrics.map(|_| Instant::now());
+        QueryTimer {
+            metrics,
+            start,
+            phase_start: start,
+            phases: Vec::new(),
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    pub(crate) fn query_timer(&self) -> QueryTimer {
+        QueryTimer
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    /// Keeps every sample, to check what searches report.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<QuerySample>>);
+
+    impl QueryMetrics for Recorder {
+        fn record(&self, sample: &QuerySample) {
+            self.0.lock().unwrap().push(sample.clone());
+        }
+    }
+
+    fn sample(millis: u64) -> QuerySample {
+        QuerySample {
+            query: format!("query {}", millis),
+            phases: vec![("scoring", Duration::from_millis(millis))],
+            total: Duration::from_millis(millis),
+            results: 1,
+        }
+    }
+
+    #[test]
+    fn test_metrics_hook() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "moon rocket");
+        searcher.add_document("2", "ocean tide");
+
+        let recorder = Arc::new(Recorder::default());
+        let histogram = Arc::new(LatencyHistogram::new());
+        searcher.set_metrics(Some(Arc::new(vec![recorder.clone() as Arc<dyn QueryMetrics>, histogram.clone()])));
+
+        searcher.search("moon rocket");
+        searcher.search_query(&Query::parse("+ocean -moon", ParseMode::Strict).unwrap());
+        let samples = recorder.0.lock().unwrap().clone();
+        assert_eq!(samples.len(), 2);
+        assert_eq!((samples[0].query.as_str(), samples[0].results), ("moon rocket", 1));
+        let phases: Vec<&str> = samples[0].phases.iter().map(|(phase, _)| *phase).collect();
+        assert_eq!(phases, ["analysis", "scoring"]);
+        assert!(samples[1].query.contains("ocean"));
+        assert!(samples.iter().all(|sample| sample.phases.iter().map(|(_, time)| *time).sum::<Duration>() <= sample.total));
+        assert_eq!(histogram.count(), 2);
+
+        searcher.set_metrics(None);
+        searcher.search("moon");
+        assert_eq!(recorder.0.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_latency_histogram() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.quantile(0.5), None);
+        for millis in [1, 1, 1, 2, 100] {
+            histogram.record(&sample(millis));
+        }
+        assert_eq!(histogram.count(), 5);
+        assert_eq!(histogram.quantile(0.5), Some(Duration::from_micros(1024)));
+        assert_eq!(histogram.quantile(1.0), Some(Duration::from_micros(131_072)));
+        assert_eq!(histogram.buckets().len(), 3);
+    }
+
+    /// A writer whose output can be read back after it was moved into the log.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_slow_query_log() {
+        let buffer = SharedBuffer::default();
+        let log = SlowQueryLog::new(Duration::from_millis(10), 2).with_writer(buffer.clone());
+        for millis in [5, 10, 20, 30] {
+            log.record(&sample(millis));
+        }
+        let kept: Vec<String> = log.entries().into_iter().map(|sample| sample.query).collect();
+        assert_eq!(kept, ["query 20", "query 30"]);
+
+        let written = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(written.lines().count(), 3);
+        assert!(written.starts_with("slow query (10ms, 1 results, scoring 10ms): query 10"), "{}", written);
+    }
+}
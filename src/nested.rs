@@ -0,0 +1,126 @@
+//! Nested documents: sub-objects of a document (e.g. the comments of a post) indexed separately,
+//! so that all conditions of a `Query::Nested` have to match within the same child.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::backend::Failures;
+use crate::{HashMap, IndexBackend, Query, Searcher};
+
+/// The children stored under one nested path.
+pub(crate) struct NestedDocs {
+    pub(crate) searcher: Searcher,
+    pub(crate) parents: HashMap<String, String>, // child_id -> parent doc_id
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index a child object of `parent_id` under `path`, with optional keyword values. The child is
+    /// only searchable through `Query::Nested`. Returns the generated child id, or None if the parent
+    /// document does not exist.
+    pub fn add_nested(&mut self, parent_id: &str, path: &str, content: &str, keywords: &[(&str, &str)]) -> Option<String> {
+        if !self.docs.contains_key(parent_id) {
+            return None;
+        }
+
+        if !self.nested.contains_key(path) {
+            let nested = NestedDocs {
+                searcher: self.child_searcher(),
+                parents: HashMap::new(),
+            };
+            self.nested.insert(path.to_string(), nested);
+        }
+        let nested = self.nested.get_mut(path).expect("inserted above");
+
+        let sibling_count = nested.parents.values().filter(|parent| *parent == parent_id).count();
+        let child_id = format!("{}/{}/{}", parent_id, path, sibling_count);
+        nested.searcher.add_document(&child_id, content);
+        for (field, value) in keywords {
+            nested.searcher.add_keyword(&child_id, field, value);
+        }
+        nested.parents.insert(child_id.clone(), parent_id.to_string());
+        self.generation += 1;
+
+        Some(child_id)
+    }
+
+    /// For each parent with children under `path` matching `query`, the matching child ids and
+    /// their scores, best first.
+    pub fn nested_hits(&self, path: &str, query: &Query) -> HashMap<String, Vec<(String, f32)>> {
+        self.nested_matches(path, query, self.k1, self.b, &Failures::default()).unwrap_or_default()
+    }
+
+    pub(crate) fn nested_matches(
+        &self,
+        path: &str,
+        query: &Query,
+        k1: f32,
+        b: f32,
+        failures: &Failures,
+    ) -> Option<HashMap<String, Vec<(String, f32)>>> {
+        let Some(nested) = self.nested.get(path) else {
+            return Some(HashMap::new());
+        };
+
+        let mut hits: HashMap<String, Vec<(String, f32)>> = HashMap::new();
+        for (child_id, score) in nested.searcher.evaluate(query, k1, b, &nested.searcher.field_boosts, None, failures)? {
+            let parent_id = &nested.parents[&child_id];
+            hits.entry(parent_id.clone()).or_default().push((child_id, score));
+        }
+        for children in hits.values_mut() {
+            children.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        }
+        Some(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BoolQuery, Query, Searcher};
+
+    fn comments_searcher() -> Searcher {
+        let mut searcher = Searcher::new();
+        searcher.add_document("post1", "release announcement");
+        searcher.add_document("post2", "roadmap discussion");
+        searcher.add_nested("post1", "comments", "superb release", &[("author", "alice")]);
+        searcher.add_nested("post1", "comments", "crash report", &[("author", "bob")]);
+        searcher.add_nested("post2", "comments", "superb roadmap, crash unlikely", &[("author", "alice")]);
+        searcher
+    }
+
+    #[test]
+    fn test_nested_conditions_match_same_child() {
+        let searcher = comments_searcher();
+
+        // bob commented on post1 and someone wrote "superb" there, but not in the same comment
+        let query = Query::Nested {
+            path: "comments".to_string(),
+            query: Box::new(Query::Bool(BoolQuery {
+                must: vec![Query::Term("superb".to_string())],
+                filter: vec![Query::Keyword {
+                    field: "author".to_string(),
+                    value: "bob".to_string(),
+                }],
+                ..Default::default()
+            })),
+        };
+        assert!(searcher.search_query(&query).is_empty());
+
+        let query = Query::Nested {
+            path: "comments".to_string(),
+            query: Box::new(Query::Term("crash".to_string())),
+        };
+        let results = searcher.search_query(&query);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn test_nested_hits() {
+        let mut searcher = comments_searcher();
+        assert_eq!(searcher.add_nested("missing", "comments", "orphan", &[]), None);
+
+        let hits = searcher.nested_hits("comments", &Query::Term("report".to_string()));
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits["post1"][0].0, "post1/comments/1");
+    }
+}
@@ -0,0 +1,170 @@
+//! Parallel evaluation of a single heavy query: the range of document ordinals is cut into
+//! chunks that worker threads take one at a time, each keeping the best matches it has seen, and
+//! the per-thread results are merged at the end. Threads that finish a sparse chunk early go on to
+//! take the next one instead of waiting for a thread stuck in a dense one.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::atomic::{self, AtomicUsize};
+
+use crate::backend::Failures;
+use crate::daat::{open_cursors, Cursor};
+use crate::{DocId, IndexBackend, Searcher};
+
+/// Smallest number of ordinals a worker takes at once, so that seeking into the postings of a
+/// chunk doesn't cost more than scoring it.
+const MIN_CHUNK: usize = 256;
+
+struct Hit {
+    score: f32,
+    doc_id: DocId,
+}
+
+/// Better hits compare greater: higher scores first, then lower doc ids.
+impl Ord for Hit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.score.total_cmp(&other.score).then_with(|| other.doc_id.cmp(&self.doc_id))
+    }
+}
+
+impl PartialOrd for Hit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Hit {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Hit {}
+
+/// The `k` best hits pushed so far, with the worst of them on top.
+struct TopK {
+    k: usize,
+    heap: BinaryHeap<Reverse<Hit>>,
+}
+
+impl TopK {
+    fn new(k: usize) -> TopK {
+        TopK { k, heap: BinaryHeap::with_capacity(k.min(1024) + 1) }
+    }
+
+    fn push(&mut self, hit: Hit) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(hit));
+        } else if self.heap.peek().is_some_and(|Reverse(worst)| hit > *worst) {
+            self.heap.pop();
+            self.heap.push(Reverse(hit));
+        }
+    }
+
+    fn merge(mut self, other: TopK) -> TopK {
+        for Reverse(hit) in other.heap {
+            self.push(hit);
+        }
+        self
+    }
+
+    /// The hits, best first.
+    fn into_sorted(self) -> Vec<(String, f32)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(hit)| (hit.doc_id.to_string(), hit.score))
+            .collect()
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// The `k` best documents for a text query, best first, scored like `search` does, and no more
+    /// than `max_results`. The postings are traversed by up to `threads` threads, which pays off
+    /// for queries with long postings lists on large indexes. Ties are broken by doc id.
+    pub fn search_parallel(&self, query: &str, k: usize, threads: usize) -> Vec<(String, f32)> {
+        let k = self.max_results.map_or(k, |max_results| k.min(max_results));
+        let mut timer = self.query_timer();
+        let tokens = self.analyze_with(query, &self.query_stop_words(query));
+        timer.phase("analysis");
+        let failures = Failures::default();
+        let mut postings = Vec::new();
+        self.fetch_postings(self.with_synonyms(tokens.iter().map(|token| token.term.as_ref())), &mut Vec::new(), &mut postings, &failures);
+        let cursors: Vec<Cursor> = open_cursors(&postings).collect();
+        if k == 0 || cursors.is_empty() {
+            timer.finish(|| query.to_string(), 0);
+            return Vec::new();
+        }
+
+        let phrase_docs = self.phrase_docs(query, &failures);
+        let end = self.next_ordinal as usize;
+        let threads = threads.clamp(1, end.div_ceil(MIN_CHUNK).max(1));
+        // a few chunks per thread, so that uneven ones even out
+        let chunk = (end / (threads * 4)).max(MIN_CHUNK);
+        let next = AtomicUsize::new(0);
+
+        let worker = || {
+            let mut top = TopK::new(k);
+            loop {
+                let start = next.fetch_add(chunk, atomic::Ordering::Relaxed);
+                if start >= end {
+                    return top;
+                }
+                let mut chunk_cursors: Vec<Cursor> = cursors.clone();
+                let range = start as u32..(start + chunk).min(end) as u32;
+                self.traverse(&mut chunk_cursors, range, 1, self.k1, self.b, |doc_id, score| {
+                    if phrase_docs.as_ref().is_some_and(|docs| !docs.contains(doc_id.as_ref())) {
+                        return;
+                    }
+                    let score = score * self.docs[doc_id].static_rank;
+                    top.push(Hit { score, doc_id: doc_id.clone() });
+                });
+            }
+        };
+
+        let top = std::thread::scope(|scope| {
+            let workers: Vec<_> = (1..threads).map(|_| scope.spawn(worker)).collect();
+            // the calling thread works too
+            let top = worker();
+            workers
+                .into_iter()
+                .map(|handle| handle.join().expect("search worker panicked"))
+                .fold(top, TopK::merge)
+        });
+        let results = top.into_sorted();
+        timer.phase("scoring");
+        timer.finish(|| query.to_string(), results.len());
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_parallel() {
+        let mut searcher = Searcher::new();
+        for i in 0..2000 {
+            let content = match i % 5 {
+                0 => "moon rocket".to_string(),
+                1 => format!("moon {}", "ocean ".repeat(i % 7)),
+                2 => "rocket tide".to_string(),
+                _ => "borrow checker".to_string(),
+            };
+            searcher.add_document(&i.to_string(), &content);
+        }
+        searcher.set_static_rank("7", 3.0).unwrap();
+
+        let mut expected: Vec<(String, f32)> = searcher.search("moon rocket").into_iter().collect();
+        expected.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        for threads in [1, 4] {
+            assert_eq!(searcher.search_parallel("moon rocket", 10, threads), expected[..10]);
+            assert_eq!(searcher.search_parallel("moon rocket", 5000, threads), expected);
+        }
+        assert_eq!(searcher.search_parallel("moon rocket", 1, 4)[0].0, "7");
+        assert!(searcher.search_parallel("comet", 10, 4).is_empty());
+        assert!(searcher.search_parallel("moon", 0, 4).is_empty());
+    }
+}
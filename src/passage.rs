@@ -0,0 +1,170 @@
+//! Passage retrieval: the best matching sentences or word windows across the whole corpus,
+//! rather than whole documents.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::backend::Failures;
+use crate::highlight::Wanted;
+use crate::{HashMap, IndexBackend, Query, Searcher};
+
+/// How documents are cut into passages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Sentence,
+    /// Windows of this many words, overlapping by half.
+    Window(usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passage {
+    pub doc_id: String,
+    pub text: String,
+    /// Byte range of the passage in the document content.
+    pub span: Range<usize>,
+    pub score: f32,
+}
+
+/// Byte ranges of the sentences in `text`, trimmed of surrounding whitespace. A sentence ends after
+/// `.`, `!` or `?` followed by whitespace, or at a line break.
+pub(crate) fn sentences(text: &str) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let next_is_space = chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        let end = match c {
+            '.' | '!' | '?' if next_is_space => i + c.len_utf8(),
+            '\n' => i,
+            _ => continue,
+        };
+        push_trimmed(text, start..end, &mut sentences);
+        start = end;
+    }
+    push_trimmed(text, start..text.len(), &mut sentences);
+
+    sentences
+}
+
+fn push_trimmed(text: &str, range: Range<usize>, ranges: &mut Vec<Range<usize>>) {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    if start < end {
+        ranges.push(start..end);
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// The `limit` best passages for `query` across all matching documents, best first. Passages are
+    /// scored with BM25 against the corpus idf, normalized by the average passage length.
+    pub fn best_passages(&self, query: &Query, granularity: Granularity, limit: usize) -> Vec<Passage> {
+        let failures = Failures::default();
+        let mut wanted = Wanted::default();
+        self.collect_wanted(query, &mut wanted, &failures);
+
+        // (doc_id, span, passage text, passage length, query term frequencies)
+        let mut candidates = Vec::new();
+        let matches = self.search_query_in(query, &failures);
+        for doc_id in matches.keys() {
+            // documents whose content can't be fetched have no passages to offer
+            let Some(content) = self.content(doc_id) else {
+                continue;
+            };
+            let content = content.as_ref();
+            let tokens = self.analyze_text(content);
+            let spans: Vec<Range<usize>> = match granularity {
+                Granularity::Sentence => sentences(content),
+                Granularity::Window(size) => {
+                    let size = size.max(1);
+                    let step = (size / 2).max(1);
+                    (0..tokens.len())
+                        .step_by(step)
+                        .map(|i| tokens[i].span.start..tokens[(i + size).min(tokens.len()) - 1].span.end)
+                        .collect()
+                }
+            };
+
+            for span in spans {
+                let inside = tokens.iter().filter(|t| span.start <= t.span.start && t.span.end <= span.end);
+                let mut length = 0;
+                let mut frequencies: HashMap<String, f32> = HashMap::new();
+                for token in inside {
+                    length += 1;
+                    if wanted.terms.contains(token.term.as_ref()) {
+                        *frequencies.entry(token.term.to_string()).or_insert(0.0) += 1.0;
+                    }
+                }
+                if !frequencies.is_empty() {
+                    candidates.push((doc_id, span.clone(), content[span].to_string(), length, frequencies));
+                }
+            }
+        }
+
+        let average_length = candidates.iter().map(|c| c.3 as f32).sum::<f32>() / candidates.len().max(1) as f32;
+        let mut passages: Vec<Passage> = candidates
+            .into_iter()
+            .map(|(doc_id, span, text, length, frequencies)| {
+                let score = frequencies
+                    .iter()
+                    .map(|(term, tf)| {
+                        let norm = self.k1 * ((1.0 - self.b) + self.b * (length as f32 / average_length));
+                        self.idf(term, &failures) * tf * (self.k1 + 1.0) / norm
+                    })
+                    .sum();
+                Passage {
+                    doc_id: doc_id.clone(),
+                    text,
+                    span,
+                    score,
+                }
+            })
+            .collect();
+
+        passages.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.doc_id.cmp(&b.doc_id))
+                .then(a.span.start.cmp(&b.span.start))
+        });
+        passages.truncate(limit);
+        passages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseMode;
+
+    #[test]
+    fn test_sentences() {
+        let text = "First one. Second? v1.2 is out!\nNo dot here\n\n  Last.";
+        let split: Vec<&str> = sentences(text).into_iter().map(|r| &text[r]).collect();
+        assert_eq!(split, vec!["First one.", "Second?", "v1.2 is out!", "No dot here", "Last."]);
+    }
+
+    #[test]
+    fn test_best_passages() {
+        let mut searcher = Searcher::new();
+        searcher.add_document(
+            "rust.md",
+            "Rust is a systems language. The borrow checker enforces ownership rules. Cargo builds crates.",
+        );
+        searcher.add_document("go.md", "Go has a garbage collector. Ownership is not tracked by the compiler.");
+        searcher.add_document("misc.md", "Nothing relevant here.");
+
+        let query = Query::parse("borrow checker ownership", ParseMode::Strict).unwrap();
+        let passages = searcher.best_passages(&query, Granularity::Sentence, 2);
+        assert_eq!(passages.len(), 2);
+        assert_eq!(passages[0].doc_id, "rust.md");
+        assert_eq!(passages[0].text, "The borrow checker enforces ownership rules.");
+        assert_eq!(passages[1].doc_id, "go.md");
+        assert!(passages[0].score > passages[1].score);
+
+        let passages = searcher.best_passages(&query, Granularity::Window(2), 1);
+        assert_eq!(passages[0].text, "borrow checker");
+    }
+}
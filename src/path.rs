@@ -0,0 +1,125 @@
+//! Document ids as file paths: when enabled, the id of every document is split into path terms
+//! (directories, file name, extension, camelCase words) so term queries find files by name.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{fold_case, validate_boost, ConfigError, DocId, HashMap, HashSet, IndexBackend, Searcher};
+
+/// Terms of a file path: every component whole plus its words, split at punctuation and camelCase.
+/// `src/SearchEngine.rs` gives `src`, `searchengine.rs`, `search`, `engine` and `rs`.
+pub(crate) fn path_terms(path: &str) -> HashSet<String> {
+    let mut terms = HashSet::new();
+    for component in path.split(['/', '\\']).filter(|c| !c.is_empty()) {
+        terms.insert(fold_case(component));
+        for part in component.split(|c: char| !c.is_alphanumeric()).filter(|p| !p.is_empty()) {
+            terms.extend(camel_case_words(part).into_iter().map(fold_case));
+        }
+    }
+    terms
+}
+
+/// Split `HTTPServerConfig2` into `HTTP`, `Server`, `Config`, `2`.
+fn camel_case_words(part: &str) -> Vec<&str> {
+    let chars: Vec<(usize, char)> = part.char_indices().collect();
+    let mut words = Vec::new();
+    let mut start = 0;
+    for (k, &(i, c)) in chars.iter().enumerate().skip(1) {
+        let prev = chars[k - 1].1;
+        let next = chars.get(k + 1).map(|&(_, next)| next);
+        let boundary = (prev.is_lowercase() && c.is_uppercase())
+            || (prev.is_uppercase() && c.is_uppercase() && next.is_some_and(char::is_lowercase))
+            || (prev.is_numeric() != c.is_numeric());
+        if boundary {
+            words.push(&part[start..i]);
+            start = i;
+        }
+    }
+    words.push(&part[start..]);
+    words
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Also index the ids of documents added from now on as file paths, so that term queries find
+    /// files by name as well as by content.
+    pub fn set_index_paths(&mut self, index_paths: bool) {
+        self.index_paths = index_paths;
+    }
+
+    /// Weigh path matches `boost` times as much as their idf, so with the default of 2 a file
+    /// name match counts more than the same term in the content. 0 ignores paths when ranking.
+    pub fn set_path_boost(&mut self, boost: f32) -> Result<(), ConfigError> {
+        self.path_boost = validate_boost(boost)?;
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub(crate) fn add_path(&mut self, doc_id: &DocId) {
+        for term in path_terms(doc_id) {
+            self.paths.entry(term).or_default().insert(doc_id.clone());
+        }
+    }
+
+    /// Scores of the documents whose path contains terms of `text`, analyzed as a path.
+    pub(crate) fn path_scores(&self, text: &str) -> HashMap<String, f32> {
+        let mut scores = HashMap::new();
+        if self.paths.is_empty() {
+            return scores;
+        }
+        for term in path_terms(text) {
+            let Some(docs) = self.paths.get(&term) else {
+                continue;
+            };
+            let score = self.idf_for_count(docs.len()) * self.path_boost;
+            for doc_id in docs {
+                *scores.entry(doc_id.to_string()).or_insert(0.0) += score;
+            }
+        }
+        scores
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    #[test]
+    fn test_path_terms() {
+        let mut terms: Vec<String> = path_terms("src/HTTPServer_v2.rs").into_iter().collect();
+        terms.sort();
+        assert_eq!(terms, vec!["2", "http", "httpserver_v2.rs", "rs", "server", "src", "v"]);
+    }
+
+    #[test]
+    fn test_search_paths() {
+        let mut searcher = Searcher::new();
+        searcher.set_index_paths(true);
+        searcher.add_document("src/main.rs", "fn main() { run() }");
+        searcher.add_document("src/lib.rs", "pub fn run() {}");
+        searcher.add_document("docs/notes.md", "the main entry point lives in main.rs");
+
+        let query = Query::parse("main.rs", ParseMode::Strict).unwrap();
+        let results = searcher.search_query(&query);
+        assert_eq!(results.len(), 3);
+        assert!(results["src/main.rs"] > results["docs/notes.md"]);
+        assert!(results["src/main.rs"] > results["src/lib.rs"]);
+    }
+
+    #[test]
+    fn test_path_boost() {
+        let mut searcher = Searcher::new();
+        searcher.set_index_paths(true);
+        searcher.add_document("notes/rocket.md", "launch checklist");
+        searcher.add_document("notes/launch.md", "rocket launch notes");
+        let query = Query::Term("rocket".to_string());
+
+        let results = searcher.search_query(&query);
+        assert!(results["notes/rocket.md"] > results["notes/launch.md"]);
+
+        searcher.set_path_boost(0.0).unwrap();
+        let results = searcher.search_query(&query);
+        assert_eq!(results["notes/rocket.md"], 0.0);
+        assert_eq!(searcher.set_path_boost(-1.0), Err(ConfigError::InvalidBoost(-1.0)));
+    }
+}
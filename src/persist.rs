@@ -0,0 +1,702 @@
+//! Saving an index to disk and loading it back, so a corpus doesn't have to be analyzed again on
+//! every start. The format is a custom little-endian binary encoding behind a magic number and a
+//! version. Maps and sets are written sorted and documents are renumbered in a canonical order, so
+//! that the same documents give the same bytes whatever order they were added, replaced or
+//! removed in. Only Searchers keeping their postings in a `MemoryBackend` are saved this way;
+//! other backends store them themselves.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Bound, Range};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+use crate::backend::Failures;
+#[cfg(feature = "std")]
+use crate::{IndexEvent, Stemmer};
+use crate::{nested, validate_b, validate_boost, validate_k1, DocId, FieldScoring, HashMap, HashSet, IndexBackend, Language, Positions, Searcher, StoredDoc};
+
+const MAGIC: &[u8; 4] = b"PMSE";
+const VERSION: u32 = 11;
+
+/// Deepest nesting of field and nested document indexes accepted, so hostile files can't overflow
+/// the stack. Indexes built through the API nest one level deep.
+const MAX_DEPTH: usize = 8;
+
+/// Error returned when an index can't be saved or loaded.
+#[derive(Debug)]
+pub enum PersistError {
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// The file is not a saved index.
+    BadMagic,
+    /// The index was saved by a version of the format this build can't read.
+    UnsupportedVersion(u32),
+    /// The file ends early or holds invalid data.
+    Corrupt,
+    /// The index stems its terms, which needs the `std` feature: without it queries couldn't be
+    /// analyzed like the documents were.
+    Stemmed,
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            PersistError::Io(err) => write!(f, "{}", err),
+            PersistError::BadMagic => write!(f, "not a saved index"),
+            PersistError::UnsupportedVersion(version) => {
+                write!(f, "unsupported index format version {}, expected {}", version, VERSION)
+            }
+            PersistError::Corrupt => write!(f, "corrupt index file"),
+            PersistError::Stemmed => write!(f, "the index stems its terms, which this build can't without `std`"),
+        }
+    }
+}
+
+impl core::error::Error for PersistError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            PersistError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for PersistError {
+    fn from(err: std::io::Error) -> Self {
+        PersistError::Io(err)
+    }
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u64(len as u64);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn strs<'a, I: ExactSizeIterator<Item = &'a String>>(&mut self, values: I) {
+        self.len(values.len());
+        for value in values {
+            self.str(value);
+        }
+    }
+
+    fn set(&mut self, values: &HashSet<String>) {
+        self.strs(sorted(values).into_iter());
+    }
+}
+
+/// The items of a set in order, for output that doesn't depend on hashing.
+fn sorted<T: Ord, I: IntoIterator<Item = T>>(items: I) -> Vec<T> {
+    let mut items: Vec<T> = items.into_iter().collect();
+    items.sort();
+    items
+}
+
+/// The entries of a map in key order.
+fn by_key<K: Ord, V, I: IntoIterator<Item = (K, V)>>(entries: I) -> Vec<(K, V)> {
+    let mut entries: Vec<(K, V)> = entries.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    entries
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], PersistError> {
+        if self.bytes.len() < n {
+            return Err(PersistError::Corrupt);
+        }
+        let (head, rest) = self.bytes.split_at(n);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PersistError> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8, PersistError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, PersistError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u32(&mut self) -> Result<u32, PersistError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, PersistError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn f32(&mut self) -> Result<f32, PersistError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+
+    fn usize(&mut self) -> Result<usize, PersistError> {
+        usize::try_from(self.u64()?).map_err(|_| PersistError::Corrupt)
+    }
+
+    /// A length, checked against the bytes left so corrupt files can't trigger huge allocations.
+    fn len(&mut self) -> Result<usize, PersistError> {
+        let len = self.usize()?;
+        if len > self.bytes.len() {
+            return Err(PersistError::Corrupt);
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String, PersistError> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| PersistError::Corrupt)
+    }
+
+    fn strings<C: FromIterator<String>>(&mut self) -> Result<C, PersistError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.string()).collect()
+    }
+}
+
+impl Searcher {
+    /// Write the index, its documents and its configuration to `path`.
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), PersistError> {
+        std::fs::write(&path, self.to_bytes())?;
+        self.notify(IndexEvent::Committed { path: path.as_ref() });
+        Ok(())
+    }
+
+    /// Read an index written by `save`. Tokenizers, scorers and content providers aren't saved: an
+    /// index built with them needs them set again with `set_tokenizer`, `set_scorer` and
+    /// `set_content_provider`.
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Searcher, PersistError> {
+        Searcher::from_bytes(&std::fs::read(path)?)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer { bytes: MAGIC.to_vec() };
+        writer.u32(VERSION);
+        self.write(&mut writer);
+        writer.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Searcher, PersistError> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len()).map_err(|_| PersistError::BadMagic)? != MAGIC {
+            return Err(PersistError::BadMagic);
+        }
+        match reader.u32()? {
+            VERSION => {}
+            version => return Err(PersistError::UnsupportedVersion(version)),
+        }
+        let searcher = Searcher::read(&mut reader, 0)?;
+        match reader.bytes.is_empty() {
+            true => Ok(searcher),
+            false => Err(PersistError::Corrupt),
+        }
+    }
+
+    /// The documents in the order they are saved in, which their ordinals are renumbered by: those
+    /// the saved queries have seen, then the new ones, each by id.
+    fn canonical_order(&self) -> Vec<(&DocId, &StoredDoc)> {
+        let mut docs: Vec<(&DocId, &StoredDoc)> = self.docs.iter().collect();
+        docs.sort_by_key(|(doc_id, doc)| (doc.ordinal >= self.alerted_ordinal, *doc_id));
+        docs
+    }
+
+    fn write(&self, w: &mut Writer) {
+        w.f32(self.k1);
+        w.f32(self.b);
+        w.u64(self.max_expansions as u64);
+        w.u8(self.store_offsets as u8);
+        w.u8(self.index_paths as u8);
+        w.f32(self.path_boost);
+        w.set(&self.stop_words);
+        w.len(self.synonyms.len());
+        for (term, synonyms) in by_key(&self.synonyms) {
+            w.str(term);
+            w.strs(synonyms.iter());
+        }
+        #[cfg(feature = "std")]
+        w.str(self.stemmer.map_or("", Stemmer::code));
+        #[cfg(not(feature = "std"))]
+        w.str("");
+        w.len(self.query_languages.len());
+        for language in &self.query_languages {
+            w.str(&language.name);
+            w.set(&language.stop_words);
+        }
+        w.set(&self.copy_fields);
+
+        let docs = self.canonical_order();
+        let ordinals: HashMap<u32, u32> = docs.iter().enumerate().map(|(ordinal, (_, doc))| (doc.ordinal, ordinal as u32)).collect();
+        w.len(docs.len());
+        for &(doc_id, doc) in &docs {
+            w.str(doc_id);
+            match &doc.content {
+                None => w.u8(0),
+                Some(content) => {
+                    w.u8(1);
+                    w.str(content);
+                }
+            }
+            w.u32(doc.nterms as u32);
+            w.u32(doc.end_position);
+            w.f32(doc.static_rank);
+            w.u32(ordinals[&doc.ordinal]);
+            w.len(doc.keywords.len());
+            for (field, values) in by_key(&doc.keywords) {
+                w.str(field);
+                w.strs(values.iter());
+            }
+            match &doc.offsets {
+                None => w.u8(0),
+                Some(offsets) => {
+                    w.u8(1);
+                    w.len(offsets.len());
+                    for span in offsets {
+                        w.u64(span.start as u64);
+                        w.u64(span.end as u64);
+                    }
+                }
+            }
+        }
+
+        // reads of postings in memory can't fail
+        let failures = Failures::default();
+        w.len(failures.ok(self.index.term_count()));
+        for term in self.index.terms_from(Bound::Unbounded).map_while(|term| failures.ok(term.map(Some))) {
+            let postings = failures.ok(self.index.postings(&term)).expect("listed terms have postings");
+            let mut postings: Vec<_> = postings.iter().collect();
+            postings.sort_by_key(|posting| ordinals[&posting.ordinal]);
+            w.str(&term);
+            w.len(postings.len());
+            for posting in postings {
+                w.str(&posting.doc_id);
+                w.len(posting.positions.len());
+                for &position in &posting.positions {
+                    w.u32(position);
+                }
+            }
+        }
+
+        w.len(self.paths.len());
+        for (term, doc_ids) in by_key(&self.paths) {
+            w.str(term);
+            w.len(doc_ids.len());
+            for doc_id in sorted(doc_ids) {
+                w.str(doc_id);
+            }
+        }
+
+        w.len(self.nested.len());
+        for (path, nested) in by_key(&self.nested) {
+            w.str(path);
+            nested.searcher.write(w);
+            w.len(nested.parents.len());
+            for (child_id, parent_id) in by_key(&nested.parents) {
+                w.str(child_id);
+                w.str(parent_id);
+            }
+        }
+
+        w.len(self.fields.len());
+        for (name, field) in by_key(&self.fields) {
+            w.str(name);
+            field.write(w);
+        }
+        w.len(self.field_boosts.len());
+        for (name, boost) in by_key(&self.field_boosts) {
+            w.str(name);
+            w.f32(*boost);
+        }
+        w.u8(match self.field_scoring {
+            FieldScoring::Sum => 0,
+            FieldScoring::Bm25f => 1,
+        });
+        w.len(self.saved_queries.len());
+        for (name, (query, _)) in &self.saved_queries {
+            w.str(name);
+            w.str(query);
+        }
+        // the documents the saved queries have seen come first
+        w.u32(docs.iter().filter(|(_, doc)| doc.ordinal < self.alerted_ordinal).count() as u32);
+        match self.max_results {
+            None => w.u8(0),
+            Some(max_results) => {
+                w.u8(1);
+                w.u64(max_results as u64);
+            }
+        }
+    }
+
+    /// Read a Searcher written by `write`, `depth` levels below the saved one. Everything that
+    /// searching relies on is checked, so that a corrupt or hostile file gives an error rather than
+    /// an index that panics or overflows later.
+    fn read(r: &mut Reader, depth: usize) -> Result<Searcher, PersistError> {
+        if depth > MAX_DEPTH {
+            return Err(PersistError::Corrupt);
+        }
+        let mut searcher = Searcher::new();
+        searcher.k1 = validate_k1(r.f32()?).map_err(|_| PersistError::Corrupt)?;
+        searcher.b = validate_b(r.f32()?).map_err(|_| PersistError::Corrupt)?;
+        searcher.max_expansions = r.usize()?;
+        searcher.store_offsets = r.bool()?;
+        searcher.index_paths = r.bool()?;
+        searcher.path_boost = validate_boost(r.f32()?).map_err(|_| PersistError::Corrupt)?;
+        searcher.stop_words = r.strings()?;
+        for _ in 0..r.len()? {
+            let term = r.string()?;
+            if searcher.synonyms.insert(term, r.strings()?).is_some() {
+                return Err(PersistError::Corrupt);
+            }
+        }
+        let stemmer = r.string()?;
+        #[cfg(feature = "std")]
+        {
+            searcher.stemmer = match stemmer.as_str() {
+                "" => None,
+                code => Some(Stemmer::from_code(code).ok_or(PersistError::Corrupt)?),
+            };
+        }
+        #[cfg(not(feature = "std"))]
+        if !stemmer.is_empty() {
+            return Err(PersistError::Stemmed);
+        }
+        for _ in 0..r.len()? {
+            let name = r.string()?;
+            let stop_words: Vec<String> = r.strings()?;
+            searcher.query_languages.push(Language::new(&name, stop_words));
+        }
+        searcher.copy_fields = r.strings()?;
+
+        let mut ordinals = HashSet::new();
+        for _ in 0..r.len()? {
+            let doc_id = DocId::from(r.string()?);
+            let content = match r.bool()? {
+                false => None,
+                true => Some(r.string()?),
+            };
+            let nterms = i32::try_from(r.u32()?).map_err(|_| PersistError::Corrupt)?;
+            let end_position = r.u32()?;
+            let static_rank = validate_boost(r.f32()?).map_err(|_| PersistError::Corrupt)?;
+            let ordinal = r.u32()?;
+            if searcher.docs.contains_key(&doc_id) || !ordinals.insert(ordinal) {
+                return Err(PersistError::Corrupt);
+            }
+            let next_ordinal = ordinal.checked_add(1).ok_or(PersistError::Corrupt)?;
+            searcher.next_ordinal = searcher.next_ordinal.max(next_ordinal);
+            let mut keywords = HashMap::new();
+            for _ in 0..r.len()? {
+                let field = r.string()?;
+                let values: Vec<String> = r.strings()?;
+                for value in &values {
+                    let docs = searcher.keywords.entry(field.clone()).or_default();
+                    docs.entry(value.clone()).or_default().insert(doc_id.clone());
+                }
+                keywords.insert(field, values);
+            }
+            let offsets = match r.bool()? {
+                false => None,
+                true => Some((0..r.len()?).map(|_| Ok(r.usize()?..r.usize()?)).collect::<Result<Vec<_>, PersistError>>()?),
+            };
+            // highlighting slices the content with the offsets
+            let fits = |span: &Range<usize>| span.start <= span.end && content.as_ref().is_none_or(|content| content.get(span.clone()).is_some());
+            if !offsets.iter().flatten().all(fits) {
+                return Err(PersistError::Corrupt);
+            }
+            searcher.collection.terms = searcher.collection.terms.checked_add(nterms as u64).ok_or(PersistError::Corrupt)?;
+            searcher.collection.documents += 1;
+            let doc = StoredDoc {
+                content,
+                nterms,
+                end_position,
+                keywords,
+                offsets,
+                static_rank,
+                ordinal,
+                terms: Vec::new(),
+            };
+            searcher.docs.insert(doc_id, doc);
+        }
+
+        let failures = Failures::default();
+        for _ in 0..r.len()? {
+            let term = r.string()?;
+            for _ in 0..r.len()? {
+                let (doc_id, doc) = searcher.docs.get_key_value(r.string()?.as_str()).ok_or(PersistError::Corrupt)?;
+                let (doc_id, ordinal, end_position) = (doc_id.clone(), doc.ordinal, doc.end_position);
+                let positions = (0..r.len()?).map(|_| r.u32()).collect::<Result<Positions, _>>()?;
+                // phrase matching relies on positions being increasing and within the document
+                let increasing = positions.windows(2).all(|pair| pair[0] < pair[1]);
+                let listed = failures.ok(searcher.index.postings(&term)).is_some_and(|postings| postings.get(ordinal).is_some());
+                if positions.is_empty() || !increasing || positions[positions.len() - 1] >= end_position || listed {
+                    return Err(PersistError::Corrupt);
+                }
+                searcher.index.add_postings(&term, ordinal, &doc_id, positions);
+                searcher.docs.get_mut(&doc_id).expect("found above").terms.push(term.clone());
+            }
+        }
+
+        for _ in 0..r.len()? {
+            let term = r.string()?;
+            let mut doc_ids = HashSet::new();
+            for _ in 0..r.len()? {
+                doc_ids.insert(interned(&searcher.docs, r.string()?)?);
+            }
+            searcher.paths.insert(term, doc_ids);
+        }
+
+        for _ in 0..r.len()? {
+            let path = r.string()?;
+            let nested_searcher = Searcher::read(r, depth + 1)?;
+            let mut parents = HashMap::new();
+            for _ in 0..r.len()? {
+                let (child_id, parent_id) = (r.string()?, r.string()?);
+                if !nested_searcher.docs.contains_key(child_id.as_str()) || !searcher.docs.contains_key(parent_id.as_str()) {
+                    return Err(PersistError::Corrupt);
+                }
+                parents.insert(child_id, parent_id);
+            }
+            let nested = nested::NestedDocs {
+                searcher: nested_searcher,
+                parents,
+            };
+            searcher.nested.insert(path, nested);
+        }
+
+        for _ in 0..r.len()? {
+            let name = r.string()?;
+            let field = Searcher::read(r, depth + 1)?;
+            // fields only hold documents of the Searcher itself
+            if !field.docs.keys().all(|doc_id| searcher.docs.contains_key(doc_id)) {
+                return Err(PersistError::Corrupt);
+            }
+            searcher.fields.insert(name, field);
+        }
+        for _ in 0..r.len()? {
+            let name = r.string()?;
+            searcher.field_boosts.insert(name, validate_boost(r.f32()?).map_err(|_| PersistError::Corrupt)?);
+        }
+        searcher.field_scoring = match r.u8()? {
+            0 => FieldScoring::Sum,
+            1 => FieldScoring::Bm25f,
+            _ => return Err(PersistError::Corrupt),
+        };
+        for _ in 0..r.len()? {
+            let name = r.string()?;
+            let query = r.string()?;
+            searcher.save_query(&name, &query).map_err(|_| PersistError::Corrupt)?;
+        }
+        searcher.alerted_ordinal = r.u32()?;
+        searcher.max_results = match r.u8()? {
+            0 => None,
+            1 => Some(r.usize()?),
+            _ => return Err(PersistError::Corrupt),
+        };
+
+        Ok(searcher)
+    }
+}
+
+/// The shared id of a loaded document; ids of unknown documents mean the file is corrupt.
+fn interned(docs: &crate::FastMap<DocId, StoredDoc>, doc_id: String) -> Result<DocId, PersistError> {
+    docs.get_key_value(doc_id.as_str()).map(|(id, _)| id.clone()).ok_or(PersistError::Corrupt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "std")]
+    use crate::{HighlightOptions, Query};
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_round_trip() {
+        let mut searcher = Searcher::new();
+        searcher.set_store_offsets(true);
+        searcher.set_index_paths(true);
+        searcher.set_b(0.5).unwrap();
+        searcher.set_max_expansions(1000);
+        searcher.set_max_results(None);
+        searcher.set_stemmer(Some(Stemmer::English));
+        searcher.set_synonyms([["moon", "luna"]]).unwrap();
+        searcher.add_copy_field("title");
+        searcher.add_document("src/rocket.rs", "The rust rocket flies to the moon");
+        searcher.add_document("notes.md", "moon landing notes, moon rocks");
+        searcher.add_keyword("src/rocket.rs", "title", "borrow checker");
+        searcher.set_static_rank("notes.md", 0.5).unwrap();
+        searcher.add_nested("notes.md", "comments", "ownership rules", &[("author", "ferris")]).unwrap();
+
+        let loaded = Searcher::from_bytes(&searcher.to_bytes()).unwrap();
+        assert_eq!((loaded.b(), loaded.max_expansions, loaded.max_results), (0.5, 1000, None));
+        assert_eq!(loaded.collection, searcher.collection);
+        for query in ["moon", "luna", "rockets", "checker", "title:\"borrow checker\"", "rocket.rs", "rock*"] {
+            let query = Query::parse(query, crate::ParseMode::Strict).unwrap();
+            assert_eq!(loaded.search_query(&query), searcher.search_query(&query));
+        }
+        let nested = Query::Nested {
+            path: "comments".to_string(),
+            query: Box::new(Query::Term("ownership".to_string())),
+        };
+        assert_eq!(loaded.search_query(&nested).len(), 1);
+
+        let options = HighlightOptions::default();
+        let query = Query::Term("moon".to_string());
+        assert_eq!(loaded.highlight("src/rocket.rs", &query, &options), searcher.highlight("src/rocket.rs", &query, &options));
+    }
+
+    #[test]
+    fn test_bytes_independent_of_order() {
+        let seen = [("a.md", "moon rocket"), ("b.md", "ocean tide moon"), ("c.md", "rust compiler")];
+        let new = [("d.md", "borrow checker rocket"), ("e.md", "moon landing")];
+        let build = |reverse: bool| {
+            let mut searcher = Searcher::new();
+            searcher.set_index_paths(true);
+            searcher.set_stop_words(["the", "a", "of", "and", "to"]);
+            searcher.add_copy_field("tag");
+            searcher.add_copy_field("title");
+            searcher.set_field_boost("title", 2.0).unwrap();
+            searcher.save_query("moon", "moon").unwrap();
+            let ordered = |docs: &[(&'static str, &'static str)]| {
+                let mut docs = docs.to_vec();
+                if reverse {
+                    docs.reverse();
+                }
+                docs
+            };
+            for (doc_id, content) in ordered(&seen) {
+                searcher.add_fields(&crate::Document::new(doc_id).field("title", doc_id).field("body", content));
+                searcher.add_keyword(doc_id, "tag", "seen");
+                searcher.add_keyword(doc_id, "lang", "en");
+                searcher.add_nested(doc_id, "comments", content, &[("author", "ferris")]).unwrap();
+            }
+            searcher.run_alerts();
+            for (doc_id, content) in ordered(&new) {
+                searcher.add_document(doc_id, content);
+                searcher.add_keyword(doc_id, "tag", "new");
+            }
+            searcher
+        };
+
+        let (forward, backward) = (build(false), build(true));
+        assert!(forward.to_bytes() == backward.to_bytes());
+        // documents the saved queries have seen stay seen
+        let mut loaded = Searcher::from_bytes(&forward.to_bytes()).unwrap();
+        let alerts: Vec<String> = loaded.run_alerts().into_iter().map(|alert| alert.doc_id).collect();
+        assert_eq!(alerts, ["e.md"]);
+        assert_eq!(loaded.search("rocket"), forward.search("rocket"));
+    }
+
+    #[test]
+    fn test_bytes_independent_of_history() {
+        let mut direct = Searcher::new();
+        direct.add_document("a.md", "moon rocket");
+        direct.add_document("b.md", "ocean tide");
+        direct.add_keyword("b.md", "lang", "en");
+
+        let mut edited = Searcher::new();
+        edited.add_document("b.md", "rust compiler");
+        edited.add_document("draft.md", "moon landing");
+        edited.add_document("a.md", "moon rocket");
+        edited.set_k1(2.0).unwrap();
+        edited.set_k1(1.2).unwrap();
+        edited.update_document("b.md", "ocean tide");
+        edited.remove_document("draft.md");
+        edited.add_keyword("b.md", "lang", "en");
+
+        // how often the index changed doesn't matter, only what it holds
+        assert!(edited.generation != direct.generation);
+        assert!(edited.to_bytes() == direct.to_bytes());
+        assert_eq!(Searcher::from_bytes(&edited.to_bytes()).unwrap().generation, 0);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(matches!(Searcher::from_bytes(b"nope"), Err(PersistError::BadMagic)));
+
+        let mut bytes = Searcher::new().to_bytes();
+        bytes[4] = 99;
+        assert!(matches!(Searcher::from_bytes(&bytes), Err(PersistError::UnsupportedVersion(99))));
+
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust rocket");
+        let bytes = searcher.to_bytes();
+        assert!(matches!(Searcher::from_bytes(&bytes[..bytes.len() - 3]), Err(PersistError::Corrupt)));
+    }
+
+    #[test]
+    fn test_load_hostile() {
+        let corrupt = |searcher: &Searcher| matches!(Searcher::from_bytes(&searcher.to_bytes()), Err(PersistError::Corrupt));
+        let mut searcher = Searcher::new();
+        searcher.set_store_offsets(true);
+        searcher.add_document("1", "rust rocket");
+        assert!(!corrupt(&searcher));
+
+        // offsets past the end of the content
+        searcher.docs.get_mut("1").unwrap().offsets = Some(vec![0..4, 5..40]);
+        assert!(corrupt(&searcher));
+        searcher.docs.get_mut("1").unwrap().offsets = None;
+
+        // lengths that don't fit
+        searcher.docs.get_mut("1").unwrap().nterms = -1;
+        assert!(corrupt(&searcher));
+        searcher.docs.get_mut("1").unwrap().nterms = 2;
+
+        // positions past the end of the document
+        searcher.docs.get_mut("1").unwrap().end_position = 1;
+        assert!(corrupt(&searcher));
+        searcher.docs.get_mut("1").unwrap().end_position = 2;
+
+        // fields of documents the index doesn't have
+        let mut field = Searcher::new();
+        field.add_document("2", "moon");
+        searcher.fields.insert("title".to_string(), field);
+        assert!(corrupt(&searcher));
+        searcher.fields.clear();
+
+        // field indexes nested deeper than the stack allows
+        let mut deep = Searcher::new();
+        for _ in 0..=MAX_DEPTH {
+            let mut parent = Searcher::new();
+            parent.fields.insert("title".to_string(), deep);
+            deep = parent;
+        }
+        assert!(corrupt(&deep));
+        assert!(!corrupt(&searcher));
+    }
+}
@@ -0,0 +1,109 @@
+//! Postings of a term: the documents containing it and the positions it appears at, ordered by
+//! document ordinal so that several lists can be merged a document at a time. Most terms are
+//! rare, so a single posting is stored inline, and a few positions per document fit without a
+//! separate allocation.
+
+use smallvec::SmallVec;
+
+use crate::DocId;
+
+/// Positions of a term in one document, inline for up to two occurrences.
+pub type Positions = SmallVec<[u32; 2]>;
+
+#[derive(Debug, Clone)]
+pub(crate) struct Posting {
+    pub(crate) ordinal: u32, // order in which the document was indexed
+    pub(crate) doc_id: DocId,
+    pub(crate) positions: Positions,
+}
+
+/// The documents containing a term, with its positions in each, see `IndexBackend`.
+#[derive(Debug, Clone, Default)]
+pub struct Postings(SmallVec<[Posting; 1]>);
+
+impl Postings {
+    /// Number of documents containing the term.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The postings in document order.
+    pub(crate) fn as_slice(&self) -> &[Posting] {
+        &self.0
+    }
+
+    pub(crate) fn iter(&self) -> core::slice::Iter<'_, Posting> {
+        self.0.iter()
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Positions> {
+        self.0.iter().map(|posting| &posting.positions)
+    }
+
+    fn find(&self, ordinal: u32) -> Result<usize, usize> {
+        self.0.binary_search_by_key(&ordinal, |posting| posting.ordinal)
+    }
+
+    /// The positions of the term in document `ordinal`, None if the document lacks it.
+    pub fn get(&self, ordinal: u32) -> Option<&Positions> {
+        self.find(ordinal).ok().map(|i| &self.0[i].positions)
+    }
+
+    /// The positions of the term in document `ordinal`, added empty if the document isn't listed
+    /// yet. Appending the newest document is the cheap case.
+    pub fn positions_mut(&mut self, ordinal: u32, doc_id: &DocId) -> &mut Positions {
+        let i = match self.0.last() {
+            Some(last) if last.ordinal == ordinal => self.0.len() - 1,
+            Some(last) if last.ordinal > ordinal => self.find(ordinal).unwrap_or_else(|i| {
+                self.0.insert(i, Posting { ordinal, doc_id: doc_id.clone(), positions: Positions::new() });
+                i
+            }),
+            _ => {
+                self.0.push(Posting { ordinal, doc_id: doc_id.clone(), positions: Positions::new() });
+                self.0.len() - 1
+            }
+        };
+        &mut self.0[i].positions
+    }
+
+    /// Drop document `ordinal`, if listed.
+    pub fn remove(&mut self, ordinal: u32) {
+        if let Ok(i) = self.find(ordinal) {
+            self.0.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_postings() {
+        let (one, two, three): (DocId, DocId, DocId) = ("1".into(), "2".into(), "3".into());
+        let mut postings = Postings::default();
+        assert!(postings.is_empty());
+
+        postings.positions_mut(1, &one).push(0);
+        postings.positions_mut(1, &one).push(3);
+        assert!(!postings.0.spilled());
+        assert_eq!(postings.get(1).unwrap().as_slice(), [0, 3]);
+        assert!(postings.get(2).is_none());
+
+        postings.positions_mut(3, &three).push(1);
+        // documents that get terms late, e.g. from copied keyword values, are put in order
+        postings.positions_mut(2, &two).push(7);
+        assert_eq!(postings.iter().map(|p| p.ordinal).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(postings.values().map(|p| p.len()).sum::<usize>(), 4);
+
+        postings.remove(1);
+        assert_eq!(postings.iter().map(|p| p.doc_id.as_ref()).collect::<Vec<_>>(), ["2", "3"]);
+        postings.remove(2);
+        postings.remove(3);
+        assert!(postings.is_empty());
+    }
+}
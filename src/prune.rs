@@ -0,0 +1,108 @@
+//! Shrinking an index by dropping the terms that help ranking least: those in very few documents,
+//! mostly typos, ids and OCR noise on dirty corpora, and those in nearly all of them, which rank
+//! like stop words. Queries for a dropped term find nothing, and phrases containing one no longer
+//! match.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use core::ops::Bound;
+
+use crate::backend::Failures;
+use crate::{DocId, HashMap, HashSet, IndexBackend, Searcher};
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Drop the terms found in fewer than `min_df` documents or in more than `max_df_ratio` of
+    /// them, from this index and those of its fields and nested documents, and return how many
+    /// were dropped. With `keep_lengths`, documents still count the dropped terms in their length,
+    /// so that the remaining terms score as before; otherwise lengths are recounted as if the
+    /// dropped terms had never been indexed.
+    pub fn prune(&mut self, min_df: usize, max_df_ratio: f32, keep_lengths: bool) -> usize {
+        let max_df = max_df_ratio * self.collection.documents as f32;
+        let failures = Failures::default();
+        let dropped: HashSet<String> = self
+            .index
+            .terms_from(Bound::Unbounded)
+            .map_while(|term| failures.ok(term.map(Some)))
+            .filter(|term| {
+                let df = failures.ok(self.index.doc_frequency(term));
+                df < min_df || df as f32 > max_df
+            })
+            .map(Cow::into_owned)
+            .collect();
+
+        let mut dropped_occurrences: HashMap<DocId, usize> = HashMap::new();
+        for term in &dropped {
+            // None only if a backend outside the process failed to read them
+            let Some(postings) = failures.ok(self.index.remove_term(term)) else {
+                continue;
+            };
+            for posting in postings.iter() {
+                *dropped_occurrences.entry(posting.doc_id.clone()).or_default() += posting.positions.len();
+            }
+        }
+        for (doc_id, occurrences) in dropped_occurrences {
+            let doc = self.docs.get_mut(&doc_id).expect("postings only list indexed documents");
+            doc.terms.retain(|term| !dropped.contains(term));
+            if !keep_lengths {
+                doc.nterms -= occurrences as i32;
+                self.collection.shrink(occurrences as u64);
+            }
+        }
+        failures.ok(self.index.flush());
+
+        let mut count = dropped.len();
+        for child in self.children_mut() {
+            count += child.prune(min_df, max_df_ratio, keep_lengths);
+        }
+        if count > 0 {
+            self.generation += 1;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Document, ParseMode, Query};
+
+    fn corpus() -> Searcher {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust compiler report, compiler rust");
+        searcher.add_document("2", "rust borrow checker report");
+        searcher.add_document("3", "rust compiler xqzt report");
+        searcher.add_document("4", "moon rocket report");
+        searcher.add_fields(&Document::new("5").field("title", "rust rocket report"));
+        searcher
+    }
+
+    fn scores(searcher: &Searcher, query: &str) -> HashMap<String, f32> {
+        searcher.search_query(&Query::parse(query, ParseMode::Strict).unwrap())
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut searcher = corpus();
+        let before = scores(&searcher, "compiler rust");
+        let collection = searcher.collection;
+
+        // borrow, checker, xqzt and moon are in a single document, report in every one; the title
+        // field's only document holds all of its terms
+        assert_eq!(searcher.prune(2, 0.9, true), 5 + 3);
+        assert!(scores(&searcher, "xqzt").is_empty());
+        assert!(scores(&searcher, "report").is_empty());
+        assert!(scores(&searcher, "title:rocket").is_empty());
+        assert_eq!(scores(&searcher, "rocket").len(), 2);
+        assert!(searcher.term_vector("2").unwrap().iter().map(|entry| entry.term.as_str()).eq(["rust"]));
+        // lengths are kept, so what is left scores as before
+        assert_eq!(searcher.collection, collection);
+        assert_eq!(scores(&searcher, "compiler rust"), before);
+        assert_eq!(searcher.prune(2, 0.9, true), 0);
+
+        let mut recounted = corpus();
+        recounted.prune(2, 0.9, false);
+        assert_eq!(recounted.collection.terms, collection.terms - 9);
+        assert_eq!(recounted.docs["2"].nterms, 1);
+        assert_ne!(scores(&recounted, "compiler rust"), before);
+    }
+}
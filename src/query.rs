@@ -0,0 +1,720 @@
+//! Query syntax: turns user input into a `Query` tree that the Searcher can evaluate.
+//!
+//! The syntax is a whitespace separated list of clauses. A clause is a bare word, a word with
+//! wildcards (`data*`, `te?t`), a word followed by `~` and the number of typos to allow (`serach~1`,
+//! `~` alone allows two), a `"quoted phrase"` whose words have to appear next to each other
+//! in order, an exact `field:value` keyword
+//! match (`field:"a value"` for values with spaces) or text match in a text field of a structured
+//! `Document` (`title:rust`, `title:"a phrase"`), a `/regex/` matched against indexed terms, or
+//! a parenthesised group of clauses, optionally prefixed by
+//! `+` (must match), `-` (must not match) or `#` (must match, without affecting the score).
+//! A `-` clause followed by `^factor` demotes matching documents instead of excluding them, e.g.
+//! `-archive^0.2` multiplies their score by 0.2. A backslash makes the next character literal, see `escape`.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Bound, Range};
+
+/// Characters with a meaning in the query syntax.
+const SPECIAL_CHARS: &[char] = &['\\', '+', '-', '#', '^', ':', '(', ')', '/', '"', '*', '?', '~'];
+
+/// Deepest nesting of `()` groups accepted, so hostile input can't overflow the stack.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Free text, analyzed into terms at search time.
+    Term(String),
+    /// An exact, non-analyzed value of a keyword field. In a text field of the same name, the value
+    /// is searched as text instead, as a phrase if it has several words.
+    Keyword { field: String, value: String },
+    /// Values of a keyword field between two bounds. Values and bounds that are both numbers are
+    /// compared as numbers, others as strings.
+    Range { field: String, lower: Bound<String>, upper: Bound<String> },
+    /// A regular expression matched against whole indexed terms.
+    Regex(String),
+    /// A pattern matched against whole indexed terms, where `*` matches any run of characters, `?`
+    /// exactly one character and a backslash makes the next character literal.
+    Wildcard(String),
+    /// Indexed terms within `distance` edits of `term`, an edit being the insertion, deletion or
+    /// substitution of a character or the swap of two adjacent ones. At most 2.
+    Fuzzy { term: String, distance: u8 },
+    /// Proximity matching over term positions.
+    Intervals(Intervals),
+    /// Matches documents with a child under `path` matching `query`, scored by the best child.
+    Nested { path: String, query: Box<Query> },
+    /// Matches documents whose `to` keyword field shares a value with the `from` keyword field of a
+    /// document matching `query`, scored by the best such document. E.g. posts whose comments mention
+    /// X: `from: "post_id"` on comments, `to: "id"` on posts.
+    Join { from: String, to: String, query: Box<Query> },
+    /// A group of clauses combined with boolean semantics.
+    Bool(BoolQuery),
+    /// The matches of `query` with their scores multiplied by `factor`.
+    Boost { query: Box<Query>, factor: f32 },
+}
+
+/// Documents have to match every `must` and `filter` clause and none of the `must_not` clauses. When
+/// there are no `must` or `filter` clauses at least one `should` clause has to match. Matching `must`
+/// and `should` clauses add to the score, `filter` clauses only narrow the results. The score of
+/// documents matching a `demote` clause is multiplied by its factor.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BoolQuery {
+    pub must: Vec<Query>,
+    pub should: Vec<Query>,
+    pub must_not: Vec<Query>,
+    pub filter: Vec<Query>,
+    pub demote: Vec<(Query, f32)>,
+}
+
+/// A source of position intervals within a document, e.g. "error within 5 words of timeout" is
+/// `Intervals::unordered(vec![Intervals::term("error"), Intervals::term("timeout")], Some(4))`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Intervals {
+    /// Every occurrence of the text. Text analyzing to several terms has to match them as a phrase.
+    Term(String),
+    /// All sources in the given order, with at most `max_gaps` words in between them.
+    Ordered { sources: Vec<Intervals>, max_gaps: Option<u32> },
+    /// All sources in any order, with at most `max_gaps` words in between them.
+    Unordered { sources: Vec<Intervals>, max_gaps: Option<u32> },
+    /// Intervals of `big` that contain an interval of `small`.
+    Containing { big: Box<Intervals>, small: Box<Intervals> },
+    /// Intervals of `big` that contain no interval of `small`.
+    NotContaining { big: Box<Intervals>, small: Box<Intervals> },
+}
+
+impl Intervals {
+    pub fn term(text: &str) -> Intervals {
+        Intervals::Term(text.to_string())
+    }
+
+    pub fn ordered(sources: Vec<Intervals>, max_gaps: Option<u32>) -> Intervals {
+        Intervals::Ordered { sources, max_gaps }
+    }
+
+    pub fn unordered(sources: Vec<Intervals>, max_gaps: Option<u32>) -> Intervals {
+        Intervals::Unordered { sources, max_gaps }
+    }
+
+    pub fn containing(self, small: Intervals) -> Intervals {
+        Intervals::Containing {
+            big: Box::new(self),
+            small: Box::new(small),
+        }
+    }
+
+    pub fn not_containing(self, small: Intervals) -> Intervals {
+        Intervals::NotContaining {
+            big: Box::new(self),
+            small: Box::new(small),
+        }
+    }
+}
+
+/// How `Query::parse` treats input that is not valid syntax.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Reject invalid syntax with a `ParseError`.
+    #[default]
+    Strict,
+    /// Fall back to treating every word of the input as a plain term.
+    Lenient,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    /// What the parser was looking for, e.g. `')'` or `a term or group`.
+    pub expected: String,
+    /// The character found instead, None when the input ended early.
+    pub found: Option<char>,
+    /// Byte range of the offending input, empty when the input ended early.
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    /// Byte offset in the query where the problem starts.
+    pub fn position(&self) -> usize {
+        self.span.start
+    }
+
+    /// Render `query` with a line of carets underneath the offending span.
+    pub fn underline(&self, query: &str) -> String {
+        let start = query[..self.span.start].chars().count();
+        let width = query[self.span.clone()].chars().count().max(1);
+        format!("{}\n{}{}", query, " ".repeat(start), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {} at position {}, found ", self.expected, self.position())?;
+        match self.found {
+            Some(c) => write!(f, "`{}`", c),
+            None => write!(f, "end of input"),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {}
+
+/// Escape every character that has a meaning in the query syntax, so `input` is searched as plain text.
+pub fn escape(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+    for c in input.chars() {
+        if SPECIAL_CHARS.contains(&c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The `"quoted"` parts of free text, ignoring a quote left unclosed.
+pub(crate) fn quoted_phrases(text: &str) -> Vec<&str> {
+    let parts: Vec<&str> = text.split('"').collect();
+    parts
+        .iter()
+        .enumerate()
+        .filter(|&(i, phrase)| i % 2 == 1 && i + 1 < parts.len() && !phrase.trim().is_empty())
+        .map(|(_, phrase)| *phrase)
+        .collect()
+}
+
+impl Query {
+    pub fn parse(input: &str, mode: ParseMode) -> Result<Query, ParseError> {
+        match Parser::new(input).parse() {
+            Ok(query) => Ok(query),
+            Err(_) if mode == ParseMode::Lenient => Ok(Query::from_words(input)),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// A query matching any of the whitespace separated words in `text`, ignoring all syntax.
+    pub fn from_words(text: &str) -> Query {
+        Query::Bool(BoolQuery {
+            should: text.split_whitespace().map(|word| Query::Term(word.to_string())).collect(),
+            ..Default::default()
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Occur {
+    Must,
+    Should,
+    MustNot,
+    Filter,
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,   // byte offset of the next unread character
+    depth: usize, // number of currently open groups
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Parser<'a> {
+        Parser { input, pos: 0, depth: 0 }
+    }
+
+    fn parse(&mut self) -> Result<Query, ParseError> {
+        let query = self.parse_clauses()?;
+        match self.peek() {
+            None => Ok(query),
+            Some(_) => Err(self.error("a clause or end of input")),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// An error about the character at the current position, or the end of the input.
+    fn error(&self, expected: &str) -> ParseError {
+        let found = self.peek();
+        ParseError {
+            expected: expected.to_string(),
+            found,
+            span: self.pos..self.pos + found.map_or(0, char::len_utf8),
+        }
+    }
+
+    /// Parse clauses up to the end of input or a closing parenthesis, which is left unread.
+    fn parse_clauses(&mut self) -> Result<Query, ParseError> {
+        let mut bool_query = BoolQuery::default();
+
+        loop {
+            self.skip_whitespace();
+            let occur = match self.peek() {
+                None | Some(')') => break,
+                Some('+') => Occur::Must,
+                Some('-') => Occur::MustNot,
+                Some('#') => Occur::Filter,
+                Some(_) => Occur::Should,
+            };
+
+            if let Some(operator) = match occur {
+                Occur::Must => Some('+'),
+                Occur::MustNot => Some('-'),
+                Occur::Filter => Some('#'),
+                Occur::Should => None,
+            } {
+                self.bump();
+                if self.peek().is_none_or(|c| c.is_whitespace() || c == ')') {
+                    return Err(self.error(&format!("a term or group after `{}`", operator)));
+                }
+            }
+
+            let clause = self.parse_atom()?;
+            if self.peek() == Some('^') {
+                if !matches!(occur, Occur::MustNot) {
+                    return Err(self.error("whitespace or end of input (only `-` clauses can be demoted)"));
+                }
+                self.bump();
+                let factor = self.parse_factor()?;
+                bool_query.demote.push((clause, factor));
+                continue;
+            }
+            match occur {
+                Occur::Must => bool_query.must.push(clause),
+                Occur::Should => bool_query.should.push(clause),
+                Occur::MustNot => bool_query.must_not.push(clause),
+                Occur::Filter => bool_query.filter.push(clause),
+            }
+        }
+
+        Ok(Query::Bool(bool_query))
+    }
+
+    fn parse_atom(&mut self) -> Result<Query, ParseError> {
+        if self.peek() == Some('(') {
+            if self.depth == MAX_DEPTH {
+                return Err(self.error(&format!("at most {} nested groups", MAX_DEPTH)));
+            }
+            self.bump();
+            self.depth += 1;
+            let group = self.parse_clauses()?;
+            self.depth -= 1;
+            if group == Query::Bool(BoolQuery::default()) {
+                return Err(self.error("a clause inside `()`"));
+            }
+            if self.peek() != Some(')') {
+                return Err(self.error("`)`"));
+            }
+            self.bump();
+            return Ok(group);
+        }
+
+        if self.peek() == Some('/') {
+            return self.parse_regex();
+        }
+
+        if self.peek() == Some('"') {
+            let start = self.pos;
+            let phrase = self.parse_quoted()?;
+            if phrase.trim().is_empty() {
+                return Err(ParseError {
+                    expected: "a phrase inside `\"\"`".to_string(),
+                    found: Some('"'),
+                    span: start..self.pos,
+                });
+            }
+            return Ok(Query::Intervals(Intervals::Term(phrase)));
+        }
+
+        self.parse_word()
+    }
+
+    fn parse_regex(&mut self) -> Result<Query, ParseError> {
+        self.bump();
+        let start = self.pos;
+        let mut pattern = String::new();
+
+        loop {
+            match self.peek() {
+                None => return Err(self.error("`/` to close the regular expression")),
+                Some('/') => break,
+                Some('\\') => {
+                    self.bump();
+                    match self.bump() {
+                        Some('/') => pattern.push('/'),
+                        Some(c) => {
+                            pattern.push('\\');
+                            pattern.push(c);
+                        }
+                        None => return Err(self.error("a character to escape after `\\`")),
+                    }
+                }
+                Some(c) => {
+                    self.bump();
+                    pattern.push(c);
+                }
+            }
+        }
+
+        if let Err(expected) = check_regex(&pattern) {
+            return Err(ParseError {
+                expected: expected.to_string(),
+                found: self.input[start..].chars().next(),
+                span: start..self.pos,
+            });
+        }
+        self.bump();
+        Ok(Query::Regex(pattern))
+    }
+
+    /// Parse a `"`-delimited string, in which a backslash makes the next character literal.
+    fn parse_quoted(&mut self) -> Result<String, ParseError> {
+        self.bump();
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("`\"` to close the phrase")),
+                Some('"') => return Ok(text),
+                Some('\\') => match self.bump() {
+                    Some(escaped) => text.push(escaped),
+                    None => return Err(self.error("a character to escape after `\\`")),
+                },
+                Some(c) => text.push(c),
+            }
+        }
+    }
+
+    /// Parse the edit distance after `~`, 2 if there is none, up to the end of the word.
+    fn parse_fuzziness(&mut self) -> Result<u8, ParseError> {
+        let distance = match self.peek() {
+            Some(c @ '0'..='9') => {
+                self.bump();
+                c as u8 - b'0'
+            }
+            _ => crate::fuzzy::MAX_DISTANCE,
+        };
+        if distance > crate::fuzzy::MAX_DISTANCE {
+            return Err(ParseError {
+                expected: format!("an edit distance of at most {}", crate::fuzzy::MAX_DISTANCE),
+                found: Some((b'0' + distance) as char),
+                span: self.pos - 1..self.pos,
+            });
+        }
+        match self.peek() {
+            None | Some(')' | '^') => Ok(distance),
+            Some(c) if c.is_whitespace() => Ok(distance),
+            Some(_) => Err(self.error("whitespace or end of input after the edit distance")),
+        }
+    }
+
+    /// Parse a demotion factor between 0 and 1.
+    fn parse_factor(&mut self) -> Result<f32, ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.bump();
+        }
+
+        match self.input[start..self.pos].parse::<f32>() {
+            Ok(factor) if (0.0..=1.0).contains(&factor) => Ok(factor),
+            _ => Err(ParseError {
+                expected: "a demotion factor between 0 and 1".to_string(),
+                found: self.input[start..].chars().next(),
+                span: start..self.pos,
+            }),
+        }
+    }
+
+    fn parse_word(&mut self) -> Result<Query, ParseError> {
+        let mut word = String::new();
+        let mut pattern = String::new(); // the word with wildcards, and escapes for literal ones
+        let mut wildcard = false;
+        let mut field = None;
+        let mut url = false;
+
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || c == '(' || c == ')' || c == '^' {
+                break;
+            }
+            self.bump();
+            match c {
+                '\\' => match self.bump() {
+                    Some(escaped) => {
+                        word.push(escaped);
+                        if matches!(escaped, '*' | '?' | '\\') {
+                            pattern.push('\\');
+                        }
+                        pattern.push(escaped);
+                    }
+                    None => return Err(self.error("a character to escape after `\\`")),
+                },
+                '~' if field.is_none() && !url && !wildcard && !word.is_empty() => {
+                    let distance = self.parse_fuzziness()?;
+                    return Ok(Query::Fuzzy { term: word, distance });
+                }
+                '*' | '?' => {
+                    wildcard = true;
+                    word.push(c);
+                    pattern.push(c);
+                }
+                ':' if field.is_none() && !url && !word.is_empty() && self.peek() == Some('"') => {
+                    field = Some(core::mem::take(&mut word));
+                    word = self.parse_quoted()?;
+                    break;
+                }
+                ':' if field.is_none() && !url && !word.is_empty() => {
+                    // `scheme://` starts a URL, not a field
+                    if self.input[self.pos..].starts_with("//") {
+                        url = true;
+                        word.push(c);
+                        pattern.push(c);
+                    } else {
+                        field = Some(core::mem::take(&mut word));
+                    }
+                }
+                _ => {
+                    word.push(c);
+                    pattern.push(c);
+                }
+            }
+        }
+
+        match field {
+            // a `?` in a URL is its query string
+            None if wildcard && !url => Ok(Query::Wildcard(pattern)),
+            None => Ok(Query::Term(word)),
+            Some(_) if word.is_empty() => Err(self.error("a value after `:`")),
+            Some(field) => Ok(Query::Keyword { field, value: word }),
+        }
+    }
+}
+
+/// Reject patterns the regex engine can't compile.
+#[cfg(feature = "regex")]
+fn check_regex(pattern: &str) -> Result<(), &'static str> {
+    regex::Regex::new(pattern).map(|_| ()).map_err(|_| "a valid regular expression")
+}
+
+#[cfg(not(feature = "regex"))]
+fn check_regex(_pattern: &str) -> Result<(), &'static str> {
+    Err("no regular expression (built without the `regex` feature)")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str) -> Query {
+        Query::Term(text.to_string())
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse("rust +search -(java python) foo-bar", ParseMode::Strict).unwrap();
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                must: vec![term("search")],
+                should: vec![term("rust"), term("foo-bar")],
+                must_not: vec![Query::Bool(BoolQuery {
+                    should: vec![term("java"), term("python")],
+                    ..Default::default()
+                })],
+                ..Default::default()
+            })
+        );
+
+        let query = Query::parse("#rust search", ParseMode::Strict).unwrap();
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                should: vec![term("search")],
+                filter: vec![term("rust")],
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_keyword() {
+        let query = Query::parse("#ext:rs lang:C\\+\\+ a\\:b", ParseMode::Strict).unwrap();
+        let keyword = |field: &str, value: &str| Query::Keyword {
+            field: field.to_string(),
+            value: value.to_string(),
+        };
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                should: vec![keyword("lang", "C++"), term("a:b")],
+                filter: vec![keyword("ext", "rs")],
+                ..Default::default()
+            })
+        );
+
+        let err = Query::parse("ext: rs", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 4..5);
+
+        // a URL is not a field
+        let query = Query::parse("https://example.com/a:b", ParseMode::Strict).unwrap();
+        assert_eq!(query, Query::from_words("https://example.com/a:b"));
+    }
+
+    #[test]
+    fn test_parse_demotion() {
+        let query = Query::parse("rust -archive^0.25 -(old stale)^0", ParseMode::Strict).unwrap();
+        let demoted_group = Query::Bool(BoolQuery {
+            should: vec![term("old"), term("stale")],
+            ..Default::default()
+        });
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                should: vec![term("rust")],
+                demote: vec![(term("archive"), 0.25), (demoted_group, 0.0)],
+                ..Default::default()
+            })
+        );
+
+        let err = Query::parse("rust -archive^2", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 14..15);
+        let err = Query::parse("rust -archive^", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 14..14);
+        let err = Query::parse("rust^0.5", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 4..5);
+    }
+
+    #[test]
+    fn test_parse_phrase() {
+        let query = Query::parse("+\"hello world\" rust title:\"borrow \\\"checker\\\"\"", ParseMode::Strict).unwrap();
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                must: vec![Query::Intervals(Intervals::term("hello world"))],
+                should: vec![
+                    term("rust"),
+                    Query::Keyword {
+                        field: "title".to_string(),
+                        value: "borrow \"checker\"".to_string(),
+                    }
+                ],
+                ..Default::default()
+            })
+        );
+
+        let err = Query::parse("rust \"hello world", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 17..17);
+        let err = Query::parse("rust \" \"", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 5..8);
+
+        assert_eq!(quoted_phrases(r#"a "b c" d "e"#), ["b c"]);
+    }
+
+    #[test]
+    fn test_parse_wildcard() {
+        let query = Query::parse("data* -te?t a\\*b\\? https://example.com/?q=1 ext:r*", ParseMode::Strict).unwrap();
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                should: vec![
+                    Query::Wildcard("data*".to_string()),
+                    term("a*b?"),
+                    term("https://example.com/?q=1"),
+                    Query::Keyword {
+                        field: "ext".to_string(),
+                        value: "r*".to_string(),
+                    }
+                ],
+                must_not: vec![Query::Wildcard("te?t".to_string())],
+                ..Default::default()
+            })
+        );
+        let query = Query::parse("serach~1 engnie~ -a\\~b", ParseMode::Strict).unwrap();
+        let fuzzy = |term: &str, distance| Query::Fuzzy {
+            term: term.to_string(),
+            distance,
+        };
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                should: vec![fuzzy("serach", 1), fuzzy("engnie", 2)],
+                must_not: vec![term("a~b")],
+                ..Default::default()
+            })
+        );
+        assert_eq!(Query::parse("serach~3", ParseMode::Strict).unwrap_err().span, 7..8);
+        assert_eq!(Query::parse("serach~1x", ParseMode::Strict).unwrap_err().span, 8..9);
+
+        let query = Query::parse("a\\*b*", ParseMode::Strict).unwrap();
+        assert_eq!(query, Query::Bool(BoolQuery { should: vec![Query::Wildcard("a\\*b*".to_string())], ..Default::default() }));
+    }
+
+    #[test]
+    #[cfg(feature = "regex")]
+    fn test_parse_regex() {
+        let query = Query::parse("+/te?st(ing)?/ /a\\/b\\d/", ParseMode::Strict).unwrap();
+        assert_eq!(
+            query,
+            Query::Bool(BoolQuery {
+                must: vec![Query::Regex("te?st(ing)?".to_string())],
+                should: vec![Query::Regex("a/b\\d".to_string())],
+                ..Default::default()
+            })
+        );
+
+        let err = Query::parse("/abc", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 4..4);
+        let err = Query::parse("x /a(b/", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 3..6);
+        assert_eq!(err.expected, "a valid regular expression");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let err = Query::parse("rust (search", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 12..12);
+        assert_eq!(err.to_string(), "expected `)` at position 12, found end of input");
+        let err = Query::parse("rust)", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 4..5);
+        assert_eq!(err.found, Some(')'));
+        let err = Query::parse("rust - java", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 6..7);
+        assert_eq!(err.to_string(), "expected a term or group after `-` at position 6, found ` `");
+        let err = Query::parse("()", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 1..2);
+        let err = Query::parse("rust\\", ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 5..5);
+        let err = Query::parse(&"(".repeat(100_000), ParseMode::Strict).unwrap_err();
+        assert_eq!(err.span, 32..33);
+    }
+
+    #[test]
+    fn test_parse_error_underline() {
+        let query = "héllo -)";
+        let err = Query::parse(query, ParseMode::Strict).unwrap_err();
+        assert_eq!(err.position(), 8);
+        assert_eq!(err.underline(query), "héllo -)\n       ^");
+    }
+
+    #[test]
+    fn test_lenient_mode() {
+        let query = Query::parse("rust (search", ParseMode::Lenient).unwrap();
+        assert_eq!(query, Query::from_words("rust (search"));
+    }
+
+    #[test]
+    fn test_escape() {
+        let input = "-(a+b)\\c/#^:\"*?~";
+        let escaped = escape(input);
+        assert_eq!(escaped, "\\-\\(a\\+b\\)\\\\c\\/\\#\\^\\:\\\"\\*\\?\\~");
+        assert_eq!(Query::parse(&escaped, ParseMode::Strict).unwrap(), Query::from_words(input));
+    }
+}
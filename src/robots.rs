@@ -0,0 +1,190 @@
+//! The robots exclusion protocol (RFC 9309): which paths of a site a crawler may fetch, read from
+//! the site's `/robots.txt`, and how long it asks crawlers to wait between requests.
+
+use std::time::Duration;
+
+/// The rules of a robots.txt that apply to one user agent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String, // path prefix, with `*` matching any characters and a trailing `$` the end
+}
+
+/// A group of lines applying to the user agents it starts with.
+#[derive(Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl Robots {
+    /// Rules allowing every path, e.g. for a site without robots.txt.
+    pub(crate) fn allow_all() -> Robots {
+        Robots::default()
+    }
+
+    /// Rules allowing no path, e.g. for a site whose robots.txt can't be read.
+    pub(crate) fn disallow_all() -> Robots {
+        Robots { rules: vec![Rule { allow: false, pattern: "/".to_string() }], crawl_delay: None }
+    }
+
+    /// Read the rules of `robots_txt` for `user_agent`. The groups naming its product token, e.g.
+    /// `searcher` for `searcher/0.1`, apply; without any, the `*` groups do.
+    pub(crate) fn parse(robots_txt: &str, user_agent: &str) -> Robots {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut in_agents = false;
+        for line in robots_txt.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    // consecutive user-agent lines share a group
+                    if !in_agents {
+                        groups.push(Group::default());
+                        in_agents = true;
+                    }
+                    groups.last_mut().expect("pushed above").agents.push(value.to_ascii_lowercase());
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    // an empty disallow allows everything, which no rule already does
+                    if let (Some(group), false) = (groups.last_mut(), value.is_empty()) {
+                        group.rules.push(Rule { allow: key == "allow", pattern: value.to_string() });
+                    }
+                }
+                "crawl-delay" => {
+                    in_agents = false;
+                    if let (Some(group), Ok(seconds)) = (groups.last_mut(), value.parse::<f64>()) {
+                        group.crawl_delay = Duration::try_from_secs_f64(seconds).ok();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let token = user_agent.split(['/', ' ']).next().unwrap_or_default().to_ascii_lowercase();
+        let named = groups.iter().any(|group| group.agents.contains(&token));
+        let wanted = if named { token } else { "*".to_string() };
+        let mut robots = Robots::default();
+        for group in groups.into_iter().filter(|group| group.agents.contains(&wanted)) {
+            robots.rules.extend(group.rules);
+            robots.crawl_delay = robots.crawl_delay.max(group.crawl_delay);
+        }
+        robots
+    }
+
+    /// Whether `path`, with its query, may be fetched. The rule with the longest pattern matching
+    /// it decides, an allow rule winning a tie; `/robots.txt` itself is always allowed.
+    pub(crate) fn allowed(&self, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.rules
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+
+    /// How long the site asks crawlers to wait between two requests, if it says.
+    pub(crate) fn crawl_delay(&self) -> Option<Duration> {
+        self.crawl_delay
+    }
+}
+
+/// Whether a robots.txt path pattern matches the start of `path`, or all of it with a trailing `$`.
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return !anchored || rest.is_empty();
+    };
+    // each `*` takes the shortest run it can, which leaves the most for the parts after it
+    for part in middle {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    if anchored {
+        rest.ends_with(last)
+    } else {
+        rest.contains(last)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_robots() {
+        let robots_txt = "
+# comments are ignored
+User-agent: other-bot
+Disallow: /
+
+User-agent: *
+Disallow: /private
+Allow: /private/open   # longer, so it wins
+Disallow: /*.pdf$
+Crawl-delay: 2.5
+
+User-agent: Searcher
+User-agent: friend
+Disallow: /drafts/
+Allow: /drafts/*/final$
+Crawl-delay: 1
+";
+        let everyone = Robots::parse(robots_txt, "curl/8.0");
+        assert!(everyone.allowed("/"));
+        assert!(!everyone.allowed("/private/notes"));
+        assert!(everyone.allowed("/private/open/notes"));
+        assert!(!everyone.allowed("/docs/moon.pdf"));
+        assert!(everyone.allowed("/docs/moon.pdf?download=1"));
+        assert_eq!(everyone.crawl_delay(), Some(Duration::from_millis(2500)));
+
+        // only the groups naming the product token apply, not the `*` ones
+        let searcher = Robots::parse(robots_txt, "searcher/0.1.0");
+        assert!(searcher.allowed("/private/notes"));
+        assert!(!searcher.allowed("/drafts/moon"));
+        assert!(searcher.allowed("/drafts/moon/final"));
+        assert!(!searcher.allowed("/drafts/moon/final/draft"));
+        assert_eq!(searcher.crawl_delay(), Some(Duration::from_secs(1)));
+
+        assert!(!Robots::parse(robots_txt, "other-bot").allowed("/"));
+        assert!(Robots::parse(robots_txt, "other-bot").allowed("/robots.txt"));
+        assert!(Robots::parse("User-agent: *\nDisallow:\n", "searcher").allowed("/private"));
+        assert!(!Robots::disallow_all().allowed("/moon"));
+        assert!(Robots::allow_all().allowed("/moon"));
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("/fish", "/fish.html"));
+        assert!(!matches("/fish", "/Fish.asp"));
+        assert!(matches("/fish*.php", "/fish/salmon.php?id=1"));
+        assert!(matches("/*.php$", "/folder/filename.php"));
+        assert!(!matches("/*.php$", "/filename.php5"));
+        assert!(matches("/a*b*c", "/aXbYbZc"));
+        assert!(matches("/fish$", "/fish"));
+        assert!(!matches("/fish$", "/fish/"));
+    }
+}
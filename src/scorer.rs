@@ -0,0 +1,172 @@
+//! Scoring functions: how the frequency of a term in a document, the length of the document and
+//! the number of documents containing the term make up a score. `Bm25` is the default; the others
+//! can be set with `Searcher::set_scorer` to compare rankings on the same index.
+
+use crate::math;
+
+/// Turns term and document statistics into scores. The score of a document for a term is
+/// `idf * tf`, summed over the terms of the query.
+pub trait Scorer {
+    /// Weight of a term found in `docs_with_term` of the `documents` indexed documents.
+    fn idf(&self, documents: usize, docs_with_term: usize) -> f32;
+
+    /// Score of a document containing a term `tf` times, before weighting by the idf of the term.
+    /// `length` is the number of terms of the document and `average_length` the average over the
+    /// index; `k1` and `b` are the BM25 parameters of the search, which a scorer may ignore.
+    fn tf(&self, tf: f32, length: f32, average_length: f32, k1: f32, b: f32) -> f32;
+}
+
+/// BM25 with the smooth idf variant, which never goes negative.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Bm25;
+
+impl Scorer for Bm25 {
+    fn idf(&self, documents: usize, docs_with_term: usize) -> f32 {
+        let (documents, docs_with_term) = (documents as f32, docs_with_term as f32);
+        math::ln((documents - docs_with_term + 0.5) / (docs_with_term + 0.5) + 1.0)
+    }
+
+    fn tf(&self, tf: f32, length: f32, average_length: f32, k1: f32, b: f32) -> f32 {
+        let numerator = tf * (k1 + 1.0);
+        let denominator = k1 * ((1.0 - b) + b * (length / average_length));
+        numerator / denominator
+    }
+}
+
+/// Classic TF-IDF, as the cosine similarity of the query and the document with the document norm
+/// approximated by the square root of its length: `sqrt(tf) * idf² / sqrt(length)`, with
+/// `idf = 1 + ln(documents / (docs_with_term + 1))`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TfIdf;
+
+impl Scorer for TfIdf {
+    fn idf(&self, documents: usize, docs_with_term: usize) -> f32 {
+        // once for the query vector and once for the document vector
+        let idf = 1.0 + math::ln(documents as f32 / (docs_with_term as f32 + 1.0));
+        idf * idf
+    }
+
+    fn tf(&self, tf: f32, length: f32, _average_length: f32, _k1: f32, _b: f32) -> f32 {
+        math::sqrt(tf / length.max(1.0))
+    }
+}
+
+/// BM25+ (Lv and Zhai, 2011): BM25 with `delta` added to the score of every document containing
+/// the term, so that a very long document matching it can't score about as low as one that
+/// doesn't match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25Plus {
+    pub delta: f32, // 1.0 by default
+}
+
+impl Default for Bm25Plus {
+    fn default() -> Self {
+        Bm25Plus { delta: 1.0 }
+    }
+}
+
+impl Scorer for Bm25Plus {
+    fn idf(&self, documents: usize, docs_with_term: usize) -> f32 {
+        math::ln((documents as f32 + 1.0) / docs_with_term.max(1) as f32)
+    }
+
+    fn tf(&self, tf: f32, length: f32, average_length: f32, k1: f32, b: f32) -> f32 {
+        let norm = (1.0 - b) + b * (length / average_length);
+        tf * (k1 + 1.0) / (k1 * norm + tf) + self.delta
+    }
+}
+
+/// BM25L (Lv and Zhai, 2011): BM25 on the length-normalized frequency shifted by `delta`, which
+/// penalizes long documents less.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bm25L {
+    pub delta: f32, // 0.5 by default
+}
+
+impl Default for Bm25L {
+    fn default() -> Self {
+        Bm25L { delta: 0.5 }
+    }
+}
+
+impl Scorer for Bm25L {
+    fn idf(&self, documents: usize, docs_with_term: usize) -> f32 {
+        math::ln((documents as f32 + 1.0) / (docs_with_term as f32 + 0.5))
+    }
+
+    fn tf(&self, tf: f32, length: f32, average_length: f32, k1: f32, b: f32) -> f32 {
+        let shifted = tf / ((1.0 - b) + b * (length / average_length)) + self.delta;
+        (k1 + 1.0) * shifted / (k1 + shifted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Document, HashMap, ParseMode, Query, Searcher};
+
+    fn corpus() -> Searcher {
+        let mut searcher = Searcher::new();
+        searcher.add_document("short", "moon rocket");
+        searcher.add_document("long", &format!("moon {}", "ocean tide launch pad crew fuel ".repeat(10)));
+        searcher.add_document("other", "ocean tide");
+        searcher
+    }
+
+    fn moon(searcher: &Searcher) -> HashMap<String, f32> {
+        searcher.search_query(&Query::Term("moon".to_string()))
+    }
+
+    #[test]
+    fn test_scorers() {
+        let mut searcher = corpus();
+        let (k1, b) = (searcher.k1, searcher.b);
+        let average = searcher.collection.avdl();
+        let bm25 = moon(&searcher);
+        assert_eq!(bm25["short"], Bm25.idf(3, 2) * Bm25.tf(1.0, 2.0, average, k1, b));
+
+        searcher.set_scorer(TfIdf);
+        let tf_idf = moon(&searcher);
+        assert_eq!(tf_idf["short"], TfIdf.idf(3, 2) * (1.0f32 / 2.0).sqrt());
+        assert!(tf_idf["short"] > tf_idf["long"]);
+
+        // the long document keeps at least idf * delta
+        searcher.set_scorer(Bm25Plus::default());
+        let plus = moon(&searcher);
+        let idf = (4.0f32 / 2.0).ln();
+        assert!(plus["long"] > idf * 1.0);
+        assert!(plus["long"] / plus["short"] > bm25["long"] / bm25["short"]);
+
+        searcher.set_scorer(Bm25L::default());
+        let l = moon(&searcher);
+        assert!(l["long"] / l["short"] > bm25["long"] / bm25["short"]);
+
+        searcher.set_scorer(Bm25);
+        assert_eq!(moon(&searcher), bm25);
+    }
+
+    #[test]
+    fn test_custom_scorer() {
+        /// Counts matching terms, whatever their frequency and the document length.
+        struct Matches;
+        impl Scorer for Matches {
+            fn idf(&self, _documents: usize, _docs_with_term: usize) -> f32 {
+                1.0
+            }
+            fn tf(&self, _tf: f32, _length: f32, _average_length: f32, _k1: f32, _b: f32) -> f32 {
+                1.0
+            }
+        }
+
+        let mut searcher = corpus();
+        searcher.add_fields(&Document::new("post").field("title", "Moon"));
+        searcher.set_scorer(Matches);
+        let scores = searcher.search_query(&Query::parse("moon rocket", ParseMode::Strict).unwrap());
+        assert_eq!(scores["short"], 2.0);
+        assert_eq!(scores["long"], 1.0);
+        // fields added before and after follow the scorer
+        searcher.add_fields(&Document::new("note").field("body", "moon"));
+        assert_eq!(searcher.search_query(&Query::parse("title:moon", ParseMode::Strict).unwrap())["post"], 1.0);
+        assert_eq!(searcher.search_query(&Query::parse("body:moon", ParseMode::Strict).unwrap())["note"], 1.0);
+    }
+}
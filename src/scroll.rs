@@ -0,0 +1,128 @@
+//! Scrolling through every match of a query, page by page, e.g. to export or reindex a whole
+//! index. Pages follow indexing order rather than score, which stays stable while the index
+//! changes: each page picks up after the last document of the previous one, and documents added
+//! after the first page are left out, so a scroll sees the index as it was when it started, minus
+//! documents removed since.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::backend::Failures;
+use crate::{IndexBackend, Query, Searcher};
+
+/// Where a scroll stands, to pass back for the next page. Its string form, e.g. to hand to an HTTP
+/// client, is read back with `parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScrollCursor {
+    next: u32, // ordinal of the first document not seen yet
+    end: u32,  // ordinal of the first document added after the scroll started
+}
+
+impl fmt::Display for ScrollCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}-{:x}", self.next, self.end)
+    }
+}
+
+/// Error returned when a string is not a `ScrollCursor`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidCursor;
+
+impl fmt::Display for InvalidCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid scroll cursor")
+    }
+}
+
+impl core::error::Error for InvalidCursor {}
+
+impl FromStr for ScrollCursor {
+    type Err = InvalidCursor;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (next, end) = s.split_once('-').ok_or(InvalidCursor)?;
+        let next = u32::from_str_radix(next, 16).map_err(|_| InvalidCursor)?;
+        let end = u32::from_str_radix(end, 16).map_err(|_| InvalidCursor)?;
+        match next <= end {
+            true => Ok(ScrollCursor { next, end }),
+            false => Err(InvalidCursor),
+        }
+    }
+}
+
+/// One page of a scroll.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScrollPage {
+    /// Matching documents with their scores, in indexing order.
+    pub hits: Vec<(String, f32)>,
+    /// The cursor of the next page, None once every match was returned.
+    pub next: Option<ScrollCursor>,
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// The next page of up to `size` documents matching `query`, starting a new scroll when
+    /// `cursor` is None.
+    pub fn scroll(&self, query: &Query, cursor: Option<ScrollCursor>, size: usize) -> ScrollPage {
+        let cursor = cursor.unwrap_or(ScrollCursor {
+            next: 0,
+            end: self.next_ordinal,
+        });
+        let mut hits: Vec<(u32, String, f32)> = self
+            .search_query_in(query, &Failures::default())
+            .into_iter()
+            .filter_map(|(doc_id, score)| {
+                let ordinal = self.docs.get(doc_id.as_str())?.ordinal;
+                (cursor.next..cursor.end).contains(&ordinal).then_some((ordinal, doc_id, score))
+            })
+            .collect();
+        hits.sort_unstable_by_key(|(ordinal, _, _)| *ordinal);
+
+        let next = match hits.len() > size {
+            true => Some(ScrollCursor {
+                next: hits[size].0,
+                end: cursor.end,
+            }),
+            false => None,
+        };
+        hits.truncate(size);
+        ScrollPage {
+            hits: hits.into_iter().map(|(_, doc_id, score)| (doc_id, score)).collect(),
+            next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scroll() {
+        let mut searcher = Searcher::new();
+        for i in 0..10 {
+            searcher.add_document(&i.to_string(), if i % 3 == 0 { "ocean tide" } else { "moon rocket" });
+        }
+        let query = Query::Term("moon".to_string());
+
+        let first = searcher.scroll(&query, None, 3);
+        assert_eq!(first.hits.iter().map(|(doc_id, _)| doc_id.as_str()).collect::<Vec<_>>(), ["1", "2", "4"]);
+        assert_eq!(first.hits[0].1, searcher.search_query(&query)["1"]);
+
+        // changes after the scroll started: a removal, an addition and an update
+        searcher.remove_document("5");
+        searcher.add_document("10", "moon");
+        searcher.update_document("3", "moon landing");
+
+        let cursor: ScrollCursor = first.next.unwrap().to_string().parse().unwrap();
+        let second = searcher.scroll(&query, Some(cursor), 3);
+        assert_eq!(second.hits.iter().map(|(doc_id, _)| doc_id.as_str()).collect::<Vec<_>>(), ["7", "8"]);
+        assert_eq!(second.next, None);
+
+        assert_eq!(searcher.scroll(&query, None, 100).hits.len(), 7);
+        assert!(searcher.scroll(&Query::Term("comet".to_string()), None, 3).hits.is_empty());
+        assert_eq!("2-1".parse::<ScrollCursor>(), Err(InvalidCursor));
+        assert_eq!("zz".parse::<ScrollCursor>(), Err(InvalidCursor));
+    }
+}
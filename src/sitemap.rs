@@ -0,0 +1,601 @@
+//! Site search from a sitemap: the pages a site lists in its `sitemap.xml` are fetched and their
+//! text indexed, by URL. Unlike a crawler following links, only the listed pages are read.
+//!
+//! A `SitemapCrawler` remembers the `ETag` and `Last-Modified` validators of the pages it fetched,
+//! so that crawling the same sitemap again only downloads the pages that changed, and removes the
+//! pages the sitemap no longer lists from the index.
+//!
+//! The crawler is polite: it reads each site's `robots.txt` and skips the pages it disallows, waits
+//! between requests to the same host, at least as long as the site's `Crawl-delay` asks, and
+//! fetches a page listed under several spellings of its URL once.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::html::html_text;
+use crate::robots::Robots;
+use crate::xml::decode_entities;
+use crate::{BuildReport, IndexBackend, Searcher};
+
+/// Number of pages fetched before they are indexed, which bounds the memory held by fetched pages.
+const BATCH: usize = 256;
+
+/// Sitemap indexes listing sitemap indexes are followed this deep.
+const MAX_SITEMAP_DEPTH: usize = 2;
+
+/// Longest `Crawl-delay` honoured, so that a site can't stall a crawl.
+const MAX_CRAWL_DELAY: Duration = Duration::from_secs(60);
+
+/// Only the start of a larger robots.txt is read, as RFC 9309 allows.
+const MAX_ROBOTS_SIZE: u64 = 500 * 1024;
+
+/// Error returned when a sitemap can't be read. Pages that can't be fetched don't fail a crawl;
+/// they are recorded as skipped instead.
+#[derive(Debug)]
+pub enum SitemapError {
+    /// The sitemap could not be downloaded.
+    Fetch { url: String, message: String },
+    /// The sitemap is not a `urlset` or `sitemapindex` document.
+    Invalid { url: String },
+}
+
+impl fmt::Display for SitemapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SitemapError::Fetch { url, message } => write!(f, "could not fetch sitemap {}: {}", url, message),
+            SitemapError::Invalid { url } => write!(f, "{} is not a sitemap", url),
+        }
+    }
+}
+
+impl std::error::Error for SitemapError {}
+
+/// What a crawl changed in the index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrawlSummary {
+    pub indexed: usize,       // pages fetched and indexed, new or changed
+    pub unchanged: usize,     // pages the server said didn't change since the last crawl
+    pub removed: Vec<String>, // pages no longer listed, removed from the index
+}
+
+/// Validators of the last fetch of a page, sent back to only download it again if it changed.
+#[derive(Debug, Clone, Default)]
+struct Validators {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+enum Fetched {
+    Page { text: String, validators: Validators },
+    NotModified,
+    Failed(String),
+}
+
+/// Fetches the pages of sitemaps into a Searcher.
+pub struct SitemapCrawler {
+    agent: ureq::Agent,
+    user_agent: String,                 // sent with every request and matched against robots.txt
+    concurrency: usize,                 // pages fetched at once
+    host_concurrency: usize,            // pages of the same host fetched at once
+    host_delay: Duration,               // least time between the starts of two requests to a host
+    max_page_size: u64,                 // larger pages are skipped
+    pages: HashMap<String, Validators>, // url -> validators, for every page indexed so far
+}
+
+/// The hosts of a crawl, and how busy each is, shared by the fetching threads.
+struct Hosts {
+    hosts: Mutex<HashMap<String, Host>>, // origin, e.g. `https://example.com`, -> host
+    released: Condvar,                   // signalled when a request to any host ends
+    concurrency: usize,
+}
+
+struct Host {
+    robots: Robots,
+    delay: Duration,       // least time between the starts of two requests
+    active: usize,         // requests under way
+    next_request: Instant, // when the next request may start
+}
+
+impl Default for SitemapCrawler {
+    fn default() -> Self {
+        SitemapCrawler::new()
+    }
+}
+
+impl SitemapCrawler {
+    pub fn new() -> SitemapCrawler {
+        let user_agent = concat!("searcher/", env!("CARGO_PKG_VERSION"));
+        SitemapCrawler {
+            agent: agent(user_agent),
+            user_agent: user_agent.to_string(),
+            concurrency: 4,
+            host_concurrency: 1,
+            host_delay: Duration::from_secs(1),
+            max_page_size: 4 * 1024 * 1024,
+            pages: HashMap::new(),
+        }
+    }
+
+    /// Identify as `user_agent`, `searcher/<version>` by default. Its product token, the part
+    /// before the `/`, picks the robots.txt rules that apply.
+    pub fn set_user_agent(&mut self, user_agent: &str) {
+        self.agent = agent(user_agent);
+        self.user_agent = user_agent.to_string();
+    }
+
+    /// Fetch at most `concurrency` pages at once, 4 by default.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency = concurrency.max(1);
+    }
+
+    /// Fetch at most `host_concurrency` pages of the same host at once, 1 by default.
+    pub fn set_host_concurrency(&mut self, host_concurrency: usize) {
+        self.host_concurrency = host_concurrency.max(1);
+    }
+
+    /// Start requests to the same host at least `delay` apart, 1 second by default. A longer
+    /// `Crawl-delay` in the host's robots.txt, up to a minute, is honoured instead.
+    pub fn set_host_delay(&mut self, delay: Duration) {
+        self.host_delay = delay;
+    }
+
+    /// Skip pages larger than `max_page_size` bytes, 4 MiB by default.
+    pub fn set_max_page_size(&mut self, max_page_size: u64) {
+        self.max_page_size = max_page_size;
+    }
+
+    /// Index the pages listed by the sitemap at `url`, following sitemap indexes. Pages are
+    /// indexed by URL, replacing what an earlier crawl indexed; pages an earlier crawl indexed that
+    /// are no longer listed, or that robots.txt now disallows, are removed. Pages that can't be
+    /// fetched, aren't text or are disallowed are recorded in `report` as skipped.
+    pub fn crawl<B: IndexBackend>(&mut self, searcher: &mut Searcher<B>, url: &str, report: &mut BuildReport) -> Result<CrawlSummary, SitemapError> {
+        let mut seen = HashSet::new();
+        let mut urls = Vec::new();
+        for page_url in self.page_urls(url, 0)? {
+            match normalize_url(&page_url) {
+                Some(page_url) if seen.insert(page_url.clone()) => urls.push(page_url),
+                Some(_) => {}
+                None => report.skip(&page_url, "not an http(s) URL"),
+            }
+        }
+
+        let mut hosts = self.hosts(&urls);
+        urls.retain(|page_url| {
+            let (origin, path) = split_origin(page_url);
+            let allowed = hosts.hosts.get_mut().expect("no thread holds the lock yet")[origin].robots.allowed(path);
+            if !allowed {
+                report.skip(page_url, "disallowed by robots.txt");
+            }
+            allowed
+        });
+
+        let mut summary = CrawlSummary::default();
+        for batch in urls.chunks(BATCH) {
+            for (page_url, fetched) in batch.iter().zip(self.fetch_all(batch, searcher, &hosts)) {
+                match fetched {
+                    Fetched::Page { text, validators } => {
+                        searcher.update_document(page_url, &text);
+                        self.pages.insert(page_url.clone(), validators);
+                        summary.indexed += 1;
+                    }
+                    Fetched::NotModified => summary.unchanged += 1,
+                    Fetched::Failed(reason) => report.skip(page_url, &reason),
+                }
+            }
+        }
+
+        let listed: HashSet<&String> = urls.iter().collect();
+        let mut removed: Vec<String> = self.pages.keys().filter(|page_url| !listed.contains(page_url)).cloned().collect();
+        removed.sort();
+        for page_url in &removed {
+            self.pages.remove(page_url);
+            searcher.remove_document(page_url);
+        }
+        summary.removed = removed;
+        Ok(summary)
+    }
+
+    /// The page URLs of the sitemap at `url`, in order.
+    fn page_urls(&self, url: &str, depth: usize) -> Result<Vec<String>, SitemapError> {
+        let fetch_error = |message: String| SitemapError::Fetch { url: url.to_string(), message };
+        let xml = self
+            .agent
+            .get(url)
+            .call()
+            .map_err(|e| fetch_error(e.to_string()))?
+            .into_string()
+            .map_err(|e| fetch_error(e.to_string()))?;
+
+        let mut urls = Vec::new();
+        match parse_sitemap(&xml) {
+            Some(Sitemap::Pages(pages)) => urls = pages,
+            Some(Sitemap::Index(sitemaps)) if depth < MAX_SITEMAP_DEPTH => {
+                for sitemap in sitemaps {
+                    urls.extend(self.page_urls(&sitemap, depth + 1)?);
+                }
+            }
+            Some(Sitemap::Index(_)) => {}
+            None => return Err(SitemapError::Invalid { url: url.to_string() }),
+        }
+        Ok(urls)
+    }
+
+    /// The hosts of `urls`, with their robots.txt read, one after the other.
+    fn hosts(&self, urls: &[String]) -> Hosts {
+        let mut hosts = HashMap::new();
+        for url in urls {
+            let (origin, _) = split_origin(url);
+            if hosts.contains_key(origin) {
+                continue;
+            }
+            let robots = self.fetch_robots(origin);
+            let delay = robots.crawl_delay().map_or(self.host_delay, |delay| delay.min(MAX_CRAWL_DELAY).max(self.host_delay));
+            // fetching robots.txt was the first request to the host
+            let next_request = Instant::now() + delay;
+            hosts.insert(origin.to_string(), Host { robots, delay, active: 0, next_request });
+        }
+        Hosts { hosts: Mutex::new(hosts), released: Condvar::new(), concurrency: self.host_concurrency }
+    }
+
+    /// The robots.txt rules of a host for this crawler.
+    fn fetch_robots(&self, origin: &str) -> Robots {
+        match self.agent.get(&format!("{}/robots.txt", origin)).call() {
+            Ok(response) => {
+                let mut bytes = Vec::new();
+                match response.into_reader().take(MAX_ROBOTS_SIZE).read_to_end(&mut bytes) {
+                    Ok(_) => Robots::parse(&String::from_utf8_lossy(&bytes), &self.user_agent),
+                    Err(_) => Robots::disallow_all(),
+                }
+            }
+            // no robots.txt: every page may be fetched
+            Err(ureq::Error::Status(status, _)) if (400..500).contains(&status) => Robots::allow_all(),
+            // the host may be down or overloaded: fetch none of its pages
+            Err(_) => Robots::disallow_all(),
+        }
+    }
+
+    /// Fetch `urls` on up to `concurrency` threads, in the order of `urls`.
+    fn fetch_all<B: IndexBackend>(&self, urls: &[String], searcher: &Searcher<B>, hosts: &Hosts) -> Vec<Fetched> {
+        let next = AtomicUsize::new(0);
+        let results: Mutex<Vec<Option<Fetched>>> = Mutex::new(urls.iter().map(|_| None).collect());
+        let worker = || loop {
+            let i = next.fetch_add(1, Ordering::Relaxed);
+            let Some(url) = urls.get(i) else {
+                break;
+            };
+            // a page removed from the index since has to be fetched whole again
+            let validators = self.pages.get(url).filter(|_| searcher.docs.contains_key(url.as_str()));
+            let (origin, _) = split_origin(url);
+            hosts.acquire(origin);
+            let fetched = self.fetch(url, validators);
+            hosts.release(origin);
+            results.lock().expect("no worker panics while holding the lock")[i] = Some(fetched);
+        };
+        std::thread::scope(|scope| {
+            for _ in 1..self.concurrency.min(urls.len()) {
+                scope.spawn(worker);
+            }
+            worker();
+        });
+        results.into_inner().expect("no worker panics while holding the lock").into_iter().flatten().collect()
+    }
+
+    /// Fetch a page, conditionally if it was fetched before.
+    fn fetch(&self, url: &str, validators: Option<&Validators>) -> Fetched {
+        let mut request = self.agent.get(url);
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.set("If-None-Match", etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.set("If-Modified-Since", last_modified);
+            }
+        }
+        let response = match request.call() {
+            Ok(response) if response.status() == 304 => return Fetched::NotModified,
+            Ok(response) => response,
+            Err(ureq::Error::Status(status, _)) => return Fetched::Failed(format!("status {}", status)),
+            Err(e) => return Fetched::Failed(e.to_string()),
+        };
+
+        let validators = Validators {
+            etag: response.header("ETag").map(str::to_string),
+            last_modified: response.header("Last-Modified").map(str::to_string),
+        };
+        let content_type = response.content_type().to_ascii_lowercase();
+        let html = match content_type.as_str() {
+            "text/html" | "application/xhtml+xml" => true,
+            "text/plain" => false,
+            other => return Fetched::Failed(format!("not text ({})", other)),
+        };
+        let mut bytes = Vec::new();
+        if let Err(e) = response.into_reader().take(self.max_page_size + 1).read_to_end(&mut bytes) {
+            return Fetched::Failed(e.to_string());
+        }
+        if bytes.len() as u64 > self.max_page_size {
+            return Fetched::Failed("too large".to_string());
+        }
+        let body = String::from_utf8_lossy(&bytes);
+        let text = if html { html_text(&body) } else { body.into_owned() };
+        Fetched::Page { text, validators }
+    }
+}
+
+impl Hosts {
+    /// Wait until a request to `origin` may start, and count it as under way.
+    fn acquire(&self, origin: &str) {
+        let mut hosts = self.hosts.lock().expect("no thread panics while holding the lock");
+        loop {
+            let host = hosts.get_mut(origin).expect("every page's host is known");
+            let now = Instant::now();
+            if host.active < self.concurrency && host.next_request <= now {
+                host.active += 1;
+                host.next_request = now + host.delay;
+                return;
+            }
+            let wait = host.next_request.saturating_duration_since(now);
+            hosts = if host.active < self.concurrency {
+                self.released.wait_timeout(hosts, wait).expect("no thread panics while holding the lock").0
+            } else {
+                self.released.wait(hosts).expect("no thread panics while holding the lock")
+            };
+        }
+    }
+
+    /// Count a request to `origin` as over.
+    fn release(&self, origin: &str) {
+        let mut hosts = self.hosts.lock().expect("no thread panics while holding the lock");
+        hosts.get_mut(origin).expect("every page's host is known").active -= 1;
+        self.released.notify_all();
+    }
+}
+
+fn agent(user_agent: &str) -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(Duration::from_secs(30)).user_agent(user_agent).build()
+}
+
+/// `url` spelled the way every URL of the same page is: scheme and host lowercased, default port,
+/// fragment and `.` and `..` path segments dropped. None if it isn't an http(s) URL.
+fn normalize_url(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    let default_port = match scheme.as_str() {
+        "http" => ":80",
+        "https" => ":443",
+        _ => return None,
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (authority, rest) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+    let authority = authority.to_ascii_lowercase();
+    let authority = authority.strip_suffix(default_port).unwrap_or(&authority);
+    if authority.is_empty() {
+        return None;
+    }
+    let (path, query) = match rest.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (rest, None),
+    };
+
+    let mut segments: Vec<&str> = Vec::new();
+    let mut directory = false;
+    for segment in path.split('/').skip(1) {
+        directory = matches!(segment, "." | "..");
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if directory {
+        // `/a/b/..` is the directory `/a/`
+        segments.push("");
+    }
+    let mut normalized = format!("{}://{}/{}", scheme, authority, segments.join("/"));
+    if let Some(query) = query {
+        normalized.push('?');
+        normalized.push_str(query);
+    }
+    Some(normalized)
+}
+
+/// Split a normalized URL into its origin, e.g. `https://example.com`, and its path with the query.
+fn split_origin(url: &str) -> (&str, &str) {
+    let authority = url.find("://").map_or(0, |at| at + 3);
+    url.split_at(url[authority..].find('/').map_or(url.len(), |at| authority + at))
+}
+
+#[derive(Debug, PartialEq)]
+enum Sitemap {
+    Pages(Vec<String>), // a `urlset`
+    Index(Vec<String>), // a `sitemapindex`, listing other sitemaps
+}
+
+/// Read the `loc` of every entry of a sitemap. None if it is neither kind of sitemap.
+fn parse_sitemap(xml: &str) -> Option<Sitemap> {
+    let locations = || {
+        xml.split("<loc>")
+            .skip(1)
+            .filter_map(|rest| rest.split_once("</loc>"))
+            .map(|(loc, _)| {
+                let loc = loc.trim();
+                // the URL may be wrapped in CDATA instead of being escaped
+                let loc = loc.strip_prefix("<![CDATA[").and_then(|loc| loc.strip_suffix("]]>")).map_or_else(|| decode_entities(loc), str::to_string);
+                loc.trim().to_string()
+            })
+            .filter(|loc| !loc.is_empty())
+            .collect()
+    };
+    if xml.contains("<sitemapindex") {
+        Some(Sitemap::Index(locations()))
+    } else if xml.contains("<urlset") {
+        Some(Sitemap::Pages(locations()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_sitemap() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+  <url><loc>https://example.com/</loc><lastmod>2024-01-01</lastmod></url>
+  <url><loc> https://example.com/search?q=moon&amp;page=2 </loc></url>
+  <url><loc><![CDATA[https://example.com/a&b]]></loc></url>
+</urlset>"#;
+        assert_eq!(
+            parse_sitemap(xml),
+            Some(Sitemap::Pages(vec![
+                "https://example.com/".to_string(),
+                "https://example.com/search?q=moon&page=2".to_string(),
+                "https://example.com/a&b".to_string()
+            ]))
+        );
+        let index = "<sitemapindex><sitemap><loc>https://example.com/pages.xml</loc></sitemap></sitemapindex>";
+        assert_eq!(parse_sitemap(index), Some(Sitemap::Index(vec!["https://example.com/pages.xml".to_string()])));
+        assert_eq!(parse_sitemap("<html></html>"), None);
+    }
+
+    #[test]
+    fn test_normalize_url() {
+        let normalized = |url| normalize_url(url).unwrap();
+        assert_eq!(normalized("HTTPS://Example.COM:443/Moon/./a/../b?q=Tide#top"), "https://example.com/Moon/b?q=Tide");
+        assert_eq!(normalized("http://example.com:80"), "http://example.com/");
+        assert_eq!(normalized("http://example.com:8080/a/b/.."), "http://example.com:8080/a/");
+        assert_eq!(normalized("https://example.com?q=moon"), "https://example.com/?q=moon");
+        assert_eq!(normalize_url("ftp://example.com/moon"), None);
+        assert_eq!(normalize_url("https:///moon"), None);
+        assert_eq!(split_origin("https://example.com/moon?q=1"), ("https://example.com", "/moon?q=1"));
+    }
+
+    /// A web server answering from `routes`, path -> (content type, body), with an ETag per body.
+    /// Requests are recorded as `path` or `path (conditional)`.
+    fn serve(routes: Arc<Mutex<HashMap<String, (String, String)>>>, requests: Arc<Mutex<Vec<String>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split(' ').nth(1).unwrap_or("/").to_string();
+                let mut if_none_match = None;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("if-none-match") {
+                            if_none_match = Some(value.trim().to_string());
+                        }
+                    }
+                }
+                requests.lock().unwrap().push(if if_none_match.is_some() { format!("{} (conditional)", path) } else { path.clone() });
+
+                let response = match routes.lock().unwrap().get(&path) {
+                    None => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                    Some((content_type, body)) => {
+                        let etag = format!("\"{}\"", body.len());
+                        match if_none_match == Some(etag.clone()) {
+                            true => "HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                            false => format!(
+                                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                                content_type,
+                                etag,
+                                body.len(),
+                                body
+                            ),
+                        }
+                    }
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn test_crawl() {
+        let routes = Arc::new(Mutex::new(HashMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let base = serve(routes.clone(), requests.clone());
+        let html = |body: &str| ("text/html; charset=utf-8".to_string(), format!("<html><body><p>{}</p><script>ocean()</script></body></html>", body));
+        let sitemap = |paths: &[&str]| {
+            let urls: String = paths.iter().map(|path| format!("<url><loc>{}{}</loc></url>", base, path)).collect();
+            ("application/xml".to_string(), format!("<urlset>{}</urlset>", urls))
+        };
+        {
+            let mut routes = routes.lock().unwrap();
+            routes.insert("/index.xml".to_string(), ("application/xml".to_string(), format!("<sitemapindex><sitemap><loc>{}/pages.xml</loc></sitemap></sitemapindex>", base)));
+            routes.insert("/pages.xml".to_string(), sitemap(&["/moon", "/tide", "/logo.png", "/missing", "/./moon#top", "/private"]));
+            routes.insert("/robots.txt".to_string(), ("text/plain".to_string(), "User-agent: *\nDisallow: /private\n".to_string()));
+            routes.insert("/private".to_string(), html("rocket secrets"));
+            routes.insert("/moon".to_string(), html("The moon rocket"));
+            routes.insert("/tide".to_string(), html("Tide charts"));
+            routes.insert("/logo.png".to_string(), ("image/png".to_string(), "PNG".to_string()));
+        }
+
+        let mut searcher = Searcher::new();
+        let mut crawler = SitemapCrawler::new();
+        crawler.set_concurrency(3);
+        crawler.set_host_concurrency(3);
+        crawler.set_host_delay(Duration::ZERO);
+        let mut report = BuildReport::default();
+        let summary = crawler.crawl(&mut searcher, &format!("{}/index.xml", base), &mut report).unwrap();
+        assert_eq!(summary, CrawlSummary { indexed: 2, unchanged: 0, removed: vec![] });
+        assert_eq!(searcher.search("rocket").keys().collect::<Vec<_>>(), [&format!("{}/moon", base)]);
+        assert!(searcher.search("ocean").is_empty());
+        let mut skipped = report.skipped.clone();
+        skipped.sort();
+        assert_eq!(
+            skipped,
+            [
+                (format!("{}/logo.png", base), "not text (image/png)".to_string()),
+                (format!("{}/missing", base), "status 404".to_string()),
+                (format!("{}/private", base), "disallowed by robots.txt".to_string())
+            ]
+        );
+        assert_eq!(requests.lock().unwrap().iter().filter(|path| path.starts_with("/moon")).count(), 1);
+
+        // only the changed page is downloaded again, and the page no longer listed is dropped
+        {
+            let mut routes = routes.lock().unwrap();
+            routes.insert("/pages.xml".to_string(), sitemap(&["/moon", "/launch"]));
+            routes.insert("/moon".to_string(), html("The moon landing"));
+            routes.insert("/launch".to_string(), ("text/plain".to_string(), "launch window".to_string()));
+        }
+        requests.lock().unwrap().clear();
+        let summary = crawler.crawl(&mut searcher, &format!("{}/pages.xml", base), &mut BuildReport::default()).unwrap();
+        assert_eq!(summary, CrawlSummary { indexed: 2, unchanged: 0, removed: vec![format!("{}/tide", base)] });
+        assert!(searcher.search("rocket").is_empty() && searcher.search("tide").is_empty());
+        assert_eq!(searcher.search("landing").len(), 1);
+        let mut seen = requests.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen, ["/launch", "/moon (conditional)", "/pages.xml", "/robots.txt"]);
+
+        // requests to the host, robots.txt first, start a host delay apart
+        crawler.set_host_delay(Duration::from_millis(100));
+        let start = Instant::now();
+        let summary = crawler.crawl(&mut searcher, &format!("{}/pages.xml", base), &mut BuildReport::default()).unwrap();
+        assert_eq!(summary, CrawlSummary { indexed: 0, unchanged: 2, removed: vec![] });
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        assert!(matches!(crawler.crawl(&mut searcher, &format!("{}/moon", base), &mut report), Err(SitemapError::Invalid { .. })));
+    }
+}
@@ -0,0 +1,504 @@
+//! Statistics about the indexed terms, per document and across the corpus.
+
+use alloc::borrow::Cow;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::ops::{Bound, Range};
+
+use crate::backend::Failures;
+use crate::format::{csv_field, json_string};
+use crate::{IndexBackend, Positions, Searcher, StoredDoc};
+
+/// Global statistics of one indexed term.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermStats {
+    pub term: String,
+    pub df: usize,       // number of documents containing the term
+    pub total_tf: usize, // occurrences across all documents
+    pub idf: f32,
+}
+
+/// A term of one document and where it occurs in it, see `Searcher::term_vector`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TermVectorEntry {
+    pub term: String,
+    pub tf: usize,                          // occurrences in the document
+    pub positions: Vec<u32>,                // word positions, in order
+    pub offsets: Option<Vec<Range<usize>>>, // byte ranges in the content, if stored, see `set_store_offsets`
+}
+
+/// Differences between two indexes, e.g. two builds over crawls of the same tree. Every list is
+/// sorted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexDiff {
+    pub added_docs: Vec<String>,
+    pub removed_docs: Vec<String>,
+    pub changed_docs: Vec<String>, // documents whose text or keyword values differ
+    pub added_terms: Vec<String>,
+    pub removed_terms: Vec<String>,
+    pub df_changes: Vec<(String, usize, usize)>, // (term, old df, new df) for terms in both indexes
+}
+
+impl IndexDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == IndexDiff::default()
+    }
+}
+
+impl fmt::Display for IndexDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "documents: {} added, {} removed, {} changed; terms: {} added, {} removed, {} in a different number of documents",
+            self.added_docs.len(),
+            self.removed_docs.len(),
+            self.changed_docs.len(),
+            self.added_terms.len(),
+            self.removed_terms.len(),
+            self.df_changes.len()
+        )?;
+        for (heading, items) in [
+            ("added documents", &self.added_docs),
+            ("removed documents", &self.removed_docs),
+            ("changed documents", &self.changed_docs),
+            ("added terms", &self.added_terms),
+            ("removed terms", &self.removed_terms),
+        ] {
+            if !items.is_empty() {
+                writeln!(f, "{}:", heading)?;
+                for item in items {
+                    writeln!(f, "  {}", item)?;
+                }
+            }
+        }
+        if !self.df_changes.is_empty() {
+            writeln!(f, "document frequency changes:")?;
+            for (term, old, new) in &self.df_changes {
+                writeln!(f, "  {} ({} -> {})", term, old, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The number and total length of the documents of an index, which BM25 normalizes document
+/// lengths with. Kept exact as documents are added, replaced and removed, so it always equals a
+/// recount over the current documents.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CollectionStats {
+    pub documents: usize,
+    pub terms: u64, // indexed terms across all documents
+}
+
+impl CollectionStats {
+    /// The average number of terms per document, 0 for an empty collection.
+    pub fn avdl(&self) -> f32 {
+        match self.documents {
+            0 => 0.0,
+            documents => self.terms as f32 / documents as f32,
+        }
+    }
+
+    pub(crate) fn add(&mut self, nterms: u64) {
+        self.documents += 1;
+        self.terms += nterms;
+    }
+
+    pub(crate) fn remove(&mut self, nterms: u64) {
+        self.documents -= 1;
+        self.terms -= nterms;
+    }
+
+    /// Account for terms added to a document already counted, e.g. a copied keyword value.
+    pub(crate) fn extend(&mut self, nterms: u64) {
+        self.terms += nterms;
+    }
+
+    /// Account for terms dropped from documents still counted, e.g. by `Searcher::prune`.
+    pub(crate) fn shrink(&mut self, nterms: u64) {
+        self.terms -= nterms;
+    }
+}
+
+/// How many of the largest documents a `BuildReport` lists.
+const LARGEST_DOCUMENTS: usize = 10;
+
+/// What indexing kept and what it dropped, so that data lost along the way doesn't go unnoticed.
+/// Counts cover the documents indexed since the Searcher was created or loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BuildReport {
+    pub documents: usize,
+    pub terms: u64,                              // indexed terms across all documents
+    pub vocabulary: usize,                       // distinct terms
+    pub stop_words_dropped: u64,                 // words dropped as stop words while indexing
+    pub empty_documents: Vec<String>,            // documents without a single indexed term, sorted
+    pub largest_documents: Vec<(String, usize)>, // the largest documents by number of terms
+    pub vocabulary_growth: Vec<(usize, usize)>,  // (documents indexed, distinct terms) at powers of two
+    pub skipped: Vec<(String, String)>,          // inputs the caller didn't index, and why
+}
+
+impl BuildReport {
+    /// Record an input that was not indexed, e.g. a file that could not be read.
+    pub fn skip(&mut self, input: &str, reason: &str) {
+        self.skipped.push((input.to_string(), reason.to_string()));
+    }
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "indexed {} documents: {} terms, {} distinct, {} stop words dropped",
+            self.documents, self.terms, self.vocabulary, self.stop_words_dropped
+        )?;
+        if !self.vocabulary_growth.is_empty() {
+            let growth: Vec<String> = self.vocabulary_growth.iter().map(|(docs, terms)| format!("{} -> {}", docs, terms)).collect();
+            writeln!(f, "vocabulary by documents indexed: {}", growth.join(", "))?;
+        }
+        if !self.largest_documents.is_empty() {
+            writeln!(f, "largest documents:")?;
+            for (doc_id, terms) in &self.largest_documents {
+                writeln!(f, "  {} ({} terms)", doc_id, terms)?;
+            }
+        }
+        if !self.empty_documents.is_empty() {
+            writeln!(f, "documents without indexed terms:")?;
+            for doc_id in &self.empty_documents {
+                writeln!(f, "  {}", doc_id)?;
+            }
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f, "skipped:")?;
+            for (input, reason) in &self.skipped {
+                writeln!(f, "  {} ({})", input, reason)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Compare this index (the old one) with `other` (the new one).
+    pub fn diff(&self, other: &Searcher<B>) -> IndexDiff {
+        let mut diff = IndexDiff::default();
+        let failures = Failures::default();
+
+        for (doc_id, doc) in &self.docs {
+            match other.docs.get(doc_id) {
+                None => diff.removed_docs.push(doc_id.to_string()),
+                Some(new) if new.keywords != doc.keywords || !self.same_text(doc, other, new, &failures) => {
+                    diff.changed_docs.push(doc_id.to_string())
+                }
+                Some(_) => {}
+            }
+        }
+        diff.added_docs = other.docs.keys().filter(|id| !self.docs.contains_key(*id)).map(|id| id.to_string()).collect();
+
+        for term in self.index.terms_from(Bound::Unbounded).map_while(|term| failures.ok(term.map(Some))) {
+            let (df, new) = (failures.ok(self.index.doc_frequency(&term)), failures.ok(other.index.doc_frequency(&term)));
+            match new {
+                0 => diff.removed_terms.push(term.into_owned()),
+                new if new != df => diff.df_changes.push((term.into_owned(), df, new)),
+                _ => {}
+            }
+        }
+        diff.added_terms = other
+            .index
+            .terms_from(Bound::Unbounded)
+            .map_while(|term| failures.ok(term.map(Some)))
+            .filter(|term| failures.ok(self.index.doc_frequency(term)) == 0)
+            .map(Cow::into_owned)
+            .collect();
+
+        diff.added_docs.sort();
+        diff.removed_docs.sort();
+        diff.changed_docs.sort();
+        diff.added_terms.sort();
+        diff.removed_terms.sort();
+        diff.df_changes.sort();
+        diff
+    }
+
+    /// Whether `doc` of this index and `new` of `other` hold the same text. Documents read back
+    /// from a content provider have no stored content, so what was indexed of them is compared.
+    fn same_text(&self, doc: &StoredDoc, other: &Searcher<B>, new: &StoredDoc, failures: &Failures) -> bool {
+        match (&doc.content, &new.content) {
+            (Some(old_content), Some(new_content)) => old_content == new_content,
+            _ => doc.nterms == new.nterms && self.indexed_terms(doc, failures) == other.indexed_terms(new, failures),
+        }
+    }
+
+    /// The terms of a document with their positions in it, sorted by term.
+    fn indexed_terms(&self, doc: &StoredDoc, failures: &Failures) -> Vec<(String, Positions)> {
+        let mut terms: Vec<(String, Positions)> = doc
+            .terms
+            .iter()
+            .filter_map(|term| Some((term.clone(), failures.ok(self.index.postings(term))?.get(doc.ordinal)?.clone())))
+            .collect();
+        terms.sort();
+        terms
+    }
+
+    /// Report on the documents indexed so far. `skipped` is left for the caller to fill in.
+    pub fn build_report(&self) -> BuildReport {
+        self.build_report_in(&Failures::default())
+    }
+
+    /// Same as `build_report`, keeping the errors of the backend in `failures`.
+    pub(crate) fn build_report_in(&self, failures: &Failures) -> BuildReport {
+        let mut sizes: Vec<(String, usize)> = self.docs.iter().map(|(doc_id, doc)| (doc_id.to_string(), doc.nterms as usize)).collect();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut empty_documents: Vec<String> = sizes.iter().filter(|(_, terms)| *terms == 0).map(|(doc_id, _)| doc_id.clone()).collect();
+        empty_documents.sort();
+        sizes.retain(|(_, terms)| *terms > 0);
+        sizes.truncate(LARGEST_DOCUMENTS);
+
+        BuildReport {
+            documents: self.docs.len(),
+            terms: self.collection.terms,
+            vocabulary: failures.ok(self.index.term_count()),
+            stop_words_dropped: self.stop_words_dropped,
+            empty_documents,
+            largest_documents: sizes,
+            vocabulary_growth: self.vocabulary_growth.clone(),
+            skipped: Vec::new(),
+        }
+    }
+
+    /// The number and total length of the indexed documents.
+    pub fn collection_stats(&self) -> CollectionStats {
+        self.collection
+    }
+
+    /// Statistics of every indexed term, sorted by term.
+    pub fn term_stats(&self) -> Vec<TermStats> {
+        self.term_stats_in(&Failures::default())
+    }
+
+    /// Same as `term_stats`, keeping the errors of the backend in `failures`.
+    pub(crate) fn term_stats_in(&self, failures: &Failures) -> Vec<TermStats> {
+        let mut stats: Vec<TermStats> = self
+            .index
+            .terms_from(Bound::Unbounded)
+            .map_while(|term| failures.ok(term.map(Some)))
+            .filter_map(|term| {
+                let docs = failures.ok(self.index.postings(&term))?;
+                Some(TermStats {
+                    term: term.into_owned(),
+                    df: docs.len(),
+                    total_tf: docs.values().map(|positions| positions.len()).sum(),
+                    idf: self.idf_for_count(docs.len()),
+                })
+            })
+            .collect();
+        stats.sort_by(|a, b| a.term.cmp(&b.term));
+        stats
+    }
+
+    /// The `k` most distinctive terms of a document by tf-idf, highest first. Returns None if the
+    /// document does not exist.
+    pub fn key_terms(&self, doc_id: &str, k: usize) -> Option<Vec<(String, f32)>> {
+        let doc = self.docs.get(doc_id)?;
+        let nterms = doc.nterms.max(1) as f32;
+        let failures = Failures::default();
+
+        let mut terms: Vec<(String, f32)> = doc
+            .terms
+            .iter()
+            .filter_map(|term| {
+                let tf = failures.ok(self.index.postings(term))?.get(doc.ordinal)?.len() as f32;
+                Some((term.clone(), tf / nterms * self.idf(term, &failures)))
+            })
+            .collect();
+
+        terms.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        terms.truncate(k);
+        Some(terms)
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// The terms of a document, sorted, with their frequency and positions in it, and the byte
+    /// ranges of their occurrences if the document was added with offsets stored. Occurrences in
+    /// values of copy fields have positions but no byte range. Returns None if the document does
+    /// not exist.
+    pub fn term_vector(&self, doc_id: &str) -> Option<Vec<TermVectorEntry>> {
+        let doc = self.docs.get(doc_id)?;
+        let failures = Failures::default();
+        let mut entries: Vec<TermVectorEntry> = doc
+            .terms
+            .iter()
+            .filter_map(|term| {
+                let positions: Vec<u32> = failures.ok(self.index.postings(term))?.get(doc.ordinal)?.to_vec();
+                let offsets = doc.offsets.as_ref().map(|offsets| positions.iter().filter_map(|&p| offsets.get(p as usize).cloned()).collect());
+                Some(TermVectorEntry { term: term.clone(), tf: positions.len(), positions, offsets })
+            })
+            .collect();
+        entries.sort_by(|a, b| a.term.cmp(&b.term));
+        Some(entries)
+    }
+}
+
+/// Term statistics as CSV with a `term,df,total_tf,idf` header.
+pub fn to_csv(stats: &[TermStats]) -> String {
+    let mut csv = String::from("term,df,total_tf,idf\n");
+    for s in stats {
+        // URLs and the terms of custom tokenizers may contain commas and quotes
+        csv_field(&mut csv, &s.term);
+        writeln!(csv, ",{},{},{}", s.df, s.total_tf, s.idf).unwrap();
+    }
+    csv
+}
+
+/// Term statistics as a JSON array of objects.
+pub fn to_json(stats: &[TermStats]) -> String {
+    let mut json = String::from("[");
+    for (i, s) in stats.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(r#"{"term":"#);
+        json_string(&mut json, &s.term);
+        write!(json, r#","df":{},"total_tf":{},"idf":{}}}"#, s.df, s.total_tf, s.idf).unwrap();
+    }
+    json.push(']');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_terms() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust compiler rust borrow checker compiler rust");
+        searcher.add_document("2", "java compiler garbage collector");
+        searcher.add_document("3", "python interpreter compiler");
+
+        let terms = searcher.key_terms("1", 2).unwrap();
+        let names: Vec<&str> = terms.iter().map(|(term, _)| term.as_str()).collect();
+        // "compiler" is frequent in the document but common to every document
+        assert_eq!(names, vec!["rust", "borrow"]);
+        assert!(terms[0].1 > terms[1].1);
+
+        assert!(searcher.key_terms("missing", 2).is_none());
+    }
+
+    #[test]
+    fn test_term_vector() {
+        let mut searcher = Searcher::new();
+        searcher.set_store_offsets(true);
+        searcher.add_copy_field("tag");
+        searcher.add_document("1", "Rust rockets, rust moon");
+        searcher.add_keyword("1", "tag", "rust");
+        searcher.set_store_offsets(false);
+        searcher.add_document("2", "moon landing");
+
+        let vector = searcher.term_vector("1").unwrap();
+        let terms: Vec<&str> = vector.iter().map(|entry| entry.term.as_str()).collect();
+        assert_eq!(terms, ["moon", "rockets", "rust"]);
+        let rust = &vector[2];
+        assert_eq!((rust.tf, rust.positions.as_slice()), (3, [0, 2, 5].as_slice()));
+        // the copied tag has a position but no range in the content
+        assert_eq!(rust.offsets, Some(vec![0..4, 14..18]));
+
+        let vector = searcher.term_vector("2").unwrap();
+        assert_eq!(vector[0], TermVectorEntry { term: "landing".to_string(), tf: 1, positions: vec![1], offsets: None });
+        assert!(searcher.term_vector("missing").is_none());
+    }
+
+    #[test]
+    fn test_build_report() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "the rust compiler and the borrow checker");
+        searcher.add_document("2", "rust");
+        searcher.add_document("3", "the and of");
+        searcher.add_document("4", "ocean tide moon rocket");
+
+        let mut report = searcher.build_report();
+        assert_eq!((report.documents, report.terms, report.vocabulary), (4, 9, 8));
+        assert_eq!(report.stop_words_dropped, 6);
+        assert_eq!(report.empty_documents, ["3"]);
+        assert_eq!(report.largest_documents[0], ("1".to_string(), 4));
+        assert_eq!(report.largest_documents.len(), 3);
+        assert_eq!(report.vocabulary_growth, [(1, 4), (2, 4), (4, 8)]);
+
+        report.skip("photo.png", "not valid UTF-8");
+        let printed = report.to_string();
+        assert!(printed.starts_with("indexed 4 documents: 9 terms, 8 distinct, 6 stop words dropped\n"), "{}", printed);
+        assert!(printed.contains("  photo.png (not valid UTF-8)"));
+    }
+
+    #[test]
+    fn test_term_stats() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "rust rust compiler");
+        searcher.add_document("2", "compiler");
+
+        let stats = searcher.term_stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!((stats[0].term.as_str(), stats[0].df, stats[0].total_tf), ("compiler", 2, 2));
+        assert_eq!((stats[1].term.as_str(), stats[1].df, stats[1].total_tf), ("rust", 1, 2));
+        assert!(stats[1].idf > stats[0].idf);
+
+        let csv = to_csv(&stats);
+        assert!(csv.starts_with("term,df,total_tf,idf\n"));
+        assert_eq!(csv.lines().count(), 3);
+        assert!(to_json(&stats).starts_with(r#"[{"term":"compiler","df":2,"total_tf":2,"idf":"#));
+    }
+
+    #[test]
+    fn test_term_stats_escaping() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "see https://example.com/q?tags=a,b now");
+        let stats = searcher.term_stats();
+        let url = stats.iter().find(|s| s.term.contains(',')).unwrap();
+        assert_eq!(url.term, "https://example.com/q?tags=a,b");
+        assert!(to_csv(&stats).contains("\n\"https://example.com/q?tags=a,b\",1,1,"));
+
+        // terms of custom tokenizers can hold anything
+        let quoted = vec![TermStats { term: r#"say "hi" \ bye,"#.to_string(), df: 1, total_tf: 2, idf: 0.5 }];
+        assert_eq!(to_csv(&quoted), "term,df,total_tf,idf\n\"say \"\"hi\"\" \\ bye,\",1,2,0.5\n");
+        assert_eq!(to_json(&quoted), r#"[{"term":"say \"hi\" \\ bye,","df":1,"total_tf":2,"idf":0.5}]"#);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut old = Searcher::new();
+        old.add_document("a.txt", "rust compiler");
+        old.add_document("b.txt", "moon rocket");
+        old.add_document("c.txt", "rust");
+
+        let mut new = Searcher::new();
+        new.add_document("a.txt", "rust compiler");
+        new.add_document("b.txt", "moon landing");
+        new.add_document("d.txt", "compiler");
+        assert!(old.diff(&old).is_empty());
+
+        let diff = old.diff(&new);
+        assert_eq!(diff.added_docs, vec!["d.txt"]);
+        assert_eq!(diff.removed_docs, vec!["c.txt"]);
+        assert_eq!(diff.changed_docs, vec!["b.txt"]);
+        assert_eq!(diff.added_terms, vec!["landing"]);
+        assert_eq!(diff.removed_terms, vec!["rocket"]);
+        assert_eq!(
+            diff.df_changes,
+            vec![("compiler".to_string(), 1, 2), ("rust".to_string(), 2, 1)]
+        );
+        assert!(diff.to_string().starts_with("documents: 1 added, 1 removed, 1 changed; terms: 1 added, 1 removed, 2 in"));
+        assert!(diff.to_string().contains("\nchanged documents:\n  b.txt\n"));
+
+        // documents read back from a content provider are compared by what was indexed of them
+        for doc in old.docs.values_mut().chain(new.docs.values_mut()) {
+            doc.content = None;
+        }
+        assert_eq!(old.diff(&new).changed_docs, vec!["b.txt"]);
+        new.update_document("a.txt", "compiler rust");
+        new.docs.get_mut("a.txt").unwrap().content = None;
+        assert_eq!(old.diff(&new).changed_docs, vec!["a.txt", "b.txt"]);
+    }
+}
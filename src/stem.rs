@@ -0,0 +1,115 @@
+//! Stemming: reducing the words of a language to a common stem, e.g. `running` and `runs` to
+//! `run`, so that they match each other. Uses the Snowball algorithms.
+
+use std::borrow::Cow;
+
+use rust_stemmers::Algorithm;
+
+use crate::Token;
+
+/// A Snowball stemmer, by language. `English` is the Porter2 algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stemmer {
+    Danish,
+    Dutch,
+    English,
+    Finnish,
+    French,
+    German,
+    Hungarian,
+    Italian,
+    Norwegian,
+    Portuguese,
+    Romanian,
+    Russian,
+    Spanish,
+    Swedish,
+    Turkish,
+}
+
+const CODES: [(Stemmer, &str); 15] = [
+    (Stemmer::Danish, "da"),
+    (Stemmer::Dutch, "nl"),
+    (Stemmer::English, "en"),
+    (Stemmer::Finnish, "fi"),
+    (Stemmer::French, "fr"),
+    (Stemmer::German, "de"),
+    (Stemmer::Hungarian, "hu"),
+    (Stemmer::Italian, "it"),
+    (Stemmer::Norwegian, "no"),
+    (Stemmer::Portuguese, "pt"),
+    (Stemmer::Romanian, "ro"),
+    (Stemmer::Russian, "ru"),
+    (Stemmer::Spanish, "es"),
+    (Stemmer::Swedish, "sv"),
+    (Stemmer::Turkish, "tr"),
+];
+
+impl Stemmer {
+    /// The stemmer of a language by ISO 639-1 code, e.g. `"de"`.
+    pub fn from_code(code: &str) -> Option<Stemmer> {
+        CODES.iter().find(|(_, c)| *c == code).map(|&(stemmer, _)| stemmer)
+    }
+
+    /// The ISO 639-1 code of the stemmer's language.
+    pub fn code(self) -> &'static str {
+        CODES.iter().find(|(stemmer, _)| *stemmer == self).map(|&(_, code)| code).expect("every stemmer has a code")
+    }
+
+    fn algorithm(self) -> Algorithm {
+        match self {
+            Stemmer::Danish => Algorithm::Danish,
+            Stemmer::Dutch => Algorithm::Dutch,
+            Stemmer::English => Algorithm::English,
+            Stemmer::Finnish => Algorithm::Finnish,
+            Stemmer::French => Algorithm::French,
+            Stemmer::German => Algorithm::German,
+            Stemmer::Hungarian => Algorithm::Hungarian,
+            Stemmer::Italian => Algorithm::Italian,
+            Stemmer::Norwegian => Algorithm::Norwegian,
+            Stemmer::Portuguese => Algorithm::Portuguese,
+            Stemmer::Romanian => Algorithm::Romanian,
+            Stemmer::Russian => Algorithm::Russian,
+            Stemmer::Spanish => Algorithm::Spanish,
+            Stemmer::Swedish => Algorithm::Swedish,
+            Stemmer::Turkish => Algorithm::Turkish,
+        }
+    }
+
+    /// The stem of a case folded word.
+    pub fn stem(self, word: &str) -> String {
+        rust_stemmers::Stemmer::create(self.algorithm()).stem(word).into_owned()
+    }
+
+    /// Replace the terms of `tokens` by their stems. Terms that are their own stem keep borrowing
+    /// from the text.
+    pub(crate) fn stem_tokens(self, tokens: &mut [Token]) {
+        let stemmer = rust_stemmers::Stemmer::create(self.algorithm());
+        for token in tokens {
+            if let Cow::Owned(stem) = stemmer.stem(&token.term) {
+                token.term = Cow::Owned(stem);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stem_tokens() {
+        let mut tokens = crate::analyze("Running runs quickly; the runner ran", &Default::default());
+        Stemmer::English.stem_tokens(&mut tokens);
+        let terms: Vec<&str> = tokens.iter().map(|token| token.term.as_ref()).collect();
+        assert_eq!(terms, ["run", "run", "quick", "the", "runner", "ran"]);
+        // positions and spans are those of the original words
+        assert_eq!(tokens[0].span, 0..7);
+        assert!(matches!(tokens[3].term, Cow::Borrowed("the")));
+
+        assert_eq!(Stemmer::German.stem("häuser"), "haus");
+        assert_eq!(Stemmer::from_code("en"), Some(Stemmer::English));
+        assert_eq!(Stemmer::Swedish.code(), "sv");
+        assert!(Stemmer::from_code("xx").is_none());
+    }
+}
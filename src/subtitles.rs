@@ -0,0 +1,219 @@
+//! Subtitles and captions in the SubRip (`.srt`) and WebVTT (`.vtt`) formats: a file is indexed
+//! one cue at a time, so that searching a video or lecture archive finds the moment something is
+//! said rather than the whole file.
+//!
+//! A cue is a document `source@start`, with its start and end times in the `start` and `end`
+//! keyword fields. Times are written `HH:MM:SS.mmm`, which sorts as text in time order, so that a
+//! search can be narrowed to part of a video with a `Query::Range` over `start`.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::backend::Failures;
+use crate::xml::decode_entities;
+use crate::{IndexBackend, Searcher};
+
+/// Name of the keyword field holding the time a cue is shown at.
+pub const START_FIELD: &str = "start";
+
+/// Name of the keyword field holding the time a cue is hidden at.
+pub const END_FIELD: &str = "end";
+
+/// A cue of a subtitle file: text shown from `start` to `end`, both written `HH:MM:SS.mmm`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: String,
+    pub end: String,
+    pub text: String, // without markup, one line per line of the cue
+}
+
+/// Split subtitles in the SubRip or WebVTT format into cues. Blocks without a timing line, like
+/// the WebVTT header and its NOTE and STYLE blocks, are left out, as are cues without text. Cues
+/// starting at the same time, e.g. the lines of two speakers shown together, are merged.
+pub fn parse_subtitles(subtitles: &str) -> Vec<Cue> {
+    let subtitles = subtitles.strip_prefix('\u{feff}').unwrap_or(subtitles);
+    let lines: Vec<&str> = subtitles.lines().collect();
+    let mut cues: Vec<Cue> = Vec::new();
+    for block in lines.split(|line| line.trim().is_empty()) {
+        // an identifier, e.g. the number of a SubRip cue, may come before the timing line
+        let Some(timing) = block.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+        let Some((start, end)) = timings(block[timing]) else {
+            continue;
+        };
+        let lines: Vec<String> = block[timing + 1..].iter().map(|line| strip_markup(line)).filter(|line| !line.is_empty()).collect();
+        if lines.is_empty() {
+            continue;
+        }
+        let text = lines.join("\n");
+        match cues.iter_mut().find(|cue| cue.start == start) {
+            Some(cue) => {
+                cue.text.push('\n');
+                cue.text.push_str(&text);
+                cue.end = cue.end.clone().max(end);
+            }
+            None => cues.push(Cue { start, end, text }),
+        }
+    }
+    cues
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index the cues of subtitles as documents `source@start`, with their times in the `start`
+    /// and `end` keyword fields, and return how many there were. Adding the subtitles of a source
+    /// again replaces all of its cues, e.g. after they were retimed.
+    pub fn add_subtitles(&mut self, source: &str, subtitles: &str) -> usize {
+        let prefix = format!("{}@", source);
+        let stale: Vec<String> = self.docs.keys().filter(|doc_id| doc_id.starts_with(&prefix)).map(|doc_id| doc_id.to_string()).collect();
+        for doc_id in stale {
+            self.remove_document(&doc_id);
+        }
+
+        let cues = parse_subtitles(subtitles);
+        let docs = cues.iter().map(|cue| (format!("{}{}", prefix, cue.start), cue.text.clone())).collect();
+        self.add_batch(docs, &Failures::default());
+        for cue in &cues {
+            let doc_id = format!("{}{}", prefix, cue.start);
+            self.add_keyword(&doc_id, START_FIELD, &cue.start);
+            self.add_keyword(&doc_id, END_FIELD, &cue.end);
+        }
+        cues.len()
+    }
+}
+
+/// The start and end of a timing line, e.g. `00:00:01,000 --> 00:00:04,000` in SubRip or
+/// `00:01.000 --> 00:04.000 align:start` in WebVTT, which may be followed by cue settings.
+fn timings(line: &str) -> Option<(String, String)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((timecode(start.trim())?, timecode(end)?))
+}
+
+/// A time written `HH:MM:SS,mmm`, `HH:MM:SS.mmm` or `MM:SS.mmm`, as `HH:MM:SS.mmm`.
+fn timecode(s: &str) -> Option<String> {
+    let (rest, millis) = s.rsplit_once([',', '.'])?;
+    let parts: Vec<&str> = rest.split(':').collect();
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes, seconds] => (number(hours)?, number(minutes)?, number(seconds)?),
+        [minutes, seconds] => (0, number(minutes)?, number(seconds)?),
+        _ => return None,
+    };
+    if minutes >= 60 || seconds >= 60 || millis.len() != 3 {
+        return None;
+    }
+    Some(format!("{:02}:{:02}:{:02}.{:03}", hours, minutes, seconds, number(millis)?))
+}
+
+fn number(s: &str) -> Option<u32> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+/// A line of a cue without its markup: tags like `<i>`, `<v Speaker>` and WebVTT's inline
+/// timestamps, SubRip's `{\an8}` positioning codes and character references.
+fn strip_markup(line: &str) -> String {
+    let mut text = String::new();
+    let mut rest = line;
+    loop {
+        let tag = rest.find('<').map(|start| (start, '>'));
+        let code = rest.find("{\\").map(|start| (start, '}'));
+        let Some((start, close)) = [tag, code].into_iter().flatten().min() else {
+            break;
+        };
+        text.push_str(&rest[..start]);
+        match rest[start..].find(close) {
+            Some(end) => rest = &rest[start + end + 1..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    text.push_str(rest);
+    decode_entities(text.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::*;
+    use crate::{BoolQuery, ParseMode, Query};
+
+    #[test]
+    fn test_parse_subtitles() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:04,500\r\n<i>The moon</i> pulls\r\nthe ocean.\r\n\r\n2\r\n00:00:05,000 --> 00:00:07,000\r\n{\\an8}Rocket launch!\r\n\r\n3\r\n00:00:08,000 --> 00:00:09,000\r\n\r\n";
+        let cues = parse_subtitles(srt);
+        assert_eq!(
+            cues,
+            [
+                Cue { start: "00:00:01.000".to_string(), end: "00:00:04.500".to_string(), text: "The moon pulls\nthe ocean.".to_string() },
+                Cue { start: "00:00:05.000".to_string(), end: "00:00:07.000".to_string(), text: "Rocket launch!".to_string() },
+            ]
+        );
+
+        let vtt = "WEBVTT - a lecture
+
+NOTE timings are approximate
+
+intro
+01:02.000 --> 01:04.000 align:start position:10%
+<v Ada>Tides &amp; <b>rockets</b>
+
+01:02.000 --> 01:05.000
+<v Sam>Launch <01:03.000>window
+
+1:00:00.000 --> 1:00:01.000
+The end";
+        let cues = parse_subtitles(vtt);
+        let summary: Vec<(&str, &str, &str)> = cues.iter().map(|cue| (cue.start.as_str(), cue.end.as_str(), cue.text.as_str())).collect();
+        assert_eq!(
+            summary,
+            [
+                ("00:01:02.000", "00:01:05.000", "Tides & rockets\nLaunch window"),
+                ("01:00:00.000", "01:00:01.000", "The end"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_timecode() {
+        assert_eq!(timecode("00:00:01,000").as_deref(), Some("00:00:01.000"));
+        assert_eq!(timecode("59:59.999").as_deref(), Some("00:59:59.999"));
+        assert_eq!(timecode("123:00:00.000").as_deref(), Some("123:00:00.000"));
+        for invalid in ["00:60:00.000", "00:00:01", "00:00:01.5", "1.000", "aa:00:00.000", ""] {
+            assert_eq!(timecode(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_add_subtitles() {
+        let mut searcher = Searcher::new();
+        let srt = "1\n00:00:01,000 --> 00:00:02,000\nrocket launch\n\n2\n00:10:00,000 --> 00:10:02,000\nrocket landing\n";
+        assert_eq!(searcher.add_subtitles("talk.srt", srt), 2);
+
+        let after_minute_five = Query::Bool(BoolQuery {
+            must: vec![Query::parse("rocket", ParseMode::Strict).unwrap()],
+            filter: vec![Query::Range {
+                field: START_FIELD.to_string(),
+                lower: Bound::Included("00:05:00".to_string()),
+                upper: Bound::Unbounded,
+            }],
+            ..Default::default()
+        });
+        assert!(searcher.search_query(&after_minute_five).keys().eq(["talk.srt@00:10:00.000"]));
+        let ends_at_two = Query::Keyword { field: END_FIELD.to_string(), value: "00:00:02.000".to_string() };
+        assert!(searcher.search_query(&ends_at_two).keys().eq(["talk.srt@00:00:01.000"]));
+
+        // retimed: the cues at the old times are gone
+        let retimed = srt.replace("00:10:00,000", "00:09:00,000");
+        assert_eq!(searcher.add_subtitles("talk.srt", &retimed), 2);
+        let mut ids: Vec<String> = searcher.search("rocket").into_keys().collect();
+        ids.sort();
+        assert_eq!(ids, ["talk.srt@00:00:01.000", "talk.srt@00:09:00.000"]);
+    }
+}
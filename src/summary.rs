@@ -0,0 +1,95 @@
+//! Extractive summaries: the most informative sentences of a document, biased toward the query.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::backend::Failures;
+use crate::highlight::Wanted;
+use crate::passage::sentences;
+use crate::{math, HashMap, IndexBackend, Query, Searcher};
+
+/// How much more a query term counts than any other term when ranking sentences.
+const QUERY_TERM_WEIGHT: f32 = 3.0;
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Summarize a document in at most `max_sentences` sentences, kept in document order. Sentences
+    /// are ranked by the tf-idf weight of their terms, with `query` terms weighted up. Returns None
+    /// if the document does not exist or its content can't be fetched.
+    pub fn summarize(&self, doc_id: &str, query: Option<&Query>, max_sentences: usize) -> Option<String> {
+        let content = &self.content(doc_id)?;
+        let failures = Failures::default();
+        let mut wanted = Wanted::default();
+        if let Some(query) = query {
+            self.collect_wanted(query, &mut wanted, &failures);
+        }
+
+        let tokens = self.analyze_text(content);
+        let mut frequencies: HashMap<&str, f32> = HashMap::new();
+        for token in &tokens {
+            *frequencies.entry(token.term.as_ref()).or_insert(0.0) += 1.0;
+        }
+
+        let mut ranked: Vec<(usize, core::ops::Range<usize>, f32)> = sentences(content)
+            .into_iter()
+            .enumerate()
+            .map(|(i, span)| {
+                let terms: Vec<&str> = tokens
+                    .iter()
+                    .filter(|t| span.start <= t.span.start && t.span.end <= span.end)
+                    .map(|t| t.term.as_ref())
+                    .collect();
+                let weight: f32 = terms
+                    .iter()
+                    .map(|term| {
+                        let boost = if wanted.terms.contains(*term) { QUERY_TERM_WEIGHT } else { 1.0 };
+                        boost * frequencies[term] * self.idf(term, &failures)
+                    })
+                    .sum();
+                // favour dense sentences over merely long ones
+                let score = weight / math::sqrt(terms.len() as f32).max(1.0);
+                (i, span, score)
+            })
+            .collect();
+
+        ranked.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.0.cmp(&b.0)));
+        ranked.truncate(max_sentences);
+        ranked.sort_by_key(|(i, _, _)| *i);
+
+        Some(
+            ranked
+                .into_iter()
+                .map(|(_, span, _)| &content[span])
+                .collect::<Vec<&str>>()
+                .join(" "),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParseMode;
+
+    #[test]
+    fn test_summarize() {
+        let mut searcher = Searcher::new();
+        searcher.add_document(
+            "1",
+            "Welcome to the project. The indexer tokenizes files and builds postings. \
+             Queries are ranked with BM25 scoring. Thanks for reading.",
+        );
+        searcher.add_document("2", "Welcome to another project. Thanks for reading.");
+
+        let summary = searcher.summarize("1", None, 2).unwrap();
+        assert_eq!(
+            summary,
+            "The indexer tokenizes files and builds postings. Queries are ranked with BM25 scoring."
+        );
+
+        let query = Query::parse("bm25", ParseMode::Strict).unwrap();
+        let summary = searcher.summarize("1", Some(&query), 1).unwrap();
+        assert_eq!(summary, "Queries are ranked with BM25 scoring.");
+
+        assert!(searcher.summarize("missing", None, 1).is_none());
+    }
+}
@@ -0,0 +1,96 @@
+//! Synonyms: groups of words of the same meaning, so that a query for one of them also finds
+//! documents with the others. They are applied to queries only, so the lists can change at runtime
+//! without indexing documents again.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use crate::{ConfigError, HashMap, IndexBackend, Searcher};
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Replace the synonym lists with `groups` of words of the same meaning, e.g.
+    /// `[["car", "automobile"], ["tv", "television"]]`; takes effect on the next search. Words
+    /// are analyzed like queries, so set the stop words and stemmer first. Fails without changing
+    /// anything on a word that isn't a single term, e.g. a stop word or two words.
+    pub fn set_synonyms<I, G, S>(&mut self, groups: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = G>,
+        G: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut synonyms: HashMap<String, Vec<String>> = HashMap::new();
+        for group in groups {
+            let terms = group.into_iter().map(|word| self.synonym_term(word.as_ref())).collect::<Result<Vec<_>, _>>()?;
+            for term in &terms {
+                let others = terms.iter().filter(|other| *other != term).cloned();
+                synonyms.entry(term.clone()).or_default().extend(others);
+            }
+        }
+        for others in synonyms.values_mut() {
+            others.sort();
+            others.dedup();
+        }
+        synonyms.retain(|_, others| !others.is_empty());
+
+        self.synonyms = synonyms;
+        self.generation += 1;
+        let synonyms = self.synonyms.clone();
+        for child in self.children_mut() {
+            child.synonyms = synonyms.clone();
+        }
+        Ok(())
+    }
+
+    fn synonym_term(&self, word: &str) -> Result<String, ConfigError> {
+        match self.analyze_text(word).as_slice() {
+            [token] => Ok(token.term.to_string()),
+            _ => Err(ConfigError::InvalidSynonym(word.to_string())),
+        }
+    }
+
+    /// The terms of a query, each followed by its synonyms.
+    pub(crate) fn with_synonyms<'a>(&'a self, terms: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        let mut expanded = Vec::new();
+        for term in terms {
+            expanded.push(term);
+            expanded.extend(self.synonyms.get(term).into_iter().flatten().map(String::as_str));
+        }
+        expanded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    #[test]
+    fn test_synonyms() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "a red automobile");
+        searcher.add_document("2", "a red car");
+        searcher.add_document("3", "a movie");
+        assert_eq!(searcher.search("car").len(), 1);
+
+        let generation = searcher.generation;
+        searcher.set_synonyms([["car", "Automobile", "auto"], ["film", "movie", "movie"]]).unwrap();
+        assert!(searcher.generation > generation);
+        assert_eq!(searcher.synonyms["car"], ["auto", "automobile"]);
+        assert_eq!(searcher.synonyms["movie"], ["film"]);
+
+        // later queries find the synonyms of their terms, through every kind of search
+        let car = searcher.search("car");
+        assert_eq!(car.len(), 2);
+        assert_eq!(searcher.search("automobile"), car);
+        assert_eq!(searcher.search_query(&Query::parse("+red +auto", ParseMode::Strict).unwrap()).len(), 2);
+        assert_eq!(searcher.search_each("film", |_, _| ()), 1);
+
+        // a rejected list leaves the one in use
+        assert_eq!(searcher.set_synonyms([["car", "the"]]), Err(ConfigError::InvalidSynonym("the".to_string())));
+        assert_eq!(searcher.set_synonyms([["car", "red automobile"]]), Err(ConfigError::InvalidSynonym("red automobile".to_string())));
+        assert_eq!(searcher.search("car").len(), 2);
+
+        searcher.set_synonyms(Vec::<Vec<&str>>::new()).unwrap();
+        assert_eq!(searcher.search("car").len(), 1);
+    }
+}
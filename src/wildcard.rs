@@ -0,0 +1,147 @@
+//! Prefix and wildcard queries, e.g. `data*` or `te?t`: `*` matches any run of characters and `?`
+//! exactly one. The literal start of a pattern is looked up as a range of the sorted term
+//! dictionary, so that `data*` only looks at terms starting with `data` instead of at every term.
+
+use alloc::borrow::Cow;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::Bound;
+
+use crate::backend::Failures;
+use crate::{fold_char, IndexBackend, Searcher};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Glob {
+    Char(char),
+    Any, // `*`
+    One, // `?`
+}
+
+/// A wildcard pattern, case folded like indexed terms.
+#[derive(Debug)]
+pub(crate) struct Wildcard {
+    prefix: String, // the literal characters before the first wildcard
+    globs: Vec<Glob>,
+}
+
+impl Wildcard {
+    /// Read a pattern in which a backslash makes the next character literal.
+    pub(crate) fn new(pattern: &str) -> Wildcard {
+        let mut globs = Vec::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if globs.last() == Some(&Glob::Any) => {}
+                '*' => globs.push(Glob::Any),
+                '?' => globs.push(Glob::One),
+                '\\' => globs.extend(chars.next().into_iter().flat_map(fold_char).map(Glob::Char)),
+                c => globs.extend(fold_char(c).map(Glob::Char)),
+            }
+        }
+        let prefix = globs
+            .iter()
+            .map_while(|glob| match glob {
+                Glob::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect();
+        Wildcard { prefix, globs }
+    }
+
+    /// Whether the whole of `term` matches the pattern.
+    pub(crate) fn matches(&self, term: &str) -> bool {
+        let term: Vec<char> = term.chars().collect();
+        let (mut g, mut t) = (0, 0);
+        // where to resume after the last `*` if the characters after it stop matching
+        let mut backtrack: Option<(usize, usize)> = None;
+        while t < term.len() {
+            match self.globs.get(g) {
+                Some(Glob::Any) => {
+                    backtrack = Some((g + 1, t));
+                    g += 1;
+                    continue;
+                }
+                Some(Glob::One) => {
+                    g += 1;
+                    t += 1;
+                    continue;
+                }
+                Some(Glob::Char(c)) if *c == term[t] => {
+                    g += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            // let the last `*` swallow one more character
+            match backtrack {
+                Some((after_any, start)) => {
+                    g = after_any;
+                    t = start + 1;
+                    backtrack = Some((after_any, start + 1));
+                }
+                None => return false,
+            }
+        }
+        self.globs[g..].iter().all(|glob| *glob == Glob::Any)
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// The index terms matching a wildcard `pattern`, capped at `max_expansions`.
+    pub(crate) fn expand_wildcard(&self, pattern: &str, failures: &Failures) -> Vec<Cow<'_, str>> {
+        let wildcard = Wildcard::new(pattern);
+        let candidates = self
+            .index
+            .terms_from(Bound::Included(wildcard.prefix.as_str()))
+            .map_while(|term| failures.ok(term.map(Some)))
+            .take_while(|term| term.starts_with(&wildcard.prefix))
+            .filter(|term| wildcard.matches(term));
+        self.most_frequent(candidates, failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    #[test]
+    fn test_matches() {
+        let matches = |pattern: &str, term: &str| Wildcard::new(pattern).matches(term);
+        assert!(matches("data*", "data") && matches("data*", "database"));
+        assert!(!matches("data*", "dat"));
+        assert!(matches("te?t", "test") && !matches("te?t", "tet") && !matches("te?t", "tests"));
+        assert!(matches("*base", "database") && matches("d*b*e", "database") && !matches("d*b*x", "database"));
+        assert!(matches("**", "") && matches("a\\*", "a*") && !matches("a\\*", "ab"));
+        #[cfg(feature = "std")]
+        assert!(matches("STRAßE*", "strasse"));
+        assert_eq!(Wildcard::new("Da?a*").prefix, "da");
+    }
+
+    #[test]
+    fn test_wildcard_query() {
+        let mut searcher = Searcher::new();
+        searcher.add_document("1", "database migrations");
+        searcher.add_document("2", "dataset of tents");
+        searcher.add_document("3", "data tent");
+        searcher.add_document("4", "datum");
+
+        let search = |query: &str| {
+            let mut ids: Vec<String> = searcher.search_query(&Query::parse(query, ParseMode::Strict).unwrap()).into_keys().collect();
+            ids.sort();
+            ids
+        };
+        assert_eq!(search("data*"), ["1", "2", "3"]);
+        assert_eq!(search("te?t"), ["3"]);
+        assert_eq!(search("te?t*"), ["2", "3"]);
+        assert_eq!(search("+dat* -*base"), ["2", "3", "4"]);
+        assert!(search("zebra*").is_empty());
+
+        searcher.set_max_expansions(1);
+        assert_eq!(searcher.expand_wildcard("data*", &Failures::default()).len(), 1);
+
+        searcher.remove_document("4");
+        assert!(searcher.expand_wildcard("datum", &Failures::default()).is_empty());
+    }
+}
@@ -0,0 +1,559 @@
+//! XML corpora, e.g. Wikipedia dumps or JATS articles: each record element of a file is indexed
+//! as a structured `Document`, with selectors picking its id, text fields and keyword values out
+//! of the record.
+//!
+//! Selectors are a small part of XPath: element names separated by `/` for a child or `//` for a
+//! descendant at any depth, `*` for any element, and an optional last step `@attribute`. Selectors
+//! of ids and fields are relative to the record, so `revision/text` is the `text` child of the
+//! record's `revision` child, `@id` an attribute of the record and `.` the record itself. The
+//! record selector matches at any depth, unless it starts with `/`. The text of an element
+//! includes that of its descendants.
+//!
+//! The parser reads what such corpora are made of (elements, attributes, character references,
+//! CDATA sections, comments, processing instructions and a doctype) but doesn't validate the file
+//! or resolve namespaces: prefixed names are matched as written, e.g. `dc:title`.
+
+use alloc::borrow::Cow;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+
+use crate::{BuildReport, Document, IndexBackend, Searcher};
+
+/// Error returned when an XML file can't be indexed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlError {
+    /// The file is not well-formed at byte `position`.
+    Syntax { position: usize, message: String },
+    /// A selector of the mapping is invalid.
+    Selector { selector: String, message: &'static str },
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlError::Syntax { position, message } => write!(f, "invalid XML at byte {}: {}", position, message),
+            XmlError::Selector { selector, message } => write!(f, "invalid selector `{}`: {}", selector, message),
+        }
+    }
+}
+
+impl core::error::Error for XmlError {}
+
+/// Which elements of an XML file are documents, and where their id, fields and keyword values are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlMapping {
+    record: String,
+    id: String,
+    fields: Vec<(String, String)>,   // (text field, selector)
+    keywords: Vec<(String, String)>, // (keyword field, selector)
+}
+
+impl XmlMapping {
+    /// Documents are the elements matching `record`, identified by the text of the first match
+    /// of `id` in them, e.g. `XmlMapping::new("page", "id")` for a Wikipedia dump.
+    pub fn new(record: &str, id: &str) -> XmlMapping {
+        XmlMapping {
+            record: record.to_string(),
+            id: id.to_string(),
+            fields: Vec::new(),
+            keywords: Vec::new(),
+        }
+    }
+
+    /// Index the text of every match of `selector` as a value of text field `field`.
+    pub fn field(mut self, field: &str, selector: &str) -> Self {
+        self.fields.push((field.to_string(), selector.to_string()));
+        self
+    }
+
+    /// Attach the text of every match of `selector` as an exact value of keyword field `field`,
+    /// e.g. for categories or a language.
+    pub fn keyword(mut self, field: &str, selector: &str) -> Self {
+        self.keywords.push((field.to_string(), selector.to_string()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Selector {
+    steps: Vec<Step>,
+    attribute: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    name: String,     // `*` for any element
+    descendant: bool, // at any depth below the previous step, not only as its child
+}
+
+impl Selector {
+    /// Parse a selector. `anywhere` lets a selector that doesn't start with `/` match at any depth.
+    fn parse(selector: &str, anywhere: bool) -> Result<Selector, XmlError> {
+        let error = |message| XmlError::Selector { selector: selector.to_string(), message };
+        let mut rest = selector.trim();
+        if rest == "." {
+            return Ok(Selector { steps: Vec::new(), attribute: None });
+        }
+        if !anywhere && rest.starts_with('/') && !rest.starts_with("//") {
+            return Err(error("selectors of ids and fields are relative to the record"));
+        }
+
+        let mut steps = Vec::new();
+        let mut descendant = anywhere && !rest.starts_with('/');
+        loop {
+            if let Some(after) = rest.strip_prefix("//") {
+                descendant = true;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('/') {
+                rest = after;
+            }
+            let (name, after) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+            rest = after;
+            if let Some(attribute) = name.strip_prefix('@') {
+                if attribute.is_empty() || !rest.is_empty() {
+                    return Err(error("an attribute has to be the last step"));
+                }
+                return Ok(Selector { steps, attribute: Some(attribute.to_string()) });
+            }
+            if name.is_empty() || name.contains(['[', ']', '(', ')', '=']) {
+                return Err(error("steps are element names, `*` or `@attribute`"));
+            }
+            steps.push(Step { name: name.to_string(), descendant });
+            descendant = false;
+            if rest.is_empty() {
+                return Ok(Selector { steps, attribute: None });
+            }
+        }
+    }
+
+    /// Whether the element at the end of `path`, a list of element names, matches the steps.
+    fn matches(&self, path: &[&str]) -> bool {
+        matches(&self.steps, path)
+    }
+}
+
+fn matches(steps: &[Step], path: &[&str]) -> bool {
+    let Some((step, steps)) = steps.split_first() else {
+        return path.is_empty();
+    };
+    let fits = |name: &str| step.name == "*" || step.name == name;
+    match step.descendant {
+        true => (0..path.len()).any(|skip| fits(path[skip]) && matches(steps, &path[skip + 1..])),
+        false => path.first().is_some_and(|&name| fits(name)) && matches(steps, &path[1..]),
+    }
+}
+
+pub(crate) enum Event<'a> {
+    Start { name: &'a str, attributes: Vec<(&'a str, String)>, empty: bool },
+    End(&'a str),
+    Text(Cow<'a, str>),
+}
+
+/// Reads the elements and text of an XML document one event at a time. It doesn't check that end
+/// tags match start tags.
+pub(crate) struct Parser<'a> {
+    xml: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(xml: &'a str) -> Parser<'a> {
+        Parser { xml, position: 0 }
+    }
+
+    fn error(&self, message: impl Into<String>) -> XmlError {
+        XmlError::Syntax { position: self.position, message: message.into() }
+    }
+
+    pub(crate) fn next(&mut self) -> Result<Option<Event<'a>>, XmlError> {
+        loop {
+            let rest = &self.xml[self.position..];
+            if rest.is_empty() {
+                return Ok(None);
+            }
+            if !rest.starts_with('<') {
+                let end = rest.find('<').unwrap_or(rest.len());
+                self.position += end;
+                let text = &rest[..end];
+                let text = if text.contains('&') { Cow::Owned(decode_entities(text)) } else { Cow::Borrowed(text) };
+                return Ok(Some(Event::Text(text)));
+            }
+            if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+                let end = cdata.find("]]>").ok_or_else(|| self.error("an unclosed CDATA section"))?;
+                self.position += "<![CDATA[".len() + end + "]]>".len();
+                return Ok(Some(Event::Text(Cow::Borrowed(&cdata[..end]))));
+            }
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                let end = comment.find("-->").ok_or_else(|| self.error("an unclosed comment"))?;
+                self.position += "<!--".len() + end + "-->".len();
+                continue;
+            }
+            if let Some(instruction) = rest.strip_prefix("<?") {
+                let end = instruction.find("?>").ok_or_else(|| self.error("an unclosed processing instruction"))?;
+                self.position += "<?".len() + end + "?>".len();
+                continue;
+            }
+            if rest.starts_with("<!") {
+                // a doctype, whose internal subset in `[]` may hold `>`
+                let mut depth = 0;
+                let end = rest.find(|c| {
+                    match c {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    c == '>' && depth == 0
+                });
+                self.position += end.ok_or_else(|| self.error("an unclosed declaration"))? + 1;
+                continue;
+            }
+            if let Some(end_tag) = rest.strip_prefix("</") {
+                let end = end_tag.find('>').ok_or_else(|| self.error("an unclosed end tag"))?;
+                self.position += "</".len() + end + 1;
+                return Ok(Some(Event::End(end_tag[..end].trim_end())));
+            }
+
+            self.position += 1;
+            let name = self.name()?;
+            let mut attributes = Vec::new();
+            loop {
+                self.skip_whitespace();
+                let rest = &self.xml[self.position..];
+                if rest.starts_with("/>") || rest.starts_with('>') {
+                    let empty = rest.starts_with('/');
+                    self.position += if empty { 2 } else { 1 };
+                    return Ok(Some(Event::Start { name, attributes, empty }));
+                }
+                let attribute = self.name()?;
+                self.skip_whitespace();
+                self.position += self.xml[self.position..].strip_prefix('=').map(|_| 1).ok_or_else(|| self.error("`=` after an attribute name"))?;
+                self.skip_whitespace();
+                let rest = &self.xml[self.position..];
+                let quote = rest.chars().next().filter(|c| matches!(c, '"' | '\'')).ok_or_else(|| self.error("a quoted attribute value"))?;
+                let end = rest[1..].find(quote).ok_or_else(|| self.error("the end of an attribute value"))?;
+                attributes.push((attribute, decode_entities(&rest[1..end + 1])));
+                self.position += end + 2;
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, XmlError> {
+        let rest = &self.xml[self.position..];
+        let end = rest.find(|c: char| c.is_whitespace() || matches!(c, '/' | '>' | '=' | '<')).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("a name"));
+        }
+        self.position += end;
+        Ok(&rest[..end])
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.xml[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+}
+
+/// What a selector of the mapping fills in.
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    Id,
+    Field(usize),
+    Keyword(usize),
+}
+
+/// The record being read.
+struct Record {
+    depth: usize,                           // number of open elements, the record's included
+    captures: Vec<(Target, usize, String)>, // text of the open elements matching a selector, with their depth
+    values: Vec<(Target, String)>,          // values of the closed ones, and of matching attributes
+}
+
+impl Record {
+    /// An element opened at `path`, relative to the record.
+    fn open(&mut self, path: &[&str], attributes: &[(&str, String)], selectors: &[(Target, Selector)]) {
+        // an element boundary separates words
+        for (_, _, text) in &mut self.captures {
+            text.push('\n');
+        }
+        for (target, selector) in selectors {
+            if !selector.matches(path) {
+                continue;
+            }
+            match &selector.attribute {
+                None => self.captures.push((*target, self.depth + path.len(), String::new())),
+                Some(name) => {
+                    let value = attributes.iter().find(|(attribute, _)| attribute == name);
+                    self.values.extend(value.map(|(_, value)| (*target, value.clone())));
+                }
+            }
+        }
+    }
+
+    /// The element at `depth` closed.
+    fn close(&mut self, depth: usize) {
+        let mut i = 0;
+        while i < self.captures.len() {
+            if self.captures[i].1 == depth {
+                let (target, _, text) = self.captures.remove(i);
+                self.values.push((target, text));
+            } else {
+                self.captures[i].2.push('\n');
+                i += 1;
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        for (_, _, captured) in &mut self.captures {
+            captured.push_str(text);
+        }
+    }
+}
+
+impl<B: IndexBackend> Searcher<B> {
+    /// Index the records of an XML document as structured documents, as `mapping` says. Records
+    /// without an id are recorded in `report` as skipped. Returns how many documents were indexed.
+    pub fn add_xml(&mut self, xml: &str, mapping: &XmlMapping, report: &mut BuildReport) -> Result<usize, XmlError> {
+        let record_selector = Selector::parse(&mapping.record, true)?;
+        let mut selectors = vec![(Target::Id, Selector::parse(&mapping.id, false)?)];
+        for (i, (_, selector)) in mapping.fields.iter().enumerate() {
+            selectors.push((Target::Field(i), Selector::parse(selector, false)?));
+        }
+        for (i, (_, selector)) in mapping.keywords.iter().enumerate() {
+            selectors.push((Target::Keyword(i), Selector::parse(selector, false)?));
+        }
+
+        let mut parser = Parser::new(xml);
+        let mut stack: Vec<&str> = Vec::new();
+        let mut record: Option<Record> = None;
+        let mut records = 0;
+        let mut indexed = 0;
+        while let Some(event) = parser.next()? {
+            let closed = match event {
+                Event::Text(text) => {
+                    if let Some(record) = &mut record {
+                        record.text(&text);
+                    }
+                    None
+                }
+                Event::Start { name, attributes, empty } => {
+                    stack.push(name);
+                    if record.is_none() && record_selector.matches(&stack) {
+                        record = Some(Record { depth: stack.len(), captures: Vec::new(), values: Vec::new() });
+                    }
+                    if let Some(record) = &mut record {
+                        record.open(&stack[record.depth..], &attributes, &selectors);
+                    }
+                    empty.then_some(name)
+                }
+                Event::End(name) => Some(name),
+            };
+            let Some(name) = closed else {
+                continue;
+            };
+
+            match stack.last() {
+                Some(open) if *open == name => {}
+                Some(open) => return Err(parser.error(format!("`</{}>` closing `<{}>`", name, open))),
+                None => return Err(parser.error(format!("`</{}>` without a start tag", name))),
+            }
+            if let Some(open) = &mut record {
+                open.close(stack.len());
+                if open.depth == stack.len() {
+                    records += 1;
+                    let values = record.take().expect("a record is open").values;
+                    match self.add_record(values, mapping) {
+                        true => indexed += 1,
+                        false => report.skip(&format!("record {}", records), "no id"),
+                    }
+                }
+            }
+            stack.pop();
+        }
+        match stack.last() {
+            Some(open) => Err(parser.error(format!("a `</{}>` end tag", open))),
+            None => Ok(indexed),
+        }
+    }
+
+    /// Index a record from the values its selectors captured. Returns false if it has no id.
+    fn add_record(&mut self, values: Vec<(Target, String)>, mapping: &XmlMapping) -> bool {
+        let Some(id) = values.iter().find(|(target, value)| *target == Target::Id && !value.trim().is_empty()) else {
+            return false;
+        };
+        let id = id.1.trim().to_string();
+        let mut document = Document::new(&id);
+        let mut keywords = Vec::new();
+        for (target, value) in values {
+            let value = value.trim();
+            match target {
+                _ if value.is_empty() => {}
+                Target::Id => {}
+                Target::Field(i) => document = document.field(&mapping.fields[i].0, value),
+                Target::Keyword(i) => keywords.push((&mapping.keywords[i].0, value.to_string())),
+            }
+        }
+        self.add_fields(&document);
+        for (field, value) in keywords {
+            self.add_keyword(&id, field, &value);
+        }
+        true
+    }
+}
+
+/// Decode character references, e.g. `&amp;` and `&#233;`. Unknown names are left as they are.
+pub(crate) fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest[1..].find(';').filter(|&end| end <= 10).map(|end| &rest[1..end + 1]);
+        let c = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => match reference.strip_prefix('#')? {
+                hex if hex.starts_with(['x', 'X']) => char::from_u32(u32::from_str_radix(&hex[1..], 16).ok()?),
+                decimal => char::from_u32(decimal.parse().ok()?),
+            },
+        });
+        match (c, reference) {
+            (Some(c), Some(reference)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ParseMode, Query};
+
+    fn search(searcher: &Searcher, query: &str) -> Vec<String> {
+        let mut ids: Vec<String> = searcher.search_query(&Query::parse(query, ParseMode::Strict).unwrap()).into_keys().collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_selector() {
+        let path = |path: &str| path.split('/').filter(|name| !name.is_empty()).map(str::to_string).collect::<Vec<_>>();
+        let matches = |selector: &str, anywhere, at: &str| {
+            let at = path(at);
+            Selector::parse(selector, anywhere).unwrap().matches(&at.iter().map(String::as_str).collect::<Vec<_>>())
+        };
+        assert!(matches("page", true, "mediawiki/page"));
+        assert!(!matches("/page", true, "mediawiki/page"));
+        assert!(matches("/mediawiki/page", true, "mediawiki/page"));
+        assert!(matches("revision/text", false, "revision/text"));
+        assert!(!matches("text", false, "revision/text"));
+        assert!(matches("//text", false, "revision/text"));
+        assert!(matches("front//article-title", false, "front/article-meta/title-group/article-title"));
+        assert!(matches("*/text", false, "revision/text"));
+        assert!(matches(".", false, ""));
+        assert!(matches("@id", false, ""));
+        assert_eq!(Selector::parse("a/@b", false).unwrap().attribute.as_deref(), Some("b"));
+
+        for invalid in ["", "a/", "a//", "@b/c", "a/@", "/a", "a[1]"] {
+            assert!(matches!(Selector::parse(invalid, false), Err(XmlError::Selector { .. })), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_wikipedia_dump() {
+        let dump = r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE mediawiki [ <!ENTITY copy "&#169;"> ]>
+<mediawiki xml:lang="en">
+  <siteinfo><sitename>Wikipedia</sitename></siteinfo>
+  <page>
+    <title>Moon</title>
+    <id>1</id>
+    <revision>
+      <id>1001</id>
+      <!-- the <id> of the revision is not the page's -->
+      <text xml:space="preserve">The Moon raises the ocean &amp; its tides.</text>
+    </revision>
+  </page>
+  <page>
+    <title>Rocket</title>
+    <id>2</id>
+    <revision><id>1002</id><text><![CDATA[A <rocket> is launched]]></text></revision>
+  </page>
+  <page><title>Tide</title><revision><text>without an id</text></revision></page>
+</mediawiki>"#;
+        let mapping = XmlMapping::new("page", "id").field("title", "title").field("text", "revision/text");
+        let mut searcher = Searcher::new();
+        let mut report = BuildReport::default();
+        assert_eq!(searcher.add_xml(dump, &mapping, &mut report), Ok(2));
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(searcher.docs.len(), 2);
+
+        assert_eq!(search(&searcher, "title:moon"), ["1"]);
+        assert_eq!(search(&searcher, "text:tides"), ["1"]);
+        assert_eq!(search(&searcher, "text:rocket"), ["2"]);
+        assert!(search(&searcher, "sitename").is_empty());
+        assert_eq!(searcher.fields["text"].docs.len(), 2);
+    }
+
+    #[test]
+    fn test_jats_article() {
+        let article = r#"<articles>
+<article article-type="research-article" xml:lang="en">
+  <front><article-meta>
+    <article-id pub-id-type="doi">10.1000/moon.1</article-id>
+    <title-group><article-title>Ocean <italic>tides</italic> and moon</article-title></title-group>
+    <kwd-group><kwd>moon</kwd><kwd>tide</kwd></kwd-group>
+  </article-meta></front>
+  <body><sec><title>Launch</title><p>The rocket<xref ref-type="bibr"/>reached orbit.</p></sec></body>
+</article>
+</articles>"#;
+        let mapping = XmlMapping::new("article", "front//article-id")
+            .field("title", "front//article-title")
+            .field("body", "body//p")
+            .keyword("type", "@article-type")
+            .keyword("keyword", "//kwd");
+        let mut searcher = Searcher::new();
+        assert_eq!(searcher.add_xml(article, &mapping, &mut BuildReport::default()), Ok(1));
+        assert_eq!(search(&searcher, "title:\"ocean tides\""), ["10.1000/moon.1"]);
+        assert_eq!(search(&searcher, "type:research-article"), ["10.1000/moon.1"]);
+        assert_eq!(search(&searcher, "keyword:tide"), ["10.1000/moon.1"]);
+        // elements separate words, so `rocket` isn't joined to `reached`
+        assert_eq!(search(&searcher, "body:reached"), ["10.1000/moon.1"]);
+        assert!(search(&searcher, "body:launch").is_empty());
+    }
+
+    #[test]
+    fn test_xml_errors() {
+        let mapping = XmlMapping::new("page", "id");
+        let mut searcher = Searcher::new();
+        let mut add = |xml: &str| searcher.add_xml(xml, &mapping, &mut BuildReport::default());
+        assert_eq!(
+            add("<a><page><id>1</id></a>"),
+            Err(XmlError::Syntax { position: 23, message: "`</a>` closing `<page>`".to_string() })
+        );
+        assert!(matches!(add("<a><page>"), Err(XmlError::Syntax { .. })));
+        assert!(matches!(add("<a b=c/>"), Err(XmlError::Syntax { .. })));
+        assert!(matches!(add("<a><!-- unclosed </a>"), Err(XmlError::Syntax { .. })));
+        assert_eq!(add("<page id='1'/><page><id>2</id></page>"), Ok(1));
+        let invalid = XmlMapping::new("page", "id").field("title", "title/");
+        assert!(matches!(searcher.add_xml("<page/>", &invalid, &mut BuildReport::default()), Err(XmlError::Selector { .. })));
+    }
+}
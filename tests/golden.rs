@@ -0,0 +1,68 @@
+//! Golden-ranking regression tests: the top results of every query in `tests/golden/queries.txt`
+//! over the fixture corpus have to match the rankings recorded in `tests/golden/rankings.txt`.
+//!
+//! After an intended ranking change, re-record them with
+//! `UPDATE_GOLDEN=1 cargo test --test golden` and review the diff.
+
+use std::fs;
+use std::path::Path;
+
+use searcher::{ParseMode, Query, Searcher};
+
+const TOP_K: usize = 5;
+
+fn golden_dir() -> &'static Path {
+    Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden"))
+}
+
+/// Index the fixture corpus the way the CLI indexes a directory.
+fn corpus_searcher() -> Searcher {
+    let mut searcher = Searcher::new();
+    for entry in fs::read_dir(golden_dir().join("corpus")).unwrap() {
+        let path = entry.unwrap().path();
+        let filename = path.file_name().unwrap().to_string_lossy().to_string();
+        searcher.add_document(&filename, &fs::read_to_string(&path).unwrap());
+        if let Some(ext) = path.extension() {
+            searcher.add_keyword(&filename, "ext", &ext.to_string_lossy());
+        }
+    }
+    searcher
+}
+
+/// The top doc ids for `query`, best first with ties broken by doc id.
+fn ranking(searcher: &Searcher, query: &str) -> Vec<String> {
+    let query = Query::parse(query, ParseMode::Strict).unwrap();
+    let mut results: Vec<(String, f32)> = searcher.search_query(&query).into_iter().collect();
+    results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    results.into_iter().take(TOP_K).map(|(doc_id, _)| doc_id).collect()
+}
+
+#[test]
+fn test_golden_rankings() {
+    let searcher = corpus_searcher();
+    let queries = fs::read_to_string(golden_dir().join("queries.txt")).unwrap();
+    let actual: String = queries
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|query| format!("{}\t{}\n", query, ranking(&searcher, query).join(" ")))
+        .collect();
+
+    let rankings_path = golden_dir().join("rankings.txt");
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&rankings_path, &actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(&rankings_path).unwrap_or_default();
+    let changed: Vec<String> = expected
+        .lines()
+        .zip(actual.lines())
+        .filter(|(e, a)| e != a)
+        .map(|(e, a)| format!("  expected: {}\n  actual:   {}", e, a))
+        .collect();
+    assert!(
+        changed.is_empty() && expected.lines().count() == actual.lines().count(),
+        "rankings changed (rerun with UPDATE_GOLDEN=1 if intended):\n{}",
+        changed.join("\n")
+    );
+}
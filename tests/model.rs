@@ -0,0 +1,171 @@
+//! Model-based tests: random sequences of index operations are applied both to a `Searcher` and
+//! to a naive reference index that recomputes every statistic from scratch, and the search results
+//! of the two have to agree.
+
+use std::collections::{BTreeMap, HashMap};
+
+use proptest::prelude::*;
+use searcher::{CollectionStats, Searcher};
+
+const VOCABULARY: &[&str] = &["rust", "moon", "rocket", "borrow", "checker", "compiler", "ocean", "tide"];
+const COPY_FIELD: &str = "title";
+
+#[derive(Debug, Clone)]
+enum Op {
+    Add(Vec<usize>),               // a new document with these vocabulary words
+    CopyValue(usize, Vec<usize>),  // a copy field value added to an existing document
+    Update(usize, Vec<usize>),     // an existing document given new content
+    Remove(usize),                 // an existing document removed
+    Readd(usize, Vec<usize>),      // an existing document added again under the same id
+}
+
+/// The reference implementation: documents as plain word lists.
+#[derive(Default)]
+struct Model {
+    docs: BTreeMap<String, Vec<String>>,
+    copied: BTreeMap<String, Vec<String>>, // doc_id -> copy field values already added
+}
+
+impl Model {
+    fn collection_stats(&self) -> CollectionStats {
+        CollectionStats {
+            documents: self.docs.len(),
+            terms: self.docs.values().map(|words| words.len() as u64).sum(),
+        }
+    }
+
+    fn search(&self, query: &[String], k1: f32, b: f32) -> HashMap<String, f32> {
+        let n = self.docs.len() as f32;
+        let avdl = self.docs.values().map(Vec::len).sum::<usize>() as f32 / n;
+
+        let mut scores = HashMap::new();
+        for term in query {
+            let df = self.docs.values().filter(|words| words.contains(term)).count() as f32;
+            let idf = ((n - df + 0.5) / (df + 0.5) + 1.0).ln();
+            for (doc_id, words) in &self.docs {
+                let tf = words.iter().filter(|word| *word == term).count() as f32;
+                if tf > 0.0 {
+                    let dl = words.len() as f32;
+                    *scores.entry(doc_id.clone()).or_insert(0.0) += idf * tf * (k1 + 1.0) / (k1 * ((1.0 - b) + b * dl / avdl));
+                }
+            }
+        }
+        scores
+    }
+}
+
+fn words(indexes: &[usize]) -> Vec<String> {
+    indexes.iter().map(|&i| VOCABULARY[i].to_string()).collect()
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let words = || prop::collection::vec(0..VOCABULARY.len(), 0..8);
+    prop_oneof![
+        3 => words().prop_map(Op::Add),
+        1 => (any::<usize>(), words()).prop_map(|(doc, words)| Op::CopyValue(doc, words)),
+        1 => (any::<usize>(), words()).prop_map(|(doc, words)| Op::Update(doc, words)),
+        1 => any::<usize>().prop_map(Op::Remove),
+        1 => (any::<usize>(), words()).prop_map(|(doc, words)| Op::Readd(doc, words)),
+    ]
+}
+
+/// A new searcher and model, set up alike.
+fn setup() -> (Searcher, Model) {
+    let mut searcher = Searcher::new();
+    searcher.set_stop_words(Vec::<String>::new());
+    searcher.add_copy_field(COPY_FIELD);
+    (searcher, Model::default())
+}
+
+/// Apply `op` to both the searcher and the model. Operations on existing documents pick one by
+/// index among the model's documents and do nothing on an empty index.
+fn apply(searcher: &mut Searcher, model: &mut Model, next_id: &mut usize, op: Op) -> Result<(), TestCaseError> {
+    let existing = |model: &Model, doc: usize| model.docs.keys().nth(doc % model.docs.len().max(1)).cloned();
+    match op {
+        Op::Add(indexes) => {
+            let doc_id = next_id.to_string();
+            *next_id += 1;
+            searcher.add_document(&doc_id, &words(&indexes).join(" "));
+            model.docs.insert(doc_id, words(&indexes));
+        }
+        Op::CopyValue(doc, indexes) => {
+            let Some(doc_id) = existing(model, doc) else {
+                return Ok(());
+            };
+            let value = words(&indexes).join(" ");
+            prop_assert!(searcher.add_keyword(&doc_id, COPY_FIELD, &value));
+
+            let copied = model.copied.entry(doc_id.clone()).or_default();
+            if !copied.contains(&value) {
+                copied.push(value);
+                model.docs.get_mut(&doc_id).unwrap().extend(words(&indexes));
+            }
+        }
+        Op::Update(doc, indexes) => {
+            let Some(doc_id) = existing(model, doc) else {
+                return Ok(());
+            };
+            searcher.update_document(&doc_id, &words(&indexes).join(" "));
+
+            // copied values stay with the document
+            let mut doc_words = words(&indexes);
+            for value in model.copied.get(&doc_id).into_iter().flatten() {
+                doc_words.extend(value.split_whitespace().map(str::to_string));
+            }
+            model.docs.insert(doc_id, doc_words);
+        }
+        Op::Remove(doc) => {
+            let Some(doc_id) = existing(model, doc) else {
+                return Ok(());
+            };
+            prop_assert!(searcher.remove_document(&doc_id));
+            model.docs.remove(&doc_id);
+            model.copied.remove(&doc_id);
+        }
+        Op::Readd(doc, indexes) => {
+            let Some(doc_id) = existing(model, doc) else {
+                return Ok(());
+            };
+            searcher.add_document(&doc_id, &words(&indexes).join(" "));
+            // nothing of the old document is kept
+            model.docs.insert(doc_id.clone(), words(&indexes));
+            model.copied.remove(&doc_id);
+        }
+    }
+    Ok(())
+}
+
+proptest! {
+    #[test]
+    fn test_search_matches_model(ops in prop::collection::vec(op(), 1..20), query in prop::collection::vec(0..VOCABULARY.len(), 1..4)) {
+        let (mut searcher, mut model) = setup();
+        let mut next_id = 0;
+        for op in ops {
+            apply(&mut searcher, &mut model, &mut next_id, op)?;
+        }
+
+        let query = words(&query);
+        let expected = model.search(&query, searcher.k1(), searcher.b());
+        let actual = searcher.search(&query.join(" "));
+
+        let mut expected_ids: Vec<_> = expected.keys().collect();
+        let mut actual_ids: Vec<_> = actual.keys().collect();
+        expected_ids.sort();
+        actual_ids.sort();
+        prop_assert_eq!(expected_ids, actual_ids);
+        for (doc_id, score) in &expected {
+            prop_assert!((score - actual[doc_id]).abs() <= 1e-4 * score.abs().max(1.0), "{}: {} != {}", doc_id, score, actual[doc_id]);
+        }
+    }
+
+    #[test]
+    fn test_collection_stats_match_recount(ops in prop::collection::vec(op(), 1..30)) {
+        let (mut searcher, mut model) = setup();
+        let mut next_id = 0;
+        for op in ops {
+            apply(&mut searcher, &mut model, &mut next_id, op)?;
+            prop_assert_eq!(searcher.collection_stats(), model.collection_stats());
+            prop_assert_eq!(searcher.collection_stats().avdl().to_bits(), model.collection_stats().avdl().to_bits());
+        }
+    }
+}
@@ -0,0 +1,209 @@
+//! Integration tests of the Redis backend and cache against a real server: each test starts Redis in a
+//! Docker container with testcontainers, or uses the server at `REDIS_URL` if set. They are
+//! ignored by default; run them with `cargo test --features redis --test redis -- --ignored`.
+//!
+//! A Searcher has to behave the same with its postings in Redis as in memory, so most tests apply
+//! the same changes to one of each and compare what every query finds and scores.
+
+#![cfg(feature = "redis")]
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use searcher::backend::redis::RedisBackend;
+use searcher::cache::redis::RedisCache;
+use searcher::{Cache, ParseMode, Query, SearchEngine, Searcher};
+use testcontainers_modules::redis::{Redis, REDIS_PORT};
+use testcontainers_modules::testcontainers::runners::SyncRunner;
+use testcontainers_modules::testcontainers::Container;
+
+const QUERIES: &[&str] = &[
+    "moon",
+    "moon rocket moon",
+    "+ocean -tide",
+    "rocket -tide^0.5",
+    "lang:en ocean",
+    "rust",
+    "the",
+    "\"ocean tide\"",
+    "\"moon rocket\" -rust",
+    "roc* o?ean",
+    "rocet~1 mon~2",
+    "/oc.an|ti[a-z]e/",
+    "+/r.*/ -\"ocean tide\"",
+];
+
+/// The queries of `QUERIES` this build can run, as regular expressions need the `regex` feature.
+fn queries() -> impl Iterator<Item = Query> {
+    QUERIES
+        .iter()
+        .filter(|query| cfg!(feature = "regex") || !query.contains('/'))
+        .map(|query| Query::parse(query, ParseMode::Strict).unwrap())
+}
+
+/// A Redis server for the length of a test, stopped when dropped if it runs in a container.
+struct Server {
+    url: String,
+    _container: Option<Container<Redis>>,
+}
+
+impl Server {
+    fn start() -> Server {
+        if let Ok(url) = std::env::var("REDIS_URL") {
+            return Server { url, _container: None };
+        }
+        let container = Redis::default().start().expect("starting a Redis container needs Docker");
+        let host = container.get_host().unwrap();
+        let port = container.get_host_port_ipv4(REDIS_PORT).unwrap();
+        Server { url: format!("redis://{}:{}", host, port), _container: Some(container) }
+    }
+
+    /// An empty index with its postings under `prefix`.
+    fn open(&self, prefix: &str) -> Searcher<RedisBackend> {
+        Searcher::with_backend(RedisBackend::open(&self.url, prefix).unwrap())
+    }
+}
+
+/// Apply the same change to the index on Redis and the one in memory.
+fn both(redis: &mut Searcher<RedisBackend>, memory: &mut Searcher, change: impl Fn(&mut dyn SearchEngine)) {
+    change(redis);
+    change(memory);
+}
+
+fn assert_same(redis: &Searcher<RedisBackend>, memory: &Searcher) {
+    assert_eq!(SearchEngine::doc_count(redis).unwrap(), SearchEngine::doc_count(memory).unwrap());
+    assert_eq!(SearchEngine::term_stats(redis).unwrap(), SearchEngine::term_stats(memory).unwrap());
+    for query in queries() {
+        assert_eq!(SearchEngine::search_query(redis, &query).unwrap(), SearchEngine::search_query(memory, &query).unwrap(), "{:?}", query);
+    }
+}
+
+#[test]
+#[ignore = "needs Docker or a Redis server at REDIS_URL"]
+fn test_redis_index() {
+    let server = Server::start();
+    let index: &mut dyn SearchEngine = &mut server.open("searcher-test:index");
+    index.add_document("1", "The moon rocket launched. Ocean tide charts were updated.").unwrap();
+    index.add_document("2", "rust compiler").unwrap();
+    assert!(index.add_keyword("2", "ext", "rs").unwrap());
+    assert!(!index.add_keyword("missing", "ext", "rs").unwrap());
+    assert_eq!(index.doc_count().unwrap(), 2);
+
+    let rocket = Query::parse("rocket", ParseMode::Strict).unwrap();
+    let hits = index.search_hits(&rocket, 0, 10).unwrap().hits;
+    assert_eq!(hits.iter().map(|hit| hit.doc_id.as_str()).collect::<Vec<_>>(), ["1"]);
+    assert_eq!(hits[0].snippet("[", "]").unwrap().unwrap(), "The moon [rocket] launched. Ocean tide charts were updated.");
+    let ext = Query::parse("ext:rs", ParseMode::Strict).unwrap();
+    assert!(index.search_query(&ext).unwrap().contains_key("2"));
+
+    // updating keeps the keyword values, adding again drops them
+    index.update_document("2", "borrow checker").unwrap();
+    assert!(index.search_query(&ext).unwrap().contains_key("2"));
+    index.add_document("2", "borrow checker").unwrap();
+    assert!(index.search_query(&ext).unwrap().is_empty());
+
+    assert!(index.remove_document("1").unwrap());
+    assert!(!index.remove_document("1").unwrap());
+    assert!(index.search_query(&rocket).unwrap().is_empty());
+    assert!(index.term_stats().unwrap().iter().all(|stats| stats.term != "rocket"));
+
+    // opening the prefix again starts from an empty index
+    let reopened = server.open("searcher-test:index");
+    assert!(SearchEngine::term_stats(&reopened).unwrap().is_empty());
+}
+
+#[test]
+#[ignore = "needs Docker or a Redis server at REDIS_URL"]
+fn test_redis_scores_like_in_memory() {
+    let server = Server::start();
+    let mut redis = server.open("searcher-test:scores");
+    let mut memory = Searcher::new();
+    both(&mut redis, &mut memory, |engine| {
+        engine.add_document("1", "moon rocket ocean").unwrap();
+        engine.add_document("2", "moon").unwrap();
+        engine.add_document("3", "rocket ocean ocean tide").unwrap();
+        engine.add_document("4", "the ocean tide and the moon").unwrap();
+        engine.add_keyword("3", "lang", "en").unwrap();
+        engine.set_static_rank("4", 0.5).unwrap();
+    });
+
+    assert_same(&redis, &memory);
+    for query in queries() {
+        assert_eq!(SearchEngine::explain(&redis, &query, "3").unwrap(), SearchEngine::explain(&memory, &query, "3").unwrap(), "{:?}", query);
+    }
+    assert_eq!(SearchEngine::build_report(&redis).unwrap(), SearchEngine::build_report(&memory).unwrap());
+}
+
+#[test]
+#[ignore = "needs Docker or a Redis server at REDIS_URL"]
+fn test_redis_parity_through_changes() {
+    let server = Server::start();
+    let mut redis = server.open("searcher-test:changes");
+    let mut memory = Searcher::new();
+
+    both(&mut redis, &mut memory, |engine| {
+        let docs = [("a", "moon rocket"), ("b", "ocean tide tide"), ("c", "rust moon"), ("d", "rocket ocean moon")];
+        engine.add_documents(docs.iter().map(|(doc_id, content)| (doc_id.to_string(), content.to_string())).collect()).unwrap();
+        engine.add_keyword("b", "lang", "en").unwrap();
+    });
+    assert_same(&redis, &memory);
+
+    // removing a document drops its postings and the terms only it had
+    both(&mut redis, &mut memory, |engine| assert!(engine.remove_document("c").unwrap()));
+    assert_same(&redis, &memory);
+    assert!(SearchEngine::term_stats(&redis).unwrap().iter().all(|stats| stats.term != "rust"));
+
+    // replaced content changes the collection statistics every score depends on
+    both(&mut redis, &mut memory, |engine| engine.update_document("b", "ocean ocean ocean tide moon rocket").unwrap());
+    assert_same(&redis, &memory);
+    both(&mut redis, &mut memory, |engine| engine.add_document("a", "rust").unwrap());
+    assert_same(&redis, &memory);
+
+    both(&mut redis, &mut memory, |engine| {
+        for doc_id in ["a", "b", "d"] {
+            assert!(engine.remove_document(doc_id).unwrap());
+        }
+    });
+    assert_same(&redis, &memory);
+    assert!(SearchEngine::term_stats(&redis).unwrap().is_empty());
+}
+
+#[test]
+#[ignore = "needs Docker or a Redis server at REDIS_URL"]
+fn test_redis_cache() {
+    let server = Server::start();
+    let mut redis = server.open("searcher-test:cache");
+    let mut memory = Searcher::new();
+    both(&mut redis, &mut memory, |engine| {
+        let docs = [("a", "moon rocket"), ("b", "ocean tide tide"), ("c", "rust moon")];
+        engine.add_documents(docs.iter().map(|(doc_id, content)| (doc_id.to_string(), content.to_string())).collect()).unwrap();
+    });
+
+    let idf = Arc::new(RedisCache::<f32>::open(&server.url, "searcher-test:cache").unwrap());
+    let query_cache = RedisCache::<HashMap<String, f32>>::open(&server.url, "searcher-test:cache").unwrap();
+    idf.clear();
+    query_cache.clear();
+    redis.set_idf_cache(Some(idf.clone()));
+    // the second time round every idf and query comes from the caches
+    for _ in 0..2 {
+        assert_same(&redis, &memory);
+        for query in queries() {
+            assert_eq!(redis.search_query_cached(&query, &query_cache), memory.search_query(&query), "{:?}", query);
+        }
+    }
+
+    // another process with a cache under the same prefix finds what this one cached, but not one
+    // under a nested prefix
+    let elsewhere = RedisCache::<f32>::open(&server.url, "searcher-test:cache").unwrap();
+    let nested = RedisCache::<f32>::open(&server.url, "searcher-test:cache:nested").unwrap();
+    idf.insert("moon".to_string(), 1.5);
+    assert_eq!(elsewhere.get(&"moon".to_string()), Some(1.5));
+    assert_eq!(nested.get(&"moon".to_string()), None);
+
+    // clearing drops the entries for every process, and only those under the prefix
+    nested.insert("moon".to_string(), 2.5);
+    elsewhere.clear();
+    assert_eq!(idf.get(&"moon".to_string()), None);
+    assert_eq!(nested.get(&"moon".to_string()), Some(2.5));
+    nested.clear();
+}